@@ -0,0 +1,4810 @@
+use std::{collections::{BTreeSet, HashMap, HashSet}, path::{Component, Path, PathBuf}};
+
+use anyhow::{Context, Result};
+use chrono::Timelike;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use epub::doc::EpubDoc;
+use image::RgbImage;
+use log::{debug, error, info, warn};
+use ratatui::{
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{block::{Position, Title}, Block, Borders, Clear, List, ListItem, ListState, Paragraph, Tabs, Wrap},
+};
+
+use crate::big_print::{self, GLYPH_HEIGHT, GLYPH_WIDTH};
+use crate::blocks;
+use crate::view::{View, DEFAULT_LIBRARY_WIDTH};
+use crate::watcher::LibraryWatcher;
+use crate::book::{self, Book, Document, SalvagedBook};
+use crate::bookmark::Bookmarks;
+use crate::catalog::{Catalog, Entry, Query, Term};
+use crate::collections::{Collection, Collections};
+use crate::config::{DictionaryConfig, HooksConfig, LibraryPreview};
+use crate::discord::Activity;
+use crate::diagnostics::{self, Diagnostics, Validation};
+use crate::dictionary;
+use crate::history::History;
+use crate::images::{self, Protocol};
+use crate::hooks::{self, Event, EventContext};
+use crate::download::{self, Download};
+use crate::footnotes::{self, NoteRef};
+use crate::fuzzy::{self, Match};
+use crate::inspector;
+use crate::layout::{self, ChapterLayout, ParagraphFormat, MINIMAP_WIDTH, PARAGRAPH_INDENT, SPACED_LINE_WIDTH};
+use crate::library::{self, ScanOptions};
+use crate::library_index::LibraryIndex;
+use crate::library_stats::{self, LibraryStats};
+use crate::page_list::PageList;
+use crate::perf::{self, PerfStats};
+use crate::plugins::{Hook, PluginAction, Plugins};
+use crate::popup::{self, ErrorPopup};
+use crate::prompt_history::{Prompt, PromptHistory};
+use crate::readability::Readability;
+use crate::queue::ReadingQueue;
+use crate::reading_time::{ReadingTime, Reminders};
+use crate::notifications;
+use crate::regex_patterns::RegexPatterns;
+use crate::relative_time;
+use crate::remote::{RemoteCommand, RemoteStatus};
+use crate::sanitize::{self, Limits};
+use crate::sort::{self, SortOrder};
+use crate::status_file::{self, ReadingStatus};
+
+pub struct App {
+    /// Books shown in the list: the library, narrowed by the filter
+    epub_files: Vec<String>,
+    /// Every book in the books directory
+    library: Vec<String>,
+    /// Metadata of the library, read the first time a filter needs it
+    catalog: Option<Catalog>,
+    /// Query the list is filtered by, empty for the whole library
+    filter: String,
+    /// The query being typed after `/`
+    filter_input: Option<String>,
+    /// The URL being typed after `o`
+    url_input: Option<String>,
+    /// Saved filters, listed as folders above the books
+    collections: Collections,
+    /// The name being typed to save the filter as a collection after `+`
+    collection_input: Option<String>,
+    /// The page being typed after `G`
+    page_input: Option<String>,
+    /// The word being typed after `K`
+    lookup_input: Option<String>,
+    /// The book `d`, `r` or `m` was pressed on in the library, and what
+    /// is to be done with it
+    file_operation: Option<(String, FileOperation)>,
+    /// The review being written in the book info popup
+    review_input: Option<String>,
+    /// The note on the current part being written, in its popup
+    chapter_note_input: Option<String>,
+    /// The note whose reference is previewed with `F`
+    note_preview: Option<NotePreview>,
+    /// Book shown in the book info popup, with its metadata
+    info: Option<(String, Entry)>,
+    /// Cover of the book in `info`, decoded
+    info_cover: Option<RgbImage>,
+    /// Description of the book in `info`, formatted as its parts are
+    info_description: Option<String>,
+    /// Small covers for the shelf, `None` for books without one
+    thumbnails: HashMap<String, Option<RgbImage>>,
+    /// Row of cards at the top of the shelf, and cards in a row, as last drawn
+    shelf_offset: usize,
+    shelf_columns: usize,
+    /// How pictures are drawn; `None` leaves them out
+    graphics: Option<Protocol>,
+    /// The book whose cover the last frame placed, and where, when kitty
+    /// or sixel draw it over the frame
+    picture: Option<(String, Rect)>,
+    /// The cover on screen now, as `picture` was when it was last drawn
+    shown_picture: Option<(String, Rect)>,
+    /// Metadata of the book last previewed in the library
+    preview: Option<(String, Entry)>,
+    library_preview: LibraryPreview,
+    /// `relative_time` format of the library's last-read times
+    last_read_format: String,
+    /// The first page of body text of the book last previewed with
+    /// `LibraryPreview::FirstPage`; `None` when it has no text to show
+    sample: Option<(String, Option<ChapterLayout>)>,
+    /// Where downloaded books are saved
+    books_dir: String,
+    /// Directories the library is read from
+    roots: Vec<String>,
+    /// How the roots are read; `.` toggles the hidden files
+    scan: ScanOptions,
+    /// Move past publisher pages between parts
+    skip_boilerplate: bool,
+    /// Parts of each book opened that look like publisher pages, found
+    /// when first moving between parts with `skip_boilerplate`
+    boilerplate: HashMap<String, BTreeSet<usize>>,
+    /// What was entered in the prompts before
+    prompt_history: PromptHistory,
+    /// The entry of `prompt_history` shown in the open prompt, with what
+    /// was typed before going back to it
+    recalled: Option<(usize, String)>,
+    /// Metadata, part sizes and progress of the library's books
+    index: LibraryIndex,
+    /// Book being downloaded, opened once it arrives
+    download: Option<Download>,
+    selected: usize,
+    /// Books pinned above the list under "Continue reading", and how many
+    /// at most
+    pinned: Vec<String>,
+    continue_reading: usize,
+    current_content: Option<String>,
+    list_state: ListState,
+    current_book: Option<Book>,
+    current_chapter: usize,
+    total_chapters: usize,
+    scroll_offset: usize,
+    mode: Mode,
+    bookmarks: Bookmarks,
+    current_file: Option<String>,
+    /// Language of the current book, declared or told from its text
+    current_language: Option<String>,
+    content_length: usize,
+    last_scroll_time: std::time::Instant,
+    scroll_speed: usize,
+    regex: RegexPatterns,
+    debug_mode: bool,
+    plugins: Plugins,
+    applying_plugin_actions: bool,
+    perf_overlay: bool,
+    perf: PerfStats,
+    error_popup: Option<ErrorPopup>,
+    layout: Option<ChapterLayout>,
+    /// Open books in tab order. The active tab's slot is `None`: its state
+    /// lives in the `current_*` fields above while it is shown.
+    tabs: Vec<Option<Tab>>,
+    active_tab: usize,
+    /// `g` was pressed and the next key completes a `gt`/`gT` command
+    pending_g: bool,
+    /// `b` was pressed and the next digit names the slot to save to
+    pending_slot: bool,
+    /// `'` was pressed and the next digit names the slot to jump to
+    pending_jump: bool,
+    /// Count typed before a motion, vim-style: `10j`, `3l`
+    count: Option<usize>,
+    /// `M` or `@` was pressed and the next key names the register
+    pending_macro: Option<MacroKey>,
+    /// Register being recorded to, with the keys pressed so far
+    recording: Option<(char, Vec<KeyEvent>)>,
+    /// Recorded key sequences by register
+    macros: HashMap<char, Vec<KeyEvent>>,
+    /// Register played last, for `@@`
+    last_macro: Option<char>,
+    /// Macros being played inside one another
+    macro_depth: usize,
+    split: Option<Split>,
+    history: History,
+    reading_time: ReadingTime,
+    reminders: Reminders,
+    /// Last key press; reading time stops counting after `IDLE_AFTER`
+    last_activity: std::time::Instant,
+    last_tick: std::time::Instant,
+    /// First bookmark change not yet written to disk
+    unsaved_since: Option<std::time::Instant>,
+    history_state: ListState,
+    /// Books to read next
+    queue: ReadingQueue,
+    queue_state: ListState,
+    /// Every copy of the books the library has several copies of, by the
+    /// copy listed for them
+    copies: HashMap<String, Vec<String>>,
+    /// The copies to pick from in the popup, and the one picked
+    copy_choice: Vec<String>,
+    copies_state: ListState,
+    /// Selection in the highlights of the current book
+    highlights_state: ListState,
+    finder: Option<Finder>,
+    /// Mode to go back to when the history view is closed
+    previous_mode: Mode,
+    /// Show the parts of the current book instead of the library while reading
+    chapter_sidebar: bool,
+    /// Part titles of the book they were read from
+    part_titles: Option<(String, Vec<String>)>,
+    minimap: bool,
+    /// Wrap prose to the pane; off, long lines are cut and scroll sideways
+    wrap: bool,
+    paragraph_format: ParagraphFormat,
+    line_numbers: bool,
+    /// Text drawn in big block letters, a few words to a line
+    large_print: bool,
+    /// Percent of the screen the library takes, 0 to hide it while reading
+    library_width: u16,
+    /// The layout as last saved, to carry over to the next session
+    view: View,
+    inspector_scroll: u16,
+    /// What the library is made of, gathered when the statistics view opens
+    library_stats: LibraryStats,
+    /// Readability of the part the statistics view was opened on, shown
+    /// there instead of the library's
+    readability: Option<(String, Readability)>,
+    stats_scroll: u16,
+    /// The book checked by the validation view and what was found
+    validation: Option<(String, Validation)>,
+    validation_scroll: u16,
+    /// Text length of every part, by book, for the whole-book progress
+    part_sizes: HashMap<String, Vec<usize>>,
+    /// Share of the current part to scroll to once it is laid out
+    seek: Option<f64>,
+    /// Pages of the printed edition, by book, empty for books without them
+    page_lists: HashMap<String, PageList>,
+    /// Text rows of the focused pane at the last draw
+    visible_height: usize,
+    /// Text copied since the last event, for the terminal to pick up
+    clipboard: Option<String>,
+    /// Shown in place of the help bar until the next key press
+    status: Option<String>,
+    /// Positions before each chapter change, latest last, across all books
+    undo: Vec<Place>,
+    /// The book shown before the current one, to switch back to
+    alternate_book: Option<String>,
+    /// Where the notes followed were referenced, the latest last
+    link_returns: Vec<Place>,
+    hooks: HooksConfig,
+    dictionary: DictionaryConfig,
+    /// Book shown at the last tick and whether its end was in view, to
+    /// tell when the end is reached
+    was_at_end: Option<(String, bool)>,
+    watcher: Option<LibraryWatcher>,
+    /// When the watcher last saw a book change, not yet rescanned
+    library_changed: Option<std::time::Instant>,
+}
+
+/// A book in a background tab, with the reading state it had when the user
+/// switched away from it.
+struct Tab {
+    book: Book,
+    file: String,
+    chapter: usize,
+    total_chapters: usize,
+    scroll_offset: usize,
+    content: Option<String>,
+    content_length: usize,
+    layout: Option<ChapterLayout>,
+    /// Whether `content` is raw HTML from debug mode
+    debug: bool,
+    language: Option<String>,
+}
+
+impl Tab {
+    /// Wraps the content to `width` (or cuts it there, without `wrap`),
+    /// keeping the text that was at the top of the pane in place. Prose is
+    /// hyphenated for the book's language. Returns `None` when the layout
+    /// was already up to date, otherwise whether the scroll offset moved.
+    fn fit(&mut self, width: usize, wrap: bool, format: ParagraphFormat) -> Option<bool> {
+        let Some(content) = &self.content else {
+            self.layout = None;
+            return None;
+        };
+        match &mut self.layout {
+            Some(layout) if layout.width() == width && layout.wraps() == wrap => None,
+            Some(layout) => {
+                let (source, offset) = layout.position_of(self.scroll_offset);
+                layout.set_wrap(wrap);
+                layout.rewrap(width);
+                let scroll_offset = layout.index_of(source, offset);
+                debug!("Resized to width {}: scroll offset {} -> {}", width, self.scroll_offset, scroll_offset);
+                let moved = scroll_offset != self.scroll_offset;
+                self.scroll_offset = scroll_offset;
+                Some(moved)
+            }
+            None => {
+                let mut layout = if self.debug {
+                    ChapterLayout::html(content, width)
+                } else {
+                    let mut layout = ChapterLayout::with_format(content, width, format);
+                    if let Some(language) = &self.language {
+                        layout.hyphenate(language);
+                    }
+                    layout
+                };
+                layout.set_wrap(wrap);
+                self.layout = Some(layout);
+                Some(false)
+            }
+        }
+    }
+
+    /// Moves the scroll offset to the same relative position in this part
+    /// as `leader` has in its own. Returns whether it moved.
+    fn follow(&mut self, leader: &Tab, height: usize) -> bool {
+        let (Some(leader_layout), Some(layout)) = (&leader.layout, &self.layout) else {
+            return false;
+        };
+        let leader_max = leader_layout.len().saturating_sub(height);
+        if leader_max == 0 {
+            return false;
+        }
+        let max = layout.len().saturating_sub(height);
+        let scroll_offset = leader.scroll_offset.min(leader_max) * max / leader_max;
+        let moved = scroll_offset != self.scroll_offset;
+        self.scroll_offset = scroll_offset;
+        moved
+    }
+}
+
+/// A second book, or a second view of the same book, shown beside the
+/// current tab.
+struct Split {
+    /// The pane without the focus; the focused one lives in `App`'s fields
+    other: Tab,
+    focus_right: bool,
+    /// Scrolling the focused pane moves the other to the same position
+    synced: bool,
+}
+
+/// The fuzzy finder opened with Ctrl-P, over every book of the library.
+struct Finder {
+    query: String,
+    /// Books matching `query`, best first, with where their text matched
+    matches: Vec<(String, Match)>,
+    state: ListState,
+    /// The mode to return to when the finder closes
+    back: Mode,
+}
+
+/// What is done to a book from the library, with the name or folder
+/// being typed for it.
+#[derive(Debug, Clone, PartialEq)]
+enum FileOperation {
+    /// Waiting for `y` to confirm
+    Delete,
+    Rename(String),
+    /// To a folder of the library root the book is in
+    Move(String),
+}
+
+/// What the register named after `M` or `@` is for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MacroKey {
+    Record,
+    /// Play, this many times
+    Play(usize),
+}
+
+/// Macros played from within macros deeper than this are skipped, so a
+/// macro that plays itself ends.
+const MAX_MACRO_DEPTH: usize = 10;
+
+/// Where the reader was before a chapter change, for `u` to go back to.
+struct Place {
+    file: String,
+    chapter: usize,
+    scroll_offset: usize,
+}
+
+/// A note shown in a popup over the text that references it.
+struct NotePreview {
+    /// Which of the references on screen, and how many there are
+    index: usize,
+    count: usize,
+    label: String,
+    text: String,
+}
+
+/// Time without a key press after which the reader is taken to be away.
+const IDLE_AFTER: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// How often the help bar shows a download's progress.
+const DOWNLOAD_REFRESH: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// How long the library has to stay unchanged before it is rescanned,
+/// so a book still being copied in is read once it is whole.
+const WATCH_SETTLE: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Widest a cover is shown in the book info, in columns.
+const MAX_COVER_WIDTH: u16 = 30;
+
+/// Books the "Continue reading" section shows by default.
+pub const DEFAULT_CONTINUE_READING: usize = 5;
+
+/// Width and height of a card on the shelf, borders included.
+const CARD_WIDTH: u16 = 18;
+const CARD_HEIGHT: u16 = 11;
+/// Longest side covers are decoded at for the shelf.
+const THUMBNAIL_SIDE: u32 = 64;
+
+/// Longest a scroll position stays unsaved.
+const FLUSH_AFTER: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Columns the arrow keys move preformatted text sideways.
+const HSCROLL_STEP: isize = 8;
+
+/// Chapter changes remembered for undo; older ones are forgotten.
+const MAX_UNDO: usize = 100;
+
+/// Largest count a motion takes, so a held digit key can't overflow it.
+const MAX_COUNT: usize = 9999;
+
+/// Followed notes remembered to go back from.
+const MAX_LINK_RETURNS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    FileList,
+    Content,
+    History,
+    Inspector,
+    Statistics,
+    Queue,
+    BookInfo,
+    Validation,
+    Completion,
+    Highlights,
+    Finder,
+    /// The copies of a book the library has several of, to pick one
+    Copies,
+    /// The library as a grid of covers and title cards
+    Shelf,
+}
+
+impl App {
+    /// Opens the library in the `roots` directories, with the bookmarks
+    /// kept in `bookmarks_file`.
+    pub fn new(bookmarks_file: &Path, roots: &[String], scan: ScanOptions) -> Result<Self> {
+        let bookmarks = Bookmarks::load_from(bookmarks_file).context("Failed to load bookmarks")?;
+        Self::with_library(roots, scan, bookmarks)
+    }
+
+    pub fn with_books_dir(books_dir: &str, bookmarks: Bookmarks) -> Result<Self> {
+        Self::with_library(&[books_dir.to_string()], ScanOptions::default(), bookmarks)
+    }
+
+    /// Opens the library in the `roots` directories, read as `scan` says.
+    /// Downloads go to the first of them.
+    pub fn with_library(roots: &[String], scan: ScanOptions, bookmarks: Bookmarks) -> Result<Self> {
+        let mut epub_files = library::scan_roots(roots, &scan)?;
+
+        let mut list_state = ListState::default();
+        list_state.select(Some(0));
+        
+        let regex = RegexPatterns::new()
+            .context("Failed to compile regex patterns")?;
+        let history = History::beside(bookmarks.path()).context("Failed to load reading history")?;
+        let reading_time = ReadingTime::beside(bookmarks.path()).context("Failed to load reading time")?;
+        let queue = ReadingQueue::beside(bookmarks.path()).context("Failed to load the reading queue")?;
+        let collections = Collections::beside(bookmarks.path()).context("Failed to load the collections")?;
+        let view = View::beside(bookmarks.path()).context("Failed to load the view")?;
+        let prompt_history = PromptHistory::beside(bookmarks.path()).context("Failed to load the prompt history")?;
+        let mut index = LibraryIndex::beside(bookmarks.path()).unwrap_or_else(|e| {
+            error!("Failed to open the library index, keeping it in memory: {:#}", e);
+            LibraryIndex::in_memory()
+        });
+        if let Err(e) = index.refresh(&epub_files, &bookmarks) {
+            error!("Failed to update the library index: {:#}", e);
+        }
+        sort::sort(&mut epub_files, view.sort, &index, &bookmarks);
+        let library = epub_files.clone();
+        if view.hide_finished {
+            epub_files.retain(|file| !bookmarks.is_finished(file));
+        }
+        let copies = group_copies(&mut epub_files, &index, &bookmarks);
+        let pinned = continue_reading(&library, &bookmarks, DEFAULT_CONTINUE_READING);
+        // The cursor starts on the first book of the list itself
+        list_state.select(Some(pinned.len()));
+
+        Ok(Self {
+            mode: if view.shelf { Mode::Shelf } else { Mode::FileList },
+            list_state,
+            library,
+            epub_files,
+            catalog: None,
+            filter: String::new(),
+            filter_input: None,
+            url_input: None,
+            collections,
+            collection_input: None,
+            file_operation: None,
+            page_input: None,
+            lookup_input: None,
+            review_input: None,
+            chapter_note_input: None,
+            note_preview: None,
+            info: None,
+            info_cover: None,
+            info_description: None,
+            thumbnails: HashMap::new(),
+            shelf_offset: 0,
+            shelf_columns: 1,
+            graphics: Some(Protocol::Blocks),
+            picture: None,
+            shown_picture: None,
+            preview: None,
+            library_preview: LibraryPreview::default(),
+            last_read_format: relative_time::RELATIVE.to_string(),
+            sample: None,
+            books_dir: roots.first().cloned().unwrap_or_else(|| library::DEFAULT_ROOT.to_string()),
+            roots: roots.to_vec(),
+            scan,
+            skip_boilerplate: false,
+            boilerplate: HashMap::new(),
+            prompt_history,
+            recalled: None,
+            index,
+            download: None,
+            selected: pinned.len(),
+            pinned,
+            continue_reading: DEFAULT_CONTINUE_READING,
+            current_book: None,
+            current_file: None,
+            current_language: None,
+            current_content: None,
+            content_length: 0,
+            scroll_offset: 0,
+            current_chapter: 0,
+            total_chapters: 0,
+            bookmarks,
+            last_scroll_time: std::time::Instant::now(),
+            scroll_speed: 1,
+            debug_mode: false,
+            regex,
+            plugins: Plugins::new(),
+            applying_plugin_actions: false,
+            perf_overlay: false,
+            perf: PerfStats::default(),
+            error_popup: None,
+            layout: None,
+            tabs: Vec::new(),
+            active_tab: 0,
+            pending_g: false,
+            pending_slot: false,
+            pending_jump: false,
+            count: None,
+            pending_macro: None,
+            recording: None,
+            macros: HashMap::new(),
+            last_macro: None,
+            macro_depth: 0,
+            split: None,
+            history,
+            reading_time,
+            reminders: Reminders::default(),
+            last_activity: std::time::Instant::now(),
+            last_tick: std::time::Instant::now(),
+            unsaved_since: None,
+            history_state: ListState::default(),
+            queue,
+            queue_state: ListState::default(),
+            copies,
+            copy_choice: Vec::new(),
+            copies_state: ListState::default(),
+            highlights_state: ListState::default(),
+            finder: None,
+            previous_mode: Mode::FileList,
+            chapter_sidebar: view.chapter_sidebar,
+            part_titles: None,
+            minimap: view.minimap,
+            wrap: view.wrap,
+            paragraph_format: ParagraphFormat::default(),
+            line_numbers: view.line_numbers,
+            large_print: view.large_print,
+            library_width: view.library_width,
+            view,
+            inspector_scroll: 0,
+            library_stats: LibraryStats::default(),
+            readability: None,
+            stats_scroll: 0,
+            validation: None,
+            validation_scroll: 0,
+            part_sizes: HashMap::new(),
+            page_lists: HashMap::new(),
+            seek: None,
+            visible_height: 0,
+            clipboard: None,
+            status: None,
+            undo: Vec::new(),
+            link_returns: Vec::new(),
+            alternate_book: None,
+            hooks: HooksConfig::default(),
+            dictionary: DictionaryConfig::default(),
+            was_at_end: None,
+            watcher: None,
+            library_changed: None,
+        })
+    }
+
+    pub fn set_plugins(&mut self, plugins: Plugins) {
+        self.plugins = plugins;
+    }
+
+    /// Keeps bookmarks in sidecar files beside the books as well, taking
+    /// in those already there.
+    pub fn use_sidecars(&mut self) {
+        self.bookmarks.use_sidecars(&self.library);
+        self.sort_library();
+    }
+
+    pub fn set_reminders(&mut self, reminders: Reminders) {
+        self.reminders = reminders;
+    }
+
+    pub fn set_skip_boilerplate(&mut self, skip: bool) {
+        self.skip_boilerplate = skip;
+    }
+
+    pub fn set_hooks(&mut self, hooks: HooksConfig) {
+        self.hooks = hooks;
+    }
+
+    pub fn set_dictionary(&mut self, dictionary: DictionaryConfig) {
+        self.dictionary = dictionary;
+    }
+
+    /// Draws covers with `protocol`, or leaves them out with `None`.
+    pub fn set_graphics(&mut self, protocol: Option<Protocol>) {
+        self.graphics = protocol;
+    }
+
+    pub fn set_library_preview(&mut self, preview: LibraryPreview) {
+        self.library_preview = preview;
+    }
+
+    /// Sets how the library shows last-read times, keeping them relative
+    /// when `format` is not a usable strftime pattern.
+    pub fn set_last_read_format(&mut self, format: &str) {
+        if relative_time::is_valid(format) {
+            self.last_read_format = format.to_string();
+        } else {
+            warn!("Invalid last_read_format {:?}, showing relative times", format);
+            self.last_read_format = relative_time::RELATIVE.to_string();
+        }
+    }
+
+    /// Sets how paragraphs are laid out, laying out open books again.
+    pub fn set_paragraph_format(&mut self, format: ParagraphFormat) {
+        self.paragraph_format = format;
+        self.layout = None;
+        for tab in self.tabs.iter_mut().flatten() {
+            tab.layout = None;
+        }
+        if let Some(split) = &mut self.split {
+            split.other.layout = None;
+        }
+    }
+
+    fn fire_hook(&mut self, hook: Hook, args: impl rhai::FuncArgs + Clone) {
+        // Actions applied on behalf of a script don't re-trigger hooks,
+        // otherwise a script navigating from a hook could loop forever.
+        if self.applying_plugin_actions || self.plugins.is_empty() {
+            return;
+        }
+        let actions = self.plugins.fire(hook, args);
+        self.apply_plugin_actions(actions);
+    }
+
+    /// Runs the shell hook for `event` on the current book.
+    fn fire_event(&mut self, event: Event, chapter: usize) {
+        let Some(book) = self.current_file.clone() else {
+            return;
+        };
+        let Some((title, author)) = self.book_metadata() else {
+            return;
+        };
+        let context = EventContext {
+            book,
+            title,
+            author,
+            chapter: chapter + 1,
+            total_chapters: self.total_chapters,
+            ..Default::default()
+        };
+        hooks::run(&self.hooks, event, &context);
+    }
+
+    fn apply_plugin_actions(&mut self, actions: Vec<PluginAction>) {
+        self.applying_plugin_actions = true;
+        for action in actions {
+            debug!("Applying plugin action: {:?}", action);
+            match action {
+                PluginAction::NextChapter => self.next_chapter(),
+                PluginAction::PrevChapter => self.prev_chapter(),
+                PluginAction::GoToChapter(index) => self.go_to_chapter(index),
+                PluginAction::ScrollDown(lines) => {
+                    self.scroll_offset = self.scroll_offset.saturating_add(lines);
+                    self.save_bookmark();
+                }
+                PluginAction::ScrollUp(lines) => {
+                    self.scroll_offset = self.scroll_offset.saturating_sub(lines);
+                    self.save_bookmark();
+                }
+            }
+        }
+        self.applying_plugin_actions = false;
+    }
+
+    pub fn remote_status(&self) -> RemoteStatus {
+        RemoteStatus {
+            book: self.current_file.clone(),
+            mode: format!("{:?}", self.mode),
+            chapter: if self.current_book.is_some() { self.current_chapter + 1 } else { 0 },
+            total_chapters: self.total_chapters,
+            scroll_offset: self.scroll_offset,
+        }
+    }
+
+    pub fn apply_remote_command(&mut self, command: RemoteCommand) -> RemoteStatus {
+        info!("Remote command: {:?}", command);
+        match command {
+            RemoteCommand::Status => {}
+            RemoteCommand::NextChapter => self.next_chapter(),
+            RemoteCommand::PrevChapter => self.prev_chapter(),
+            RemoteCommand::Jump { chapter, offset } => {
+                if chapter.saturating_sub(1) != self.current_chapter {
+                    self.go_to_chapter(chapter.saturating_sub(1));
+                }
+                if let Some(offset) = offset {
+                    self.scroll_offset = offset;
+                    self.save_bookmark();
+                }
+            }
+        }
+        self.remote_status()
+    }
+
+    /// Rows of collections above the books: the collections are listed
+    /// while the whole library is.
+    fn folder_rows(&self) -> usize {
+        if self.filter.is_empty() {
+            self.collections.len()
+        } else {
+            0
+        }
+    }
+
+    /// The collection under the cursor of the list, if it is on one.
+    fn selected_collection(&self) -> Option<&Collection> {
+        let row = self.selected.checked_sub(self.pinned.len())?;
+        self.collections.all().get(row).filter(|_| row < self.folder_rows())
+    }
+
+    /// Row of the list holding its first book, below the books pinned
+    /// under "Continue reading" and the collections.
+    fn first_book_row(&self) -> usize {
+        self.pinned.len() + self.folder_rows()
+    }
+
+    /// The book under the cursor of the list, unless it is on a collection.
+    fn selected_file(&self) -> Option<&String> {
+        if let Some(pinned) = self.pinned.get(self.selected) {
+            return Some(pinned);
+        }
+        self.epub_files.get(self.selected.checked_sub(self.first_book_row())?)
+    }
+
+    /// Puts the cursor of the list on the book at `index` of the books shown.
+    fn select_file_at(&mut self, index: usize) {
+        self.selected = index + self.first_book_row();
+        self.list_state.select(Some(self.selected));
+    }
+
+    /// Where `book` is among the books shown, or the copy listed for it.
+    fn listed_position(&self, book: &str) -> Option<usize> {
+        self.epub_files
+            .iter()
+            .position(|file| file == book || self.copies.get(file).is_some_and(|copies| copies.iter().any(|copy| copy == book)))
+    }
+
+    /// Pins the most recently read books above the list, while it shows
+    /// the whole library.
+    fn refresh_pinned(&mut self) {
+        self.pinned = if self.filter.is_empty() {
+            continue_reading(&self.library, &self.bookmarks, self.continue_reading)
+        } else {
+            Vec::new()
+        };
+    }
+
+    /// Pins up to `count` books under "Continue reading"; 0 leaves the
+    /// section out.
+    pub fn set_continue_reading(&mut self, count: usize) {
+        let selected = self.selected_file().cloned();
+        self.continue_reading = count;
+        self.refresh_pinned();
+        let index = selected.and_then(|selected| self.epub_files.iter().position(|file| *file == selected)).unwrap_or(0);
+        self.select_file_at(index);
+    }
+
+    /// Narrows the book list to the books matching `query`, keeping the
+    /// selected book selected when it still matches.
+    fn apply_filter(&mut self, query: &str) {
+        let parsed = Query::parse(query);
+        let selected = self.selected_file().cloned();
+        self.epub_files = if parsed.is_empty() {
+            self.library.clone()
+        } else {
+            let catalog = self.catalog.get_or_insert_with(|| self.index.catalog());
+            let opened: HashSet<&str> = self.history.visits().map(|visit| visit.book.as_str()).collect();
+            self.library
+                .iter()
+                .filter(|file| {
+                    let read = opened.contains(file.as_str()) || self.bookmarks.get_bookmark(file).is_some();
+                    let pages = parsed.needs_pages().then(|| {
+                        let sizes = self
+                            .part_sizes
+                            .entry(file.to_string())
+                            .or_insert_with(|| self.index.part_sizes(file, || book::part_sizes_of(file)));
+                        library_stats::estimated_pages(sizes.iter().sum())
+                    });
+                    catalog.get(file).is_some_and(|entry| parsed.matches(file, entry, read, pages))
+                })
+                .cloned()
+                .collect()
+        };
+        if self.view.hide_finished {
+            self.epub_files.retain(|file| !self.bookmarks.is_finished(file));
+        }
+        self.copies = group_copies(&mut self.epub_files, &self.index, &self.bookmarks);
+        self.filter = query.trim().to_string();
+        self.refresh_pinned();
+        info!("Filter {:?} matches {} of {} books", self.filter, self.epub_files.len(), self.library.len());
+
+        let index = selected.and_then(|selected| self.listed_position(&selected)).unwrap_or(0);
+        self.select_file_at(index);
+    }
+
+    /// Saves the filter as a collection named `name`.
+    fn save_collection(&mut self, name: &str) {
+        if name.trim().is_empty() || self.filter.is_empty() {
+            return;
+        }
+        self.collections.save_query(name, &self.filter);
+        self.status = Some(format!("Saved collection {}", name.trim()));
+    }
+
+    /// Downloads the book at `url` into the books directory and opens it
+    /// when it arrives. Progress is shown in the help bar meanwhile.
+    pub fn open_url(&mut self, url: &str) {
+        if let Some(download) = &self.download {
+            self.status = Some(format!("Still downloading {}", download.name));
+            return;
+        }
+        info!("Downloading {}", url);
+        self.download = Some(Download::start(url, &self.books_dir));
+    }
+
+    pub fn is_downloading(&self) -> bool {
+        self.download.is_some()
+    }
+
+    /// Opens the book at `path`, which need not be in the library.
+    /// Opens the book at `path`, selecting it in the library when it is
+    /// one of its books.
+    pub fn open_file(&mut self, path: &str) {
+        let path = self.library_path(path);
+        if let Some(index) = self.epub_files.iter().position(|file| *file == path) {
+            self.select_file_at(index);
+        }
+        self.load_epub(&path, false);
+    }
+
+    /// `path` as the library lists it, so a book opened by another name
+    /// finds its bookmark. Books outside the library go by their full path.
+    fn library_path(&self, path: &str) -> String {
+        let Ok(full) = Path::new(path).canonicalize() else {
+            return path.to_string();
+        };
+        self.library
+            .iter()
+            .find(|book| Path::new(book).canonicalize().is_ok_and(|book| book == full))
+            .cloned()
+            .unwrap_or_else(|| full.to_string_lossy().to_string())
+    }
+
+    /// Shows `document`, read from standard input, as an open book.
+    pub fn open_document(&mut self, document: Document) {
+        self.open_book(book::STDIN, Book::Document(document), false);
+    }
+
+    fn poll_download(&mut self) {
+        let Some(outcome) = self.download.as_mut().and_then(Download::poll) else {
+            return;
+        };
+        let download = self.download.take().expect("polled a download");
+        match outcome {
+            Ok(path) => {
+                self.rescan_library();
+                if let Some(index) = self.epub_files.iter().position(|file| *file == path) {
+                    self.select_file_at(index);
+                }
+                self.load_epub(&path, false);
+            }
+            Err(reason) => self.show_error(
+                ErrorPopup::new(
+                    "Download failed",
+                    format!("Couldn't download {}.", download.url),
+                    "Check the URL and the connection, then try again with o.",
+                )
+                .with_details(vec![reason]),
+            ),
+        }
+    }
+
+    /// Reads the library directories again, keeping the filter.
+    fn rescan_library(&mut self) {
+        match library::scan_roots(&self.roots, &self.scan) {
+            Ok(books) => {
+                if self.bookmarks.uses_sidecars() {
+                    let added: Vec<String> = books.iter().filter(|book| !self.library.contains(book)).cloned().collect();
+                    self.bookmarks.use_sidecars(&added);
+                }
+                if let Err(e) = self.index.refresh(&books, &self.bookmarks) {
+                    error!("Failed to update the library index: {:#}", e);
+                }
+                self.library = books;
+                self.catalog = None;
+                self.sort_library();
+            }
+            Err(e) => error!("Failed to rescan the library: {:?}", e),
+        }
+    }
+
+    /// Rescans the library whenever a book is added to or removed from
+    /// the roots, calling `wake` as soon as one is.
+    pub fn watch_library(&mut self, wake: impl Fn() + Send + 'static) {
+        let recursive = self.scan.max_depth > 0;
+        match LibraryWatcher::start_waking(&self.roots, recursive, wake) {
+            Ok(watcher) => self.watcher = Some(watcher),
+            Err(e) => error!("Failed to watch the library: {:#}", e),
+        }
+    }
+
+    /// Takes in the changes the watcher saw, once they have settled.
+    fn poll_watcher(&mut self, now: std::time::Instant) {
+        if self.watcher.as_ref().is_some_and(LibraryWatcher::changed) {
+            self.library_changed = Some(now);
+        }
+        if self.library_changed.is_none_or(|since| now.duration_since(since) < WATCH_SETTLE) {
+            return;
+        }
+        self.library_changed = None;
+        let before: HashSet<String> = self.library.iter().cloned().collect();
+        self.rescan_library();
+        let added = self.library.iter().filter(|book| !before.contains(*book)).count();
+        let removed = before.len() + added - self.library.len();
+        let changes: Vec<String> = [(added, "added"), (removed, "removed")]
+            .into_iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, what)| format!("{} book{} {}", count, if count == 1 { "" } else { "s" }, what))
+            .collect();
+        if !changes.is_empty() {
+            self.status = Some(format!("Library: {}", changes.join(", ")));
+        }
+    }
+
+    /// Puts the library in the chosen order, keeping the filter and the
+    /// selected book.
+    fn sort_library(&mut self) {
+        // The open book's progress counts as it is now
+        if let Some(file) = self.current_file.as_deref() {
+            if let Some(bookmark) = self.bookmarks.get_bookmark(file) {
+                self.index.record_reading(file, self.percent_read(file), bookmark.last_read);
+            }
+        }
+        sort::sort(&mut self.library, self.view.sort, &self.index, &self.bookmarks);
+        let filter = self.filter.clone();
+        self.apply_filter(&filter);
+    }
+
+    /// Marks the selected book finished, or not finished if it was.
+    fn toggle_finished(&mut self) {
+        let Some(file) = self.selected_file().cloned() else {
+            return;
+        };
+        let finished = self.bookmarks.get_bookmark(&file).and_then(|bookmark| bookmark.finished);
+        if !self.bookmarks.set_finished(&file, finished.xor(Some(chrono::Utc::now()))) {
+            self.status = Some("Open the book before marking it finished".to_string());
+            return;
+        }
+        self.status = Some(format!("{} {}", if finished.is_some() { "Unfinished" } else { "Finished" }, file_name(&file)));
+        // Finished books leave "Continue reading", and the list when hidden
+        let filter = self.filter.clone();
+        self.apply_filter(&filter);
+    }
+
+    /// Shows or hides the books marked finished, kept for next time.
+    fn toggle_hide_finished(&mut self) {
+        let hide = !self.view.hide_finished;
+        self.view.update(|view| view.hide_finished = hide);
+        let filter = self.filter.clone();
+        self.apply_filter(&filter);
+        self.status = Some(format!("Finished books {}", if hide { "hidden" } else { "shown" }));
+    }
+
+    /// Switches the library to the next sort order, kept for next time.
+    fn cycle_sort(&mut self) {
+        let order = self.view.sort.next();
+        self.view.update(|view| view.sort = order);
+        self.sort_library();
+        self.status = Some(format!("Sorted by {}", order.name()));
+    }
+
+    fn handle_collection_key(&mut self, key: KeyEvent) -> bool {
+        let Some(input) = &mut self.collection_input else {
+            return false;
+        };
+        match key.code {
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Enter => {
+                let name = self.collection_input.take().unwrap_or_default();
+                self.save_collection(&name);
+            }
+            KeyCode::Esc => self.collection_input = None,
+            _ => {}
+        }
+        false
+    }
+
+    /// Asks what to do to the selected book: deleting it needs a `y`,
+    /// renaming and moving it a name or folder, filled in with the current
+    /// one. Books open in a tab or unpacked from an archive are left be.
+    fn start_file_operation(&mut self, operation: FileOperation) {
+        let Some(book) = self.selected_file().cloned() else {
+            return;
+        };
+        if Path::new(&book).starts_with(&self.scan.unpack_dir) {
+            self.status = Some("Books unpacked from an archive are changed in the archive".to_string());
+            return;
+        }
+        if self.tab_index_of(&book).is_some() || self.split.as_ref().is_some_and(|split| split.other.file == book) {
+            self.status = Some(format!("Close {} (x) before changing its file", file_name(&book)));
+            return;
+        }
+        let path = Path::new(&book);
+        let operation = match operation {
+            FileOperation::Rename(_) => FileOperation::Rename(path.file_name().unwrap_or_default().to_string_lossy().to_string()),
+            FileOperation::Move(_) => {
+                let folder = path.parent().unwrap_or(Path::new(""));
+                let root = self.root_of(&book).map(Path::new).unwrap_or(Path::new(""));
+                FileOperation::Move(folder.strip_prefix(root).unwrap_or(folder).to_string_lossy().to_string())
+            }
+            FileOperation::Delete => FileOperation::Delete,
+        };
+        self.file_operation = Some((book, operation));
+    }
+
+    /// The library root `book` is in.
+    fn root_of(&self, book: &str) -> Option<&str> {
+        self.roots.iter().map(String::as_str).find(|root| Path::new(book).starts_with(root))
+    }
+
+    fn handle_file_operation_key(&mut self, key: KeyEvent) -> bool {
+        let Some((book, operation)) = &mut self.file_operation else {
+            return false;
+        };
+        match (operation, key.code) {
+            (FileOperation::Delete, KeyCode::Char('y')) => {
+                let book = book.clone();
+                self.file_operation = None;
+                self.delete_book(&book);
+            }
+            (FileOperation::Delete, _) | (_, KeyCode::Esc) => self.file_operation = None,
+            (FileOperation::Rename(input) | FileOperation::Move(input), KeyCode::Char(c)) => input.push(c),
+            (FileOperation::Rename(input) | FileOperation::Move(input), KeyCode::Backspace) => {
+                input.pop();
+            }
+            (_, KeyCode::Enter) => {
+                if let Some((book, operation)) = self.file_operation.take() {
+                    self.relocate_book(&book, &operation);
+                }
+            }
+            _ => {}
+        }
+        false
+    }
+
+    fn delete_book(&mut self, book: &str) {
+        if let Err(e) = std::fs::remove_file(book) {
+            error!("Failed to delete {}: {}", book, e);
+            self.status = Some(format!("Failed to delete {}: {}", file_name(book), e));
+            return;
+        }
+        info!("Deleted {}", book);
+        self.bookmarks.remove(book);
+        self.queue.remove(book);
+        self.rescan_library();
+        self.status = Some(format!("Deleted {}", file_name(book)));
+    }
+
+    /// Renames or moves `book` as `operation` says, taking its bookmark,
+    /// place in the queue and index record along.
+    fn relocate_book(&mut self, book: &str, operation: &FileOperation) {
+        let path = Path::new(book);
+        let name = path.file_name().unwrap_or_default();
+        let target = match operation {
+            FileOperation::Rename(input) if input.trim().is_empty() || input.contains(std::path::MAIN_SEPARATOR) => {
+                self.status = Some("Give a file name; m moves a book to another folder".to_string());
+                return;
+            }
+            FileOperation::Rename(input) => {
+                // Without its extension the book would leave the library
+                let mut input = input.trim().to_string();
+                if let Some(extension) = path.extension().filter(|extension| Path::new(&input).extension() != Some(*extension)) {
+                    input = format!("{}.{}", input, extension.to_string_lossy());
+                }
+                path.with_file_name(input)
+            }
+            FileOperation::Move(folder) if Path::new(folder.trim()).components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) => {
+                self.status = Some("Give a folder inside the library".to_string());
+                return;
+            }
+            FileOperation::Move(folder) => {
+                let root = self.root_of(book).unwrap_or(&self.books_dir);
+                Path::new(root).join(folder.trim()).join(name)
+            }
+            FileOperation::Delete => return,
+        };
+        let Some(to) = target.to_str().map(str::to_string) else {
+            return;
+        };
+        if to == book {
+            return;
+        }
+        if let Err(e) = library::move_book(path, &target) {
+            error!("Failed to move {}: {:#}", book, e);
+            self.status = Some(format!("Failed to move {}: {:#}", file_name(book), e));
+            return;
+        }
+        self.bookmarks.rename(book, &to);
+        self.queue.rename(book, &to);
+        self.index.rename(book, &to);
+        self.rescan_library();
+        if let Some(index) = self.listed_position(&to) {
+            self.select_file_at(index);
+        }
+        self.status = Some(match operation {
+            FileOperation::Rename(_) => format!("Renamed {} to {}", name.to_string_lossy(), target.file_name().unwrap_or_default().to_string_lossy()),
+            _ => format!("Moved {} to {}", name.to_string_lossy(), target.parent().unwrap_or(Path::new("")).display()),
+        });
+    }
+
+    fn handle_page_key(&mut self, key: KeyEvent) -> bool {
+        let Some(input) = &mut self.page_input else {
+            return false;
+        };
+        match key.code {
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Enter => {
+                let page = self.page_input.take().unwrap_or_default();
+                self.go_to_page(&page);
+            }
+            KeyCode::Esc => self.page_input = None,
+            _ => {}
+        }
+        false
+    }
+
+    fn handle_lookup_key(&mut self, key: KeyEvent) -> bool {
+        let Some(input) = &mut self.lookup_input else {
+            return false;
+        };
+        match key.code {
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Enter => {
+                let word = self.lookup_input.take().unwrap_or_default();
+                self.look_up(&word);
+            }
+            KeyCode::Esc => self.lookup_input = None,
+            _ => {}
+        }
+        false
+    }
+
+    /// Opens the dictionary page for `word`, in the dictionary of the
+    /// current book's language.
+    fn look_up(&mut self, word: &str) {
+        if word.trim().is_empty() {
+            return;
+        }
+        let url = dictionary::url(&self.dictionary, word, self.current_language.as_deref());
+        dictionary::open(&self.dictionary, &url);
+        self.status = Some(format!("Looking up {} at {}", word.trim(), url));
+    }
+
+    /// Jumps to the start of page `label`: a page of the printed edition
+    /// when the book lists them, otherwise a virtual page.
+    fn go_to_page(&mut self, label: &str) {
+        let Some(file) = self.current_file.clone() else {
+            return;
+        };
+        if let Some(mut book) = self.current_book.take() {
+            self.measure(&file, &mut book);
+            self.current_book = Some(book);
+        }
+        let label = label.trim();
+        let target = match self.page_lists.get(&file).filter(|page_list| !page_list.is_empty()) {
+            Some(page_list) => page_list.find(label).map(|page| (page.chapter, page.fraction)),
+            None => label
+                .parse()
+                .ok()
+                .and_then(|page| library_stats::page_start(self.part_sizes.get(&file)?, page)),
+        };
+        let Some((chapter, fraction)) = target else {
+            self.status = Some(format!("No page {} in this book", label));
+            return;
+        };
+        info!("Going to page {}: part {} at {:.2}", label, chapter + 1, fraction);
+        self.go_to_fraction(chapter, fraction);
+    }
+
+    /// Goes to `fraction` of the text of part `chapter`, remembering the
+    /// place left for undo.
+    fn go_to_fraction(&mut self, chapter: usize, fraction: f64) {
+        if chapter != self.current_chapter {
+            self.go_to_chapter(chapter);
+            if self.current_chapter != chapter {
+                return;
+            }
+        } else {
+            self.remember_position();
+        }
+        self.seek = Some(fraction);
+    }
+
+    /// The footnote and endnote references of the current part that are on
+    /// screen.
+    fn notes_on_screen(&mut self) -> Vec<NoteRef> {
+        let Some(layout) = &self.layout else {
+            return Vec::new();
+        };
+        let rows = self.scroll_offset..self.scroll_offset + self.visible_height.max(1);
+        let Some(doc) = self.current_book.as_mut().and_then(Book::as_epub_mut) else {
+            return Vec::new();
+        };
+        let Some(id) = doc.spine.get(self.current_chapter).map(|item| item.idref.clone()) else {
+            return Vec::new();
+        };
+        let (Some(base), Some((html, _mime))) = (doc.resources.get(&id).map(|resource| resource.path.clone()), doc.get_resource_str(&id)) else {
+            return Vec::new();
+        };
+        footnotes::note_refs(&html, &base)
+            .into_iter()
+            .filter(|note| rows.contains(&layout.index_at_fraction(note.fraction)))
+            .collect()
+    }
+
+    /// The part `note` points to, with its (X)HTML, or `None` with the
+    /// reason shown when the note is not in the book.
+    fn note_target(&mut self, note: &NoteRef) -> Option<(usize, String)> {
+        let doc = self.current_book.as_mut().and_then(Book::as_epub_mut)?;
+        let path = note.target.split('#').next().unwrap_or_default();
+        let Some(chapter) = doc.resource_uri_to_chapter(&PathBuf::from(path)) else {
+            self.status = Some(format!("Note {} is not in this book", note.label));
+            return None;
+        };
+        let target_id = doc.spine[chapter].idref.clone();
+        let html = doc.get_resource_str(&target_id).map(|(html, _mime)| html).unwrap_or_default();
+        Some((chapter, html))
+    }
+
+    /// Follows note reference `index` of the ones on screen, keeping the
+    /// place of the reference to come back to with `return_from_note`.
+    fn follow_note(&mut self, index: usize) {
+        let Some(file) = self.current_file.clone() else {
+            return;
+        };
+        let Some(note) = self.notes_on_screen().into_iter().nth(index) else {
+            self.status = Some("No note reference on screen".to_string());
+            return;
+        };
+        let Some((chapter, html)) = self.note_target(&note) else {
+            return;
+        };
+        let fragment = note.target.split_once('#').map_or("", |(_, fragment)| fragment);
+        let fraction = book::anchor_fraction(&html, fragment).unwrap_or(0.0);
+        info!("Following note {} to part {} at {:.2}", note.label, chapter + 1, fraction);
+        self.link_returns.push(Place {
+            file,
+            chapter: self.current_chapter,
+            scroll_offset: self.scroll_offset,
+        });
+        if self.link_returns.len() > MAX_LINK_RETURNS {
+            self.link_returns.remove(0);
+        }
+        self.note_preview = None;
+        self.go_to_fraction(chapter, fraction);
+        self.status = Some(format!("Note {} | Backspace: Back to the text", note.label));
+    }
+
+    /// Shows the text of note reference `index` of the ones on screen in a
+    /// popup, without leaving the page. Past the last one it starts over.
+    fn preview_note(&mut self, index: usize) {
+        let notes = self.notes_on_screen();
+        if notes.is_empty() {
+            self.note_preview = None;
+            self.status = Some("No note reference on screen".to_string());
+            return;
+        }
+        let index = index % notes.len();
+        let note = &notes[index];
+        let Some((_, html)) = self.note_target(note) else {
+            return;
+        };
+        let fragment = note.target.split_once('#').map_or("", |(_, fragment)| fragment);
+        let text = footnotes::note_text(&html, fragment).unwrap_or_else(|| "The note could not be found.".to_string());
+        self.note_preview = Some(NotePreview {
+            index,
+            count: notes.len(),
+            label: note.label.clone(),
+            text,
+        });
+    }
+
+    /// The saved places of the current book while `'` waits for the slot
+    /// to jump to.
+    fn draw_slots(&self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(file) = self.current_file.as_deref().filter(|_| self.pending_jump) else {
+            return;
+        };
+        let mut lines: Vec<Line> = self
+            .bookmarks
+            .slots(file)
+            .flat_map(|(slot, place)| {
+                let mut lines = vec![Line::from(vec![
+                    Span::styled(format!("{}  ", slot), Style::default().fg(Color::Yellow)),
+                    Span::raw(format!("Part {}, line {}", place.chapter + 1, place.scroll_offset + 1)),
+                ])];
+                if let Some(snippet) = &place.snippet {
+                    lines.push(Line::from(format!("   {}", snippet)).style(Style::default().fg(Color::DarkGray)));
+                }
+                lines
+            })
+            .collect();
+        if lines.is_empty() {
+            lines.push(Line::from("No places saved yet; b and a digit saves one"));
+        }
+        let popup = popup::centered_rect(90, (lines.len() as u16 * 2 + 2).min(area.height), area);
+        f.render_widget(Clear, popup);
+        let paragraph = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title("Jump to slot"))
+            .wrap(Wrap { trim: false });
+        f.render_widget(paragraph, popup);
+    }
+
+    fn draw_note_preview(&self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(preview) = &self.note_preview else {
+            return;
+        };
+        let lines = vec![
+            Line::from(preview.text.clone()),
+            Line::from(""),
+            Line::from("Enter: Go to the note | F: Next on screen | Esc: Close").style(Style::default().fg(Color::DarkGray)),
+        ];
+        let popup = popup::centered_rect(60, 10.min(area.height), area);
+        f.render_widget(Clear, popup);
+        let title = format!("Note {} ({}/{})", preview.label, preview.index + 1, preview.count);
+        let paragraph = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .wrap(Wrap { trim: false });
+        f.render_widget(paragraph, popup);
+    }
+
+    fn handle_note_preview_key(&mut self, key: KeyEvent) -> bool {
+        let Some(index) = self.note_preview.as_ref().map(|preview| preview.index) else {
+            return false;
+        };
+        match key.code {
+            KeyCode::Char('q') => return true,
+            KeyCode::Char('F') | KeyCode::Tab => self.preview_note(index + 1),
+            KeyCode::Enter => self.follow_note(index),
+            _ => self.note_preview = None,
+        }
+        false
+    }
+
+    /// Goes back to where the last note followed in the current book was
+    /// referenced.
+    fn return_from_note(&mut self) {
+        let Some(file) = self.current_file.as_deref() else {
+            return;
+        };
+        let Some(index) = self.link_returns.iter().rposition(|place| place.file == file) else {
+            self.status = Some("No note to go back from".to_string());
+            return;
+        };
+        let place = self.link_returns.remove(index);
+        self.seek = None;
+        self.return_to(place);
+    }
+
+    fn handle_review_key(&mut self, key: KeyEvent) -> bool {
+        let Some(input) = &mut self.review_input else {
+            return false;
+        };
+        match key.code {
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Enter => {
+                let review = self.review_input.take();
+                if let Some((file, _)) = &self.info {
+                    self.bookmarks.set_review(file, review);
+                }
+            }
+            KeyCode::Esc => self.review_input = None,
+            _ => {}
+        }
+        false
+    }
+
+    /// Opens the note on the current part for writing. The note is kept in
+    /// the book's bookmark.
+    fn edit_chapter_note(&mut self) {
+        let Some(file) = self.current_file.clone().filter(|file| file != book::STDIN) else {
+            return;
+        };
+        self.save_bookmark();
+        let note = self.bookmarks.chapter_note(&file, self.current_chapter).unwrap_or_default();
+        self.chapter_note_input = Some(note.to_string());
+    }
+
+    fn handle_chapter_note_key(&mut self, key: KeyEvent) -> bool {
+        let Some(input) = &mut self.chapter_note_input else {
+            return false;
+        };
+        match key.code {
+            // Alt-Enter starts a new line, for summaries of several points
+            KeyCode::Enter if key.modifiers.contains(KeyModifiers::ALT) => input.push('\n'),
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Enter => {
+                let note = self.chapter_note_input.take().unwrap_or_default();
+                if let Some(file) = &self.current_file {
+                    self.bookmarks.set_chapter_note(file, self.current_chapter, &note);
+                }
+            }
+            KeyCode::Esc => self.chapter_note_input = None,
+            _ => {}
+        }
+        false
+    }
+
+    fn draw_chapter_note(&self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(input) = &self.chapter_note_input else {
+            return;
+        };
+        let title = self
+            .part_titles
+            .as_ref()
+            .and_then(|(_, titles)| titles.get(self.current_chapter))
+            .map(|title| format!("Note on {}", title))
+            .unwrap_or_else(|| format!("Note on part {}", self.current_chapter + 1));
+        let mut lines: Vec<Line> = format!("{}_", input).split('\n').map(|line| Line::from(line.to_string())).collect();
+        lines.push(Line::from(""));
+        lines.push(Line::from("Enter: Save | Alt-Enter: New line | Esc: Cancel").style(Style::default().fg(Color::DarkGray)));
+        let popup = popup::centered_rect(70, (lines.len() as u16 + 2).min(area.height), area);
+        f.render_widget(Clear, popup);
+        let paragraph = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .wrap(Wrap { trim: false });
+        f.render_widget(paragraph, popup);
+    }
+
+    /// The entry of `prompt` before (`older`) or after the one shown in
+    /// `input`, going back to what was typed past the newest. `None`
+    /// when there is none further.
+    fn recall(&mut self, prompt: Prompt, input: &str, older: bool) -> Option<String> {
+        let entries = self.prompt_history.entries(prompt);
+        let (index, text) = match (self.recalled.take(), older) {
+            (None, true) => (entries.len().checked_sub(1)?, input.to_string()),
+            (None, false) => return None,
+            (Some((0, text)), true) => (0, text),
+            (Some((index, text)), true) => (index - 1, text),
+            (Some((index, text)), false) if index + 1 < entries.len() => (index + 1, text),
+            (Some((_, text)), false) => return Some(text),
+        };
+        let entry = entries.get(index)?.clone();
+        self.recalled = Some((index, text));
+        Some(entry)
+    }
+
+    fn handle_url_key(&mut self, key: KeyEvent) -> bool {
+        let Some(input) = &mut self.url_input else {
+            return false;
+        };
+        match key.code {
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Up | KeyCode::Down => {
+                let input = input.clone();
+                if let Some(entry) = self.recall(Prompt::Url, &input, key.code == KeyCode::Up) {
+                    self.url_input = Some(entry);
+                }
+            }
+            KeyCode::Enter => {
+                let url = input.trim().to_string();
+                self.url_input = None;
+                self.recalled = None;
+                if download::is_url(&url) {
+                    self.prompt_history.record(Prompt::Url, &url);
+                    self.open_url(&url);
+                } else if !url.is_empty() {
+                    self.status = Some("Only http:// and https:// URLs can be opened".to_string());
+                }
+            }
+            KeyCode::Esc => {
+                self.url_input = None;
+                self.recalled = None;
+            }
+            _ => {}
+        }
+        false
+    }
+
+    fn handle_filter_key(&mut self, key: KeyEvent) -> bool {
+        let Some(input) = &mut self.filter_input else {
+            return false;
+        };
+        // The list follows the query as it is typed
+        match key.code {
+            KeyCode::Char(c) => {
+                input.push(c);
+                let query = input.clone();
+                self.apply_filter(&query);
+            }
+            KeyCode::Backspace => {
+                input.pop();
+                let query = input.clone();
+                self.apply_filter(&query);
+            }
+            KeyCode::Up | KeyCode::Down => {
+                let input = input.clone();
+                if let Some(entry) = self.recall(Prompt::Filter, &input, key.code == KeyCode::Up) {
+                    self.apply_filter(&entry);
+                    self.filter_input = Some(entry);
+                }
+            }
+            // Enter opens the selected match, Tab only keeps the filter
+            KeyCode::Enter | KeyCode::Tab => {
+                let query = input.clone();
+                self.filter_input = None;
+                self.recalled = None;
+                self.prompt_history.record(Prompt::Filter, &query);
+                self.apply_filter(&query);
+                if key.code == KeyCode::Enter {
+                    if let Some(path) = self.selected_file().cloned() {
+                        self.open_listed(&path);
+                    }
+                }
+            }
+            KeyCode::Esc => {
+                self.filter_input = None;
+                self.recalled = None;
+                self.apply_filter("");
+            }
+            _ => {}
+        }
+        false
+    }
+
+    fn fire_selection_changed(&mut self) {
+        if let Some(path) = self.selected_file().cloned() {
+            self.fire_hook(Hook::SelectionChanged, (path,));
+        }
+    }
+
+    pub fn process_html_content(content: &str, regex: &RegexPatterns) -> String {
+        Self::process_html_content_with_limits(content, regex, &Limits::default())
+    }
+
+    /// Formats a part after sanitizing it within `limits`, so an oversized
+    /// or deeply nested document can't stall the reader.
+    pub fn process_html_content_with_limits(content: &str, regex: &RegexPatterns, limits: &Limits) -> String {
+        let sanitized = sanitize::sanitize(content, limits);
+        let (html, blocks) = blocks::extract(&sanitized.html);
+        let text = html.replace('\n', "");
+        
+        let text = regex.css_rule.replace_all(&text, "").to_string();
+
+        let text = regex.h_open.replace_all(&text, "\n").to_string();
+        let text = regex.h_close.replace_all(&text, "\n").to_string();
+
+        let text = regex.multi_space.replace_all(&text, " ").to_string();
+        let text = regex.leading_space.replace_all(&text, "").to_string();
+        let text = regex.line_leading_space.replace_all(&text, "\n").to_string();
+
+        // Paragraphs start on a new line with the canonical indent, which the
+        // layout swaps for the configured one
+        let paragraph_start = format!("\n{}", PARAGRAPH_INDENT);
+        let mut first_paragraph = true;
+        let text = regex.p_tag.replace_all(&text, |_caps: &regex::Captures| {
+            if first_paragraph {
+                first_paragraph = false;
+                ""
+            } else {
+                paragraph_start.as_str()
+            }
+        }).to_string();
+        
+        let text = text
+            .replace("</p>", "")
+            .replace("<br>", "\n")
+            .replace("<br/>", "\n")
+            .replace("<br />", "\n")
+            .replace("<blockquote>", &paragraph_start)
+            .replace("</blockquote>", "\n")
+            .replace("<em>", "_")
+            .replace("</em>", "_")
+            .replace("<i>", "_")
+            .replace("</i>", "_")
+            .replace("<strong>", "**")
+            .replace("</strong>", "**")
+            .replace("<b>", "**")
+            .replace("</b>", "**");
+
+        let text = regex.remaining_tags.replace_all(&text, "").to_string();
+
+        let text = sanitize::decode_entities(&text);
+
+        let text = regex.empty_lines.replace_all(&text, "\n").to_string();
+        let mut text = regex.multi_newline.replace_all(&text, "\n").to_string();
+        
+        if text.starts_with(&paragraph_start) {
+            text.replace_range(1..paragraph_start.len(), "");
+        }
+
+        let text = blocks::restore(&text, &blocks);
+        let text = text.trim();
+        match sanitized.notice {
+            Some(notice) => format!("{}\n\n{}", text, notice),
+            None => text.to_string(),
+        }
+    }
+
+    /// Opens `path`, replacing the book in the current tab or, with
+    /// `new_tab`, in a new tab after it. A book that is already open in a
+    /// tab is switched to instead.
+    fn load_epub(&mut self, path: &str, new_tab: bool) {
+        self.focus_left();
+        if let Some(index) = self.tab_index_of(path) {
+            self.switch_tab(index);
+            self.mode = Mode::Content;
+            return;
+        }
+        if let Some((book, diagnostics)) = self.read_book(path) {
+            self.open_book(path, book, new_tab);
+            self.show_diagnostics(&diagnostics);
+        }
+    }
+
+    /// Opens `path` in the right-hand pane of a split view, next to the
+    /// current book. Without an open book it is simply opened.
+    fn open_split(&mut self, path: &str) {
+        if self.current_book.is_none() {
+            self.load_epub(path, false);
+            return;
+        }
+        self.close_split();
+        let Some((book, diagnostics)) = self.read_book(path) else {
+            return;
+        };
+        if let Some(left) = self.take_tab() {
+            self.show_book(path, book);
+            self.split = Some(Split {
+                other: left,
+                focus_right: true,
+                synced: false,
+            });
+            self.show_diagnostics(&diagnostics);
+        }
+    }
+
+    /// Parses `path`, falling back to salvaging its content documents. On
+    /// failure the reason is shown in a popup and `None` returned.
+    fn read_book(&mut self, path: &str) -> Option<(Book, Diagnostics)> {
+        info!("Attempting to load EPUB: {}", path);
+        if let Some(drm) = diagnostics::detect_drm(Path::new(path)) {
+            warn!("{} is protected by {} DRM", path, drm);
+            self.show_error(ErrorPopup::new(
+                "DRM-protected book",
+                format!("This book is protected by {} DRM, so bookrat cannot decrypt its text.", drm),
+                "Read it in the app it was bought for, or ask the seller for a DRM-free copy.",
+            ));
+            return None;
+        }
+        let (book, diagnostics) = match EpubDoc::new(path) {
+            Ok(doc) => {
+                info!("Successfully created EPUB document");
+                let diagnostics = diagnostics::check_spine(&doc, Path::new(path));
+                (Book::Epub(Box::new(doc)), diagnostics)
+            }
+            Err(e) => {
+                error!("Failed to load EPUB: {}: {}", path, e);
+                let problem = diagnostics::describe_open_error(&e);
+                match SalvagedBook::open(Path::new(path)) {
+                    Ok(salvaged) => {
+                        let found = salvaged.entries().len();
+                        warn!("Salvaged {} content documents from {}", found, path);
+                        let diagnostics = Diagnostics {
+                            problems: vec![
+                                problem,
+                                format!("Showing the {} content documents found in the archive, in file name order", found),
+                            ],
+                            total_parts: found,
+                            unreadable: Vec::new(),
+                        };
+                        (Book::Salvaged(salvaged), diagnostics)
+                    }
+                    Err(salvage_error) => {
+                        warn!("Nothing to salvage from {}: {:?}", path, salvage_error);
+                        self.show_error(
+                            ErrorPopup::new(
+                                "Could not open book",
+                                format!("{}: {}", path, e),
+                                "Check that the file is a complete, valid EPUB. Details are in the log file.",
+                            )
+                            .with_details(vec![problem, format!("Nothing could be salvaged: {}", salvage_error)]),
+                        );
+                        return None;
+                    }
+                }
+            }
+        };
+        Some((book, diagnostics))
+    }
+
+    fn show_diagnostics(&mut self, diagnostics: &Diagnostics) {
+        if !diagnostics.is_clean() {
+            self.show_error(diagnostics.to_popup(
+                "Damaged EPUB",
+                "Press h/l to read the parts that survived. Details are in the log file.",
+            ));
+        }
+    }
+
+    fn open_book(&mut self, path: &str, book: Book, new_tab: bool) {
+        if self.tabs.is_empty() {
+            self.tabs.push(None);
+            self.active_tab = 0;
+        } else if new_tab && self.current_book.is_some() {
+            self.tabs[self.active_tab] = self.take_tab();
+            self.active_tab += 1;
+            self.tabs.insert(self.active_tab, None);
+        }
+        self.show_book(path, book);
+    }
+
+    /// Makes `book` the shown book, at its bookmark if it has one.
+    fn show_book(&mut self, path: &str, mut book: Book) {
+        self.leave_book(path);
+        self.total_chapters = book.num_chapters();
+        self.current_chapter = 0;
+        self.scroll_offset = 0;
+        info!("Total chapters: {}", self.total_chapters);
+
+        if let Some(bookmark) = self.bookmarks.get_bookmark(path) {
+            info!("Found bookmark: chapter {}, offset {}", bookmark.chapter, bookmark.scroll_offset);
+            if bookmark.chapter > 0 {
+                book.set_current_chapter(0);
+                for _ in 0..bookmark.chapter {
+                    if !book.go_next() {
+                        error!("Failed to navigate to bookmarked chapter at index {}", bookmark.chapter);
+                        book.set_current_chapter(0);
+                        break;
+                    }
+                }
+                if book.current_chapter() == bookmark.chapter {
+                    self.current_chapter = bookmark.chapter;
+                    self.scroll_offset = bookmark.scroll_offset;
+                } else {
+                    error!("Could not reach bookmarked chapter index {}", bookmark.chapter);
+                    if self.total_chapters > 1 {
+                        book.set_current_chapter(1);
+                        self.current_chapter = 1;
+                    } else {
+                        book.set_current_chapter(0);
+                    }
+                }
+            }
+        } else if self.total_chapters > 1 && matches!(book, Book::Epub(_)) {
+            // Salvaged parts are in file name order, not a spine known to
+            // start with a title page, so there is nothing to skip there
+            if book.go_next() {
+                self.current_chapter = 1;
+                info!("Skipped potential metadata page, moved to chapter 1 (index 1)");
+            } else {
+                error!("Failed to move to chapter 1");
+            }
+        }
+
+        // Replacing the previous document closes its file handle
+        self.current_content = None;
+        self.current_language = book.as_epub().and_then(|doc| Entry::from_doc(doc, path).detect_language(path).language);
+        self.current_book = Some(book);
+        self.current_file = Some(path.to_string());
+        if path != book::STDIN {
+            self.history.record(path, self.current_chapter, self.scroll_offset);
+        }
+        self.flush();
+        self.update_content();
+        self.mode = Mode::Content;
+        self.was_at_end = None;
+        self.fire_event(Event::BookOpened, self.current_chapter);
+        self.fire_hook(Hook::BookOpened, (path.to_string(),));
+    }
+
+    /// Remembers the shown book as the one to switch back to, as `next` is
+    /// about to be shown instead.
+    fn leave_book(&mut self, next: &str) {
+        if let Some(file) = self.current_file.as_ref().filter(|file| *file != next && *file != book::STDIN) {
+            self.alternate_book = Some(file.clone());
+        }
+    }
+
+    /// Switches to the book shown before the current one, at its place.
+    fn switch_to_alternate(&mut self) {
+        let Some(file) = self.alternate_book.clone() else {
+            self.status = Some("No other book opened yet".to_string());
+            return;
+        };
+        if !Path::new(&file).exists() {
+            self.status = Some(format!("{} is gone", file));
+            self.alternate_book = None;
+            return;
+        }
+        info!("Switching back to {}", file);
+        self.save_bookmark();
+        self.load_epub(&file, false);
+    }
+
+    /// Moves the shown book's state out of `self` into a `Tab`.
+    fn take_tab(&mut self) -> Option<Tab> {
+        let book = self.current_book.take()?;
+        Some(Tab {
+            book,
+            file: self.current_file.take().unwrap_or_default(),
+            chapter: self.current_chapter,
+            total_chapters: self.total_chapters,
+            scroll_offset: self.scroll_offset,
+            content: self.current_content.take(),
+            content_length: self.content_length,
+            layout: self.layout.take(),
+            debug: self.debug_mode,
+            language: self.current_language.take(),
+        })
+    }
+
+    fn restore_tab(&mut self, tab: Tab) {
+        self.current_book = Some(tab.book);
+        self.current_file = Some(tab.file);
+        self.current_chapter = tab.chapter;
+        self.total_chapters = tab.total_chapters;
+        self.scroll_offset = tab.scroll_offset;
+        self.current_content = tab.content;
+        self.content_length = tab.content_length;
+        self.layout = tab.layout;
+        self.current_language = tab.language;
+        if tab.debug != self.debug_mode {
+            self.update_content();
+        }
+    }
+
+    fn tab_index_of(&self, path: &str) -> Option<usize> {
+        if self.current_file.as_deref() == Some(path) {
+            return Some(self.active_tab);
+        }
+        self.tabs
+            .iter()
+            .position(|tab| tab.as_ref().is_some_and(|tab| tab.file == path))
+    }
+
+    fn switch_tab(&mut self, index: usize) {
+        if index == self.active_tab || index >= self.tabs.len() {
+            return;
+        }
+        self.focus_left();
+        if let Some(next) = self.tabs[index].as_ref().map(|tab| tab.file.clone()) {
+            self.leave_book(&next);
+        }
+        self.tabs[self.active_tab] = self.take_tab();
+        self.active_tab = index;
+        if let Some(tab) = self.tabs[index].take() {
+            info!("Switching to tab {}: {}", index + 1, tab.file);
+            self.restore_tab(tab);
+        }
+    }
+
+    /// Switches to the next tab (`forward`) or the previous one, wrapping
+    /// around at either end.
+    fn cycle_tab(&mut self, forward: bool) {
+        let count = self.tabs.len();
+        if count > 1 {
+            let index = if forward { (self.active_tab + 1) % count } else { (self.active_tab + count - 1) % count };
+            self.switch_tab(index);
+        }
+    }
+
+    /// Closes the current tab and shows its neighbour, or the file list
+    /// when it was the last one.
+    fn close_tab(&mut self) {
+        // The progress the library lists for it is where it was closed
+        self.flush();
+        self.focus_left();
+        if self.take_tab().is_none() {
+            return;
+        }
+        self.tabs.remove(self.active_tab);
+        if self.tabs.is_empty() {
+            self.split = None;
+            self.active_tab = 0;
+            self.mode = self.library_mode();
+            return;
+        }
+        self.active_tab = self.active_tab.min(self.tabs.len() - 1);
+        if let Some(tab) = self.tabs[self.active_tab].take() {
+            self.restore_tab(tab);
+        }
+    }
+
+    /// File names of the open books, in tab order.
+    pub fn tab_titles(&self) -> Vec<String> {
+        self.tabs
+            .iter()
+            .map(|tab| {
+                let file = match (tab, &self.split) {
+                    (Some(tab), _) => tab.file.as_str(),
+                    (None, Some(split)) if split.focus_right => split.other.file.as_str(),
+                    (None, _) => self.current_file.as_deref().unwrap_or_default(),
+                };
+                Path::new(file).file_stem().unwrap_or_default().to_string_lossy().to_string()
+            })
+            .collect()
+    }
+
+    pub fn active_tab(&self) -> usize {
+        self.active_tab
+    }
+
+    /// Moves the focus to the other pane of a split view.
+    fn swap_focus(&mut self) {
+        let Some(mut split) = self.split.take() else {
+            return;
+        };
+        if let Some(focused) = self.take_tab() {
+            let other = std::mem::replace(&mut split.other, focused);
+            self.restore_tab(other);
+            split.focus_right = !split.focus_right;
+        }
+        self.split = Some(split);
+    }
+
+    /// Gives the focus to the left pane, the one that belongs to the tab.
+    fn focus_left(&mut self) {
+        if self.split.as_ref().is_some_and(|split| split.focus_right) {
+            self.swap_focus();
+        }
+    }
+
+    fn close_split(&mut self) {
+        self.focus_left();
+        self.split = None;
+    }
+
+    /// Files shown in the left and right panes of the split view.
+    pub fn split_files(&self) -> Option<(String, String)> {
+        let split = self.split.as_ref()?;
+        let focused = self.current_file.clone().unwrap_or_default();
+        let other = split.other.file.clone();
+        Some(if split.focus_right { (other, focused) } else { (focused, other) })
+    }
+
+    /// Text waiting to be put on the system clipboard, if any was copied
+    /// since the last call.
+    pub fn take_clipboard(&mut self) -> Option<String> {
+        self.clipboard.take()
+    }
+
+    /// What to write to the terminal for the pictures kitty or sixel show,
+    /// when they changed since the last call. Called after every frame.
+    pub fn take_graphics(&mut self) -> Option<images::Update> {
+        if self.picture == self.shown_picture {
+            return None;
+        }
+        let protocol = self.graphics?;
+        let mut update = images::Update { redraw: false, sequence: String::new() };
+        if self.shown_picture.is_some() {
+            match protocol {
+                Protocol::Kitty => update.sequence.push_str(images::KITTY_DELETE),
+                _ => update.redraw = true,
+            }
+        }
+        if let (Some((_, area)), Some(cover)) = (&self.picture, &self.info_cover) {
+            update.sequence.push_str(&images::escape(cover, *area, protocol));
+        }
+        self.shown_picture = self.picture.clone();
+        Some(update)
+    }
+
+    /// Message shown in place of the help bar, if any.
+    pub fn status(&self) -> Option<&str> {
+        self.status.as_deref()
+    }
+
+    fn copy_text(&mut self, what: &str, text: String) {
+        let message = format!("Copied the {} ({} characters)", what, text.chars().count());
+        info!("{}", message);
+        self.status = Some(message);
+        self.clipboard = Some(text);
+    }
+
+    /// Copies the paragraph at the reading line, the top row of the view.
+    fn copy_paragraph(&mut self) {
+        let Some(text) = self.layout.as_ref().and_then(|layout| layout.paragraph_at(self.scroll_offset)) else {
+            return;
+        };
+        self.copy_text("paragraph", text);
+    }
+
+    /// Copies the rows currently on screen, wrapped as they are shown.
+    fn copy_screen(&mut self) {
+        let Some(layout) = &self.layout else {
+            return;
+        };
+        let text = layout.text_of(self.scroll_offset..self.scroll_offset + self.visible_height);
+        self.copy_text("screen", text);
+    }
+
+    /// Counts the time since the last tick as reading when a book is open
+    /// in the content view and a key was pressed recently, and writes
+    /// bookmarks that have been pending for long enough.
+    pub fn tick(&mut self) {
+        let now = std::time::Instant::now();
+        let elapsed = now.duration_since(self.last_tick);
+        self.last_tick = now;
+        let today = chrono::Local::now().date_naive();
+        let before = self.reading_time.minutes(today);
+        let reading = if self.is_reading(now) { elapsed } else { std::time::Duration::ZERO };
+        if !reading.is_zero() {
+            self.reading_time.add(today, self.current_file.as_deref(), reading);
+        }
+        let after = self.reading_time.minutes(today);
+        for reminder in self.reminders.update(now, reading, before, after) {
+            let message = reminder.message();
+            if self.reminders.notify {
+                notifications::send("bookrat", &message);
+            }
+            self.status = Some(message);
+        }
+        if self.unsaved_since.is_some_and(|since| now.duration_since(since) >= FLUSH_AFTER) {
+            self.flush();
+        }
+        self.poll_download();
+        self.poll_watcher(now);
+        self.remember_view();
+        self.mark_part_read();
+        self.remember_snippet();
+        if let (Some(at_end), Some(file)) = (self.at_end_of_book(), self.current_file.clone()) {
+            // A book shown already at its end, or just switched to, only
+            // counts once it is left and reached again
+            if self.was_at_end.as_ref() == Some(&(file.clone(), false)) && at_end {
+                self.fire_event(Event::BookFinished, self.current_chapter);
+                if !self.bookmarks.is_finished(&file) {
+                    self.bookmarks.set_finished(&file, Some(chrono::Utc::now()));
+                    self.status = Some(format!("Finished {}", file_name(&file)));
+                }
+                self.offer_next_in_queue(&file);
+            }
+            self.was_at_end = Some((file, at_end));
+        }
+    }
+
+    /// Marks the current part read through once its end has been on
+    /// screen.
+    fn mark_part_read(&mut self) {
+        let (Some(file), Some(layout)) = (self.current_file.clone(), &self.layout) else {
+            return;
+        };
+        let at_end = self.scroll_offset + self.visible_height >= layout.len();
+        if self.mode != Mode::Content || self.visible_height == 0 || !at_end || self.bookmarks.is_part_read(&file, self.current_chapter) {
+            return;
+        }
+        // The parts read are kept in the book's bookmark
+        self.save_bookmark();
+        self.bookmarks.mark_part_read(&file, self.current_chapter);
+    }
+
+    /// Keeps the text at the reading position with the bookmark, once the
+    /// part is laid out.
+    fn remember_snippet(&mut self) {
+        let Some(file) = self.current_file.clone() else {
+            return;
+        };
+        let at_position = self
+            .bookmarks
+            .get_bookmark(&file)
+            .is_some_and(|bookmark| (bookmark.chapter, bookmark.scroll_offset) == (self.current_chapter, self.scroll_offset));
+        if let Some(snippet) = self.snippet().filter(|_| at_position) {
+            self.bookmarks.set_snippet(&file, Some(snippet));
+        }
+    }
+
+    /// Saves the library width and the panels and reading modes turned on,
+    /// when they changed.
+    fn remember_view(&mut self) {
+        self.view.update(|view| {
+            view.library_width = self.library_width;
+            view.chapter_sidebar = self.chapter_sidebar;
+            view.minimap = self.minimap;
+            view.line_numbers = self.line_numbers;
+            view.wrap = self.wrap;
+            view.large_print = self.large_print;
+        });
+    }
+
+    /// How long the UI can wait for a key before something on screen or
+    /// on disk is due to change: the clock turning to the next minute, a
+    /// pending bookmark write, the reader going idle, a break starting or
+    /// ending, or a download moving on.
+    pub fn next_wakeup(&self) -> std::time::Duration {
+        let now = std::time::Instant::now();
+        let second = u64::from(chrono::Local::now().second());
+        let mut wait = std::time::Duration::from_secs(60 - second);
+        let reading = self.is_reading(now);
+        let mut due = |duration: std::time::Duration| wait = wait.min(duration);
+        if let Some(since) = self.unsaved_since {
+            due(FLUSH_AFTER.saturating_sub(now.duration_since(since)));
+        }
+        if reading {
+            due(IDLE_AFTER.saturating_sub(now.duration_since(self.last_activity)));
+        }
+        if let Some(duration) = self.reminders.next_due(now, reading) {
+            due(duration);
+        }
+        if self.download.is_some() {
+            due(DOWNLOAD_REFRESH);
+        }
+        if let Some(since) = self.library_changed {
+            due(WATCH_SETTLE.saturating_sub(now.duration_since(since)));
+        }
+        wait
+    }
+
+    /// Whether a book is open in the content view and a key was pressed
+    /// recently.
+    fn is_reading(&self, now: std::time::Instant) -> bool {
+        self.mode == Mode::Content && self.current_book.is_some() && now.duration_since(self.last_activity) < IDLE_AFTER
+    }
+
+    /// Title and first author of the current book.
+    fn book_metadata(&self) -> Option<(String, Option<String>)> {
+        let file = self.current_file.as_deref()?;
+        Some(match self.current_book.as_ref()? {
+            Book::Epub(doc) => {
+                let entry = Entry::from_doc(doc, file);
+                (entry.title, entry.authors.into_iter().next())
+            }
+            Book::Document(document) => (document.title().to_string(), None),
+            Book::Salvaged(_) => (Path::new(file).file_stem()?.to_string_lossy().to_string(), None),
+        })
+    }
+
+    /// Whether the last part is shown down to its end, or `None` while
+    /// there is nothing laid out in the content view.
+    fn at_end_of_book(&self) -> Option<bool> {
+        let layout = self.layout.as_ref().filter(|_| self.mode == Mode::Content && self.visible_height > 0)?;
+        let last = self.current_chapter + 1 >= self.total_chapters;
+        Some(last && self.scroll_offset + self.visible_height >= layout.len())
+    }
+
+    /// The book being read and how far through it, while reading.
+    pub fn now_reading(&self) -> Option<Activity> {
+        if !self.is_reading(std::time::Instant::now()) {
+            return None;
+        }
+        let file = self.current_file.as_deref()?;
+        let (title, author) = self.book_metadata()?;
+        Some(Activity { title, author, progress: self.percent_read(file) })
+    }
+
+    /// Percent of `file` read, as it is now for the open book and as last
+    /// recorded for the others.
+    fn progress_of(&self, file: &str) -> Option<u32> {
+        if self.current_file.as_deref() == Some(file) {
+            Some(self.percent_read(file))
+        } else {
+            self.index.progress(file)
+        }
+    }
+
+    /// Percent of the current book, `file`, read up to the top of the view.
+    fn percent_read(&self, file: &str) -> u32 {
+        let chapter_progress = chapter_progress(self.layout.as_ref(), self.scroll_offset, self.visible_height);
+        match self.part_sizes.get(file) {
+            Some(sizes) => book_progress(sizes, self.current_chapter, chapter_progress),
+            None => (self.current_chapter * 100 / self.total_chapters.max(1)) as u32,
+        }
+    }
+
+    /// The current book and how far through it, for status bars.
+    pub fn reading_status(&self) -> ReadingStatus {
+        let (Some(file), Some((title, author))) = (self.current_file.as_deref(), self.book_metadata()) else {
+            return ReadingStatus::default();
+        };
+        let percent = self.percent_read(file);
+        let minutes_left = self.part_sizes.get(file).map(|sizes| {
+            let left = sizes.iter().sum::<usize>() as u64 * (100 - percent.min(100)) as u64 / 100;
+            left / status_file::CHARS_PER_MINUTE as u64
+        });
+        ReadingStatus::new(file, title, author, self.current_chapter + 1, self.total_chapters, percent, minutes_left)
+    }
+
+    pub fn error_popup(&self) -> Option<&ErrorPopup> {
+        self.error_popup.as_ref()
+    }
+
+    fn show_error(&mut self, popup: ErrorPopup) {
+        self.error_popup = Some(popup);
+    }
+
+    pub fn mode(&self) -> &Mode {
+        &self.mode
+    }
+
+    fn save_bookmark(&mut self) {
+        if let Some(path) = self.current_file.clone().filter(|path| path != book::STDIN) {
+            self.save_bookmark_for(&path, self.current_chapter, self.scroll_offset);
+            self.history.record(&path, self.current_chapter, self.scroll_offset);
+        }
+    }
+
+    /// The start of the text at the top of the screen, kept with saved
+    /// places so they can be told apart.
+    fn snippet(&self) -> Option<String> {
+        self.layout.as_ref()?.snippet_at(self.scroll_offset)
+    }
+
+    /// Moves the bookmark of `path`. Scrolling within a part is written to
+    /// disk at most every `FLUSH_AFTER`; a change of part right away.
+    fn save_bookmark_for(&mut self, path: &str, chapter: usize, scroll_offset: usize) {
+        if path == book::STDIN {
+            return;
+        }
+        let changed_part = self.bookmarks.get_bookmark(path).is_none_or(|bookmark| bookmark.chapter != chapter);
+        self.bookmarks.update_bookmark(path, chapter, scroll_offset);
+        if changed_part {
+            self.flush();
+        } else {
+            self.unsaved_since.get_or_insert_with(std::time::Instant::now);
+        }
+    }
+
+    /// Writes pending bookmark, history and reading time changes to disk.
+    pub fn flush(&mut self) {
+        self.remember_view();
+        if let Some(file) = self.current_file.as_deref() {
+            if let Some(bookmark) = self.bookmarks.get_bookmark(file) {
+                self.index.record_reading(file, self.percent_read(file), bookmark.last_read);
+            }
+        }
+        if let Err(e) = self.bookmarks.flush() {
+            error!("Failed to save bookmarks: {}", e);
+        }
+        if let Err(e) = self.history.flush() {
+            error!("Failed to save history: {}", e);
+        }
+        if let Err(e) = self.reading_time.flush() {
+            error!("Failed to save reading time: {}", e);
+        }
+        self.unsaved_since = None;
+    }
+
+    fn update_content(&mut self) {
+        // EpubDoc reads spine items from the zip on demand, so the only
+        // chapter text held in memory is ours. Release it before the next
+        // part is decompressed to keep peak usage to a single chapter.
+        self.current_content = None;
+        self.layout = None;
+        if let Some(book) = &mut self.current_book {
+            if let Some(content) = book.get_current_str() {
+                debug!("Raw content length: {} bytes", content.len());
+
+                if self.debug_mode {
+                    self.content_length = content.len();
+                    self.current_content = Some(content);
+                } else {
+                    let elapsed = perf::start_timer();
+                    let text = Self::process_html_content(&content, &self.regex);
+                    self.perf.processing = elapsed();
+                    debug!("Processed text length: {} bytes", text.len());
+                    debug!("Text after HTML cleanup: {}", text.chars().take(100).collect::<String>());
+
+                    if text.is_empty() {
+                        warn!("Converted text is empty");
+                        self.current_content = Some("No content available in this chapter.".to_string());
+                        self.content_length = 0;
+                        self.show_error(ErrorPopup::new(
+                            "Empty part",
+                            format!("Part {} has no readable text.", self.current_chapter + 1),
+                            "It may contain only images or styling. Press h/l to move to another part, or d to inspect the raw HTML.",
+                        ));
+                    } else {
+                        self.content_length = text.len(); 
+                        self.current_content = Some(text);
+                    }
+                }
+            } else {
+                error!("Failed to get current chapter content for index {}", self.current_chapter);
+                self.current_content = Some("Error reading chapter content.".to_string());
+                self.content_length = 0;
+                self.show_error(ErrorPopup::new(
+                    "Could not read part",
+                    format!("Part {} could not be read from the EPUB.", self.current_chapter + 1),
+                    "The file may be damaged. Press h/l to try another part. Details are in the log file.",
+                ));
+            }
+        } else {
+            error!("No EPUB document loaded");
+            self.current_content = Some("No EPUB document loaded.".to_string());
+            self.content_length = 0;
+        }
+    }
+
+    /// The part `h` or `l` moves to from the current one: the next or
+    /// previous one, past publisher pages when they are skipped and a part
+    /// of the book's text is left that way.
+    fn neighbour_part(&mut self, forward: bool) -> usize {
+        let adjacent = if forward { self.current_chapter + 1 } else { self.current_chapter.saturating_sub(1) };
+        let (Some(file), Some(book), true) = (self.current_file.clone(), self.current_book.as_mut(), self.skip_boilerplate) else {
+            return adjacent;
+        };
+        let skipped = self.boilerplate.entry(file).or_insert_with(|| book.boilerplate_parts());
+        let target = if forward {
+            (adjacent..self.total_chapters).find(|part| !skipped.contains(part))
+        } else {
+            (0..self.current_chapter).rev().find(|part| !skipped.contains(part))
+        };
+        let target = target.unwrap_or(adjacent);
+        let passed = target.abs_diff(adjacent);
+        if passed > 0 {
+            let plural = if passed == 1 { "" } else { "s" };
+            self.status = Some(format!("Skipped {} publisher page{}", passed, plural));
+        }
+        target
+    }
+
+    fn next_chapter(&mut self) {
+        if self.current_book.is_none() {
+            return;
+        }
+        let target = self.neighbour_part(true);
+        if let Some(book) = &mut self.current_book {
+            if self.current_chapter < self.total_chapters.saturating_sub(1) {
+                if book.set_current_chapter(target) {
+                    self.remember_position();
+                    self.fire_event(Event::ChapterFinished, self.current_chapter);
+                    self.current_chapter = target;
+                    info!("Moving to next chapter: {}", self.current_chapter);
+                    self.update_content();
+                    self.scroll_offset = 0;
+                    self.save_bookmark();
+                    self.fire_chapter_changed();
+                } else {
+                    error!("Failed to move to next chapter from {}", self.current_chapter);
+                }
+            } else {
+                info!("Already at last chapter {}", self.current_chapter);
+            }
+        }
+    }
+
+    fn prev_chapter(&mut self) {
+        if self.current_book.is_none() {
+            return;
+        }
+        let target = self.neighbour_part(false);
+        if let Some(book) = &mut self.current_book {
+            if self.current_chapter > 0 {
+                if book.set_current_chapter(target) {
+                    self.remember_position();
+                    self.current_chapter = target;
+                    info!("Moving to previous chapter: {}", self.current_chapter);
+                    self.update_content();
+                    self.scroll_offset = 0;
+                    self.save_bookmark();
+                    self.fire_chapter_changed();
+                } else {
+                    error!("Failed to move to previous chapter from {}", self.current_chapter);
+                }
+            } else {
+                info!("Already at first chapter (0)");
+            }
+        }
+    }
+
+    fn go_to_chapter(&mut self, index: usize) {
+        if let Some(book) = &mut self.current_book {
+            if index < self.total_chapters && book.set_current_chapter(index) {
+                self.remember_position();
+                self.current_chapter = index;
+                info!("Jumping to chapter: {}", self.current_chapter);
+                self.update_content();
+                self.scroll_offset = 0;
+                self.save_bookmark();
+                self.fire_chapter_changed();
+            } else {
+                error!("Failed to jump to chapter {}", index);
+            }
+        }
+    }
+
+    /// Remembers the current position before leaving the chapter.
+    fn remember_position(&mut self) {
+        let Some(file) = self.current_file.clone() else {
+            return;
+        };
+        self.undo.push(Place {
+            file,
+            chapter: self.current_chapter,
+            scroll_offset: self.scroll_offset,
+        });
+        if self.undo.len() > MAX_UNDO {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Goes back to the chapter and offset the current book was at before
+    /// its last chapter change.
+    fn undo_navigation(&mut self) {
+        let Some(file) = self.current_file.as_deref() else {
+            return;
+        };
+        let Some(index) = self.undo.iter().rposition(|place| place.file == file) else {
+            info!("No chapter change to undo");
+            return;
+        };
+        let place = self.undo.remove(index);
+        self.return_to(place);
+    }
+
+    /// Goes to the chapter and offset of `place`, in the current book.
+    fn return_to(&mut self, place: Place) {
+        if place.chapter != self.current_chapter {
+            let Some(book) = &mut self.current_book else {
+                return;
+            };
+            if place.chapter >= self.total_chapters || !book.set_current_chapter(place.chapter) {
+                error!("Failed to go back to chapter {}", place.chapter);
+                return;
+            }
+            self.current_chapter = place.chapter;
+            info!("Going back to chapter {} offset {}", place.chapter, place.scroll_offset);
+            self.update_content();
+            self.fire_chapter_changed();
+        }
+        self.scroll_offset = place.scroll_offset;
+        self.save_bookmark();
+    }
+
+    fn save_slot(&mut self, slot: u8) {
+        let Some(file) = self.current_file.clone() else {
+            return;
+        };
+        if file == book::STDIN {
+            self.status = Some("Places in piped text aren't kept".to_string());
+            return;
+        }
+        let snippet = self.snippet();
+        self.bookmarks.set_slot(&file, slot, self.current_chapter, self.scroll_offset, snippet);
+        info!("Saved part {} offset {} to slot {}", self.current_chapter + 1, self.scroll_offset, slot);
+        self.status = Some(format!("Saved to slot {}", slot));
+    }
+
+    /// Jumps to the place saved in number slot `slot` of the current book.
+    fn jump_to_slot(&mut self, slot: u8) {
+        let Some(place) = self.current_file.as_deref().and_then(|file| self.bookmarks.slot(file, slot)).cloned() else {
+            self.status = Some(format!("Slot {} is empty; b{} saves this place to it", slot, slot));
+            return;
+        };
+        if place.chapter != self.current_chapter {
+            self.go_to_chapter(place.chapter);
+            if self.current_chapter != place.chapter {
+                return;
+            }
+        } else {
+            self.remember_position();
+        }
+        self.scroll_offset = place.scroll_offset;
+        self.save_bookmark();
+    }
+
+    fn fire_chapter_changed(&mut self) {
+        let args = ((self.current_chapter + 1) as i64, self.total_chapters as i64);
+        self.fire_hook(Hook::ChapterChanged, args);
+    }
+
+    fn scroll_down(&mut self) {
+        if self.at_end_of_book() == Some(true) {
+            return self.open_completion();
+        }
+        if let Some(content) = &self.current_content {
+            let now = std::time::Instant::now();
+            if now.duration_since(self.last_scroll_time) < std::time::Duration::from_millis(100) {
+                self.scroll_speed = (self.scroll_speed + 1).min(10);
+            } else {
+                self.scroll_speed = 1;
+            }
+            self.last_scroll_time = now;
+
+            self.scroll_offset = self.scroll_offset.saturating_add(self.scroll_speed);
+            let total_lines = content.lines().count();
+            debug!("Scrolling down to offset: {}/{} (speed: {})", self.scroll_offset, total_lines, self.scroll_speed);
+            self.save_bookmark();
+        }
+    }
+
+    /// Scrolls exactly `rows`, down or up, for a counted `j`/`k`.
+    fn scroll_by(&mut self, rows: usize, down: bool) {
+        if self.current_content.is_some() {
+            self.scroll_speed = 1;
+            self.scroll_offset = if down { self.scroll_offset.saturating_add(rows) } else { self.scroll_offset.saturating_sub(rows) };
+            if let Some(layout) = &self.layout {
+                self.scroll_offset = self.scroll_offset.min(layout.len().saturating_sub(1));
+            }
+            debug!("Scrolling {} by {} rows to offset {}", if down { "down" } else { "up" }, rows, self.scroll_offset);
+            self.save_bookmark();
+        }
+    }
+
+    fn scroll_up(&mut self) {
+        if let Some(content) = &self.current_content {
+            let now = std::time::Instant::now();
+            if now.duration_since(self.last_scroll_time) < std::time::Duration::from_millis(100) {
+                self.scroll_speed = (self.scroll_speed + 1).min(10);
+            } else {
+                self.scroll_speed = 1;
+            }
+            self.last_scroll_time = now;
+
+            self.scroll_offset = self.scroll_offset.saturating_sub(self.scroll_speed);
+            let total_lines = content.lines().count();
+            debug!("Scrolling up to offset: {}/{} (speed: {})", self.scroll_offset, total_lines, self.scroll_speed);
+            self.save_bookmark();
+        }
+    }
+
+    /// Title of the library: the filter or collection shown, and the order
+    /// when it isn't the default.
+    fn library_title(&self) -> String {
+        let mut title = if self.filter.is_empty() {
+            "Books".to_string()
+        } else {
+            let name = self.collections.name_of(&self.filter).unwrap_or(&self.filter);
+            format!("Books {}/{}: {}", self.epub_files.len(), self.library.len(), name)
+        };
+        if self.view.sort != SortOrder::default() {
+            title.push_str(&format!(" by {}", self.view.sort.name()));
+        }
+        if self.view.hide_finished {
+            title.push_str(", unfinished");
+        }
+        title
+    }
+
+    fn draw_file_list(&self, f: &mut ratatui::Frame, area: Rect) {
+        let title = self.library_title();
+        let mut area = area;
+        if !self.pinned.is_empty() {
+            let height = self.pinned.len() as u16 + 2;
+            let pinned_area = Rect { height: height.min(area.height), ..area };
+            self.draw_continue_reading(f, pinned_area);
+            area.y += pinned_area.height;
+            area.height -= pinned_area.height;
+        }
+        let folders = self.collections.all().iter().take(self.folder_rows()).map(|collection| {
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("▸ {}", collection.name), Style::default().fg(Color::Cyan)),
+                Span::styled(format!(" ({})", collection.query), Style::default().fg(Color::DarkGray)),
+            ]))
+        });
+        let now = chrono::Utc::now();
+        // What the filter matched by name is picked out in the labels
+        let query = Query::parse(self.filter_input.as_deref().unwrap_or(&self.filter));
+        let needles: Vec<&str> = query
+            .terms()
+            .iter()
+            .filter_map(|term| match term {
+                Term::Text(text) | Term::Title(text) | Term::Author(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        let matched = Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD);
+        let items: Vec<ListItem> = folders
+            .chain(self.epub_files.iter().map(|file| {
+                let bookmark = self.bookmarks.get_bookmark(file);
+                let last_read = bookmark
+                    .map(|b| relative_time::format(&self.last_read_format, b.last_read, now))
+                    .unwrap_or_else(|| "Never".to_string());
+
+                let mut spans = highlight_matches(&self.index.label(file), &needles, matched);
+                // Finished books are set apart, in green with a check mark
+                if bookmark.is_some_and(|bookmark| bookmark.finished.is_some()) {
+                    let finished = Style::default().fg(Color::Green);
+                    for span in &mut spans {
+                        span.style = finished.patch(span.style);
+                    }
+                    spans.insert(0, Span::styled("✓ ", finished));
+                }
+                let progress = bookmark.and(self.progress_of(file));
+                if let Some(copies) = self.copies.get(file) {
+                    spans.push(Span::styled(format!(" ×{}", copies.len()), Style::default().fg(Color::Cyan)));
+                }
+                let details = match progress {
+                    Some(percent) => format!(" ({}%, {})", percent, last_read),
+                    None => format!(" ({})", last_read),
+                };
+                spans.push(Span::styled(details, Style::default().fg(Color::DarkGray)));
+                ListItem::new(Line::from(spans))
+            }))
+            .collect();
+
+        let files = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(Style::default().bg(Color::White).fg(Color::Black));
+
+        let mut state = self.list_state.clone();
+        state.select(self.selected.checked_sub(self.pinned.len()));
+        f.render_stateful_widget(files, area, &mut state);
+    }
+
+    /// Lists the books pinned above the library, with the part each was
+    /// left at and how far through it is.
+    fn draw_continue_reading(&self, f: &mut ratatui::Frame, area: Rect) {
+        let dim = Style::default().fg(Color::DarkGray);
+        let items: Vec<ListItem> = self
+            .pinned
+            .iter()
+            .map(|file| {
+                let chapter = if self.current_file.as_ref() == Some(file) {
+                    self.current_chapter
+                } else {
+                    self.bookmarks.get_bookmark(file).map_or(0, |bookmark| bookmark.chapter)
+                };
+                let percent = self.progress_of(file);
+                let parts = self.index.part_sizes(file, || book::part_sizes_of(file)).len();
+                let percent = percent.map(|percent| format!("{:>3}% ", percent)).unwrap_or_else(|| "     ".to_string());
+                ListItem::new(Line::from(vec![
+                    Span::styled(percent, Style::default().fg(Color::Cyan)),
+                    Span::raw(self.index.label(file)),
+                    Span::styled(format!(" · part {}/{}", chapter + 1, parts.max(chapter + 1)), dim),
+                ]))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title("Continue reading"))
+            .highlight_style(Style::default().bg(Color::White).fg(Color::Black));
+        let mut state = ListState::default();
+        state.select(self.pinned.get(self.selected).map(|_| self.selected));
+        f.render_stateful_widget(list, area, &mut state);
+    }
+
+    /// Lists the parts of the current book, marking the ones read through
+    /// and the ones only looked at (visited according to the history), and
+    /// highlighting the current one.
+    fn draw_chapter_sidebar(&mut self, f: &mut ratatui::Frame, area: Rect) {
+        let (Some(book), Some(file)) = (&mut self.current_book, &self.current_file) else {
+            return;
+        };
+        if self.part_titles.as_ref().map(|(cached, _)| cached) != Some(file) {
+            self.part_titles = Some((file.clone(), book.part_titles()));
+        }
+        let visited: HashSet<usize> = self
+            .history
+            .visits()
+            .filter(|visit| &visit.book == file)
+            .map(|visit| visit.chapter)
+            .collect();
+        let titles = self.part_titles.as_ref().map(|(_, titles)| titles.as_slice()).unwrap_or_default();
+        let items: Vec<ListItem> = titles
+            .iter()
+            .enumerate()
+            .map(|(i, title)| {
+                let marker = if self.bookmarks.is_part_read(file, i) {
+                    Span::styled("✓ ", Style::default().fg(Color::Green))
+                } else if visited.contains(&i) {
+                    Span::styled("· ", Style::default().fg(Color::DarkGray))
+                } else {
+                    Span::raw("  ")
+                };
+                let note = if self.bookmarks.chapter_note(file, i).is_some() { "✎ " } else { "" };
+                ListItem::new(Line::from(vec![
+                    marker,
+                    Span::styled(note, Style::default().fg(Color::Yellow)),
+                    Span::raw(title.clone()),
+                ]))
+            })
+            .collect();
+
+        let read = (0..titles.len()).filter(|&i| self.bookmarks.is_part_read(file, i)).count();
+        let parts = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(format!("Parts ({}/{} read)", read, titles.len())))
+            .highlight_style(Style::default().bg(Color::White).fg(Color::Black));
+        let mut state = ListState::default().with_selected(Some(self.current_chapter));
+        f.render_stateful_widget(parts, area, &mut state);
+    }
+
+    /// Wraps `tab` to the inside of `area`. A resize moves the scroll
+    /// offset to keep the same text in view, and the bookmark follows it.
+    fn fit_tab(&mut self, tab: &mut Tab, area: Rect) {
+        self.measure(&tab.file, &mut tab.book);
+        let wrap_elapsed = perf::start_timer();
+        let spaced = self.bookmarks.is_spaced(&tab.file) && !tab.debug;
+        let mut width = self.text_width(area, tab.layout.as_ref());
+        if spaced {
+            width = width.min(SPACED_LINE_WIDTH);
+        }
+        if self.large_print && !tab.debug {
+            width = (width / GLYPH_WIDTH).max(1);
+        }
+        if let Some(moved) = tab.fit(width, self.wrap, self.paragraph_format.spaced(spaced)) {
+            self.perf.wrap = wrap_elapsed();
+            if moved {
+                self.save_bookmark_for(&tab.file, tab.chapter, tab.scroll_offset);
+            }
+        }
+    }
+
+    /// Turns the dyslexia-friendly layout of the current book on or off,
+    /// keeping the same text at the top of the pane.
+    fn toggle_spaced(&mut self) {
+        let Some(file) = self.current_file.clone() else {
+            return;
+        };
+        // The setting is kept in the book's bookmark
+        self.save_bookmark();
+        let spaced = !self.bookmarks.is_spaced(&file);
+        if !self.bookmarks.set_spaced(&file, spaced) {
+            return;
+        }
+        if let Some(layout) = self.layout.take() {
+            self.seek = Some(layout.fraction_before(self.scroll_offset));
+        }
+        self.status = Some(format!("Dyslexia-friendly layout {} for this book", if spaced { "on" } else { "off" }));
+    }
+
+    /// Scrolls `tab`, just laid out, to where `go_to_page` or
+    /// `toggle_spaced` asked.
+    fn apply_seek(&mut self, tab: &mut Tab) {
+        let Some(fraction) = self.seek.take() else {
+            return;
+        };
+        if let (Some(layout), false) = (&tab.layout, tab.debug) {
+            tab.scroll_offset = layout.index_at_fraction(fraction);
+            self.save_bookmark_for(&tab.file, tab.chapter, tab.scroll_offset);
+        }
+    }
+
+    /// Reads the part sizes and page list of `book`, opened from `file`,
+    /// unless they are known already.
+    fn measure(&mut self, file: &str, book: &mut Book) {
+        if !self.part_sizes.contains_key(file) {
+            let sizes = self.index.part_sizes(file, || book.part_sizes());
+            self.part_sizes.insert(file.to_string(), sizes);
+        }
+        if !self.page_lists.contains_key(file) {
+            let page_list = book.as_epub_mut().map(PageList::read).unwrap_or_default();
+            self.page_lists.insert(file.to_string(), page_list);
+        }
+    }
+
+    /// Columns available for text inside a pane, leaving room for the
+    /// line number gutter and the minimap when they are shown.
+    fn text_width(&self, area: Rect, layout: Option<&ChapterLayout>) -> usize {
+        let mut width = area.width.saturating_sub(2) as usize;
+        if self.line_numbers {
+            width = width.saturating_sub(gutter_width(layout) + 1);
+        }
+        if self.minimap {
+            width = width.saturating_sub(MINIMAP_WIDTH);
+        }
+        width
+    }
+
+    /// Rows of the layout a pane shows: fewer in large print, where each
+    /// takes `GLYPH_HEIGHT` rows of the terminal.
+    fn text_height(&self, area: Rect, debug: bool) -> usize {
+        let rows = area.height.saturating_sub(2) as usize;
+        if self.large_print && !debug {
+            rows / GLYPH_HEIGHT
+        } else {
+            rows
+        }
+    }
+
+    fn draw_pane(&self, f: &mut ratatui::Frame, area: Rect, tab: Option<&mut Tab>, border: Style, label: &str) {
+        let visible_width = self.text_width(area, tab.as_ref().and_then(|tab| tab.layout.as_ref()));
+        let Some(tab) = tab else {
+            let placeholder = Paragraph::new("Select a file to view its content")
+                .block(Block::default().borders(Borders::ALL).title("Content"));
+            f.render_widget(placeholder, area);
+            return;
+        };
+        let large_print = self.large_print && !tab.debug;
+        let visible_height = self.text_height(area, tab.debug);
+
+        let title = if !tab.debug {
+            let chapter_progress = if visible_width > 0 {
+                chapter_progress(tab.layout.as_ref(), tab.scroll_offset, visible_height)
+            } else {
+                0
+            };
+            let book_progress = self
+                .part_sizes
+                .get(&tab.file)
+                .map(|sizes| format!(" | Book: {}%", book_progress(sizes, tab.chapter, chapter_progress)))
+                .unwrap_or_default();
+            format!(
+                "Part {}/{} | Progress: {}%{}{}{}",
+                tab.chapter + 1,
+                tab.total_chapters,
+                chapter_progress,
+                book_progress,
+                if self.wrap { "" } else { " [NO WRAP]" },
+                label
+            )
+        } else {
+            format!("Part {}/{} [DEBUG MODE]{}", tab.chapter + 1, tab.total_chapters, label)
+        };
+
+        let viewport = tab.scroll_offset..tab.scroll_offset + visible_height;
+        let mut styled_content: Vec<Line> = match &mut tab.layout {
+            Some(layout) => layout.render(viewport.clone()),
+            None => Vec::new(),
+        };
+        let rows_per_line = if large_print {
+            styled_content = styled_content.iter().flat_map(big_print::enlarge).collect();
+            GLYPH_HEIGHT
+        } else {
+            1
+        };
+        if self.line_numbers {
+            let width = gutter_width(tab.layout.as_ref());
+            for (i, line) in styled_content.iter_mut().enumerate() {
+                // Only the top row of a line in big letters is numbered
+                let number = match i % rows_per_line {
+                    0 => format!("{:>width$} ", tab.scroll_offset + i / rows_per_line + 1),
+                    _ => " ".repeat(width + 1),
+                };
+                line.spans.insert(0, Span::styled(number, Style::default().fg(Color::DarkGray)));
+            }
+        }
+
+        // Virtual pages of a fixed amount of text, the same at any size
+        let mut block = Block::default().borders(Borders::ALL).border_style(border).title(title);
+        if let (false, Some(sizes)) = (tab.debug, self.part_sizes.get(&tab.file)) {
+            let fraction = tab.layout.as_ref().map_or(0.0, |layout| layout.fraction_before(tab.scroll_offset));
+            let (mut page, pages) = library_stats::virtual_page(sizes, tab.chapter, fraction);
+            // The row a page starts on counts as that page, the way
+            // `go_to_page` scrolls to it
+            if let (Some(layout), Some((chapter, start))) = (&tab.layout, library_stats::page_start(sizes, page + 1)) {
+                if chapter == tab.chapter && layout.index_at_fraction(start) <= tab.scroll_offset {
+                    page += 1;
+                }
+            }
+            let print_page = self
+                .page_lists
+                .get(&tab.file)
+                .and_then(|page_list| {
+                    // By row, the way `go_to_page` places them
+                    let layout = tab.layout.as_ref();
+                    page_list.current(tab.chapter, |start| layout.is_some_and(|layout| layout.index_at_fraction(start) <= tab.scroll_offset))
+                })
+                .map(|page| format!("print p. {} | ", page.label))
+                .unwrap_or_default();
+            let page = Title::from(format!(" {}p. {} / {} ", print_page, page, pages))
+                .position(Position::Bottom)
+                .alignment(Alignment::Right);
+            block = block.title(page);
+        }
+        let content_paragraph = Paragraph::new(styled_content).block(block);
+
+        f.render_widget(content_paragraph, area);
+
+        if let (true, Some(layout)) = (self.minimap, &tab.layout) {
+            let height = area.height.saturating_sub(2);
+            let strip = Rect {
+                x: area.x + area.width.saturating_sub(1 + MINIMAP_WIDTH as u16),
+                y: area.y + 1,
+                width: MINIMAP_WIDTH as u16,
+                height,
+            };
+            f.render_widget(Paragraph::new(layout.minimap(height as usize, viewport)), strip);
+        }
+    }
+
+    pub fn draw(&mut self, f: &mut ratatui::Frame) {
+        let frame_elapsed = perf::start_timer();
+        self.picture = None;
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(0),
+                Constraint::Length(3),
+            ])
+            .split(f.size());
+
+        // A hidden library still shows up for picking a book
+        let library_width = match self.library_width {
+            0 if self.mode == Mode::FileList => DEFAULT_LIBRARY_WIDTH,
+            width => width,
+        };
+        let main_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Percentage(library_width),
+                Constraint::Percentage(100 - library_width),
+            ])
+            .split(chunks[0]);
+
+        let content_area = if self.tabs.len() > 1 {
+            let areas = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(1), Constraint::Min(0)])
+                .split(main_chunks[1]);
+            let titles: Vec<String> = self
+                .tab_titles()
+                .into_iter()
+                .enumerate()
+                .map(|(i, title)| format!("{} {}", i + 1, title))
+                .collect();
+            let tabs = Tabs::new(titles)
+                .select(self.active_tab)
+                .style(Style::default().fg(Color::DarkGray))
+                .highlight_style(Style::default().fg(Color::White));
+            f.render_widget(tabs, areas[0]);
+            areas[1]
+        } else {
+            main_chunks[1]
+        };
+
+        // A book picked in the library but not open is described until it is
+        let previewed = self
+            .selected_file()
+            .filter(|file| self.mode == Mode::FileList && self.current_file.as_ref() != Some(*file))
+            .cloned();
+        let mut focused = self.take_tab();
+        let mut split = self.split.take();
+        match &mut split {
+            Some(split) => {
+                let halves = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                    .split(content_area);
+                let (focused_area, other_area) = if split.focus_right {
+                    (halves[1], halves[0])
+                } else {
+                    (halves[0], halves[1])
+                };
+                if let Some(tab) = &mut focused {
+                    self.fit_tab(tab, focused_area);
+                    self.apply_seek(tab);
+                }
+                self.visible_height = self.text_height(focused_area, focused.as_ref().is_some_and(|tab| tab.debug));
+                self.fit_tab(&mut split.other, other_area);
+                if let Some(tab) = &focused {
+                    let height = self.text_height(other_area, split.other.debug);
+                    if split.synced && split.other.follow(tab, height) {
+                        let other = &split.other;
+                        self.save_bookmark_for(&other.file, other.chapter, other.scroll_offset);
+                    }
+                }
+                let label = if split.synced { " [SYNCED]" } else { "" };
+                let focus_border = Style::default().fg(Color::White);
+                let other_border = Style::default().fg(Color::DarkGray);
+                self.draw_pane(f, focused_area, focused.as_mut(), focus_border, label);
+                self.draw_pane(f, other_area, Some(&mut split.other), other_border, label);
+            }
+            None => {
+                if let Some(tab) = &mut focused {
+                    self.fit_tab(tab, content_area);
+                    self.apply_seek(tab);
+                }
+                self.visible_height = self.text_height(content_area, focused.as_ref().is_some_and(|tab| tab.debug));
+                match previewed {
+                    Some(file) if self.library_preview == LibraryPreview::FirstPage => self.draw_sample(f, content_area, &file),
+                    Some(file) => self.draw_preview(f, content_area, &file),
+                    None => self.draw_pane(f, content_area, focused.as_mut(), Style::default(), ""),
+                }
+            }
+        }
+        self.split = split;
+        if let Some(tab) = focused {
+            self.restore_tab(tab);
+        }
+
+        // After the book is laid out, so its progress under "Continue
+        // reading" is up to date
+        if library_width > 0 {
+            if self.chapter_sidebar && self.mode == Mode::Content && self.current_book.is_some() {
+                self.draw_chapter_sidebar(f, main_chunks[0]);
+            } else {
+                self.draw_file_list(f, main_chunks[0]);
+            }
+        }
+
+        let help_text = match self.mode {
+            Mode::FileList => "j/k: Navigate | Enter: Select | t: New Tab | Tab: Switch View | q: Quit",
+            Mode::Content => "j/k: Scroll | h/l: Change Part | Tab: Switch View | d: Toggle Debug | q: Quit",
+            Mode::History => "j/k: Navigate | Enter: Jump Back | Esc: Close History | q: Quit",
+            Mode::Inspector => "j/k: Scroll | Esc: Close Inspector | q: Quit",
+            Mode::Statistics => "j/k: Scroll | Esc: Close Statistics | q: Quit",
+            Mode::Queue => "j/k: Navigate | J/K: Move | Enter: Open | d: Remove | Esc: Close Queue | q: Quit",
+            Mode::BookInfo => "1-5: Rate | 0: Clear Rating | r: Review | Esc: Close | q: Quit",
+            Mode::Validation => "j/k: Scroll | Esc: Close Report | q: Quit",
+            Mode::Highlights => "j/k: Navigate | Enter: Jump to Passage | d: Delete | Esc: Close | q: Quit",
+            Mode::Completion => "f: Mark Finished | 1-5: Rate | N: Next in Queue | Esc: Keep Reading | q: Quit",
+            Mode::Finder => "Type to Search | Up/Down: Navigate | Enter: Open | Esc: Close",
+            Mode::Shelf => "h/j/k/l: Navigate | Enter: Open | /: Filter | B: List View | q: Quit",
+            Mode::Copies => "j/k: Navigate | Enter: Open This Copy | Esc: Close | q: Quit",
+        };
+        let prompt = self
+            .filter_input
+            .as_ref()
+            .map(|input| format!("Filter: {}_ | Enter: Open | Tab: Keep | Esc: Clear", input))
+            .or_else(|| self.url_input.as_ref().map(|input| format!("Open URL: {}_ | Enter: Download | Esc: Cancel", input)))
+            .or_else(|| self.review_input.as_ref().map(|input| format!("Review: {}_ | Enter: Save | Esc: Cancel", input)))
+            .or_else(|| {
+                let input = self.collection_input.as_ref()?;
+                Some(format!("Save collection as: {}_ | Enter: Save | Esc: Cancel", input))
+            })
+            .or_else(|| self.page_input.as_ref().map(|input| format!("Go to page: {}_ | Enter: Go | Esc: Cancel", input)))
+            .or_else(|| self.lookup_input.as_ref().map(|input| format!("Look up: {}_ | Enter: Open dictionary | Esc: Cancel", input)))
+            .or_else(|| {
+                let (book, operation) = self.file_operation.as_ref()?;
+                let name = Path::new(book).file_name().unwrap_or_default().to_string_lossy();
+                Some(match operation {
+                    FileOperation::Delete => format!("Delete {}? y: Delete | any other key: Keep", name),
+                    FileOperation::Rename(input) => format!("Rename {} to: {}_ | Enter: Rename | Esc: Cancel", name, input),
+                    FileOperation::Move(input) => format!("Move {} to folder: {}_ | Enter: Move | Esc: Cancel", name, input),
+                })
+            })
+            .or_else(|| self.count.map(|count| format!("{}_", count)));
+        let progress = self.download.as_ref().map(Download::describe);
+        let help_text = prompt
+            .as_deref()
+            .or(self.status.as_deref())
+            .or(progress.as_deref())
+            .unwrap_or(help_text);
+        let now = chrono::Local::now();
+        let minutes = self.reading_time.minutes(now.date_naive());
+        let goal = self.reminders.goal.filter(|goal| *goal > 0).map(|goal| format!("/{}", goal)).unwrap_or_default();
+        let on_break = if self.reminders.on_break(std::time::Instant::now()) { " | on break" } else { "" };
+        let recording = self.recording.as_ref().map(|(register, _)| format!("recording @{} | ", register)).unwrap_or_default();
+        let clock = format!(" {}{} | {}{} min read today{} ", recording, now.format("%H:%M"), minutes, goal, on_break);
+        let help = Paragraph::new(help_text)
+            .block(Block::default().borders(Borders::ALL).title(Title::from(clock).alignment(Alignment::Right)))
+            .style(Style::default().fg(Color::DarkGray));
+        f.render_widget(help, chunks[1]);
+
+        if self.mode == Mode::History {
+            self.draw_history(f, chunks[0]);
+        }
+        if self.mode == Mode::Inspector {
+            self.draw_inspector(f, chunks[0]);
+        }
+        if self.mode == Mode::Statistics {
+            self.draw_statistics(f, chunks[0]);
+        }
+        if self.mode == Mode::Queue {
+            self.draw_queue(f, chunks[0]);
+        }
+        if self.mode == Mode::BookInfo {
+            self.draw_book_info(f, chunks[0]);
+        }
+        if self.mode == Mode::Validation {
+            self.draw_validation(f, chunks[0]);
+        }
+        if self.mode == Mode::Completion {
+            self.draw_completion(f, chunks[0]);
+        }
+        if self.mode == Mode::Highlights {
+            self.draw_highlights(f, chunks[0]);
+        }
+        if self.mode == Mode::Shelf {
+            self.draw_shelf(f, chunks[0]);
+        }
+        if self.mode == Mode::Finder {
+            self.draw_finder(f, chunks[0]);
+        }
+        if self.mode == Mode::Copies {
+            self.draw_copies(f, chunks[0]);
+        }
+
+        self.perf.frame = frame_elapsed();
+        if self.perf_overlay {
+            perf::render_overlay(f, content_area, &self.perf);
+        }
+        self.draw_chapter_note(f, content_area);
+        self.draw_note_preview(f, content_area);
+        self.draw_slots(f, content_area);
+        if let Some(popup) = &self.error_popup {
+            popup.render(f, f.size());
+        }
+    }
+
+    fn draw_history(&mut self, f: &mut ratatui::Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .history
+            .visits()
+            .map(|visit| {
+                let name = Path::new(&visit.book).file_stem().unwrap_or_default().to_string_lossy().to_string();
+                ListItem::new(Line::from(vec![
+                    Span::styled(visit.time.format("%a %Y-%m-%d %H:%M  ").to_string(), Style::default().fg(Color::DarkGray)),
+                    Span::raw(name),
+                    Span::styled(
+                        format!("  Part {}, line {}", visit.chapter + 1, visit.scroll_offset + 1),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                ]))
+            })
+            .collect();
+        let empty = items.is_empty();
+        let popup = popup::centered_rect(80, area.height.saturating_sub(2), area);
+        f.render_widget(Clear, popup);
+        let block = Block::default().borders(Borders::ALL).title("History");
+        if empty {
+            f.render_widget(Paragraph::new("Nothing read yet.").block(block), popup);
+        } else {
+            let list = List::new(items)
+                .block(block)
+                .highlight_style(Style::default().bg(Color::White).fg(Color::Black));
+            f.render_stateful_widget(list, popup, &mut self.history_state);
+        }
+    }
+
+    fn draw_inspector(&self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(book) = &self.current_book else {
+            return;
+        };
+        let popup = popup::centered_rect(90, area.height.saturating_sub(2), area);
+        f.render_widget(Clear, popup);
+        let title = format!("Inspector: {}", self.current_file.as_deref().unwrap_or_default());
+        let paragraph = Paragraph::new(inspector::describe(book))
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .scroll((self.inspector_scroll, 0));
+        f.render_widget(paragraph, popup);
+    }
+
+    fn handle_inspector_key(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Char('q') => return true,
+            KeyCode::Char('j') | KeyCode::Down => self.inspector_scroll = self.inspector_scroll.saturating_add(1),
+            KeyCode::Char('k') | KeyCode::Up => self.inspector_scroll = self.inspector_scroll.saturating_sub(1),
+            KeyCode::Esc | KeyCode::Char('I') => self.mode = Mode::Content,
+            _ => {}
+        }
+        false
+    }
+
+    /// Counts up the whole library for the statistics view. Books not
+    /// opened yet are read once for their length.
+    fn open_statistics(&mut self) {
+        let catalog = self.catalog.get_or_insert_with(|| self.index.catalog());
+        let opened: HashSet<&str> = self.history.visits().map(|visit| visit.book.as_str()).collect();
+        let mut stats = LibraryStats::default();
+        for file in &self.library {
+            if !self.part_sizes.contains_key(file) {
+                self.part_sizes.insert(file.clone(), self.index.part_sizes(file, || book::part_sizes_of(file)));
+            }
+            let characters = self.part_sizes[file].iter().sum();
+            let read = opened.contains(file.as_str()) || self.bookmarks.get_bookmark(file).is_some();
+            let entry = catalog.get(file).cloned().unwrap_or_default();
+            stats.add(&entry, characters, read);
+            if let Some(when) = self.bookmarks.get_bookmark(file).and_then(|bookmark| bookmark.finished) {
+                stats.add_finished(&entry, when);
+            }
+        }
+        self.library_stats = stats;
+        self.readability = None;
+        self.stats_scroll = 0;
+        self.previous_mode = self.mode;
+        self.mode = Mode::Statistics;
+    }
+
+    /// Measures the readability of the current part for the statistics
+    /// view.
+    fn open_part_statistics(&mut self) {
+        let Some(content) = &self.current_content else {
+            return;
+        };
+        let text: String = layout::parse_markup(content)
+            .iter()
+            .map(|runs| runs.iter().map(|run| run.text.as_str()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let title = self
+            .part_titles
+            .as_ref()
+            .filter(|(file, _)| Some(file) == self.current_file.as_ref())
+            .and_then(|(_, titles)| titles.get(self.current_chapter).cloned())
+            .unwrap_or_else(|| format!("Part {}", self.current_chapter + 1));
+        self.readability = Some((title, Readability::measure(&text)));
+        self.stats_scroll = 0;
+        self.previous_mode = self.mode;
+        self.mode = Mode::Statistics;
+    }
+
+    fn draw_statistics(&self, f: &mut ratatui::Frame, area: Rect) {
+        let popup = popup::centered_rect(60, area.height.saturating_sub(2), area);
+        f.render_widget(Clear, popup);
+        let (title, lines) = match &self.readability {
+            Some((part, readability)) => (format!("Statistics of {}", part), readability.describe()),
+            None => ("Library statistics".to_string(), self.library_stats.describe()),
+        };
+        let paragraph = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .scroll((self.stats_scroll, 0));
+        f.render_widget(paragraph, popup);
+    }
+
+    fn handle_statistics_key(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Char('q') => return true,
+            KeyCode::Char('j') | KeyCode::Down => self.stats_scroll = self.stats_scroll.saturating_add(1),
+            KeyCode::Char('k') | KeyCode::Up => self.stats_scroll = self.stats_scroll.saturating_sub(1),
+            KeyCode::Esc | KeyCode::Char('L') => self.mode = self.previous_mode,
+            _ => {}
+        }
+        false
+    }
+
+    /// Checks `file` for structural problems and shows the report.
+    fn open_validation(&mut self, file: &str) {
+        let validation = diagnostics::validate(Path::new(file));
+        info!("Validated {}: {} problems", file, validation.problem_count());
+        self.validation = Some((file.to_string(), validation));
+        self.validation_scroll = 0;
+        self.previous_mode = self.mode;
+        self.mode = Mode::Validation;
+    }
+
+    fn draw_validation(&self, f: &mut ratatui::Frame, area: Rect) {
+        let Some((file, validation)) = &self.validation else {
+            return;
+        };
+        let popup = popup::centered_rect(80, area.height.saturating_sub(2), area);
+        f.render_widget(Clear, popup);
+        let paragraph = Paragraph::new(validation.describe())
+            .block(Block::default().borders(Borders::ALL).title(format!("Validation: {}", file_name(file))))
+            .wrap(Wrap { trim: false })
+            .scroll((self.validation_scroll, 0));
+        f.render_widget(paragraph, popup);
+    }
+
+    fn handle_validation_key(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Char('q') => return true,
+            KeyCode::Char('j') | KeyCode::Down => self.validation_scroll = self.validation_scroll.saturating_add(1),
+            KeyCode::Char('k') | KeyCode::Up => self.validation_scroll = self.validation_scroll.saturating_sub(1),
+            KeyCode::Esc | KeyCode::Char('V') => self.mode = self.previous_mode,
+            _ => {}
+        }
+        false
+    }
+
+    /// Opens a book never opened before, picked at random from the list
+    /// as the filter narrows it.
+    fn pick_unread(&mut self) {
+        let opened: HashSet<&str> = self.history.visits().map(|visit| visit.book.as_str()).collect();
+        let unread: Vec<String> = self
+            .epub_files
+            .iter()
+            .filter(|file| !opened.contains(file.as_str()) && self.bookmarks.get_bookmark(file).is_none())
+            .cloned()
+            .collect();
+        match library::pick_random(&unread).cloned() {
+            Some(path) => {
+                info!("Picked {} out of {} unread books", path, unread.len());
+                self.load_epub(&path, false);
+            }
+            None => self.status = Some("No unread books to pick from".to_string()),
+        }
+    }
+
+    /// Shows what is known of `file`: its metadata, when it was read, and
+    /// the rating and review given to it.
+    fn open_book_info(&mut self, file: &str) {
+        // The book being read is as good as read, and can be rated
+        if self.current_file.as_deref() == Some(file) {
+            self.save_bookmark();
+        }
+        self.info = Some((file.to_string(), Entry::read(file)));
+        self.info_cover = self.graphics.and_then(|_| book::cover_of(file)).and_then(|cover| images::decode(&cover));
+        self.info_description = book::description_of(file).map(|description| {
+            // A plain text description keeps its paragraphs
+            let html = if description.contains('<') {
+                description
+            } else {
+                description.lines().map(|line| format!("<p>{}</p>", line)).collect()
+            };
+            Self::process_html_content(&html, &self.regex)
+        });
+        self.previous_mode = self.mode;
+        self.mode = Mode::BookInfo;
+    }
+
+    /// Describes `file` before it is opened: its title, authors, blurb,
+    /// length and language.
+    fn draw_preview(&mut self, f: &mut ratatui::Frame, area: Rect, file: &str) {
+        if self.preview.as_ref().is_none_or(|(previewed, _)| previewed != file) {
+            let entry = self.index.entry(file).cloned();
+            self.preview = Some((file.to_string(), entry.unwrap_or_else(|| Entry::read(file))));
+        }
+        let index = &self.index;
+        let sizes = self.part_sizes.entry(file.to_string()).or_insert_with(|| index.part_sizes(file, || book::part_sizes_of(file)));
+        let (parts, characters) = (sizes.len(), sizes.iter().sum::<usize>());
+        let Some((_, entry)) = &self.preview else {
+            return;
+        };
+
+        let label = |name: &str, value: String| {
+            Line::from(vec![Span::styled(format!("{:<10}", name), Style::default().fg(Color::DarkGray)), Span::raw(value)])
+        };
+        let mut lines = vec![Line::from(Span::styled(entry.title.clone(), Style::default().fg(Color::Yellow)))];
+        if !entry.authors.is_empty() {
+            lines.push(label("By", entry.authors.join(", ")));
+        }
+        lines.push(label(
+            "Length",
+            format!(
+                "about {} pages ({} words, {} parts)",
+                library_stats::estimated_pages(characters),
+                characters / library_stats::CHARS_PER_WORD,
+                parts
+            ),
+        ));
+        if let Some(language) = &entry.language {
+            lines.push(label("Language", language.clone()));
+        }
+        lines.push(Line::from(""));
+        match &entry.description {
+            Some(description) => lines.extend(description.lines().map(|line| Line::from(line.to_string()))),
+            None => lines.push(Line::from(Span::styled("No description", Style::default().fg(Color::DarkGray)))),
+        }
+
+        let paragraph = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title("Preview"))
+            .wrap(Wrap { trim: false });
+        f.render_widget(paragraph, area);
+    }
+
+    /// Shows the first page of body text of `file`, laid out as it would
+    /// be read, or its details when it has no text.
+    fn draw_sample(&mut self, f: &mut ratatui::Frame, area: Rect, file: &str) {
+        let width = self.text_width(area, None);
+        if self.sample.as_ref().is_none_or(|(sampled, _)| sampled != file) {
+            let layout = book::first_body_part(file)
+                .map(|html| Self::process_html_content(&html, &self.regex))
+                .filter(|text| !text.is_empty())
+                .map(|text| {
+                    let mut layout = ChapterLayout::with_format(&text, width, self.paragraph_format);
+                    if let Some(language) = self.index.entry(file).and_then(|entry| entry.language.as_deref()) {
+                        layout.hyphenate(language);
+                    }
+                    layout
+                });
+            self.sample = Some((file.to_string(), layout));
+        }
+        let Some((_, Some(layout))) = &mut self.sample else {
+            self.draw_preview(f, area, file);
+            return;
+        };
+        if layout.width() != width || layout.wraps() != self.wrap {
+            layout.set_wrap(self.wrap);
+            layout.rewrap(width);
+        }
+        let height = area.height.saturating_sub(2) as usize;
+        let paragraph = Paragraph::new(layout.render(0..height))
+            .block(Block::default().borders(Borders::ALL).title(format!("First page: {}", file_name(file))));
+        f.render_widget(paragraph, area);
+    }
+
+    fn draw_book_info(&mut self, f: &mut ratatui::Frame, area: Rect) {
+        let Some((file, entry)) = &self.info else {
+            return;
+        };
+        let label = |name: &str, value: String| {
+            Line::from(vec![Span::styled(format!("{:<10}", name), Style::default().fg(Color::DarkGray)), Span::raw(value)])
+        };
+        let mut lines = vec![Line::from(Span::styled(entry.title.clone(), Style::default().fg(Color::Yellow)))];
+        if !entry.authors.is_empty() {
+            lines.push(label("By", entry.authors.join(", ")));
+        }
+        if let Some(series) = &entry.series {
+            let index = entry.series_index.as_ref().map(|index| format!(" #{}", index)).unwrap_or_default();
+            lines.push(label("Series", format!("{}{}", series, index)));
+        }
+        if let Some(publisher) = &entry.publisher {
+            lines.push(label("Publisher", publisher.clone()));
+        }
+        if let Some(date) = &entry.date {
+            // The time of day some tools add says nothing
+            lines.push(label("Published", date.split('T').next().unwrap_or(date).to_string()));
+        }
+        if !entry.tags.is_empty() {
+            lines.push(label("Subjects", entry.tags.join(", ")));
+        }
+        if let Some(language) = &entry.language {
+            lines.push(label("Language", language.clone()));
+        }
+        lines.push(label("File", file.clone()));
+        let bookmark = self.bookmarks.get_bookmark(file);
+        lines.push(label(
+            "Last read",
+            bookmark.map_or("never".to_string(), |bookmark| {
+                let time = bookmark.last_read.with_timezone(&chrono::Local);
+                let ago = relative_time::relative(bookmark.last_read, chrono::Utc::now());
+                format!("{} ({}), part {}", time.format("%Y-%m-%d %H:%M"), ago, bookmark.chapter + 1)
+            }),
+        ));
+        if let Some(snippet) = bookmark.and_then(|bookmark| bookmark.snippet.as_ref()) {
+            lines.push(label("At", format!("\u{201C}{}\u{201D}", snippet)));
+        }
+        lines.push(label(
+            "Rating",
+            match bookmark.and_then(|bookmark| bookmark.rating) {
+                Some(rating) => stars(rating),
+                None => "not rated".to_string(),
+            },
+        ));
+        if let Some(review) = bookmark.and_then(|bookmark| bookmark.review.as_ref()) {
+            lines.push(Line::from(""));
+            lines.push(Line::from(review.clone()));
+        }
+        if let Some(description) = &self.info_description {
+            lines.push(Line::from(""));
+            lines.extend(layout::parse_markup(description).into_iter().map(|runs| {
+                Line::from(
+                    runs.into_iter()
+                        .map(|run| {
+                            let mut style = Style::default();
+                            if run.italic {
+                                style = style.add_modifier(Modifier::ITALIC);
+                            }
+                            if run.bold {
+                                style = style.add_modifier(Modifier::BOLD);
+                            }
+                            Span::styled(run.text, style)
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            }));
+        }
+
+        let popup = popup::centered_rect(70, area.height.saturating_sub(2), area);
+        f.render_widget(Clear, popup);
+        let block = Block::default().borders(Borders::ALL).title("Book info");
+        let mut text = block.inner(popup);
+        f.render_widget(block, popup);
+        // The cover goes on the left, a third of the width at most
+        if let (Some(protocol), Some(cover)) = (self.graphics, &self.info_cover) {
+            let width = (text.width / 3).min(MAX_COVER_WIDTH);
+            let picture = images::fit(cover, Rect { width, ..text });
+            match protocol {
+                Protocol::Blocks => images::render_blocks(cover, picture, f.buffer_mut()),
+                _ => self.picture = Some((file.clone(), picture)),
+            }
+            text.x += picture.width + 1;
+            text.width = text.width.saturating_sub(picture.width + 1);
+        }
+        f.render_widget(Paragraph::new(lines).wrap(Wrap { trim: false }), text);
+    }
+
+    fn handle_book_info_key(&mut self, key: KeyEvent) -> bool {
+        let Some((file, _)) = self.info.clone() else {
+            self.mode = self.previous_mode;
+            return false;
+        };
+        match key.code {
+            KeyCode::Char('q') => return true,
+            KeyCode::Char(c @ '0'..='5') => {
+                let rating = (c != '0').then_some(c as u8 - b'0');
+                if !self.bookmarks.set_rating(&file, rating) {
+                    self.status = Some("Open the book before rating it".to_string());
+                }
+            }
+            KeyCode::Char('r') => {
+                if self.bookmarks.get_bookmark(&file).is_some() {
+                    let review = self.bookmarks.get_bookmark(&file).and_then(|bookmark| bookmark.review.clone());
+                    self.review_input = Some(review.unwrap_or_default());
+                } else {
+                    self.status = Some("Open the book before reviewing it".to_string());
+                }
+            }
+            KeyCode::Esc | KeyCode::Char('i') => self.mode = self.previous_mode,
+            _ => {}
+        }
+        false
+    }
+
+    /// Adds the selected book to the end of the queue, or takes it out if
+    /// it is queued already.
+    fn toggle_queued(&mut self) {
+        let Some(file) = self.selected_file().cloned() else {
+            return;
+        };
+        let name = file_name(&file);
+        self.status = Some(if self.queue.remove(&file) {
+            format!("Removed {} from the queue", name)
+        } else {
+            self.queue.push(&file);
+            format!("Queued {} as number {}", name, self.queue.len())
+        });
+    }
+
+    /// Called when `finished` was read to the end: it leaves the queue, and
+    /// the next queued book is offered.
+    fn offer_next_in_queue(&mut self, finished: &str) {
+        self.queue.remove(finished);
+        if let Some(next) = self.queue.next(Some(finished)) {
+            self.status = Some(format!("Finished! Next in your queue: {} (N: start it)", file_name(next)));
+        }
+    }
+
+    /// Shown on scrolling past the end of the book: offers to mark it
+    /// finished, rate it and start the next queued book.
+    fn open_completion(&mut self) {
+        self.save_bookmark();
+        self.previous_mode = self.mode;
+        self.mode = Mode::Completion;
+    }
+
+    fn draw_completion(&self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(file) = self.current_file.as_deref() else {
+            return;
+        };
+        let title = self.book_metadata().map_or_else(|| file_name(file), |(title, _)| title);
+        let bookmark = self.bookmarks.get_bookmark(file);
+        let lines = vec![
+            Line::from(Span::styled(title, Style::default().fg(Color::Yellow))),
+            Line::from("You have reached the end of the book."),
+            Line::from(""),
+            Line::from(match bookmark.and_then(|bookmark| bookmark.finished) {
+                Some(when) => format!("Finished {} (f: not finished)", when.with_timezone(&chrono::Local).format("%Y-%m-%d")),
+                None => "f: Mark it finished".to_string(),
+            }),
+            Line::from(match bookmark.and_then(|bookmark| bookmark.rating) {
+                Some(rating) => format!("Rating {} (0: clear)", stars(rating)),
+                None => "1-5: Rate it".to_string(),
+            }),
+            Line::from(""),
+            Line::from(match self.queue.next(Some(file)) {
+                Some(next) => format!("Next in your queue: {} (N: start it)", file_name(next)),
+                None => "Your queue is empty".to_string(),
+            }),
+        ];
+
+        let popup = popup::centered_rect(60, (lines.len() + 2) as u16, area);
+        f.render_widget(Clear, popup);
+        let paragraph = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title("The end"))
+            .wrap(Wrap { trim: false });
+        f.render_widget(paragraph, popup);
+    }
+
+    fn handle_completion_key(&mut self, key: KeyEvent) -> bool {
+        let Some(file) = self.current_file.clone() else {
+            self.mode = self.previous_mode;
+            return false;
+        };
+        match key.code {
+            KeyCode::Char('q') => return true,
+            KeyCode::Char('f') => {
+                let finished = self.bookmarks.get_bookmark(&file).and_then(|bookmark| bookmark.finished);
+                self.bookmarks.set_finished(&file, finished.xor(Some(chrono::Utc::now())));
+                self.queue.remove(&file);
+            }
+            KeyCode::Char(c @ '0'..='5') => {
+                self.bookmarks.set_rating(&file, (c != '0').then_some(c as u8 - b'0'));
+            }
+            KeyCode::Char('N') => {
+                self.mode = self.previous_mode;
+                self.open_next_queued();
+            }
+            KeyCode::Esc | KeyCode::Enter => self.mode = self.previous_mode,
+            _ => {}
+        }
+        false
+    }
+
+    /// Opens the first queued book other than the one being read.
+    fn open_next_queued(&mut self) {
+        match self.queue.next(self.current_file.as_deref()).cloned() {
+            Some(next) => self.load_epub(&next, false),
+            None => self.status = Some("The queue is empty; add books with a in the library".to_string()),
+        }
+    }
+
+    fn open_highlights(&mut self) {
+        let Some(file) = self.current_file.as_deref() else {
+            return;
+        };
+        let empty = self.bookmarks.highlights(file).is_empty();
+        self.previous_mode = self.mode;
+        self.mode = Mode::Highlights;
+        self.highlights_state.select(if empty { None } else { Some(0) });
+    }
+
+    fn draw_highlights(&mut self, f: &mut ratatui::Frame, area: Rect) {
+        let highlights = self.current_file.as_deref().map_or(&[][..], |file| self.bookmarks.highlights(file));
+        let items: Vec<ListItem> = highlights
+            .iter()
+            .map(|highlight| {
+                let mut lines = vec![Line::from(vec![
+                    Span::styled(format!("Part {}  ", highlight.chapter + 1), Style::default().fg(Color::DarkGray)),
+                    Span::styled(
+                        highlight.text.split_whitespace().collect::<Vec<_>>().join(" "),
+                        Style::default().fg(Color::Yellow),
+                    ),
+                ])];
+                if let Some(note) = &highlight.note {
+                    lines.push(Line::from(format!("  {}", note)).style(Style::default().fg(Color::DarkGray)));
+                }
+                ListItem::new(lines)
+            })
+            .collect();
+        let popup = popup::centered_rect(80, area.height.saturating_sub(2), area);
+        f.render_widget(Clear, popup);
+        let block = Block::default().borders(Borders::ALL).title(format!("Highlights ({})", items.len()));
+        if items.is_empty() {
+            f.render_widget(Paragraph::new("No highlights in this book.").block(block), popup);
+        } else {
+            let list = List::new(items)
+                .block(block)
+                .highlight_style(Style::default().bg(Color::White).fg(Color::Black));
+            f.render_stateful_widget(list, popup, &mut self.highlights_state);
+        }
+    }
+
+    /// Goes to the passage of the selected highlight, or the start of its
+    /// part when the passage can't be found there.
+    fn jump_to_highlight(&mut self) {
+        let Some(file) = self.current_file.clone() else {
+            return;
+        };
+        let Some(highlight) = self.highlights_state.selected().and_then(|i| self.bookmarks.highlights(&file).get(i)).cloned() else {
+            return;
+        };
+        self.mode = self.previous_mode;
+        if highlight.chapter != self.current_chapter {
+            self.go_to_chapter(highlight.chapter);
+            if self.current_chapter != highlight.chapter {
+                return;
+            }
+        } else {
+            self.remember_position();
+        }
+        let fraction = self.current_content.as_deref().and_then(|content| layout::fraction_of(content, &highlight.text));
+        if fraction.is_none() {
+            self.status = Some("The passage wasn't found; showing the start of its part".to_string());
+        }
+        self.seek = Some(fraction.unwrap_or(0.0));
+    }
+
+    fn handle_highlights_key(&mut self, key: KeyEvent) -> bool {
+        let count = self.current_file.as_deref().map_or(0, |file| self.bookmarks.highlights(file).len());
+        let selected = self.highlights_state.selected().unwrap_or(0);
+        match key.code {
+            KeyCode::Char('q') => return true,
+            KeyCode::Char('j') | KeyCode::Down if selected + 1 < count => {
+                self.highlights_state.select(Some(selected + 1));
+            }
+            KeyCode::Char('k') | KeyCode::Up => self.highlights_state.select(Some(selected.saturating_sub(1))),
+            KeyCode::Char('d') | KeyCode::Delete => {
+                if let Some(file) = self.current_file.clone() {
+                    if self.bookmarks.remove_highlight(&file, selected) {
+                        let last = (count - 1).checked_sub(1);
+                        self.highlights_state.select(last.map(|last| selected.min(last)));
+                    }
+                }
+            }
+            KeyCode::Enter => self.jump_to_highlight(),
+            KeyCode::Esc | KeyCode::Char('A') => self.mode = self.previous_mode,
+            _ => {}
+        }
+        false
+    }
+
+    /// The mode the library is shown in: the list, or the shelf when the
+    /// user last chose it.
+    fn library_mode(&self) -> Mode {
+        if self.view.shelf {
+            Mode::Shelf
+        } else {
+            Mode::FileList
+        }
+    }
+
+    /// Switches the library between the list and the shelf, kept for next
+    /// time.
+    fn toggle_shelf(&mut self) {
+        let shelf = self.mode != Mode::Shelf;
+        self.view.update(|view| view.shelf = shelf);
+        self.mode = self.library_mode();
+    }
+
+    fn draw_shelf(&mut self, f: &mut ratatui::Frame, area: Rect) {
+        f.render_widget(Clear, area);
+        let block = Block::default().borders(Borders::ALL).title(self.library_title());
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+        if self.epub_files.is_empty() {
+            f.render_widget(Paragraph::new("No books").style(Style::default().fg(Color::DarkGray)), inner);
+            return;
+        }
+        let columns = (inner.width / CARD_WIDTH).max(1) as usize;
+        let rows = (inner.height / CARD_HEIGHT).max(1) as usize;
+        self.shelf_columns = columns;
+        // The selected card's row stays in view
+        let row = self.shelf_index() / columns;
+        self.shelf_offset = self.shelf_offset.min(row).max((row + 1).saturating_sub(rows));
+
+        let first = self.shelf_offset * columns;
+        let last = (first + rows * columns).min(self.epub_files.len());
+        for i in first..last {
+            let (row, column) = ((i - first) / columns, (i - first) % columns);
+            let card = Rect::new(
+                inner.x + column as u16 * CARD_WIDTH,
+                inner.y + row as u16 * CARD_HEIGHT,
+                CARD_WIDTH.min(inner.width),
+                CARD_HEIGHT.min(inner.height),
+            );
+            let file = self.epub_files[i].clone();
+            self.draw_card(f, card, &file, i == self.shelf_index());
+        }
+    }
+
+    /// Draws the card of `file` on the shelf: its cover when it has one, with
+    /// its title and authors below, or those alone on a title card.
+    fn draw_card(&mut self, f: &mut ratatui::Frame, card: Rect, file: &str, selected: bool) {
+        let finished = self.bookmarks.is_finished(file);
+        let border = match (selected, finished) {
+            (true, _) => Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            (false, true) => Style::default().fg(Color::Green),
+            (false, false) => Style::default().fg(Color::DarkGray),
+        };
+        let mut block = Block::default().borders(Borders::ALL).border_style(border);
+        if finished {
+            block = block.title(Span::styled("✓", Style::default().fg(Color::Green)));
+        }
+        let mut inner = block.inner(card);
+        f.render_widget(block, card);
+
+        let (title, authors) = match self.index.entry(file) {
+            Some(entry) => (entry.title.clone(), entry.authors.join(", ")),
+            None => (file_name(file), String::new()),
+        };
+        let title_style = if selected { Style::default().add_modifier(Modifier::BOLD) } else { Style::default() };
+        let cover = match self.graphics {
+            Some(_) => self
+                .thumbnails
+                .entry(file.to_string())
+                .or_insert_with(|| book::cover_of(file).and_then(|cover| images::decode_within(&cover, THUMBNAIL_SIDE)))
+                .as_ref(),
+            None => None,
+        };
+        // Covers take all but the last two rows, left for the title and
+        // authors, cut to one line each
+        let lines = if let Some(cover) = cover.filter(|_| inner.height > 2) {
+            let picture = images::fit(cover, Rect { height: inner.height - 2, ..inner });
+            let centered = Rect { x: inner.x + (inner.width - picture.width) / 2, ..picture };
+            images::render_blocks(cover, centered, f.buffer_mut());
+            inner.y += inner.height - 2;
+            inner.height = 2;
+            vec![Line::from(Span::styled(title, title_style)), Line::from(Span::styled(authors, Style::default().fg(Color::DarkGray)))]
+        } else {
+            vec![
+                Line::from(Span::styled(title, title_style.fg(Color::Yellow))),
+                Line::from(""),
+                Line::from(Span::styled(authors, Style::default().fg(Color::DarkGray))),
+            ]
+        };
+        let wrap = cover.is_none();
+        let mut paragraph = Paragraph::new(lines).alignment(Alignment::Center);
+        if wrap {
+            paragraph = paragraph.wrap(Wrap { trim: true });
+        }
+        f.render_widget(paragraph, inner);
+    }
+
+    /// Index in the books shown of the one selected, on the shelf.
+    fn shelf_index(&self) -> usize {
+        self.selected.saturating_sub(self.first_book_row()).min(self.epub_files.len().saturating_sub(1))
+    }
+
+    fn handle_shelf_key(&mut self, key: KeyEvent) -> bool {
+        let index = self.shelf_index();
+        let last = self.epub_files.len().saturating_sub(1);
+        let columns = self.shelf_columns;
+        let moved = match key.code {
+            KeyCode::Char('q') => return true,
+            KeyCode::Char('h') | KeyCode::Left => index.saturating_sub(1),
+            KeyCode::Char('l') | KeyCode::Right => (index + 1).min(last),
+            KeyCode::Char('k') | KeyCode::Up => index.checked_sub(columns).unwrap_or(index),
+            KeyCode::Char('j') | KeyCode::Down => (index + columns).min(last),
+            KeyCode::Enter => {
+                if let Some(path) = self.epub_files.get(index).cloned() {
+                    self.open_listed(&path);
+                }
+                return false;
+            }
+            KeyCode::Char('/') => {
+                self.filter_input = Some(self.filter.clone());
+                return false;
+            }
+            KeyCode::Tab if self.current_book.is_some() => {
+                self.mode = Mode::Content;
+                return false;
+            }
+            KeyCode::Char('B') | KeyCode::Esc => {
+                self.toggle_shelf();
+                return false;
+            }
+            _ => return false,
+        };
+        if moved != index || self.selected < self.first_book_row() {
+            self.select_file_at(moved);
+            self.fire_selection_changed();
+        }
+        false
+    }
+
+    fn open_finder(&mut self) {
+        let back = if self.mode == Mode::Finder { Mode::FileList } else { self.mode };
+        self.finder = Some(Finder { query: String::new(), matches: Vec::new(), state: ListState::default(), back });
+        self.mode = Mode::Finder;
+        self.update_finder();
+    }
+
+    /// Text the finder matches a book by: its title and authors, and its
+    /// file name.
+    fn finder_text(&self, book: &str) -> String {
+        format!("{}  {}", self.index.label(book), file_name(book))
+    }
+
+    /// Scores every book of the library against the finder's query.
+    fn update_finder(&mut self) {
+        let Some(query) = self.finder.as_ref().map(|finder| finder.query.clone()) else {
+            return;
+        };
+        let mut matches: Vec<(String, Match)> = self
+            .library
+            .iter()
+            .filter_map(|book| Some((book.clone(), fuzzy::fuzzy_match(&query, &self.finder_text(book))?)))
+            .collect();
+        matches.sort_by_cached_key(|(book, found)| (std::cmp::Reverse(found.score), self.index.label(book).to_lowercase()));
+        if let Some(finder) = &mut self.finder {
+            finder.state.select(if matches.is_empty() { None } else { Some(0) });
+            finder.matches = matches;
+        }
+    }
+
+    fn draw_finder(&mut self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(finder) = &self.finder else {
+            return;
+        };
+        let matched = Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD);
+        let items: Vec<ListItem> = finder
+            .matches
+            .iter()
+            .map(|(book, found)| {
+                let label = self.index.label(book);
+                let label_len = label.chars().count();
+                let spans: Vec<Span> = self
+                    .finder_text(book)
+                    .chars()
+                    .enumerate()
+                    .map(|(i, c)| match () {
+                        _ if found.positions.binary_search(&i).is_ok() => Span::styled(c.to_string(), matched),
+                        _ if i >= label_len => Span::styled(c.to_string(), Style::default().fg(Color::DarkGray)),
+                        _ => Span::raw(c.to_string()),
+                    })
+                    .collect();
+                ListItem::new(Line::from(spans))
+            })
+            .collect();
+        let popup = popup::centered_rect(70, area.height.saturating_sub(2), area);
+        f.render_widget(Clear, popup);
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(format!("Open book ({}/{})", finder.matches.len(), self.library.len()));
+        let inner = block.inner(popup);
+        f.render_widget(block, popup);
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Min(0)])
+            .split(inner);
+        let (input, list) = (rows[0], rows[1]);
+        f.render_widget(Paragraph::new(format!("> {}_", finder.query)), input);
+        if items.is_empty() {
+            f.render_widget(Paragraph::new("No matching books.").style(Style::default().fg(Color::DarkGray)), list);
+        } else {
+            let list_widget = List::new(items).highlight_style(Style::default().bg(Color::White).fg(Color::Black));
+            if let Some(finder) = &mut self.finder {
+                f.render_stateful_widget(list_widget, list, &mut finder.state);
+            }
+        }
+    }
+
+    fn handle_finder_key(&mut self, key: KeyEvent) -> bool {
+        let Some(finder) = &mut self.finder else {
+            self.mode = Mode::FileList;
+            return false;
+        };
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        let selected = finder.state.selected();
+        match key.code {
+            KeyCode::Up => finder.state.select(selected.map(|i| i.saturating_sub(1))),
+            KeyCode::Char('p') if control => finder.state.select(selected.map(|i| i.saturating_sub(1))),
+            KeyCode::Down => finder.state.select(selected.map(|i| (i + 1).min(finder.matches.len() - 1))),
+            KeyCode::Char('n') if control => finder.state.select(selected.map(|i| (i + 1).min(finder.matches.len() - 1))),
+            KeyCode::Char(c) if !control => {
+                finder.query.push(c);
+                self.update_finder();
+            }
+            KeyCode::Backspace => {
+                finder.query.pop();
+                self.update_finder();
+            }
+            KeyCode::Enter => {
+                let book = selected.and_then(|i| finder.matches.get(i)).map(|(book, _)| book.clone());
+                self.mode = finder.back;
+                self.finder = None;
+                if let Some(book) = book {
+                    self.open_file(&book);
+                }
+            }
+            KeyCode::Esc => {
+                self.mode = finder.back;
+                self.finder = None;
+            }
+            _ => {}
+        }
+        false
+    }
+
+    /// Opens `book` as listed in the library, asking which copy when
+    /// there are several.
+    fn open_listed(&mut self, book: &str) {
+        match self.copies.get(book) {
+            Some(copies) => {
+                self.copy_choice = copies.clone();
+                self.copies_state.select(Some(0));
+                self.previous_mode = self.mode;
+                self.mode = Mode::Copies;
+            }
+            None => self.load_epub(book, false),
+        }
+    }
+
+    fn draw_copies(&mut self, f: &mut ratatui::Frame, area: Rect) {
+        let now = chrono::Utc::now();
+        let dim = Style::default().fg(Color::DarkGray);
+        let items: Vec<ListItem> = self
+            .copy_choice
+            .iter()
+            .map(|copy| {
+                let place = self
+                    .roots
+                    .iter()
+                    .find_map(|root| Path::new(copy).strip_prefix(root).ok())
+                    .unwrap_or(Path::new(copy))
+                    .to_string_lossy()
+                    .to_string();
+                let read = match (self.bookmarks.get_bookmark(copy), self.progress_of(copy)) {
+                    (Some(bookmark), Some(percent)) => {
+                        format!(" ({}%, {})", percent, relative_time::format(&self.last_read_format, bookmark.last_read, now))
+                    }
+                    (Some(bookmark), None) => format!(" ({})", relative_time::format(&self.last_read_format, bookmark.last_read, now)),
+                    (None, _) => " (Never)".to_string(),
+                };
+                ListItem::new(Line::from(vec![Span::raw(place), Span::styled(read, dim)]))
+            })
+            .collect();
+        let title = self.copy_choice.first().map(|copy| format!("Copies of {}", self.index.label(copy))).unwrap_or_default();
+        let height = (items.len() as u16 + 2).min(area.height);
+        let popup = popup::centered_rect(70, height, area);
+        f.render_widget(Clear, popup);
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(Style::default().bg(Color::White).fg(Color::Black));
+        f.render_stateful_widget(list, popup, &mut self.copies_state);
+    }
+
+    fn handle_copies_key(&mut self, key: KeyEvent) -> bool {
+        let selected = self.copies_state.selected().unwrap_or(0);
+        match key.code {
+            KeyCode::Char('q') => return true,
+            KeyCode::Char('j') | KeyCode::Down if selected + 1 < self.copy_choice.len() => {
+                self.copies_state.select(Some(selected + 1));
+            }
+            KeyCode::Char('k') | KeyCode::Up => self.copies_state.select(Some(selected.saturating_sub(1))),
+            KeyCode::Enter => {
+                if let Some(copy) = self.copy_choice.get(selected).cloned() {
+                    self.mode = self.previous_mode;
+                    self.load_epub(&copy, false);
+                }
+            }
+            KeyCode::Esc => self.mode = self.previous_mode,
+            _ => {}
+        }
+        false
+    }
+
+    fn open_queue(&mut self) {
+        self.previous_mode = self.mode;
+        self.mode = Mode::Queue;
+        self.queue_state.select(if self.queue.is_empty() { None } else { Some(0) });
+    }
+
+    fn draw_queue(&mut self, f: &mut ratatui::Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .queue
+            .books()
+            .iter()
+            .enumerate()
+            .map(|(i, book)| {
+                ListItem::new(Line::from(vec![
+                    Span::styled(format!("{:>3}. ", i + 1), Style::default().fg(Color::DarkGray)),
+                    Span::raw(file_name(book)),
+                ]))
+            })
+            .collect();
+        let popup = popup::centered_rect(60, area.height.saturating_sub(2), area);
+        f.render_widget(Clear, popup);
+        let block = Block::default().borders(Borders::ALL).title("Reading queue");
+        if items.is_empty() {
+            f.render_widget(Paragraph::new("Nothing queued. Press a on a book in the library to add it.").block(block), popup);
+        } else {
+            let list = List::new(items)
+                .block(block)
+                .highlight_style(Style::default().bg(Color::White).fg(Color::Black));
+            f.render_stateful_widget(list, popup, &mut self.queue_state);
+        }
+    }
+
+    fn handle_queue_key(&mut self, key: KeyEvent) -> bool {
+        let selected = self.queue_state.selected().unwrap_or(0);
+        match key.code {
+            KeyCode::Char('q') => return true,
+            KeyCode::Char('j') | KeyCode::Down if selected + 1 < self.queue.len() => {
+                self.queue_state.select(Some(selected + 1));
+            }
+            KeyCode::Char('k') | KeyCode::Up => self.queue_state.select(Some(selected.saturating_sub(1))),
+            KeyCode::Char(c @ ('J' | 'K')) => {
+                let moved = self.queue.shift(selected, c == 'K');
+                self.queue_state.select(Some(moved));
+            }
+            KeyCode::Char('d') | KeyCode::Delete => {
+                if let Some(book) = self.queue.books().get(selected).cloned() {
+                    self.queue.remove(&book);
+                    let last = self.queue.len().checked_sub(1);
+                    self.queue_state.select(last.map(|last| selected.min(last)));
+                }
+            }
+            KeyCode::Enter => {
+                if let Some(book) = self.queue.books().get(selected).cloned() {
+                    self.mode = self.previous_mode;
+                    self.load_epub(&book, false);
+                }
+            }
+            KeyCode::Esc | KeyCode::Char('Q') => self.mode = self.previous_mode,
+            _ => {}
+        }
+        false
+    }
+
+    fn open_history(&mut self) {
+        self.previous_mode = self.mode;
+        self.mode = Mode::History;
+        self.history_state.select(if self.history.is_empty() { None } else { Some(0) });
+    }
+
+    /// Opens the book of the selected visit at the recorded place.
+    fn jump_to_visit(&mut self) {
+        let Some(visit) = self.history_state.selected().and_then(|i| self.history.visits().nth(i)).cloned() else {
+            return;
+        };
+        info!("Jumping back to {} part {} offset {}", visit.book, visit.chapter + 1, visit.scroll_offset);
+        self.mode = self.previous_mode;
+        self.load_epub(&visit.book, false);
+        if self.current_file.as_deref() != Some(visit.book.as_str()) {
+            return;
+        }
+        if visit.chapter != self.current_chapter {
+            self.go_to_chapter(visit.chapter);
+        }
+        self.scroll_offset = visit.scroll_offset;
+        self.save_bookmark();
+    }
+
+    fn handle_history_key(&mut self, key: KeyEvent) -> bool {
+        let selected = self.history_state.selected().unwrap_or(0);
+        match key.code {
+            KeyCode::Char('q') => return true,
+            KeyCode::Char('j') | KeyCode::Down if selected + 1 < self.history.len() => {
+                self.history_state.select(Some(selected + 1));
+            }
+            KeyCode::Char('k') | KeyCode::Up => self.history_state.select(Some(selected.saturating_sub(1))),
+            KeyCode::Enter => self.jump_to_visit(),
+            KeyCode::Esc | KeyCode::Char('H') => self.mode = self.previous_mode,
+            _ => {}
+        }
+        false
+    }
+
+    /// Presses the keys recorded to `register`, `times` over. Returns
+    /// `true` when one of them quit.
+    fn play_macro(&mut self, register: char, times: usize) -> bool {
+        let Some(keys) = self.macros.get(&register).cloned() else {
+            self.status = Some(format!("Nothing recorded to @{}", register));
+            return false;
+        };
+        if self.macro_depth >= MAX_MACRO_DEPTH {
+            warn!("Macro @{} nested too deep, skipped", register);
+            return false;
+        }
+        info!("Playing macro @{} {} times", register, times);
+        self.last_macro = Some(register);
+        self.macro_depth += 1;
+        let quit = (0..times).flat_map(|_| keys.iter()).any(|key| self.handle_key(*key));
+        self.macro_depth -= 1;
+        quit
+    }
+
+    /// Applies a single key press to the application state.
+    /// Returns `true` when the user asked to quit.
+    /// Keys bound by plugins take precedence over the built-in ones. While an
+    /// error popup is open, any key other than `q` only dismisses it.
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        self.status = None;
+        self.tick();
+        self.last_activity = std::time::Instant::now();
+        if self.error_popup.is_some() && key.code != KeyCode::Char('q') {
+            self.error_popup = None;
+            return false;
+        }
+
+        // Keys a macro presses are recorded as the `@` that played it
+        if let (Some((_, keys)), 0) = (&mut self.recording, self.macro_depth) {
+            keys.push(key);
+        }
+        if let Some(pending) = self.pending_macro.take() {
+            match (pending, key.code) {
+                (MacroKey::Record, KeyCode::Char(register @ 'a'..='z')) => {
+                    info!("Recording macro @{}", register);
+                    self.recording = Some((register, Vec::new()));
+                }
+                (MacroKey::Play(times), KeyCode::Char(register @ ('a'..='z' | '@'))) => {
+                    let register = if register == '@' { self.last_macro } else { Some(register) };
+                    if let Some(register) = register {
+                        return self.play_macro(register, times);
+                    }
+                }
+                _ => {}
+            }
+            return false;
+        }
+        if self.pending_g {
+            self.pending_g = false;
+            if let KeyCode::Char(c @ ('t' | 'T')) = key.code {
+                self.cycle_tab(c == 't');
+                return false;
+            }
+        }
+
+        if self.pending_slot {
+            self.pending_slot = false;
+            if let KeyCode::Char(c @ '1'..='9') = key.code {
+                self.save_slot(c as u8 - b'0');
+                return false;
+            }
+        }
+
+        if self.pending_jump {
+            self.pending_jump = false;
+            if let KeyCode::Char(c @ '1'..='9') = key.code {
+                self.jump_to_slot(c as u8 - b'0');
+                return false;
+            }
+        }
+
+        if self.filter_input.is_some() {
+            return self.handle_filter_key(key);
+        }
+        if self.url_input.is_some() {
+            return self.handle_url_key(key);
+        }
+        if self.review_input.is_some() {
+            return self.handle_review_key(key);
+        }
+        if self.chapter_note_input.is_some() {
+            return self.handle_chapter_note_key(key);
+        }
+        if self.note_preview.is_some() {
+            return self.handle_note_preview_key(key);
+        }
+        if self.collection_input.is_some() {
+            return self.handle_collection_key(key);
+        }
+        if self.file_operation.is_some() {
+            return self.handle_file_operation_key(key);
+        }
+        if self.page_input.is_some() {
+            return self.handle_page_key(key);
+        }
+        if self.lookup_input.is_some() {
+            return self.handle_lookup_key(key);
+        }
+        if self.mode == Mode::Finder {
+            return self.handle_finder_key(key);
+        }
+        if key.code == KeyCode::Char('p') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            self.open_finder();
+            return false;
+        }
+        if self.mode == Mode::Shelf {
+            return self.handle_shelf_key(key);
+        }
+        if self.mode == Mode::History {
+            return self.handle_history_key(key);
+        }
+        if self.mode == Mode::Inspector {
+            return self.handle_inspector_key(key);
+        }
+        if self.mode == Mode::Statistics {
+            return self.handle_statistics_key(key);
+        }
+        if self.mode == Mode::Queue {
+            return self.handle_queue_key(key);
+        }
+        if self.mode == Mode::BookInfo {
+            return self.handle_book_info_key(key);
+        }
+        if self.mode == Mode::Validation {
+            return self.handle_validation_key(key);
+        }
+        if self.mode == Mode::Completion {
+            return self.handle_completion_key(key);
+        }
+        if self.mode == Mode::Highlights {
+            return self.handle_highlights_key(key);
+        }
+        if self.mode == Mode::Copies {
+            return self.handle_copies_key(key);
+        }
+
+        // Digits make up a count for the motion after them; a leading 0
+        // is no count
+        if let KeyCode::Char(c @ '0'..='9') = key.code {
+            if matches!(self.mode, Mode::Content | Mode::FileList) && (c != '0' || self.count.is_some()) {
+                let digit = (c as u8 - b'0') as usize;
+                self.count = Some((self.count.unwrap_or(0) * 10 + digit).min(MAX_COUNT));
+                return false;
+            }
+        }
+        let count = self.count.take();
+
+        if let KeyCode::Char(c) = key.code {
+            if let Some(actions) = self.plugins.run_binding(c) {
+                self.apply_plugin_actions(actions);
+                return false;
+            }
+        }
+
+        match key.code {
+            KeyCode::Char('q') => return true,
+            KeyCode::Char('j') => {
+                if self.mode == Mode::FileList {
+                    let last = (self.first_book_row() + self.epub_files.len()).saturating_sub(1);
+                    let selected = (self.selected + count.unwrap_or(1)).min(last);
+                    if selected != self.selected {
+                        self.selected = selected;
+                        self.list_state.select(Some(self.selected));
+                        self.fire_selection_changed();
+                    }
+                } else {
+                    match count {
+                        Some(rows) => self.scroll_by(rows, true),
+                        None => self.scroll_down(),
+                    }
+                }
+            }
+            KeyCode::Char('k') => {
+                if self.mode == Mode::FileList {
+                    let selected = self.selected.saturating_sub(count.unwrap_or(1));
+                    if selected != self.selected {
+                        self.selected = selected;
+                        self.list_state.select(Some(self.selected));
+                        self.fire_selection_changed();
+                    }
+                } else {
+                    match count {
+                        Some(rows) => self.scroll_by(rows, false),
+                        None => self.scroll_up(),
+                    }
+                }
+            }
+            KeyCode::Char('h') if self.mode == Mode::Content => match count {
+                Some(parts) => self.go_to_chapter(self.current_chapter.saturating_sub(parts)),
+                None => self.prev_chapter(),
+            },
+            KeyCode::Char('l') if self.mode == Mode::Content => match count {
+                Some(parts) => self.go_to_chapter((self.current_chapter + parts).min(self.total_chapters.saturating_sub(1))),
+                None => self.next_chapter(),
+            },
+            KeyCode::Char('u') if self.mode == Mode::Content => self.undo_navigation(),
+            KeyCode::Char('f') if self.mode == Mode::Content => self.follow_note(0),
+            KeyCode::Char('F') if self.mode == Mode::Content => self.preview_note(0),
+            KeyCode::Char('`') if matches!(self.mode, Mode::Content | Mode::FileList) => self.switch_to_alternate(),
+            KeyCode::Char('n') if self.mode == Mode::Content && self.current_book.is_some() => self.edit_chapter_note(),
+            KeyCode::Backspace if self.mode == Mode::Content => self.return_from_note(),
+            KeyCode::Left | KeyCode::Right if self.mode == Mode::Content => {
+                let columns = if key.code == KeyCode::Left { -HSCROLL_STEP } else { HSCROLL_STEP };
+                if let Some(layout) = &mut self.layout {
+                    layout.scroll_horizontally(columns);
+                }
+            }
+            KeyCode::Char('b') if self.mode == Mode::Content => self.pending_slot = true,
+            KeyCode::Char('\'') if self.mode == Mode::Content => self.pending_jump = true,
+            KeyCode::Enter if self.mode == Mode::FileList => {
+                // A book under "Continue reading" is the copy that was read
+                if let Some(path) = self.pinned.get(self.selected).cloned() {
+                    self.load_epub(&path, false);
+                } else if let Some(path) = self.selected_file().cloned() {
+                    self.open_listed(&path);
+                } else if let Some(collection) = self.selected_collection().cloned() {
+                    info!("Opening collection {}", collection.name);
+                    self.apply_filter(&collection.query);
+                }
+            }
+            KeyCode::Backspace if self.mode == Mode::FileList && !self.filter.is_empty() => self.apply_filter(""),
+            KeyCode::Char('+') if self.mode == Mode::FileList && !self.filter.is_empty() => {
+                let name = self.collections.name_of(&self.filter).unwrap_or_default().to_string();
+                self.collection_input = Some(name);
+            }
+            KeyCode::Char('d') if self.mode == Mode::FileList && self.selected_collection().is_some() => {
+                if let Some(collection) = self.collections.remove(self.selected - self.pinned.len()) {
+                    self.status = Some(format!("Removed collection {}", collection.name));
+                    self.selected = self.selected.min((self.first_book_row() + self.epub_files.len()).saturating_sub(1));
+                    self.list_state.select(Some(self.selected));
+                }
+            }
+            KeyCode::Char('d') | KeyCode::Delete if self.mode == Mode::FileList => self.start_file_operation(FileOperation::Delete),
+            KeyCode::Char('r') if self.mode == Mode::FileList => self.start_file_operation(FileOperation::Rename(String::new())),
+            KeyCode::Char('m') if self.mode == Mode::FileList => self.start_file_operation(FileOperation::Move(String::new())),
+            KeyCode::Char('/') if self.mode == Mode::FileList => self.filter_input = Some(self.filter.clone()),
+            KeyCode::Char('o') if self.mode == Mode::FileList => self.url_input = Some(String::new()),
+            KeyCode::Char('L') if self.mode == Mode::FileList => self.open_statistics(),
+            KeyCode::Char('L') if self.mode == Mode::Content => self.open_part_statistics(),
+            KeyCode::Char('i') => {
+                let file = if self.mode == Mode::FileList {
+                    self.selected_file().cloned()
+                } else {
+                    self.current_file.clone().filter(|file| file != book::STDIN)
+                };
+                if let Some(file) = file {
+                    self.open_book_info(&file);
+                }
+            }
+            KeyCode::Char('V') => {
+                let file = if self.mode == Mode::FileList {
+                    self.selected_file().cloned()
+                } else {
+                    self.current_file.clone().filter(|file| file != book::STDIN)
+                };
+                if let Some(file) = file {
+                    self.open_validation(&file);
+                }
+            }
+            KeyCode::Char('p') if self.mode == Mode::FileList => {
+                self.library_preview = match self.library_preview {
+                    LibraryPreview::Details => LibraryPreview::FirstPage,
+                    LibraryPreview::FirstPage => LibraryPreview::Details,
+                };
+            }
+            KeyCode::Char('R') if self.mode == Mode::FileList => self.pick_unread(),
+            KeyCode::F(5) => {
+                self.rescan_library();
+                self.status = Some(format!("Library refreshed: {} books", self.library.len()));
+            }
+            KeyCode::Char('.') if self.mode == Mode::FileList => {
+                self.scan.hidden = !self.scan.hidden;
+                self.rescan_library();
+                self.status = Some(format!("Hidden files {}", if self.scan.hidden { "shown" } else { "hidden" }));
+            }
+            KeyCode::Char('s') if self.mode == Mode::FileList => self.cycle_sort(),
+            KeyCode::Char('B') if self.mode == Mode::FileList => self.toggle_shelf(),
+            KeyCode::Char('f') if self.mode == Mode::FileList => self.toggle_finished(),
+            KeyCode::Char('F') if self.mode == Mode::FileList => self.toggle_hide_finished(),
+            KeyCode::Char('a') if self.mode == Mode::FileList => self.toggle_queued(),
+            KeyCode::Char('Q') => self.open_queue(),
+            KeyCode::Char('A') if self.mode == Mode::Content => self.open_highlights(),
+            KeyCode::Char('N') => self.open_next_queued(),
+            KeyCode::Char('t') if self.mode == Mode::FileList => {
+                if let Some(path) = self.selected_file().cloned() {
+                    self.load_epub(&path, true);
+                }
+            }
+            KeyCode::Char('g') => self.pending_g = true,
+            KeyCode::Char('M') if matches!(self.mode, Mode::Content | Mode::FileList) => match self.recording.take() {
+                Some((register, mut keys)) => {
+                    // The `M` that stops the recording isn't part of it
+                    keys.pop();
+                    let plural = if keys.len() == 1 { "" } else { "s" };
+                    self.status = Some(format!("Recorded {} key{} to @{}", keys.len(), plural, register));
+                    self.macros.insert(register, keys);
+                }
+                None => self.pending_macro = Some(MacroKey::Record),
+            },
+            KeyCode::Char('@') if matches!(self.mode, Mode::Content | Mode::FileList) => {
+                self.pending_macro = Some(MacroKey::Play(count.unwrap_or(1)));
+            }
+            KeyCode::Char('x') if self.mode == Mode::Content => {
+                if self.split.as_ref().is_some_and(|split| split.focus_right) {
+                    self.close_split();
+                } else {
+                    self.close_tab();
+                }
+            }
+            KeyCode::Char('v') if self.mode == Mode::FileList => {
+                if let Some(path) = self.selected_file().cloned() {
+                    self.open_split(&path);
+                }
+            }
+            KeyCode::Char('v') if self.mode == Mode::Content => {
+                if self.split.is_some() {
+                    self.close_split();
+                } else if let Some(path) = self.current_file.clone() {
+                    self.open_split(&path);
+                }
+            }
+            KeyCode::Char('w') => self.swap_focus(),
+            KeyCode::Char('S') => {
+                if let Some(split) = &mut self.split {
+                    split.synced = !split.synced;
+                    info!("Synced scrolling {}", if split.synced { "on" } else { "off" });
+                }
+            }
+            KeyCode::Tab => {
+                self.mode = if self.mode == Mode::FileList {
+                    Mode::Content
+                } else {
+                    self.refresh_pinned();
+                    if let Some(pos) = self.current_file.as_deref().and_then(|file| self.listed_position(file)) {
+                        self.select_file_at(pos);
+                    }
+                    self.library_mode()
+                };
+            }
+            KeyCode::Char('P') => self.perf_overlay = !self.perf_overlay,
+            KeyCode::Char('H') => self.open_history(),
+            KeyCode::Char('c') if self.mode == Mode::Content => self.chapter_sidebar = !self.chapter_sidebar,
+            KeyCode::Char('m') if self.mode == Mode::Content => self.minimap = !self.minimap,
+            KeyCode::Char('W') if self.mode == Mode::Content => self.wrap = !self.wrap,
+            KeyCode::Char('D') if self.mode == Mode::Content && self.current_book.is_some() => self.toggle_spaced(),
+            KeyCode::Char('#') if self.mode == Mode::Content => self.line_numbers = !self.line_numbers,
+            KeyCode::Char('z') if self.mode == Mode::Content => self.large_print = !self.large_print,
+            KeyCode::Char(c @ ('<' | '>')) => self.library_width = View::resized(self.library_width, c == '>'),
+            KeyCode::Char('I') if self.mode == Mode::Content && self.current_book.is_some() => {
+                self.inspector_scroll = 0;
+                self.mode = Mode::Inspector;
+            }
+            KeyCode::Char('G') if self.mode == Mode::Content && self.current_book.is_some() => match count {
+                Some(page) => self.go_to_page(&page.to_string()),
+                None => self.page_input = Some(String::new()),
+            },
+            KeyCode::Char('K') if self.mode == Mode::Content && self.current_book.is_some() => self.lookup_input = Some(String::new()),
+            KeyCode::Char('y') if self.mode == Mode::Content => self.copy_paragraph(),
+            KeyCode::Char('Y') if self.mode == Mode::Content => self.copy_screen(),
+            KeyCode::Char('d') if self.mode == Mode::Content => {
+                self.debug_mode = !self.debug_mode;
+                self.update_content();
+            }
+            _ => {}
+        }
+        false
+    }
+}
+
+impl Drop for App {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// `rating` out of five as stars, such as `★★★☆☆`.
+fn stars(rating: u8) -> String {
+    let rating = rating.min(5) as usize;
+    format!("{}{}", "★".repeat(rating), "☆".repeat(5 - rating))
+}
+
+/// `text` as spans, with the stretches matching any of the lowercase
+/// `needles` in the `matched` style.
+fn highlight_matches(text: &str, needles: &[&str], matched: Style) -> Vec<Span<'static>> {
+    let chars: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = chars.iter().map(|c| c.to_lowercase().next().unwrap_or(*c)).collect();
+    let mut marked = vec![false; chars.len()];
+    for needle in needles {
+        let needle: Vec<char> = needle.chars().collect();
+        if needle.is_empty() || needle.len() > lower.len() {
+            continue;
+        }
+        for start in 0..=lower.len() - needle.len() {
+            if lower[start..].starts_with(&needle) {
+                marked[start..start + needle.len()].fill(true);
+            }
+        }
+    }
+    let mut spans = Vec::new();
+    let mut start = 0;
+    for end in 1..=chars.len() {
+        if end == chars.len() || marked[end] != marked[start] {
+            let piece: String = chars[start..end].iter().collect();
+            spans.push(if marked[start] { Span::styled(piece, matched) } else { Span::raw(piece) });
+            start = end;
+        }
+    }
+    spans
+}
+
+/// Up to `count` books of `library` read the most recently, and not
+/// finished, for the "Continue reading" section.
+fn continue_reading(library: &[String], bookmarks: &Bookmarks, count: usize) -> Vec<String> {
+    bookmarks
+        .recently_read()
+        .into_iter()
+        .filter(|book| !bookmarks.is_finished(book))
+        .filter(|book| library.iter().any(|file| file == book))
+        .take(count)
+        .map(str::to_string)
+        .collect()
+}
+
+/// Lists every book `books` has several copies of once, by the copy read
+/// the most recently or else the first, and returns all the copies of
+/// each by the one kept. Copies share an identifier: the same book in two
+/// folders, or as an EPUB and in an archive.
+fn group_copies(books: &mut Vec<String>, index: &LibraryIndex, bookmarks: &Bookmarks) -> HashMap<String, Vec<String>> {
+    let identifier = |book: &str| index.entry(book).and_then(|entry| entry.identifier.clone());
+    let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+    for book in books.iter() {
+        if let Some(identifier) = identifier(book) {
+            groups.entry(identifier).or_default().push(book.clone());
+        }
+    }
+    let mut copies = HashMap::new();
+    for group in groups.into_values().filter(|group| group.len() > 1) {
+        let kept = group
+            .iter()
+            .min_by_key(|copy| std::cmp::Reverse(bookmarks.get_bookmark(copy).map(|bookmark| bookmark.last_read)))
+            .cloned()
+            .unwrap_or_default();
+        copies.insert(kept, group);
+    }
+    books.retain(|book| {
+        copies.contains_key(book) || !copies.values().any(|group| group.contains(book))
+    });
+    copies
+}
+
+/// File name of `book` without the extension, as lists show it.
+fn file_name(book: &str) -> String {
+    Path::new(book).file_stem().unwrap_or_default().to_string_lossy().to_string()
+}
+
+/// Percent of a part scrolled through, with `height` rows in view.
+fn chapter_progress(layout: Option<&ChapterLayout>, scroll_offset: usize, height: usize) -> u32 {
+    match layout {
+        Some(layout) if !layout.is_empty() => {
+            let max_scroll_offset = layout.len().saturating_sub(height);
+            if max_scroll_offset > 0 {
+                ((scroll_offset as f32 / max_scroll_offset as f32) * 100.0).min(100.0) as u32
+            } else {
+                100
+            }
+        }
+        _ => 0,
+    }
+}
+
+/// Percentage of the book's text before the reading position, given the
+/// text length of every part and the progress through the current one.
+pub fn book_progress(sizes: &[usize], chapter: usize, chapter_progress: u32) -> u32 {
+    let total: usize = sizes.iter().sum();
+    if total == 0 {
+        return 0;
+    }
+    let before: usize = sizes.iter().take(chapter).sum();
+    let current = sizes.get(chapter).copied().unwrap_or(0) as f64 * chapter_progress as f64 / 100.0;
+    ((before as f64 + current) / total as f64 * 100.0).round() as u32
+}
+
+/// Digits needed for the row numbers of `layout`, at least four so the
+/// gutter rarely changes width while reading.
+fn gutter_width(layout: Option<&ChapterLayout>) -> usize {
+    let rows = layout.map_or(0, |layout| layout.len());
+    rows.to_string().len().max(4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_test_regex() -> RegexPatterns {
+        RegexPatterns::new().expect("Failed to compile regex patterns")
+    }
+
+    #[test]
+    fn test_filter_matches_are_highlighted() {
+        let matched = Style::default().fg(Color::Yellow);
+        let spans = highlight_matches("War and Peace — Leo Tolstoy", &["peace", "leo", "xyz"], matched);
+        let pieces: Vec<(&str, bool)> = spans.iter().map(|span| (span.content.as_ref(), span.style == matched)).collect();
+        assert_eq!(pieces, [("War and ", false), ("Peace", true), (" — ", false), ("Leo", true), (" Tolstoy", false)]);
+        assert_eq!(highlight_matches("Émile", &["émi"], matched)[0].content, "Émi");
+        assert!(highlight_matches("", &["a"], matched).is_empty());
+    }
+
+    #[test]
+    fn test_html_formatting() {
+        let regex = get_test_regex();
+        let test_content = r#"
+            <h1>Header 1</h1>
+            <p>First paragraph with <em>italic</em> text.</p>
+            <p>Second paragraph with <strong>bold</strong> text.</p>
+            <blockquote>A blockquote</blockquote>
+            <p>Third paragraph with <br/> line break.</p>
+            <p>Fourth paragraph with &quot;quotes&quot; and &mdash; dash.</p>
+        "#;
+
+        let content = App::process_html_content(test_content, &regex);
+
+        assert!(content.contains("Header 1"));
+        assert!(content.contains("First paragraph with _italic_ text."));
+        assert!(content.contains("Second paragraph with **bold** text."));
+        assert!(content.contains("Third paragraph with \n line break."));
+        assert!(content.contains("Fourth paragraph with \"quotes\" and — dash."));
+        let paragraphs: Vec<&str> = content.split('\n').collect();
+        assert!(paragraphs.len() >= 5);
+        assert!(content.contains(&format!("{}A blockquote", PARAGRAPH_INDENT)));
+        assert!(!content.contains("&quot;"));
+        assert!(!content.contains("&mdash;"));
+        assert!(!content.contains("<em>"));
+        assert!(!content.contains("<strong>"));
+    }
+
+    #[test]
+    fn test_empty_content() {
+        let regex = get_test_regex();
+        let test_content = "";
+        let content = App::process_html_content(test_content, &regex);
+        assert!(content.is_empty());
+    }
+
+    #[test]
+    fn test_non_breaking_spaces_survive() {
+        let regex = get_test_regex();
+        let content = App::process_html_content("<p>Mr.&nbsp;Smith  carried 5&#160;kg\u{A0} &nbsp;home</p>", &regex);
+        assert_eq!(content, "Mr.\u{A0}Smith carried 5\u{A0}kg\u{A0} \u{A0}home");
+    }
+
+    #[test]
+    fn test_html_entities() {
+        let regex = get_test_regex();
+        let test_content = r#"<p>&amp; &lt; &gt; &apos; &ldquo; &rdquo; &lsquo; &rsquo;</p>"#;
+        let content = App::process_html_content(test_content, &regex);
+
+        assert!(content.contains("&"));
+        assert!(content.contains("<"));
+        assert!(content.contains(">"));
+        assert!(content.contains("'"));
+        assert!(content.contains("\u{201C}"));
+        assert!(content.contains("\u{201D}"));
+        assert!(content.contains("\u{2018}"));
+        assert!(content.contains("\u{2019}"));
+        let expected = format!("& < > ' {} {} {} {}", 
+            '\u{201C}', '\u{201D}', '\u{2018}', '\u{2019}');
+        assert_eq!(content, expected);
+    }
+
+    #[test]
+    fn test_paragraph_indentation() {
+        let regex = get_test_regex();
+        let test_content = r#"<p>First paragraph</p><p>Second paragraph</p><p>Third paragraph</p>"#;
+        let content = App::process_html_content(test_content, &regex);
+
+        let paragraphs: Vec<&str> = content.split('\n').collect();
+        assert!(paragraphs.len() == 3);
+        assert!(content.starts_with("First paragraph"));
+        assert!(content.contains("\n    Second paragraph"));
+        assert!(content.contains("\n    Third paragraph"));
+    }
+
+    #[test]
+    fn test_paragraphs_with_empty_lines() {
+        let regex = get_test_regex();
+        let test_content = r#"<p>First paragraph</p>
+
+        <p>Second paragraph</p>
+
+
+        <p>Third paragraph</p>
+
+        <p>Fourth paragraph</p>"#;
+        let content = App::process_html_content(test_content, &regex);
+
+        let paragraphs: Vec<&str> = content.split("\n").collect();
+        assert!(paragraphs.len() == 4, "Expected 4 paragraphs, got {}", paragraphs.len());
+        assert!(content.contains("First paragraph"));
+        assert!(content.contains(&format!("{}Second paragraph", PARAGRAPH_INDENT)));
+        assert!(content.contains(&format!("{}Third paragraph", PARAGRAPH_INDENT)));
+        assert!(content.contains(&format!("{}Fourth paragraph", PARAGRAPH_INDENT)));
+        let expected = "First paragraph\n    Second paragraph\n    Third paragraph\n    Fourth paragraph";
+        assert_eq!(content, expected, "Content does not match expected format");
+    }
+
+    #[test]
+    fn test_indentation_between_paragraphs_is_dropped() {
+        let regex = get_test_regex();
+        let test_content = "<p>First paragraph</p> \t <p>Second paragraph</p>";
+        let content = App::process_html_content(test_content, &regex);
+
+        assert_eq!(content, "First paragraph\n    Second paragraph");
+    }
+
+    #[test]
+    fn test_pathological_content_is_bounded() {
+        let regex = get_test_regex();
+        let limits = Limits {
+            max_bytes: 64 * 1024,
+            ..Default::default()
+        };
+        let style = format!("<p style=\"{}\">Styled</p>", "x:y;".repeat(10_000));
+        let unclosed = "<div>".repeat(50_000);
+        let test_content = format!("{}{}<p>Never shown</p>", style, unclosed);
+        let content = App::process_html_content_with_limits(&test_content, &regex, &limits);
+
+        assert!(content.starts_with("Styled"));
+        assert!(!content.contains("Never shown"));
+        assert!(content.ends_with("larger than 64 KB]"));
+    }
+
+    #[test]
+    fn test_preformatted_and_tables_keep_their_lines() {
+        let regex = get_test_regex();
+        let test_content = "<p>Listing:</p>\n<pre>if a  &amp;&amp; b {\n    run();\n}</pre>\n<table><tr><td>x</td><td>1</td></tr><tr><td>long</td><td>2</td></tr></table><p>Done.</p>";
+
+        let content = App::process_html_content(test_content, &regex);
+
+        assert_eq!(
+            content,
+            "Listing:\n```\nif a  && b {\n    run();\n}\n```\n```\nx    | 1\nlong | 2\n```\n    Done."
+        );
+    }
+
+    #[test]
+    fn test_book_progress_weights_parts_by_length() {
+        let sizes = [0, 100, 300, 600];
+        assert_eq!(book_progress(&sizes, 0, 100), 0);
+        assert_eq!(book_progress(&sizes, 2, 0), 10);
+        assert_eq!(book_progress(&sizes, 2, 50), 25);
+        assert_eq!(book_progress(&sizes, 3, 100), 100);
+        assert_eq!(book_progress(&[], 0, 50), 0);
+    }
+}
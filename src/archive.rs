@@ -0,0 +1,130 @@
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use log::info;
+use zip::ZipArchive;
+
+/// Whether `path` is a `.zip` bundle or a gzip-compressed `.epub.gz`.
+pub fn is_archive(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+    name.ends_with(".zip") || name.ends_with(".epub.gz")
+}
+
+/// The books in the archive at `path`, unpacked into their own directory
+/// under `dir`, in the folders they have in the archive. Books unpacked
+/// before are only unpacked again when the archive changed.
+pub fn unpack(path: &Path, dir: &Path) -> Result<Vec<PathBuf>> {
+    let target = dir.join(archive_key(path));
+    std::fs::create_dir_all(&target).with_context(|| format!("Failed to create {:?}", target))?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    if name.to_lowercase().ends_with(".gz") {
+        let book = target.join(&name[..name.len() - 3]);
+        if !is_fresh(&book, path) {
+            let mut decoder = GzDecoder::new(File::open(path).with_context(|| format!("Failed to open {:?}", path))?);
+            write_book(&book, &mut decoder)?;
+        }
+        return Ok(vec![book]);
+    }
+
+    let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let mut zip = ZipArchive::new(file).with_context(|| format!("Failed to read the archive {:?}", path))?;
+    let mut books = Vec::new();
+    for index in 0..zip.len() {
+        let mut entry = zip.by_index(index)?;
+        // Names climbing out of the archive are skipped, so nothing lands
+        // outside `target`
+        let Some(relative) = entry.enclosed_name() else {
+            continue;
+        };
+        let is_epub = relative.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("epub"));
+        if !entry.is_file() || relative.starts_with("__MACOSX") || !is_epub {
+            continue;
+        }
+        let book = target.join(relative);
+        if !is_fresh(&book, path) {
+            write_book(&book, &mut entry)?;
+        }
+        books.push(book);
+    }
+    Ok(books)
+}
+
+/// A directory name for `archive` that no other archive shares: its full
+/// path with the separators swapped out.
+fn archive_key(archive: &Path) -> String {
+    let full = archive.canonicalize().unwrap_or_else(|_| archive.to_path_buf());
+    full.to_string_lossy()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || "-_.".contains(c) { c } else { '_' })
+        .collect::<String>()
+        .trim_matches('_')
+        .to_string()
+}
+
+/// Whether `book` was unpacked since `archive` last changed.
+fn is_fresh(book: &Path, archive: &Path) -> bool {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    matches!((modified(book), modified(archive)), (Some(unpacked), Some(changed)) if unpacked >= changed)
+}
+
+/// Writes `book` from `reader` through a partial file, so an interrupted
+/// unpacking doesn't leave a broken book behind.
+fn write_book(book: &Path, reader: &mut impl io::Read) -> Result<()> {
+    if let Some(folder) = book.parent() {
+        std::fs::create_dir_all(folder).with_context(|| format!("Failed to create {:?}", folder))?;
+    }
+    let partial = book.with_extension("partial");
+    let mut out = File::create(&partial).with_context(|| format!("Failed to create {:?}", partial))?;
+    io::copy(reader, &mut out).with_context(|| format!("Failed to unpack {:?}", book))?;
+    std::fs::rename(&partial, book).with_context(|| format!("Failed to move the unpacked book to {:?}", book))?;
+    info!("Unpacked {:?}", book);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_books_are_unpacked_from_zip_and_gzip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let bundle = dir.path().join("bundle.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&bundle).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        for name in ["books/war.epub", "readme.txt", "__MACOSX/books/._war.epub", "peace.EPUB", "drafts/war.epub", "../escape.epub"] {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(name.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        let compressed = dir.path().join("novel.epub.gz");
+        let mut encoder = flate2::write::GzEncoder::new(File::create(&compressed).unwrap(), flate2::Compression::default());
+        encoder.write_all(b"novel").unwrap();
+        encoder.finish().unwrap();
+
+        assert!(is_archive(&bundle) && is_archive(&compressed));
+        assert!(!is_archive(Path::new("war.epub")));
+
+        let cache = dir.path().join("cache");
+        let books = unpack(&bundle, &cache).unwrap();
+        let target = books[1].parent().unwrap();
+        let names: Vec<_> = books.iter().map(|book| book.strip_prefix(target).unwrap().to_string_lossy().to_string()).collect();
+        assert_eq!(names, vec!["books/war.epub", "peace.EPUB", "drafts/war.epub"]);
+        assert_eq!(std::fs::read(&books[0]).unwrap(), b"books/war.epub");
+        assert_eq!(std::fs::read(&books[2]).unwrap(), b"drafts/war.epub");
+        assert!(!cache.join("escape.epub").exists());
+
+        let novel = unpack(&compressed, &cache).unwrap();
+        assert_eq!(novel[0].file_name().unwrap(), "novel.epub");
+        assert_eq!(std::fs::read(&novel[0]).unwrap(), b"novel");
+        assert_ne!(novel[0].parent(), books[0].parent());
+
+        // Unpacked books are kept until the archive changes
+        std::fs::write(&books[0], "kept").unwrap();
+        unpack(&bundle, &cache).unwrap();
+        assert_eq!(std::fs::read(&books[0]).unwrap(), b"kept");
+    }
+}
@@ -0,0 +1,138 @@
+use ratatui::text::{Line, Span};
+
+/// Columns a letter takes in large print, the gap after it included.
+pub const GLYPH_WIDTH: usize = 4;
+/// Rows a line of text takes in large print. Letters are five pixels tall,
+/// two to a row with half blocks; the last half row keeps lines apart.
+pub const GLYPH_HEIGHT: usize = 3;
+
+/// A 3x5 pixel font of capitals, digits and common punctuation, `#` for
+/// a lit pixel.
+const FONT: &[(char, [&str; 5])] = &[
+    ('A', [".#.", "#.#", "###", "#.#", "#.#"]),
+    ('B', ["##.", "#.#", "##.", "#.#", "##."]),
+    ('C', [".##", "#..", "#..", "#..", ".##"]),
+    ('D', ["##.", "#.#", "#.#", "#.#", "##."]),
+    ('E', ["###", "#..", "##.", "#..", "###"]),
+    ('F', ["###", "#..", "##.", "#..", "#.."]),
+    ('G', [".##", "#..", "#.#", "#.#", ".##"]),
+    ('H', ["#.#", "#.#", "###", "#.#", "#.#"]),
+    ('I', ["###", ".#.", ".#.", ".#.", "###"]),
+    ('J', ["..#", "..#", "..#", "#.#", ".#."]),
+    ('K', ["#.#", "#.#", "##.", "#.#", "#.#"]),
+    ('L', ["#..", "#..", "#..", "#..", "###"]),
+    ('M', ["#.#", "###", "###", "#.#", "#.#"]),
+    ('N', ["##.", "#.#", "#.#", "#.#", "#.#"]),
+    ('O', [".#.", "#.#", "#.#", "#.#", ".#."]),
+    ('P', ["##.", "#.#", "##.", "#..", "#.."]),
+    ('Q', [".#.", "#.#", "#.#", "##.", ".##"]),
+    ('R', ["##.", "#.#", "##.", "#.#", "#.#"]),
+    ('S', [".##", "#..", ".#.", "..#", "##."]),
+    ('T', ["###", ".#.", ".#.", ".#.", ".#."]),
+    ('U', ["#.#", "#.#", "#.#", "#.#", "###"]),
+    ('V', ["#.#", "#.#", "#.#", "#.#", ".#."]),
+    ('W', ["#.#", "#.#", "###", "###", "#.#"]),
+    ('X', ["#.#", "#.#", ".#.", "#.#", "#.#"]),
+    ('Y', ["#.#", "#.#", ".#.", ".#.", ".#."]),
+    ('Z', ["###", "..#", ".#.", "#..", "###"]),
+    ('0', ["###", "#.#", "#.#", "#.#", "###"]),
+    ('1', [".#.", "##.", ".#.", ".#.", "###"]),
+    ('2', ["##.", "..#", ".#.", "#..", "###"]),
+    ('3', ["##.", "..#", ".#.", "..#", "##."]),
+    ('4', ["#.#", "#.#", "###", "..#", "..#"]),
+    ('5', ["###", "#..", "##.", "..#", "##."]),
+    ('6', [".##", "#..", "###", "#.#", "###"]),
+    ('7', ["###", "..#", ".#.", ".#.", ".#."]),
+    ('8', ["###", "#.#", "###", "#.#", "###"]),
+    ('9', ["###", "#.#", "###", "..#", "##."]),
+    ('.', ["...", "...", "...", "...", ".#."]),
+    (',', ["...", "...", "...", ".#.", "#.."]),
+    (';', ["...", ".#.", "...", ".#.", "#.."]),
+    (':', ["...", ".#.", "...", ".#.", "..."]),
+    ('!', [".#.", ".#.", ".#.", "...", ".#."]),
+    ('?', ["##.", "..#", ".#.", "...", ".#."]),
+    ('\'', [".#.", ".#.", "...", "...", "..."]),
+    ('"', ["#.#", "#.#", "...", "...", "..."]),
+    ('-', ["...", "...", "###", "...", "..."]),
+    ('(', ["..#", ".#.", ".#.", ".#.", "..#"]),
+    (')', ["#..", ".#.", ".#.", ".#.", "#.."]),
+    ('/', ["..#", "..#", ".#.", "#..", "#.."]),
+    ('&', [".#.", "#.#", ".#.", "#.#", ".##"]),
+    (' ', ["...", "...", "...", "...", "..."]),
+];
+
+/// Drawn for letters the font doesn't have.
+const MISSING: [&str; 5] = ["#.#", ".#.", "#.#", ".#.", "#.#"];
+
+/// The letter of the font `c` is drawn with: capitals for small letters,
+/// the plain letter for an accented one and ASCII for typographic marks.
+fn font_char(c: char) -> char {
+    let plain = match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' => 'a',
+        'è' | 'é' | 'ê' | 'ë' | 'È' | 'É' | 'Ê' | 'Ë' => 'e',
+        'ì' | 'í' | 'î' | 'ï' | 'Ì' | 'Í' | 'Î' | 'Ï' => 'i',
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' => 'o',
+        'ù' | 'ú' | 'û' | 'ü' | 'Ù' | 'Ú' | 'Û' | 'Ü' => 'u',
+        'ç' | 'Ç' => 'c',
+        'ñ' | 'Ñ' => 'n',
+        '\u{2018}' | '\u{2019}' => '\'',
+        '\u{201C}' | '\u{201D}' | '«' | '»' => '"',
+        '\u{2013}' | '\u{2014}' | '\u{00AD}' => '-',
+        '\u{2026}' => '.',
+        '\u{00A0}' | '\t' => ' ',
+        c => c,
+    };
+    plain.to_ascii_uppercase()
+}
+
+fn glyph(c: char) -> &'static [&'static str; 5] {
+    let c = font_char(c);
+    FONT.iter().find(|(letter, _)| *letter == c).map_or(&MISSING, |(_, glyph)| glyph)
+}
+
+/// `line` drawn in big letters, `GLYPH_HEIGHT` rows of `GLYPH_WIDTH`
+/// columns a letter, each keeping the style of its span.
+pub fn enlarge(line: &Line) -> Vec<Line<'static>> {
+    let mut rows: Vec<Vec<Span<'static>>> = vec![Vec::new(); GLYPH_HEIGHT];
+    for span in &line.spans {
+        let glyphs: Vec<_> = span.content.chars().map(glyph).collect();
+        for (row, spans) in rows.iter_mut().enumerate() {
+            let text: String = glyphs
+                .iter()
+                .flat_map(|glyph| {
+                    let top = glyph[row * 2].chars();
+                    let bottom = glyph.get(row * 2 + 1).copied().unwrap_or("...").chars();
+                    top.zip(bottom).map(half_block).chain([' '])
+                })
+                .collect();
+            spans.push(Span::styled(text, span.style));
+        }
+    }
+    rows.into_iter().map(Line::from).collect()
+}
+
+/// The cell showing two pixels, one above the other.
+fn half_block(pixels: (char, char)) -> char {
+    match pixels {
+        ('#', '#') => '█',
+        ('#', _) => '▀',
+        (_, '#') => '▄',
+        _ => ' ',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_letters_are_drawn_with_half_blocks() {
+        let rows: Vec<String> = enlarge(&Line::from("Hi!")).iter().map(|row| row.to_string()).collect();
+        assert_eq!(rows, vec!["█ █ ▀█▀  █  ", "█▀█  █   ▀  ", "▀ ▀ ▀▀▀  ▀  "]);
+        assert_eq!(rows[0].chars().count(), 3 * GLYPH_WIDTH);
+
+        assert_eq!(glyph('é'), glyph('E'));
+        assert_eq!(glyph('\u{2019}'), glyph('\''));
+        assert_eq!(glyph('ж'), &MISSING);
+    }
+}
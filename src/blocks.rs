@@ -0,0 +1,194 @@
+use unicode_width::UnicodeWidthStr;
+
+use crate::sanitize::decode_entities;
+
+/// Line that opens and closes a preformatted block in processed text.
+/// Lines in between are shown as they are: never wrapped, without markup.
+pub const FENCE: &str = "```";
+
+/// Marks where a block was taken out of the HTML; a private-use character
+/// that no regex pass of the formatter touches.
+const PLACEHOLDER: char = '\u{E000}';
+
+/// Takes `<pre>` and `<table>` elements out of sanitized `html`, which the
+/// prose formatter would reflow, and lays each out as fixed lines. Every
+/// block is replaced by a paragraph holding a placeholder that `restore`
+/// swaps back once the rest is formatted.
+pub fn extract(html: &str) -> (String, Vec<String>) {
+    let mut out = String::with_capacity(html.len());
+    let mut blocks = Vec::new();
+    let mut rest = html;
+    loop {
+        let next = ["<pre>", "<table>"]
+            .iter()
+            .filter_map(|tag| rest.find(tag).map(|at| (at, *tag)))
+            .min();
+        let Some((at, tag)) = next else {
+            break;
+        };
+        let closing = if tag == "<pre>" { "</pre>" } else { "</table>" };
+        let inner_start = at + tag.len();
+        let (inner, after) = match rest[inner_start..].find(closing) {
+            Some(end) => (&rest[inner_start..inner_start + end], inner_start + end + closing.len()),
+            None => (&rest[inner_start..], rest.len()),
+        };
+        out.push_str(&rest[..at]);
+        let lines = if tag == "<pre>" { preformatted(inner) } else { table(inner) };
+        if !lines.is_empty() {
+            out.push_str(&format!("<p>{}{}{}</p>", PLACEHOLDER, blocks.len(), PLACEHOLDER));
+            blocks.push(lines.join("\n"));
+        }
+        rest = &rest[after..];
+    }
+    out.push_str(rest);
+    (out, blocks)
+}
+
+/// Puts the blocks taken out by `extract` back into formatted `text`,
+/// each between fences on lines of its own.
+pub fn restore(text: &str, blocks: &[String]) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut parts = text.split(PLACEHOLDER);
+    out.push_str(parts.next().unwrap_or_default());
+    while let (Some(index), Some(after)) = (parts.next(), parts.next()) {
+        let block = index.parse::<usize>().ok().and_then(|i| blocks.get(i));
+        // The placeholder takes the place of a paragraph and keeps its indent
+        let trimmed = out.trim_end_matches(' ').len();
+        out.truncate(trimmed);
+        if let Some(block) = block {
+            if !out.is_empty() && !out.ends_with('\n') {
+                out.push('\n');
+            }
+            out.push_str(&format!("{}\n{}\n{}", FENCE, block, FENCE));
+            if !after.is_empty() && !after.starts_with('\n') {
+                out.push('\n');
+            }
+        }
+        out.push_str(after);
+    }
+    out
+}
+
+/// Splits processed text into runs of lines: `(true, lines)` for the
+/// inside of a fenced block, `(false, lines)` for the prose between them.
+/// The fences themselves are dropped.
+pub fn split_blocks(text: &str) -> Vec<(bool, Vec<&str>)> {
+    let mut segments: Vec<(bool, Vec<&str>)> = Vec::new();
+    let mut in_block = false;
+    for line in text.lines() {
+        if line == FENCE {
+            in_block = !in_block;
+            segments.push((in_block, Vec::new()));
+            continue;
+        }
+        match segments.last_mut() {
+            Some((block, lines)) if *block == in_block => lines.push(line),
+            _ => segments.push((in_block, vec![line])),
+        }
+    }
+    segments.retain(|(_, lines)| !lines.is_empty());
+    segments
+}
+
+/// Text of a `<pre>` element with its line breaks and spacing kept.
+fn preformatted(inner: &str) -> Vec<String> {
+    let text = inner.replace("<br>", "\n");
+    let text = strip_tags(&text);
+    let text = decode_entities(&text).replace('\t', "    ").replace('\r', "");
+    let mut lines: Vec<String> = text.lines().map(|line| line.trim_end().to_string()).collect();
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    let leading = lines.iter().take_while(|line| line.is_empty()).count();
+    lines.drain(..leading);
+    lines
+}
+
+/// Rows of a `<table>` with the cells of each column padded to the same
+/// width. A first row of header cells is underlined.
+fn table(inner: &str) -> Vec<String> {
+    let mut rows: Vec<(bool, Vec<String>)> = Vec::new();
+    for row in inner.split("<tr>").skip(1) {
+        let header = row.contains("<th>");
+        let cells: Vec<String> = row
+            .split("<td>")
+            .flat_map(|piece| piece.split("<th>"))
+            .skip(1)
+            .map(|cell| {
+                let text = decode_entities(&strip_tags(cell));
+                text.split_whitespace().collect::<Vec<_>>().join(" ")
+            })
+            .collect();
+        if !cells.is_empty() {
+            rows.push((header, cells));
+        }
+    }
+
+    let columns = rows.iter().map(|(_, cells)| cells.len()).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|i| rows.iter().filter_map(|(_, cells)| cells.get(i)).map(|cell| cell.width()).max().unwrap_or(0))
+        .collect();
+    let mut lines = Vec::new();
+    for (i, (header, cells)) in rows.iter().enumerate() {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{}{}", cell, " ".repeat(width - cell.width())))
+            .collect();
+        lines.push(line.join(" | ").trim_end().to_string());
+        if i == 0 && *header {
+            let rule: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+            lines.push(rule.join("-+-"));
+        }
+    }
+    lines
+}
+
+/// `html` with every tag removed.
+pub fn strip_tags(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preformatted_keeps_spacing() {
+        let (html, blocks) = extract("<p>Before</p><pre>\nfn main() {\n\tlet x = 1 &lt; 2;\n}\n</pre><p>After</p>");
+        assert_eq!(blocks, vec!["fn main() {\n    let x = 1 < 2;\n}".to_string()]);
+        assert_eq!(html, format!("<p>Before</p><p>{0}0{0}</p><p>After</p>", PLACEHOLDER));
+        assert_eq!(
+            restore(&format!("Before\n    {0}0{0}\n    After", PLACEHOLDER), &blocks),
+            "Before\n```\nfn main() {\n    let x = 1 < 2;\n}\n```\n    After"
+        );
+        assert_eq!(restore(&format!("{0}0{0}tail", PLACEHOLDER), &blocks), format!("```\n{}\n```\ntail", blocks[0]));
+    }
+
+    #[test]
+    fn test_split_blocks() {
+        let text = "Intro\n```\n  code _x_\n```\n    Outro\nmore";
+        assert_eq!(
+            split_blocks(text),
+            vec![(false, vec!["Intro"]), (true, vec!["  code _x_"]), (false, vec!["    Outro", "more"])]
+        );
+    }
+
+    #[test]
+    fn test_table_columns_line_up() {
+        let html = "<table><tr><th>Name</th><th>Born</th></tr>\n<tr><td>Leo <em>Tolstoy</em></td><td>1828</td></tr><tr><td>Gogol</td></tr></table>";
+        let (_, blocks) = extract(html);
+        assert_eq!(blocks, vec!["Name        | Born\n------------+-----\nLeo Tolstoy | 1828\nGogol".to_string()]);
+        assert_eq!(extract("<table></table>x"), ("x".to_string(), Vec::new()));
+    }
+}
@@ -0,0 +1,58 @@
+use crate::blocks::strip_tags;
+use crate::book::text_length;
+
+/// Characters a part may hold at most to be taken for publisher pages;
+/// chapters are longer.
+const MAX_CHARS: usize = 2000;
+
+/// Phrases found on watermark, about-the-publisher and advertisement
+/// pages, in lowercase.
+const PHRASES: &[&str] = &[
+    "about the publisher",
+    "also available from",
+    "also by ",
+    "also available as an ebook",
+    "sign up for our newsletter",
+    "join our mailing list",
+    "for more great books",
+    "discover more books",
+    "more from this publisher",
+    "this ebook is licensed",
+    "this e-book is licensed",
+    "this ebook was purchased",
+    "watermarked",
+    "thank you for purchasing",
+    "thank you for buying",
+    "visit us at",
+    "visit our website",
+    "follow us on",
+    "all rights reserved",
+];
+
+/// Whether the part `html` looks like a page the publisher added rather
+/// than the book's text: short, and holding one of the usual phrases of
+/// such pages.
+pub fn is_boilerplate(html: &str) -> bool {
+    if text_length(html) > MAX_CHARS {
+        return false;
+    }
+    let text = strip_tags(html).to_lowercase();
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    PHRASES.iter().any(|phrase| text.contains(phrase))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_publisher_pages_are_told_from_chapters() {
+        assert!(is_boilerplate("<h1>About the Publisher</h1><p>We print books.</p>"));
+        assert!(is_boilerplate("<p>This ebook is\n licensed to jane@example.com.</p>"));
+        assert!(is_boilerplate("<p>Copyright 2020. All rights reserved.</p>"));
+        assert!(!is_boilerplate("<h1>Chapter One</h1><p>It was a cold morning.</p>"));
+
+        let chapter = format!("<p>{}</p><p>Visit us at the old mill, she said.</p>", "Words and more words. ".repeat(150));
+        assert!(!is_boilerplate(&chapter));
+    }
+}
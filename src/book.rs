@@ -0,0 +1,435 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::File,
+    io::{BufReader, Read, Seek},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use epub::doc::{EpubDoc, NavPoint};
+use zip::ZipArchive;
+
+use crate::blocks::strip_tags;
+use crate::boilerplate;
+use crate::sanitize::decode_entities;
+
+pub type Epub = EpubDoc<BufReader<File>>;
+
+/// Path standing for text read from standard input. Nothing is kept for
+/// it across runs: no bookmark, history or slots.
+pub const STDIN: &str = "<stdin>";
+
+/// An open book. Normally a parsed EPUB; when the package document is
+/// missing or unparsable, the content documents found in the zip are read
+/// directly, in file name order, so the salvageable text is still readable.
+/// A document is HTML or text that never was a file, shown as one part.
+pub enum Book {
+    Epub(Box<Epub>),
+    Salvaged(SalvagedBook),
+    Document(Document),
+}
+
+pub struct Document {
+    title: String,
+    html: String,
+}
+
+impl Document {
+    /// Wraps `input`, either HTML or plain text, for reading. Text gets a
+    /// paragraph per blank-line separated block, or per line when it has
+    /// no blank lines.
+    pub fn new(title: &str, input: &str) -> Self {
+        let html = if looks_like_html(input) { input.to_string() } else { text_to_html(input) };
+        Self {
+            title: title.to_string(),
+            html,
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+}
+
+fn looks_like_html(input: &str) -> bool {
+    let lower = input.to_ascii_lowercase();
+    ["<html", "<body", "<p>", "<p ", "<div", "<br", "<h1", "<h2"].iter().any(|tag| lower.contains(tag))
+}
+
+fn text_to_html(text: &str) -> String {
+    let text = text.replace("\r\n", "\n");
+    let escape = |s: &str| s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    let paragraphs: Vec<String> = if text.contains("\n\n") {
+        text.split("\n\n").map(|block| block.split_whitespace().collect::<Vec<_>>().join(" ")).collect()
+    } else {
+        text.lines().map(|line| line.trim().to_string()).collect()
+    };
+    paragraphs
+        .iter()
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| format!("<p>{}</p>", escape(paragraph)))
+        .collect()
+}
+
+pub struct SalvagedBook {
+    archive: ZipArchive<BufReader<File>>,
+    entries: Vec<String>,
+    current: usize,
+}
+
+impl SalvagedBook {
+    /// Opens `path` as a plain zip and collects its (X)HTML entries.
+    /// Fails if the zip itself is unreadable or holds no HTML.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+        let archive = ZipArchive::new(BufReader::new(file)).context("The zip archive is damaged")?;
+        let mut entries: Vec<String> = archive
+            .file_names()
+            .filter(|name| is_content_document(name))
+            .map(String::from)
+            .collect();
+        if entries.is_empty() {
+            anyhow::bail!("The archive contains no HTML content documents");
+        }
+        entries.sort();
+        Ok(Self {
+            archive,
+            entries,
+            current: 0,
+        })
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    fn read_entry(&mut self, name: &str) -> Option<String> {
+        let mut entry = self.archive.by_name(name).ok()?;
+        let mut content = String::new();
+        entry.read_to_string(&mut content).ok()?;
+        Some(content)
+    }
+}
+
+pub fn is_content_document(name: &str) -> bool {
+    let lower = name.to_ascii_lowercase();
+    lower.ends_with(".xhtml") || lower.ends_with(".html") || lower.ends_with(".htm")
+}
+
+/// Characters of `html` outside of tags, a cheap measure of how much text
+/// a part holds.
+pub fn text_length(html: &str) -> usize {
+    let mut in_tag = false;
+    html.chars()
+        .filter(|&c| match c {
+            '<' => {
+                in_tag = true;
+                false
+            }
+            '>' => {
+                in_tag = false;
+                false
+            }
+            c => !in_tag && !c.is_whitespace(),
+        })
+        .count()
+}
+
+/// `Book::part_sizes` of the EPUB at `path`, without keeping it open;
+/// empty when it can't be read.
+pub fn part_sizes_of(path: &str) -> Vec<usize> {
+    match EpubDoc::new(path) {
+        Ok(doc) => Book::Epub(Box::new(doc)).part_sizes(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// The cover image of the EPUB at `path`, as stored in the book; `None`
+/// when it has none or can't be read.
+pub fn cover_of(path: &str) -> Option<Vec<u8>> {
+    EpubDoc::new(path).ok()?.get_cover().map(|(image, _mime)| image)
+}
+
+/// The `dc:description` of the EPUB at `path` as written, HTML or plain
+/// text; `None` when it has none or can't be read.
+pub fn description_of(path: &str) -> Option<String> {
+    let doc = EpubDoc::new(path).ok()?;
+    doc.metadata.iter().find(|item| item.property == "description").map(|item| item.value.clone())
+}
+
+/// Byte offset in `html` of the element with the id (or, in older books,
+/// the anchor name) `fragment`.
+pub fn anchor_position(html: &str, fragment: &str) -> Option<usize> {
+    ["id", "name"]
+        .iter()
+        .flat_map(|attribute| [format!("{}=\"{}\"", attribute, fragment), format!("{}='{}'", attribute, fragment)])
+        .filter_map(|anchor| html.find(&anchor))
+        .min()
+}
+
+/// Share of the text of `html` before the element `fragment`, or `None`
+/// when there is no such element.
+pub fn anchor_fraction(html: &str, fragment: &str) -> Option<f64> {
+    let at = anchor_position(html, fragment)?;
+    let total = text_length(html);
+    if total == 0 {
+        return Some(0.0);
+    }
+    Some(text_length(&html[..at]) as f64 / total as f64)
+}
+
+/// Characters a part needs to count as body text rather than a cover,
+/// title page or table of contents.
+const BODY_MIN_CHARS: usize = 400;
+
+/// Raw (X)HTML of the first part of the EPUB at `path` holding body text:
+/// the first one in reading order long enough, or the longest if none is.
+/// `None` when the book can't be read.
+pub fn first_body_part(path: &str) -> Option<String> {
+    let mut doc = EpubDoc::new(path).ok()?;
+    let ids: Vec<String> = doc.spine.iter().filter(|item| item.linear).map(|item| item.idref.clone()).collect();
+    let mut longest: Option<(usize, String)> = None;
+    for id in ids {
+        let Some((content, _mime)) = doc.get_resource_str(&id) else {
+            continue;
+        };
+        let length = text_length(&content);
+        if length >= BODY_MIN_CHARS {
+            return Some(content);
+        }
+        if longest.as_ref().is_none_or(|(longest, _)| length > *longest) {
+            longest = Some((length, content));
+        }
+    }
+    longest.map(|(_, content)| content)
+}
+
+/// Titles of the spine items that the table of contents points at, by
+/// 0-based spine index. Where several entries point into the same item the
+/// first one wins.
+pub fn chapter_titles<R: Read + Seek>(doc: &EpubDoc<R>) -> HashMap<usize, String> {
+    fn collect<R: Read + Seek>(doc: &EpubDoc<R>, points: &[NavPoint], titles: &mut HashMap<usize, String>) {
+        for point in points {
+            let content = point.content.to_string_lossy();
+            let without_fragment = content.split('#').next().unwrap_or_default();
+            if let Some(index) = doc.resource_uri_to_chapter(&PathBuf::from(without_fragment)) {
+                titles.entry(index).or_insert_with(|| point.label.trim().to_string());
+            }
+            collect(doc, &point.children, titles);
+        }
+    }
+
+    let mut titles = HashMap::new();
+    collect(doc, &doc.toc, &mut titles);
+    titles
+}
+
+/// Text of the first `<h1>` of `html`, or of the first `<h2>` when there
+/// is no `<h1>`, with the markup stripped and whitespace collapsed.
+pub fn first_heading(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    ["h1", "h2"].iter().find_map(|tag| {
+        let open = format!("<{}", tag);
+        let close = format!("</{}", tag);
+        let mut from = 0;
+        while let Some(at) = lower[from..].find(&open) {
+            let start = from + at + open.len();
+            from = start;
+            if !lower[start..].starts_with(|c: char| c == '>' || c.is_whitespace()) {
+                continue;
+            }
+            let inner = start + lower[start..].find('>')? + 1;
+            let end = inner + lower[inner..].find(&close)?;
+            let text = decode_entities(&strip_tags(&html[inner..end]));
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !text.is_empty() {
+                return Some(text);
+            }
+        }
+        None
+    })
+}
+
+/// Titles by 0-based spine index for a book whose table of contents is
+/// missing or points at none of its parts: the first heading of every part
+/// that has one.
+pub fn heading_titles<R: Read + Seek>(doc: &mut EpubDoc<R>) -> HashMap<usize, String> {
+    let ids: Vec<String> = doc.spine.iter().map(|item| item.idref.clone()).collect();
+    ids.iter()
+        .enumerate()
+        .filter_map(|(i, id)| {
+            let (content, _mime) = doc.get_resource_str(id)?;
+            first_heading(&content).map(|title| (i, title))
+        })
+        .collect()
+}
+
+/// Titles from the table of contents, or from the headings of the parts
+/// when the table of contents is of no use.
+pub fn toc_titles<R: Read + Seek>(doc: &mut EpubDoc<R>) -> HashMap<usize, String> {
+    let titles = chapter_titles(doc);
+    if titles.is_empty() {
+        let titles = heading_titles(doc);
+        log::info!("No usable table of contents, found {} headings", titles.len());
+        titles
+    } else {
+        titles
+    }
+}
+
+impl Book {
+    pub fn as_epub(&self) -> Option<&Epub> {
+        match self {
+            Book::Epub(doc) => Some(doc),
+            Book::Salvaged(_) | Book::Document(_) => None,
+        }
+    }
+
+    pub fn as_epub_mut(&mut self) -> Option<&mut Epub> {
+        match self {
+            Book::Epub(doc) => Some(doc),
+            Book::Salvaged(_) | Book::Document(_) => None,
+        }
+    }
+
+    pub fn num_chapters(&self) -> usize {
+        match self {
+            Book::Epub(doc) => doc.get_num_chapters(),
+            Book::Salvaged(book) => book.entries.len(),
+            Book::Document(_) => 1,
+        }
+    }
+
+    pub fn current_chapter(&self) -> usize {
+        match self {
+            Book::Epub(doc) => doc.get_current_chapter(),
+            Book::Salvaged(book) => book.current,
+            Book::Document(_) => 0,
+        }
+    }
+
+    pub fn set_current_chapter(&mut self, n: usize) -> bool {
+        match self {
+            Book::Epub(doc) => doc.set_current_chapter(n),
+            Book::Salvaged(book) => {
+                if n < book.entries.len() {
+                    book.current = n;
+                    true
+                } else {
+                    false
+                }
+            }
+            Book::Document(_) => n == 0,
+        }
+    }
+
+    pub fn go_next(&mut self) -> bool {
+        match self {
+            Book::Epub(doc) => doc.go_next(),
+            Book::Salvaged(_) => {
+                let next = self.current_chapter() + 1;
+                self.set_current_chapter(next)
+            }
+            Book::Document(_) => false,
+        }
+    }
+
+    pub fn go_prev(&mut self) -> bool {
+        match self {
+            Book::Epub(doc) => doc.go_prev(),
+            Book::Salvaged(book) => {
+                if book.current > 0 {
+                    book.current -= 1;
+                    true
+                } else {
+                    false
+                }
+            }
+            Book::Document(_) => false,
+        }
+    }
+
+    /// A title for every part: the table of contents entry, or "Part N"
+    /// when there is none. Without a usable table of contents the first
+    /// heading of a part is used instead, falling back to the file name for
+    /// salvaged books.
+    pub fn part_titles(&mut self) -> Vec<String> {
+        match self {
+            Book::Epub(doc) => {
+                let titles = toc_titles(doc);
+                (0..doc.get_num_chapters())
+                    .map(|i| titles.get(&i).cloned().unwrap_or_else(|| format!("Part {}", i + 1)))
+                    .collect()
+            }
+            Book::Salvaged(book) => {
+                let names = book.entries.clone();
+                names
+                    .iter()
+                    .map(|name| book.read_entry(name).and_then(|content| first_heading(&content)).unwrap_or_else(|| name.clone()))
+                    .collect()
+            }
+            Book::Document(document) => vec![first_heading(&document.html).unwrap_or_else(|| document.title.clone())],
+        }
+    }
+
+    /// Length of the text of every part, in characters outside of tags.
+    /// Parts are read one at a time, so only one is ever held in memory.
+    pub fn part_sizes(&mut self) -> Vec<usize> {
+        match self {
+            Book::Epub(doc) => {
+                let ids: Vec<String> = doc.spine.iter().map(|item| item.idref.clone()).collect();
+                ids.iter()
+                    .map(|id| doc.get_resource_str(id).map_or(0, |(content, _mime)| text_length(&content)))
+                    .collect()
+            }
+            Book::Salvaged(book) => {
+                let names = book.entries.clone();
+                names
+                    .iter()
+                    .map(|name| {
+                        let mut content = String::new();
+                        match book.archive.by_name(name) {
+                            Ok(mut entry) => entry.read_to_string(&mut content).map_or(0, |_| text_length(&content)),
+                            Err(_) => 0,
+                        }
+                    })
+                    .collect()
+            }
+            Book::Document(document) => vec![text_length(&document.html)],
+        }
+    }
+
+    /// Parts, by 0-based index, that look like pages the publisher added:
+    /// watermarks, about-the-publisher pages and advertisements. Parts
+    /// are read one at a time, like `part_sizes` does.
+    pub fn boilerplate_parts(&mut self) -> BTreeSet<usize> {
+        let contents: Box<dyn Iterator<Item = Option<String>>> = match self {
+            Book::Epub(doc) => {
+                let ids: Vec<String> = doc.spine.iter().map(|item| item.idref.clone()).collect();
+                Box::new(ids.into_iter().map(|id| doc.get_resource_str(&id).map(|(content, _mime)| content)))
+            }
+            Book::Salvaged(book) => {
+                let names = book.entries.clone();
+                Box::new(names.into_iter().map(|name| book.read_entry(&name)))
+            }
+            Book::Document(_) => Box::new(std::iter::empty()),
+        };
+        contents
+            .enumerate()
+            .filter(|(_, content)| content.as_deref().is_some_and(boilerplate::is_boilerplate))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Raw (X)HTML of the current part.
+    pub fn get_current_str(&mut self) -> Option<String> {
+        match self {
+            Book::Epub(doc) => doc.get_current_str().map(|(content, _mime)| content),
+            Book::Salvaged(book) => {
+                let name = book.entries.get(book.current)?.clone();
+                book.read_entry(&name)
+            }
+            Book::Document(document) => Some(document.html.clone()),
+        }
+    }
+}
@@ -1,61 +1,442 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Bookmark {
     pub chapter: usize,
     pub scroll_offset: usize,
     pub last_read: chrono::DateTime<chrono::Utc>,
+    /// Places saved to the number slots 1-9 of this book
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub slots: BTreeMap<u8, Slot>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub highlights: Vec<Highlight>,
+    /// Stars out of five
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub review: Option<String>,
+    /// Notes on whole parts, by 0-based spine index
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub chapter_notes: BTreeMap<usize, String>,
+    /// Shown in the dyslexia-friendly layout
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub spaced: bool,
+    /// When the book was marked finished
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished: Option<chrono::DateTime<chrono::Utc>>,
+    /// Parts, by 0-based spine index, scrolled through to their end
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub read_parts: BTreeSet<usize>,
+    /// The start of the text at the reading position
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+}
+
+impl Bookmark {
+    fn at(chapter: usize, scroll_offset: usize, last_read: chrono::DateTime<chrono::Utc>) -> Self {
+        Self {
+            chapter,
+            scroll_offset,
+            last_read,
+            slots: BTreeMap::new(),
+            highlights: Vec::new(),
+            rating: None,
+            review: None,
+            chapter_notes: BTreeMap::new(),
+            spaced: false,
+            finished: None,
+            read_parts: BTreeSet::new(),
+            snippet: None,
+        }
+    }
+}
+
+/// A passage marked in a book, with an optional note.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Highlight {
+    pub chapter: usize,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    pub created: chrono::DateTime<chrono::Utc>,
+}
+
+/// A place saved to a numbered slot, to jump back to with one key.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Slot {
+    pub chapter: usize,
+    pub scroll_offset: usize,
+    /// The start of the text there, to recognise the place by
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Bookmarks {
     books: HashMap<String, Bookmark>,
+    #[serde(skip)]
+    path: PathBuf,
+    /// Changed since the file was last written
+    #[serde(skip)]
+    dirty: bool,
+    /// Also keep each book's bookmark in a file beside the book
+    #[serde(skip)]
+    sidecars: bool,
+    /// Books whose sidecar is out of date
+    #[serde(skip)]
+    changed: HashSet<String>,
+}
+
+impl Default for Bookmarks {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Bookmarks {
     pub fn new() -> Self {
+        Self::with_path("bookmarks.json")
+    }
+
+    pub fn with_path<P: AsRef<Path>>(path: P) -> Self {
         Self {
             books: HashMap::new(),
+            path: path.as_ref().to_path_buf(),
+            dirty: false,
+            sidecars: false,
+            changed: HashSet::new(),
         }
     }
 
     pub fn load() -> anyhow::Result<Self> {
-        let path = Path::new("bookmarks.json");
+        Self::load_from("bookmarks.json")
+    }
+
+    pub fn load_from<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
         if path.exists() {
             let content = fs::read_to_string(path)?;
-            Ok(serde_json::from_str(&content)?)
+            let mut bookmarks: Self = serde_json::from_str(&content)?;
+            bookmarks.path = path.to_path_buf();
+            Ok(bookmarks)
         } else {
-            Ok(Self::new())
+            Ok(Self::with_path(path))
         }
     }
 
-    pub fn save(&self) -> anyhow::Result<()> {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn save(&mut self) -> anyhow::Result<()> {
         let content = serde_json::to_string_pretty(self)?;
-        fs::write("bookmarks.json", content)?;
+        fs::write(&self.path, content)?;
+        self.dirty = false;
+        for book in std::mem::take(&mut self.changed) {
+            // A book deleted meanwhile gets no sidecar to take its place
+            if let Some(bookmark) = self.books.get(&book).filter(|_| Path::new(&book).exists()) {
+                fs::write(sidecar_path(&book), serde_json::to_string_pretty(bookmark)?)?;
+            }
+        }
         Ok(())
     }
 
+    /// Keeps every book's position, slots and highlights in a sidecar file
+    /// beside it too, so that tools syncing the books folder carry them
+    /// along, book by book. The sidecars found for `files` are read in:
+    /// where one was read more recently than the bookmarks file knows, it
+    /// wins.
+    pub fn use_sidecars(&mut self, files: &[String]) {
+        self.sidecars = true;
+        for file in files {
+            let path = sidecar_path(file);
+            let Ok(content) = fs::read_to_string(&path) else {
+                continue;
+            };
+            match serde_json::from_str::<Bookmark>(&content) {
+                Ok(theirs) => {
+                    let merged = match self.books.remove(file) {
+                        Some(ours) => merge_bookmark(ours, theirs),
+                        None => theirs,
+                    };
+                    self.books.insert(file.clone(), merged);
+                    self.dirty = true;
+                }
+                Err(e) => log::warn!("Ignoring unreadable sidecar {:?}: {}", path, e),
+            }
+        }
+    }
+
+    pub fn uses_sidecars(&self) -> bool {
+        self.sidecars
+    }
+
+    fn touch(&mut self, path: &str) {
+        self.dirty = true;
+        if self.sidecars {
+            self.changed.insert(path.to_string());
+        }
+    }
+
+    /// Writes the file if anything changed since it was last written.
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        if self.dirty {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
     pub fn get_bookmark(&self, path: &str) -> Option<&Bookmark> {
         self.books.get(path)
     }
 
+    /// The books with a bookmark, the most recently read first.
+    pub fn recently_read(&self) -> Vec<&str> {
+        let mut books: Vec<(&String, &Bookmark)> = self.books.iter().collect();
+        books.sort_by(|(a, a_mark), (b, b_mark)| b_mark.last_read.cmp(&a_mark.last_read).then_with(|| a.cmp(b)));
+        books.into_iter().map(|(path, _)| path.as_str()).collect()
+    }
+
+    /// Moves the reading position of `path`. The change is only kept in
+    /// memory until the next `flush` or `save`.
     pub fn update_bookmark(&mut self, path: &str, chapter: usize, scroll_offset: usize) {
-        self.books.insert(
-            path.to_string(),
-            Bookmark {
-                chapter,
-                scroll_offset,
-                last_read: chrono::Utc::now(),
-            },
-        );
-        // Only try to save if we have at least one bookmark
-        if !self.books.is_empty() {
-            if let Err(e) = self.save() {
-                log::error!("Failed to save bookmark: {}", e);
+        self.set_position(path, chapter, scroll_offset, chrono::Utc::now());
+    }
+
+    /// Keeps `snippet` as the text at the reading position of `path`, in
+    /// memory like `update_bookmark`.
+    pub fn set_snippet(&mut self, path: &str, snippet: Option<String>) {
+        if let Some(bookmark) = self.books.get_mut(path).filter(|bookmark| bookmark.snippet != snippet) {
+            bookmark.snippet = snippet;
+            self.touch(path);
+        }
+    }
+
+    /// Like `update_bookmark`, for a position reached at `last_read`, such
+    /// as one read on another device.
+    pub fn set_position(&mut self, path: &str, chapter: usize, scroll_offset: usize, last_read: chrono::DateTime<chrono::Utc>) {
+        let bookmark = self.books.entry(path.to_string()).or_insert_with(|| Bookmark::at(chapter, scroll_offset, last_read));
+        bookmark.chapter = chapter;
+        bookmark.scroll_offset = scroll_offset;
+        bookmark.last_read = last_read;
+        self.touch(path);
+    }
+
+    /// Adds `highlight` to `path` unless the same passage of the same part
+    /// is highlighted already. Returns whether it was added.
+    pub fn add_highlight(&mut self, path: &str, highlight: Highlight) -> bool {
+        let bookmark = self
+            .books
+            .entry(path.to_string())
+            .or_insert_with(|| Bookmark::at(highlight.chapter, 0, highlight.created));
+        if bookmark.highlights.iter().any(|h| h.chapter == highlight.chapter && h.text == highlight.text) {
+            return false;
+        }
+        bookmark.highlights.push(highlight);
+        self.touch(path);
+        true
+    }
+
+    /// Removes highlight `index` of `path` and writes the file. Returns
+    /// whether there was one.
+    pub fn remove_highlight(&mut self, path: &str, index: usize) -> bool {
+        let Some(bookmark) = self.books.get_mut(path).filter(|bookmark| index < bookmark.highlights.len()) else {
+            return false;
+        };
+        bookmark.highlights.remove(index);
+        self.touch(path);
+        if let Err(e) = self.save() {
+            log::error!("Failed to save the bookmark: {}", e);
+        }
+        true
+    }
+
+    pub fn highlights(&self, path: &str) -> &[Highlight] {
+        self.books.get(path).map_or(&[], |bookmark| bookmark.highlights.as_slice())
+    }
+
+    /// Saves a place of `path` to number slot `slot`, replacing what it
+    /// held. The book's reading position is left as it is.
+    pub fn set_slot(&mut self, path: &str, slot: u8, chapter: usize, scroll_offset: usize, snippet: Option<String>) {
+        let bookmark = self
+            .books
+            .entry(path.to_string())
+            .or_insert_with(|| Bookmark::at(chapter, scroll_offset, chrono::Utc::now()));
+        bookmark.slots.insert(slot, Slot { chapter, scroll_offset, snippet });
+        self.touch(path);
+        if let Err(e) = self.save() {
+            log::error!("Failed to save bookmark slot: {}", e);
+        }
+    }
+
+    pub fn slot(&self, path: &str, slot: u8) -> Option<&Slot> {
+        self.books.get(path)?.slots.get(&slot)
+    }
+
+    /// The saved places of `path`, by slot number.
+    pub fn slots(&self, path: &str) -> impl Iterator<Item = (&u8, &Slot)> {
+        self.books.get(path).into_iter().flat_map(|bookmark| bookmark.slots.iter())
+    }
+
+    /// Rates `path` with 1 to 5 stars, or clears the rating with `None`,
+    /// and writes the file. Only books opened before have a bookmark to
+    /// keep it in; returns whether `path` has one.
+    pub fn set_rating(&mut self, path: &str, rating: Option<u8>) -> bool {
+        self.review_with(path, |bookmark| bookmark.rating = rating.map(|stars| stars.clamp(1, 5)))
+    }
+
+    /// Keeps `review` for `path`, or removes it with `None`, like
+    /// `set_rating`.
+    pub fn set_review(&mut self, path: &str, review: Option<String>) -> bool {
+        let review = review.map(|review| review.trim().to_string()).filter(|review| !review.is_empty());
+        self.review_with(path, |bookmark| bookmark.review = review)
+    }
+
+    /// Keeps `note` on part `chapter` of `path`, or removes it with an
+    /// empty one, like `set_rating`.
+    pub fn set_chapter_note(&mut self, path: &str, chapter: usize, note: &str) -> bool {
+        let note = note.trim().to_string();
+        self.review_with(path, |bookmark| {
+            if note.is_empty() {
+                bookmark.chapter_notes.remove(&chapter);
+            } else {
+                bookmark.chapter_notes.insert(chapter, note);
+            }
+        })
+    }
+
+    pub fn chapter_note(&self, path: &str, chapter: usize) -> Option<&str> {
+        self.books.get(path)?.chapter_notes.get(&chapter).map(String::as_str)
+    }
+
+    /// Turns the dyslexia-friendly layout of `path` on or off, like
+    /// `set_rating`.
+    pub fn set_spaced(&mut self, path: &str, spaced: bool) -> bool {
+        self.review_with(path, |bookmark| bookmark.spaced = spaced)
+    }
+
+    pub fn is_spaced(&self, path: &str) -> bool {
+        self.books.get(path).is_some_and(|bookmark| bookmark.spaced)
+    }
+
+    /// Marks `path` finished at `when`, or not finished with `None`, like
+    /// `set_rating`.
+    pub fn set_finished(&mut self, path: &str, when: Option<chrono::DateTime<chrono::Utc>>) -> bool {
+        self.review_with(path, |bookmark| bookmark.finished = when)
+    }
+
+    /// Moves the bookmark of `from` to `to`, for a book renamed or moved,
+    /// with its sidecar. Written at once, as the file already moved.
+    pub fn rename(&mut self, from: &str, to: &str) {
+        let Some(bookmark) = self.books.remove(from) else {
+            return;
+        };
+        self.books.insert(to.to_string(), bookmark);
+        self.forget_sidecar(from);
+        self.touch(to);
+        if let Err(e) = self.save() {
+            log::error!("Failed to save the bookmarks: {}", e);
+        }
+    }
+
+    /// Drops the bookmark of `path`, for a book deleted, with its sidecar.
+    pub fn remove(&mut self, path: &str) {
+        if self.books.remove(path).is_none() {
+            return;
+        }
+        self.forget_sidecar(path);
+        self.dirty = true;
+        if let Err(e) = self.save() {
+            log::error!("Failed to save the bookmarks: {}", e);
+        }
+    }
+
+    fn forget_sidecar(&mut self, book: &str) {
+        self.changed.remove(book);
+        let sidecar = sidecar_path(book);
+        if self.sidecars && sidecar.exists() {
+            if let Err(e) = fs::remove_file(&sidecar) {
+                log::warn!("Failed to remove the sidecar {:?}: {}", sidecar, e);
             }
         }
     }
-} 
\ No newline at end of file
+
+    pub fn is_finished(&self, path: &str) -> bool {
+        self.books.get(path).is_some_and(|bookmark| bookmark.finished.is_some())
+    }
+
+    /// Marks part `chapter` of `path` as read through, like `set_rating`.
+    pub fn mark_part_read(&mut self, path: &str, chapter: usize) -> bool {
+        self.review_with(path, |bookmark| {
+            bookmark.read_parts.insert(chapter);
+        })
+    }
+
+    pub fn is_part_read(&self, path: &str, chapter: usize) -> bool {
+        self.books.get(path).is_some_and(|bookmark| bookmark.read_parts.contains(&chapter))
+    }
+
+    fn review_with(&mut self, path: &str, change: impl FnOnce(&mut Bookmark)) -> bool {
+        let Some(bookmark) = self.books.get_mut(path) else {
+            return false;
+        };
+        change(bookmark);
+        self.touch(path);
+        if let Err(e) = self.save() {
+            log::error!("Failed to save the bookmark: {}", e);
+        }
+        true
+    }
+
+    /// Takes in the bookmarks of another copy of the file, such as one
+    /// written on another machine. The more recently read position of a
+    /// book wins, along with its slots; highlights are kept from both.
+    pub fn merge(&mut self, other: Bookmarks) {
+        for (path, theirs) in other.books {
+            let merged = match self.books.remove(&path) {
+                Some(ours) => merge_bookmark(ours, theirs),
+                None => theirs,
+            };
+            self.touch(&path);
+            self.books.insert(path, merged);
+        }
+        self.dirty = true;
+    }
+}
+
+/// The file beside `book` its bookmark is kept in with sidecars on:
+/// `war.bookrat.json` for `war.epub`.
+pub fn sidecar_path(book: &str) -> PathBuf {
+    Path::new(book).with_extension("bookrat.json")
+}
+
+/// One book's bookmarks from two copies: the more recently read position
+/// with its slots, rating, review, part notes and finish date, the other's filling in
+/// where it has none, and the highlights and parts read of both.
+fn merge_bookmark(ours: Bookmark, theirs: Bookmark) -> Bookmark {
+    let (mut newer, older) = if theirs.last_read > ours.last_read { (theirs, ours) } else { (ours, theirs) };
+    for (slot, place) in older.slots {
+        newer.slots.entry(slot).or_insert(place);
+    }
+    for highlight in older.highlights {
+        if !newer.highlights.iter().any(|h| h.chapter == highlight.chapter && h.text == highlight.text) {
+            newer.highlights.push(highlight);
+        }
+    }
+    newer.highlights.sort_by_key(|highlight| highlight.created);
+    for (chapter, note) in older.chapter_notes {
+        newer.chapter_notes.entry(chapter).or_insert(note);
+    }
+    newer.rating = newer.rating.or(older.rating);
+    newer.review = newer.review.or(older.review);
+    newer.finished = newer.finished.or(older.finished);
+    newer.read_parts.extend(older.read_parts);
+    newer
+}
@@ -0,0 +1,225 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::info;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::config::CalibreConfig;
+use crate::download;
+
+/// How many books one search asks for
+const PAGE_SIZE: usize = 500;
+
+/// A book in the calibre library, as its content server describes it.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RemoteBook {
+    #[serde(rename = "application_id")]
+    pub id: u64,
+    pub title: String,
+    pub authors: Vec<String>,
+    /// Formats calibre has the book in, such as `EPUB` or `PDF`
+    pub formats: Vec<String>,
+}
+
+impl RemoteBook {
+    pub fn has_epub(&self) -> bool {
+        self.formats.iter().any(|format| format.eq_ignore_ascii_case("epub"))
+    }
+}
+
+#[derive(Deserialize)]
+struct LibraryInfo {
+    default_library: String,
+}
+
+#[derive(Deserialize)]
+struct SearchResult {
+    book_ids: Vec<u64>,
+}
+
+/// Client for the JSON API of calibre's content server (`calibre-server`
+/// or "Connect/share" in the calibre app).
+pub struct CalibreClient {
+    agent: ureq::Agent,
+    base: String,
+    library: String,
+    authorization: Option<String>,
+    read_column: String,
+}
+
+impl CalibreClient {
+    /// Connects to the server in `config`, looking up its default library
+    /// when none is configured.
+    pub fn connect(config: &CalibreConfig) -> Result<Self> {
+        let Some(url) = &config.url else {
+            bail!("No calibre server configured; set calibre.url in the config or pass --url");
+        };
+        let authorization = match (&config.username, &config.password) {
+            (Some(user), password) => Some(format!(
+                "Basic {}",
+                STANDARD.encode(format!("{}:{}", user, password.as_deref().unwrap_or_default()))
+            )),
+            (None, _) => None,
+        };
+        let mut client = Self {
+            agent: ureq::Agent::new(),
+            base: url.trim_end_matches('/').to_string(),
+            library: config.library.clone().unwrap_or_default(),
+            authorization,
+            read_column: config.read_column.clone(),
+        };
+        if client.library.is_empty() {
+            let info: LibraryInfo = client.get("/ajax/library-info")?.into_json().context("Unexpected library info")?;
+            client.library = info.default_library;
+        }
+        info!("Using calibre library {} at {}", client.library, client.base);
+        Ok(client)
+    }
+
+    pub fn library(&self) -> &str {
+        &self.library
+    }
+
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        let request = self.agent.request(method, &format!("{}{}", self.base, path));
+        match &self.authorization {
+            Some(authorization) => request.set("Authorization", authorization),
+            None => request,
+        }
+    }
+
+    fn get(&self, path: &str) -> Result<ureq::Response> {
+        self.request("GET", path).call().with_context(|| format!("calibre request {} failed", path))
+    }
+
+    /// Books matching `query`, in calibre's search syntax (empty for all),
+    /// sorted by title.
+    pub fn search(&self, query: &str) -> Result<Vec<RemoteBook>> {
+        let result: SearchResult = self
+            .request("GET", &format!("/ajax/search/{}", self.library))
+            .query("query", query)
+            .query("num", &PAGE_SIZE.to_string())
+            .query("sort", "title")
+            .query("sort_order", "asc")
+            .call()
+            .context("calibre search failed")?
+            .into_json()
+            .context("Unexpected search result")?;
+        if result.book_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let ids: Vec<String> = result.book_ids.iter().map(u64::to_string).collect();
+        let books: BTreeMap<String, Option<RemoteBook>> = self
+            .request("GET", &format!("/ajax/books/{}", self.library))
+            .query("ids", &ids.join(","))
+            .call()
+            .context("Failed to fetch book metadata")?
+            .into_json()
+            .context("Unexpected book metadata")?;
+        // Keep the order of the search, which is sorted
+        Ok(result
+            .book_ids
+            .iter()
+            .filter_map(|id| books.get(&id.to_string()).cloned().flatten())
+            .collect())
+    }
+
+    /// Writes the EPUB of book `id` to `path`, replacing what is there.
+    pub fn download(&self, id: u64, path: &Path) -> Result<()> {
+        let partial = path.with_extension("part");
+        self.fetch(id, &partial, || Ok(path.to_path_buf()))?;
+        Ok(())
+    }
+
+    /// Saves the EPUB of book `id` in `dir` as `name`, or a numbered
+    /// variant of it when a book has that name already; where it went.
+    pub fn download_into(&self, id: u64, dir: &Path, name: &str) -> Result<PathBuf> {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to make the folder {:?}", dir))?;
+        let partial = dir.join(format!(".{}.part", name));
+        self.fetch(id, &partial, || Ok(download::unused_path(dir, name)))
+    }
+
+    /// Downloads book `id` to `partial`, then moves it to the path `target`
+    /// gives. The partial file never outlives a failure.
+    fn fetch(&self, id: u64, partial: &Path, target: impl FnOnce() -> Result<PathBuf>) -> Result<PathBuf> {
+        let result = (|| -> Result<PathBuf> {
+            let response = self.get(&format!("/get/EPUB/{}/{}", id, self.library))?;
+            let mut file = File::create(partial).with_context(|| format!("Failed to create {:?}", partial))?;
+            io::copy(&mut response.into_reader(), &mut file).context("The download was interrupted")?;
+            let path = target()?;
+            std::fs::rename(partial, &path).with_context(|| format!("Failed to move the download to {:?}", path))?;
+            Ok(path)
+        })();
+        match result {
+            Ok(path) => {
+                info!("Downloaded calibre book {} to {:?}", id, path);
+                Ok(path)
+            }
+            Err(e) => {
+                let _ = std::fs::remove_file(partial);
+                Err(e)
+            }
+        }
+    }
+
+    /// Sets the configured read column of book `id`. The server has to
+    /// allow changes, from this user or from local connections.
+    pub fn set_read(&self, id: u64, read: bool) -> Result<()> {
+        self.request("POST", &format!("/cdb/set-fields/{}/{}", id, self.library))
+            .send_json(json!({ "changes": { self.read_column.as_str(): read }, "loaded_book_ids": [id] }))
+            .with_context(|| format!("Failed to set {} of book {}", self.read_column, id))?;
+        info!("Set {} of calibre book {} to {}", self.read_column, id, read);
+        Ok(())
+    }
+
+    /// Where a streamed book is kept in `dir`, made if need be: outside the
+    /// library, so it isn't listed there, but at the same path every time
+    /// so its bookmark holds.
+    pub fn cache_path(&self, dir: &Path, id: u64) -> Result<PathBuf> {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to make the folder {:?}", dir))?;
+        Ok(dir.join(format!("{}-{}.epub", sanitize(&self.library), id)))
+    }
+}
+
+/// File name for `book` in the library: `Title - Author.epub`.
+pub fn file_name(book: &RemoteBook) -> String {
+    let name = match book.authors.first() {
+        Some(author) => format!("{} - {}", book.title, author),
+        None => book.title.clone(),
+    };
+    format!("{}.epub", sanitize(&name))
+}
+
+fn sanitize(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || " -_,.'()".contains(c) { c } else { '_' })
+        .collect();
+    let name = name.trim_matches(['.', ' ']);
+    if name.is_empty() { "book".to_string() } else { name.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_and_file_name() {
+        let book: RemoteBook = serde_json::from_str(
+            r#"{"application_id": 7, "title": "War/Peace", "authors": ["Leo Tolstoy"], "formats": ["PDF", "EPUB"], "tags": []}"#,
+        )
+        .unwrap();
+        assert_eq!(book.id, 7);
+        assert!(book.has_epub());
+        assert_eq!(file_name(&book), "War_Peace - Leo Tolstoy.epub");
+        assert_eq!(file_name(&RemoteBook::default()), "book.epub");
+    }
+}
@@ -0,0 +1,472 @@
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use epub::doc::EpubDoc;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::app::book_progress;
+use crate::blocks::strip_tags;
+use crate::book::{self, Book};
+use crate::bookmark::Bookmarks;
+use crate::language;
+use crate::reading_time::ReadingTime;
+use crate::sanitize::decode_entities;
+
+/// What the package document says about a book, as far as the library
+/// needs it for filtering.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Entry {
+    pub title: String,
+    pub authors: Vec<String>,
+    /// `dc:subject` values
+    pub tags: Vec<String>,
+    /// `dc:language`, or else the language the text is written in
+    pub language: Option<String>,
+    /// `calibre:series` or the EPUB 3 collection the book belongs to
+    pub series: Option<String>,
+    /// Position in the series, as written (`2`, `1.5`)
+    pub series_index: Option<String>,
+    /// The blurb of `dc:description`, as plain text with a line per
+    /// paragraph
+    pub description: Option<String>,
+    /// `dc:publisher`
+    pub publisher: Option<String>,
+    /// `dc:date`, the date of publication as written (`2008`,
+    /// `2008-06-01T00:00:00+00:00`)
+    pub date: Option<String>,
+    /// The ISBN as `isbn:` and its digits, or else the first
+    /// `dc:identifier` as written, telling copies of one book apart from
+    /// other books
+    pub identifier: Option<String>,
+}
+
+impl Entry {
+    /// Reads the metadata of the book at `path`. Books without a readable
+    /// package document are titled after their file name.
+    pub fn read(path: &str) -> Self {
+        match EpubDoc::new(path) {
+            Ok(doc) => Self::from_doc(&doc, path).detect_language(path),
+            Err(e) => {
+                warn!("No metadata for {}: {}", path, e);
+                Self {
+                    title: file_stem(path),
+                    ..Default::default()
+                }
+            }
+        }
+    }
+
+    /// The metadata of `doc`, opened from `path`.
+    pub fn from_doc<R: Read + Seek>(doc: &EpubDoc<R>, path: &str) -> Self {
+        let values = |property: &str| -> Vec<String> {
+            doc.metadata
+                .iter()
+                .filter(|item| item.property == property)
+                .map(|item| item.value.trim().to_string())
+                .filter(|value| !value.is_empty())
+                .collect()
+        };
+        Self {
+            title: doc.get_title().map(|title| title.trim().to_string()).unwrap_or_else(|| file_stem(path)),
+            authors: values("creator"),
+            tags: values("subject"),
+            language: values("language").into_iter().find(|language| language::is_declared(language)),
+            series: values("calibre:series")
+                .into_iter()
+                .chain(values("belongs-to-collection"))
+                .next(),
+            series_index: values("calibre:series_index")
+                .into_iter()
+                .chain(values("group-position"))
+                .next(),
+            description: values("description").first().map(|description| plain_text(description)).filter(|text| !text.is_empty()),
+            publisher: values("publisher").into_iter().next(),
+            date: values("date").into_iter().next(),
+            identifier: identifier(&values("identifier")),
+        }
+    }
+
+    /// This entry with the language told from the text of the book at
+    /// `path` when the package document declares none.
+    pub(crate) fn detect_language(mut self, path: &str) -> Self {
+        if self.language.is_none() {
+            let text = book::first_body_part(path).map(|html| decode_entities(&strip_tags(&html)));
+            self.language = text.as_deref().and_then(language::guess).map(str::to_string);
+        }
+        self
+    }
+}
+
+/// The identifier copies of a book share among its `dc:identifier`
+/// values: an ISBN wherever it is, hyphens and an `urn:isbn:` prefix
+/// dropped, or else the first value.
+fn identifier(values: &[String]) -> Option<String> {
+    let isbn = values.iter().find_map(|value| {
+        let lower = value.to_lowercase();
+        let number = lower.strip_prefix("urn:isbn:").or_else(|| lower.strip_prefix("isbn:")).unwrap_or(&lower);
+        let digits: String = number.chars().filter(|c| !matches!(c, '-' | ' ')).collect();
+        let valid = matches!(digits.len(), 10 | 13) && digits.char_indices().all(|(i, c)| c.is_ascii_digit() || (c == 'x' && i == 9));
+        valid.then(|| format!("isbn:{}", digits))
+    });
+    isbn.or_else(|| values.first().cloned())
+}
+
+/// `html` without its markup, keeping paragraphs and line breaks as lines.
+/// Descriptions are often HTML, especially from calibre.
+fn plain_text(html: &str) -> String {
+    let mut lines = String::with_capacity(html.len());
+    for (i, piece) in html.split('<').enumerate() {
+        if i > 0 {
+            let tag = piece.to_ascii_lowercase();
+            if ["/p", "br", "/div", "/li"].iter().any(|name| tag.starts_with(name)) {
+                lines.push('\n');
+            }
+            lines.push('<');
+        }
+        lines.push_str(piece);
+    }
+    decode_entities(&strip_tags(&lines))
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn file_stem(path: &str) -> String {
+    Path::new(path).file_stem().unwrap_or_default().to_string_lossy().to_string()
+}
+
+/// Metadata of the books in the library, keyed by path.
+#[derive(Debug, Default)]
+pub struct Catalog {
+    entries: HashMap<String, Entry>,
+}
+
+impl Catalog {
+    pub fn scan(files: &[String]) -> Self {
+        let entries: HashMap<String, Entry> = files.iter().map(|file| (file.clone(), Entry::read(file))).collect();
+        info!("Catalogued {} books", entries.len());
+        Self { entries }
+    }
+
+    pub fn from_entries(entries: HashMap<String, Entry>) -> Self {
+        Self { entries }
+    }
+
+    pub fn get(&self, file: &str) -> Option<&Entry> {
+        self.entries.get(file)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum CatalogFormat {
+    Csv,
+    Json,
+}
+
+/// A book of the library as `bookrat catalog` lists it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Record {
+    pub path: String,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub series: Option<String>,
+    pub series_index: Option<String>,
+    pub tags: Vec<String>,
+    pub language: Option<String>,
+    /// Percent of the text before the start of the bookmarked part
+    pub progress: u32,
+    pub last_read: Option<DateTime<Utc>>,
+    pub minutes_read: u64,
+    /// Stars out of five
+    pub rating: Option<u8>,
+    pub review: Option<String>,
+}
+
+impl Record {
+    /// Describes the book at `file` with what `bookmarks` and `time` know
+    /// of the reading.
+    pub fn read(file: &str, bookmarks: &Bookmarks, time: &ReadingTime) -> Self {
+        let bookmark = bookmarks.get_bookmark(file);
+        let (entry, progress) = match EpubDoc::new(file) {
+            Ok(doc) => {
+                let entry = Entry::from_doc(&doc, file).detect_language(file);
+                let progress = bookmark.map_or(0, |bookmark| {
+                    book_progress(&Book::Epub(Box::new(doc)).part_sizes(), bookmark.chapter, 0)
+                });
+                (entry, progress)
+            }
+            Err(_) => (Entry::read(file), 0),
+        };
+        Self {
+            path: file.to_string(),
+            title: entry.title,
+            authors: entry.authors,
+            series: entry.series,
+            series_index: entry.series_index,
+            tags: entry.tags,
+            language: entry.language,
+            progress,
+            last_read: bookmark.map(|bookmark| bookmark.last_read),
+            minutes_read: time.book_minutes(file),
+            rating: bookmark.and_then(|bookmark| bookmark.rating),
+            review: bookmark.and_then(|bookmark| bookmark.review.clone()),
+        }
+    }
+}
+
+/// The library catalog in `format`, for spreadsheets and site generators.
+/// In CSV, authors and tags are joined with `; `.
+pub fn format_catalog(records: &[Record], format: CatalogFormat) -> anyhow::Result<String> {
+    match format {
+        CatalogFormat::Json => {
+            let mut json = serde_json::to_string_pretty(records)?;
+            json.push('\n');
+            Ok(json)
+        }
+        CatalogFormat::Csv => {
+            let mut csv = String::from("path,title,authors,series,series_index,tags,language,progress,last_read,minutes_read,rating,review\n");
+            for record in records {
+                let fields = [
+                    record.path.clone(),
+                    record.title.clone(),
+                    record.authors.join("; "),
+                    record.series.clone().unwrap_or_default(),
+                    record.series_index.clone().unwrap_or_default(),
+                    record.tags.join("; "),
+                    record.language.clone().unwrap_or_default(),
+                    record.progress.to_string(),
+                    record.last_read.map(|time| time.to_rfc3339()).unwrap_or_default(),
+                    record.minutes_read.to_string(),
+                    record.rating.map(|rating| rating.to_string()).unwrap_or_default(),
+                    record.review.clone().unwrap_or_default(),
+                ];
+                let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+                csv.push_str(&fields.join(","));
+                csv.push('\n');
+            }
+            Ok(csv)
+        }
+    }
+}
+
+/// `field` quoted when it holds a comma, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// One condition of a library query.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    Author(String),
+    Tag(String),
+    Title(String),
+    Language(String),
+    /// Books opened before
+    Read,
+    /// Books never opened
+    Unread,
+    /// Books of fewer estimated pages than this
+    ShorterThan(usize),
+    /// Books of more estimated pages than this
+    LongerThan(usize),
+    /// Matches the title, authors, tags or file name
+    Text(String),
+}
+
+/// A library filter such as `author:tolstoy tag:classics unread war`: every
+/// term must match. Values are compared case-insensitively by substring
+/// and may be quoted to include spaces (`author:"leo tolstoy"`).
+/// `pages:<300` and `pages:>100` compare the estimated length.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    terms: Vec<Term>,
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Self {
+        let terms = split_words(query)
+            .into_iter()
+            .map(|word| {
+                let (key, value) = match word.split_once(':') {
+                    Some((key, value)) if !value.is_empty() => (key.to_ascii_lowercase(), value.to_lowercase()),
+                    _ => (String::new(), word.to_lowercase()),
+                };
+                match key.as_str() {
+                    "author" | "by" => Term::Author(value),
+                    "tag" | "subject" => Term::Tag(value),
+                    "title" => Term::Title(value),
+                    "lang" | "language" => Term::Language(value),
+                    "pages" => {
+                        let bound = |prefix: char| value.strip_prefix(prefix).and_then(|pages| pages.parse().ok());
+                        match (bound('<'), bound('>')) {
+                            (Some(max), _) => Term::ShorterThan(max),
+                            (_, Some(min)) => Term::LongerThan(min),
+                            _ => Term::Text(word.to_lowercase()),
+                        }
+                    }
+                    "" if value == "read" => Term::Read,
+                    "" if value == "unread" => Term::Unread,
+                    "" => Term::Text(value),
+                    _ => Term::Text(word.to_lowercase()),
+                }
+            })
+            .collect();
+        Self { terms }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn terms(&self) -> &[Term] {
+        &self.terms
+    }
+
+    /// Whether matching needs the length of the books.
+    pub fn needs_pages(&self) -> bool {
+        self.terms.iter().any(|term| matches!(term, Term::ShorterThan(_) | Term::LongerThan(_)))
+    }
+
+    /// Whether the book at `file`, described by `entry`, matches; `read`
+    /// tells whether it has been opened before, and `pages` how long it
+    /// is, where `needs_pages` asks for it.
+    pub fn matches(&self, file: &str, entry: &Entry, read: bool, pages: Option<usize>) -> bool {
+        let any = |values: &[String], needle: &str| values.iter().any(|value| value.to_lowercase().contains(needle));
+        self.terms.iter().all(|term| match term {
+            Term::Author(name) => any(&entry.authors, name),
+            Term::Tag(tag) => any(&entry.tags, tag),
+            Term::Title(title) => entry.title.to_lowercase().contains(title),
+            Term::Language(lang) => entry.language.as_deref().is_some_and(|l| l.to_lowercase().starts_with(lang)),
+            Term::Read => read,
+            Term::Unread => !read,
+            Term::ShorterThan(max) => pages.is_some_and(|pages| pages < *max),
+            Term::LongerThan(min) => pages.is_some_and(|pages| pages > *min),
+            Term::Text(text) => {
+                entry.title.to_lowercase().contains(text)
+                    || any(&entry.authors, text)
+                    || any(&entry.tags, text)
+                    || Path::new(file).file_stem().is_some_and(|stem| stem.to_string_lossy().to_lowercase().contains(text))
+            }
+        })
+    }
+}
+
+/// Splits on whitespace, keeping double-quoted stretches together and
+/// dropping the quotes.
+fn split_words(query: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in query.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    words.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> Entry {
+        Entry {
+            title: "War and Peace".to_string(),
+            authors: vec!["Leo Tolstoy".to_string()],
+            tags: vec!["Classics".to_string(), "Historical fiction".to_string()],
+            language: Some("en-GB".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_isbns_identify_books_before_other_identifiers() {
+        let values = |values: &[&str]| values.iter().map(|value| value.to_string()).collect::<Vec<_>>();
+        assert_eq!(identifier(&values(&["urn:uuid:1234", "urn:isbn:978-0-14-044793-4"])).as_deref(), Some("isbn:9780140447934"));
+        assert_eq!(identifier(&values(&["0-14-044793-X"])).as_deref(), Some("isbn:014044793x"));
+        assert_eq!(identifier(&values(&["urn:uuid:1234", "calibre:42"])).as_deref(), Some("urn:uuid:1234"));
+        assert_eq!(identifier(&[]), None);
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let query = Query::parse(r#"author:"Leo Tol" tag:classics unread  peace url:http://x pages:<300 pages:many"#);
+        assert_eq!(
+            query.terms(),
+            &[
+                Term::Author("leo tol".to_string()),
+                Term::Tag("classics".to_string()),
+                Term::Unread,
+                Term::Text("peace".to_string()),
+                Term::Text("url:http://x".to_string()),
+                Term::ShorterThan(300),
+                Term::Text("pages:many".to_string()),
+            ]
+        );
+        assert!(Query::parse("   ").is_empty());
+    }
+
+    #[test]
+    fn test_description_as_plain_text() {
+        let html = "<div><p>A <b>sweeping</b> novel&nbsp;of   Russia.</p>\n<p>Now in a new<br/>translation &amp; edition.</p></div>";
+        assert_eq!(plain_text(html), "A sweeping novel of Russia.\nNow in a new\ntranslation & edition.");
+        assert_eq!(plain_text("Plain blurb"), "Plain blurb");
+    }
+
+    #[test]
+    fn test_query_matches_all_terms() {
+        let file = "books/war.epub";
+        let matches = |query: &str, read: bool| Query::parse(query).matches(file, &entry(), read, Some(120));
+        assert!(matches("author:tolstoy tag:classics unread", false));
+        assert!(!matches("author:tolstoy tag:classics unread", true));
+        assert!(matches("read lang:en historical", true));
+        assert!(matches("WAR", false));
+        assert!(!matches("author:dostoevsky", false));
+        assert!(!matches("tag:classics title:anna", false));
+        assert!(matches("", true));
+        assert!(matches("pages:<300 pages:>100", true));
+        assert!(!matches("pages:<100", true));
+        assert!(!Query::parse("pages:>1").matches(file, &entry(), true, None));
+    }
+
+    #[test]
+    fn test_catalog_csv_quotes_fields() {
+        let record = Record {
+            path: "books/war.epub".to_string(),
+            title: "War, and \"Peace\"".to_string(),
+            authors: vec!["Leo Tolstoy".to_string(), "Someone Else".to_string()],
+            series: None,
+            series_index: None,
+            tags: Vec::new(),
+            language: Some("en".to_string()),
+            progress: 42,
+            last_read: None,
+            minutes_read: 7,
+            rating: Some(4),
+            review: Some("Long,\nbut worth it".to_string()),
+        };
+        assert_eq!(
+            format_catalog(&[record], CatalogFormat::Csv).unwrap(),
+            "path,title,authors,series,series_index,tags,language,progress,last_read,minutes_read,rating,review\n\
+             books/war.epub,\"War, and \"\"Peace\"\"\",Leo Tolstoy; Someone Else,,,,en,42,,7,4,\"Long,\nbut worth it\"\n"
+        );
+    }
+}
@@ -0,0 +1,29 @@
+use std::io::{self, Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+/// Escape sequence that asks the terminal to put `text` on the system
+/// clipboard (OSC 52). It works over SSH and needs no display server, but
+/// terminals that don't support it ignore it silently.
+pub fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", STANDARD.encode(text))
+}
+
+/// Copies `text` to the system clipboard through the terminal on `out`.
+pub fn copy<W: Write>(out: &mut W, text: &str) -> io::Result<()> {
+    out.write_all(osc52(text).as_bytes())?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_osc52_encodes_text() {
+        assert_eq!(osc52("héllo"), "\x1b]52;c;aMOpbGxv\x07");
+        let mut out = Vec::new();
+        copy(&mut out, "").unwrap();
+        assert_eq!(out, b"\x1b]52;c;\x07");
+    }
+}
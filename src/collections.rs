@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::state_file;
+
+/// A library filter saved under a name, shown as a folder of the library
+/// holding whatever books match it at the time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Collection {
+    pub name: String,
+    pub query: String,
+}
+
+/// Smart collections in the order they were saved, stored as
+/// `collections.json` next to the bookmarks file. Written on every change.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Collections {
+    collections: Vec<Collection>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Collections {
+    pub fn with_path<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            collections: Vec::new(),
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Loads the collections kept beside `bookmarks_path`.
+    pub fn beside(bookmarks_path: &Path) -> anyhow::Result<Self> {
+        Self::load_from(state_file::beside(bookmarks_path, "collections.json"))
+    }
+
+    pub fn load_from<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let mut collections: Self = state_file::load(path)?.unwrap_or_default();
+        collections.path = path.to_path_buf();
+        Ok(collections)
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        state_file::save(self, &self.path)
+    }
+
+    fn changed(&self) {
+        state_file::save_changed(self, &self.path, "the collections");
+    }
+
+    pub fn all(&self) -> &[Collection] {
+        &self.collections
+    }
+
+    pub fn len(&self) -> usize {
+        self.collections.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.collections.is_empty()
+    }
+
+    /// Saves `query` as `name`, replacing the query of a collection of
+    /// that name.
+    pub fn save_query(&mut self, name: &str, query: &str) {
+        let name = name.trim();
+        match self.collections.iter_mut().find(|collection| collection.name == name) {
+            Some(collection) => collection.query = query.to_string(),
+            None => self.collections.push(Collection {
+                name: name.to_string(),
+                query: query.to_string(),
+            }),
+        }
+        self.changed();
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Collection> {
+        if index >= self.collections.len() {
+            return None;
+        }
+        let removed = self.collections.remove(index);
+        self.changed();
+        Some(removed)
+    }
+
+    /// The name of the collection saved with `query`, if any.
+    pub fn name_of(&self, query: &str) -> Option<&str> {
+        self.collections.iter().find(|collection| collection.query == query).map(|collection| collection.name.as_str())
+    }
+
+    /// Takes in another copy of the file: ours, then the collections only
+    /// saved there.
+    pub fn merge(&mut self, other: Collections) {
+        for collection in other.collections {
+            if !self.collections.iter().any(|ours| ours.name == collection.name) {
+                self.collections.push(collection);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collections_are_kept_by_name() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("collections.json");
+        let mut collections = Collections::with_path(&path);
+        collections.save_query("Short sci-fi", "tag:sci-fi pages:<300");
+        collections.save_query("Unread", "unread");
+        collections.save_query(" Short sci-fi ", "tag:sci-fi pages:<200");
+        assert_eq!(collections.len(), 2);
+        assert_eq!(collections.name_of("tag:sci-fi pages:<200"), Some("Short sci-fi"));
+
+        let reloaded = Collections::load_from(&path).unwrap();
+        assert_eq!(reloaded.all(), collections.all());
+        assert_eq!(collections.remove(0).map(|collection| collection.name), Some("Short sci-fi".to_string()));
+        assert_eq!(collections.remove(5), None);
+        assert_eq!(Collections::load_from(&path).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,369 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::app::DEFAULT_CONTINUE_READING;
+use crate::hooks::Event;
+use crate::images::Graphics;
+use crate::layout::{ParagraphFormat, ParagraphStyle, MAX_INDENT, PARAGRAPH_INDENT};
+use crate::library::{ScanOptions, DEFAULT_ROOT};
+use crate::reading_time::Reminders;
+use crate::relative_time;
+
+/// User settings read from `<config dir>/bookrat/config.json`. Every field
+/// has a default, so the file may contain only the settings being changed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub logging: LoggingConfig,
+    pub reading: ReadingConfig,
+    pub calibre: CalibreConfig,
+    pub discord: DiscordConfig,
+    pub hooks: HooksConfig,
+    pub dictionary: DictionaryConfig,
+    pub git_sync: GitSyncConfig,
+    pub bookmarks: BookmarksConfig,
+    pub library: LibraryConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LibraryConfig {
+    /// What the content pane shows of the book selected in the library
+    /// before it is opened
+    pub preview: LibraryPreview,
+    /// How the list shows when books were last read: `relative`
+    /// (`2 h ago`) or a strftime pattern such as `%Y-%m-%d %H:%M`
+    pub last_read_format: String,
+    /// Directories the books are read from, with their subdirectories;
+    /// `./books` when empty. New downloads go to the first
+    pub roots: Vec<String>,
+    /// Levels of subdirectories read below each root
+    pub max_depth: usize,
+    /// Read symlinked directories too
+    pub follow_symlinks: bool,
+    /// Pick up books added to or removed from the roots while running
+    pub watch: bool,
+    /// How covers are drawn: `auto`, `kitty`, `sixel`, `blocks` or `off`
+    pub graphics: Graphics,
+    /// Recently read books pinned above the library list; 0 for none
+    pub continue_reading: usize,
+}
+
+impl Default for LibraryConfig {
+    fn default() -> Self {
+        let scan = ScanOptions::default();
+        Self {
+            preview: LibraryPreview::default(),
+            last_read_format: relative_time::RELATIVE.to_string(),
+            roots: Vec::new(),
+            max_depth: scan.max_depth,
+            follow_symlinks: scan.follow_symlinks,
+            watch: true,
+            graphics: Graphics::default(),
+            continue_reading: DEFAULT_CONTINUE_READING,
+        }
+    }
+}
+
+impl LibraryConfig {
+    pub fn roots(&self) -> Vec<String> {
+        if self.roots.is_empty() {
+            vec![DEFAULT_ROOT.to_string()]
+        } else {
+            self.roots.iter().map(|root| expand_home(root)).collect()
+        }
+    }
+
+    pub fn scan_options(&self) -> ScanOptions {
+        ScanOptions {
+            max_depth: self.max_depth,
+            follow_symlinks: self.follow_symlinks,
+            ..ScanOptions::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LibraryPreview {
+    /// Its description, length and language
+    #[default]
+    Details,
+    /// The beginning of its text, as it would be read
+    FirstPage,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BookmarksConfig {
+    /// Also keep each book's bookmark in `<book>.bookrat.json` beside it
+    pub sidecars: bool,
+}
+
+/// Versioned sync of the reading state through a git repository.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GitSyncConfig {
+    pub enabled: bool,
+    /// Remote to pull from on start and push to on exit, when the
+    /// repository has it
+    pub remote: String,
+}
+
+impl Default for GitSyncConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            remote: "origin".to_string(),
+        }
+    }
+}
+
+/// Shell commands run on reading events, given the details in `BOOKRAT_*`
+/// environment variables.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    pub book_opened: Option<String>,
+    pub chapter_finished: Option<String>,
+    pub book_finished: Option<String>,
+    pub highlight_created: Option<String>,
+}
+
+impl HooksConfig {
+    pub fn command(&self, event: Event) -> Option<&str> {
+        let command = match event {
+            Event::BookOpened => &self.book_opened,
+            Event::ChapterFinished => &self.chapter_finished,
+            Event::BookFinished => &self.book_finished,
+            Event::HighlightCreated => &self.highlight_created,
+        };
+        command.as_deref().filter(|command| !command.trim().is_empty())
+    }
+}
+
+/// Where `K` looks words up.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DictionaryConfig {
+    /// Dictionary URL by language code, with `{word}` for the word looked
+    /// up; books in other languages use Wiktionary in their language
+    pub urls: BTreeMap<String, String>,
+    /// Shell command the page is opened with, given its URL in
+    /// `BOOKRAT_URL`; the desktop's opener when unset
+    pub command: Option<String>,
+}
+
+/// The "now reading" status on Discord, off unless an application is set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiscordConfig {
+    /// Id of the Discord application to publish as; its name is shown
+    /// as what is being played
+    pub client_id: Option<String>,
+}
+
+/// Where `bookrat calibre` finds a calibre content server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CalibreConfig {
+    /// Server address such as `http://localhost:8080`
+    pub url: Option<String>,
+    /// Library id; defaults to the server's default library
+    pub library: Option<String>,
+    /// For servers that require a login (with basic authentication)
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Yes/no column that `mark-read` sets
+    pub read_column: String,
+}
+
+impl Default for CalibreConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            library: None,
+            username: None,
+            password: None,
+            read_column: "#read".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReadingConfig {
+    /// `indent` for first-line indentation, `block` for blank lines
+    /// between unindented paragraphs
+    pub paragraph_style: ParagraphStyle,
+    /// Spaces before the first line of a paragraph, at most 8
+    pub indent_width: usize,
+    /// Minutes to read every day; the help bar counts toward it
+    pub daily_goal_minutes: Option<u64>,
+    /// Minutes of reading before a break is due
+    pub pomodoro_minutes: Option<u64>,
+    pub break_minutes: u64,
+    /// Announce the goal and breaks with desktop notifications too
+    pub notifications: bool,
+    /// Move past watermark, about-the-publisher and advertisement pages
+    /// when going from part to part
+    pub skip_boilerplate: bool,
+}
+
+impl Default for ReadingConfig {
+    fn default() -> Self {
+        Self {
+            paragraph_style: ParagraphStyle::default(),
+            indent_width: PARAGRAPH_INDENT.len(),
+            daily_goal_minutes: None,
+            pomodoro_minutes: None,
+            break_minutes: 5,
+            notifications: true,
+            skip_boilerplate: false,
+        }
+    }
+}
+
+impl ReadingConfig {
+    pub fn paragraph_format(&self) -> ParagraphFormat {
+        ParagraphFormat {
+            style: self.paragraph_style,
+            indent: self.indent_width.min(MAX_INDENT),
+            spaced: false,
+        }
+    }
+
+    pub fn reminders(&self) -> Reminders {
+        let minutes = |n: u64| Duration::from_secs(n * 60);
+        Reminders::new(
+            self.daily_goal_minutes,
+            self.pomodoro_minutes.map(minutes),
+            minutes(self.break_minutes),
+            self.notifications,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// `off`, `error`, `warn`, `info`, `debug` or `trace`
+    pub level: String,
+    /// Log file; defaults to `bookrat.log` in the platform state directory
+    pub path: Option<PathBuf>,
+    /// Rotate the log once it grows past this many kilobytes
+    pub max_size_kb: u64,
+    /// Number of rotated files (`bookrat.log.1`, ...) to keep
+    pub keep: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            path: None,
+            max_size_kb: 1024,
+            keep: 2,
+        }
+    }
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("bookrat").join("config.json"))
+    }
+
+    /// Loads the config from the default location, or defaults if there is
+    /// no config file.
+    pub fn load() -> Result<Self> {
+        match Self::default_path() {
+            Some(path) => Self::load_from(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        Self::load_layers(&[path.to_path_buf()])
+    }
+
+    /// Loads each of `paths` that exists over the ones before it: a later
+    /// file only needs the settings it changes.
+    pub fn load_layers(paths: &[PathBuf]) -> Result<Self> {
+        let mut merged = serde_json::Value::Object(Default::default());
+        for path in paths.iter().filter(|path| path.exists()) {
+            let content = fs::read_to_string(path).with_context(|| format!("Failed to read config: {:?}", path))?;
+            let layer = serde_json::from_str(&content).with_context(|| format!("Failed to parse config: {:?}", path))?;
+            overlay(&mut merged, layer);
+        }
+        serde_json::from_value(merged).context("Failed to parse config")
+    }
+}
+
+/// `path` with a leading `~` taken for the home directory.
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => format!("{}{}", home.display(), rest),
+        _ => path.to_string(),
+    }
+}
+
+/// Puts the settings in `layer` over those in `base`, object by object.
+fn overlay(base: &mut serde_json::Value, layer: serde_json::Value) {
+    match (base, layer) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(layer)) => {
+            for (key, value) in layer {
+                match base.get_mut(&key) {
+                    Some(existing) => overlay(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_config_uses_defaults() {
+        let config: Config = serde_json::from_str(r#"{"logging": {"level": "debug"}}"#).unwrap();
+        assert_eq!(config.logging.level, "debug");
+        assert_eq!(config.logging.max_size_kb, 1024);
+        assert!(config.logging.path.is_none());
+
+        let config: Config = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.logging.level, "info");
+        assert_eq!(config.library.preview, LibraryPreview::Details);
+        assert_eq!(config.library.last_read_format, "relative");
+        assert_eq!(config.library.roots(), ["./books"]);
+        assert_eq!(config.library.scan_options(), ScanOptions::default());
+        assert_eq!(config.reading.paragraph_format(), ParagraphFormat::default());
+
+        let config: Config = serde_json::from_str(r#"{"reading": {"paragraph_style": "block", "indent_width": 12}}"#).unwrap();
+        assert_eq!(config.reading.paragraph_style, ParagraphStyle::Block);
+        assert_eq!(config.reading.paragraph_format().indent, MAX_INDENT);
+    }
+
+    #[test]
+    fn test_profile_config_is_layered_over_the_shared_one() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let (shared, profile) = (dir.path().join("config.json"), dir.path().join("alice.json"));
+        fs::write(&shared, r#"{"logging": {"level": "debug"}, "reading": {"indent_width": 2, "paragraph_style": "block"}}"#).unwrap();
+        fs::write(&profile, r#"{"reading": {"indent_width": 6}}"#).unwrap();
+        let config = Config::load_layers(&[shared, profile, dir.path().join("missing.json")]).unwrap();
+        assert_eq!(config.logging.level, "debug");
+        assert_eq!(config.reading.indent_width, 6);
+        assert_eq!(config.reading.paragraph_style, ParagraphStyle::Block);
+    }
+}
@@ -0,0 +1,388 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use epub::{
+    archive::ArchiveError,
+    doc::{DocError, EpubDoc, NavPoint},
+};
+use ratatui::{
+    style::{Color, Style, Stylize},
+    text::Line,
+};
+use zip::ZipArchive;
+
+use crate::book::Epub;
+use crate::popup::ErrorPopup;
+
+/// Structural problems found in a book and which of its parts can still
+/// be read.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Diagnostics {
+    pub problems: Vec<String>,
+    pub total_parts: usize,
+    /// 0-based spine indices that cannot be read
+    pub unreadable: Vec<usize>,
+}
+
+impl Diagnostics {
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// "Readable parts: 1-3, 5 of 6", using the reader's 1-based numbering.
+    pub fn readable_summary(&self) -> String {
+        let readable: Vec<usize> = (0..self.total_parts)
+            .filter(|i| !self.unreadable.contains(i))
+            .map(|i| i + 1)
+            .collect();
+        if readable.is_empty() {
+            return "No parts are readable.".to_string();
+        }
+        format!("Readable parts: {} of {}", format_ranges(&readable), self.total_parts)
+    }
+
+    pub fn to_popup(&self, title: &str, hint: &str) -> ErrorPopup {
+        let mut details = self.problems.clone();
+        details.push(self.readable_summary());
+        ErrorPopup::new(title, "This book has structural problems:", hint).with_details(details)
+    }
+}
+
+/// Checks that every spine item is declared in the manifest and present in
+/// the zip. Only the archive's directory is read, not the parts themselves.
+pub fn check_spine(doc: &Epub, path: &Path) -> Diagnostics {
+    let mut diagnostics = Diagnostics {
+        total_parts: doc.spine.len(),
+        ..Default::default()
+    };
+    if doc.spine.is_empty() {
+        diagnostics.problems.push("The spine lists no content documents.".to_string());
+        return diagnostics;
+    }
+
+    let names = archive_names(path);
+    for (index, item) in doc.spine.iter().enumerate() {
+        let problem = match doc.resources.get(&item.idref) {
+            None => Some(format!("Part {}: spine entry {:?} is not in the manifest", index + 1, item.idref)),
+            Some(resource) => {
+                let name = archive_name(&resource.path);
+                match &names {
+                    Some(names) if !names.contains(&name) => {
+                        Some(format!("Part {}: {} is missing from the archive", index + 1, name))
+                    }
+                    _ => None,
+                }
+            }
+        };
+        if let Some(problem) = problem {
+            diagnostics.problems.push(problem);
+            diagnostics.unreadable.push(index);
+        }
+    }
+    diagnostics
+}
+
+/// Names of the entries in the zip at `path`, if it can be read.
+fn archive_names(path: &Path) -> Option<HashSet<String>> {
+    File::open(path)
+        .ok()
+        .and_then(|file| ZipArchive::new(BufReader::new(file)).ok())
+        .map(|archive| archive.file_names().map(String::from).collect())
+}
+
+fn archive_name(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// What `validate` found wrong with a book, by the part of it at fault.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Validation {
+    /// Why the book couldn't be opened at all, when it couldn't
+    pub unopenable: Option<String>,
+    pub drm: Option<Drm>,
+    pub manifest_items: usize,
+    pub toc_entries: usize,
+    pub parts: usize,
+    /// Manifest items that aren't in the archive
+    pub manifest: Vec<String>,
+    /// Table of contents entries leading nowhere
+    pub toc: Vec<String>,
+    /// Spine items that can't be read as content documents
+    pub chapters: Vec<String>,
+}
+
+impl Validation {
+    pub fn problem_count(&self) -> usize {
+        usize::from(self.unopenable.is_some())
+            + usize::from(self.drm.is_some())
+            + self.manifest.len()
+            + self.toc.len()
+            + self.chapters.len()
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.problem_count() == 0
+    }
+
+    /// The report as lines for the validation view and `bookrat validate`.
+    pub fn describe(&self) -> Vec<Line<'static>> {
+        let heading = |text: String| Line::from(text).style(Style::default().fg(Color::Yellow).bold());
+        let item = |text: &String| Line::from(format!("  {}", text));
+        let section = |lines: &mut Vec<Line<'static>>, title: String, problems: &[String], clean: &str| {
+            lines.push(Line::from(""));
+            lines.push(heading(title));
+            if problems.is_empty() {
+                lines.push(Line::from(format!("  {}", clean)).style(Style::default().fg(Color::Green)));
+            }
+            lines.extend(problems.iter().map(item));
+        };
+
+        let mut lines = vec![match self.problem_count() {
+            0 => Line::from("No problems found").style(Style::default().fg(Color::Green).bold()),
+            1 => Line::from("1 problem found").style(Style::default().fg(Color::Red).bold()),
+            count => Line::from(format!("{} problems found", count)).style(Style::default().fg(Color::Red).bold()),
+        }];
+        if let Some(reason) = &self.unopenable {
+            lines.push(item(&format!("The book can't be opened: {}", reason)));
+            return lines;
+        }
+        if let Some(drm) = self.drm {
+            lines.push(item(&format!("The content is protected with {} DRM", drm)));
+        }
+        section(&mut lines, format!("Manifest ({} items)", self.manifest_items), &self.manifest, "Every item is in the archive");
+        section(&mut lines, format!("Table of contents ({} entries)", self.toc_entries), &self.toc, "Every entry leads to a part");
+        section(&mut lines, format!("Parts ({})", self.parts), &self.chapters, "Every part can be read");
+        lines
+    }
+}
+
+/// Checks the EPUB at `path` for the structural problems that make books
+/// render badly: manifest items missing from the archive, table of contents
+/// entries pointing at no file or at an anchor the file lacks, and parts
+/// that can't be read as (X)HTML. Every part is read once.
+pub fn validate(path: &Path) -> Validation {
+    let mut validation = Validation {
+        drm: detect_drm(path),
+        ..Default::default()
+    };
+    let mut doc = match EpubDoc::new(path) {
+        Ok(doc) => doc,
+        Err(e) => {
+            validation.unopenable = Some(describe_open_error(&e));
+            return validation;
+        }
+    };
+
+    let names = archive_names(path).unwrap_or_default();
+    let mut resources: Vec<(String, PathBuf)> = doc.resources.iter().map(|(id, resource)| (id.clone(), resource.path.clone())).collect();
+    resources.sort_by(|a, b| a.1.cmp(&b.1));
+    validation.manifest_items = resources.len();
+    for (id, resource) in &resources {
+        if !names.contains(&archive_name(resource)) {
+            validation.manifest.push(format!("{} ({}) is missing from the archive", archive_name(resource), id));
+        }
+    }
+
+    let spine: Vec<String> = doc.spine.iter().map(|item| item.idref.clone()).collect();
+    validation.parts = spine.len();
+    if spine.is_empty() {
+        validation.chapters.push("The spine lists no content documents".to_string());
+    }
+    for (index, id) in spine.iter().enumerate() {
+        let Some(resource) = doc.resources.get(id) else {
+            validation.chapters.push(format!("Part {}: spine entry {:?} is not in the manifest", index + 1, id));
+            continue;
+        };
+        let (name, mime) = (archive_name(&resource.path), resource.mime.clone());
+        if !crate::book::is_content_document(&name) && !mime.contains("html") {
+            validation.chapters.push(format!("Part {}: {} is {}, not an (X)HTML document", index + 1, name, mime));
+        } else if doc.get_resource_str(id).is_none() {
+            let reason = if names.contains(&name) { "is not UTF-8 text" } else { "is missing from the archive" };
+            validation.chapters.push(format!("Part {}: {} {}", index + 1, name, reason));
+        }
+    }
+
+    let mut points: Vec<(String, String)> = Vec::new();
+    let mut pending: Vec<&NavPoint> = doc.toc.iter().rev().collect();
+    while let Some(point) = pending.pop() {
+        points.push((point.label.trim().to_string(), archive_name(&point.content)));
+        pending.extend(point.children.iter().rev());
+    }
+    validation.toc_entries = points.len();
+    if points.is_empty() {
+        validation.toc.push("The table of contents is missing or empty".to_string());
+    }
+    let mut anchors: HashMap<String, Option<String>> = HashMap::new();
+    for (label, target) in points {
+        let (file, fragment) = match target.split_once('#') {
+            Some((file, fragment)) => (file.to_string(), Some(fragment.to_string())),
+            None => (target.clone(), None),
+        };
+        if !resources.iter().any(|(_, resource)| archive_name(resource) == file) {
+            validation.toc.push(format!("{:?} links to {}, which is not in the manifest", label, file));
+            continue;
+        }
+        let Some(fragment) = fragment.filter(|fragment| !fragment.is_empty()) else {
+            continue;
+        };
+        let content = anchors.entry(file.clone()).or_insert_with(|| doc.get_resource_str_by_path(&file));
+        if !content.as_deref().is_some_and(|content| has_anchor(content, &fragment)) {
+            validation.toc.push(format!("{:?} links to #{}, which {} lacks", label, fragment, file));
+        }
+    }
+    validation
+}
+
+/// Whether some element of `html` has the id (or, in older books, the
+/// anchor name) `fragment`.
+fn has_anchor(html: &str, fragment: &str) -> bool {
+    crate::book::anchor_position(html, fragment).is_some()
+}
+
+/// Explains why `EpubDoc::new` rejected a file.
+pub fn describe_open_error(error: &DocError) -> String {
+    match error {
+        DocError::ArchiveError(ArchiveError::Zip(zip::result::ZipError::FileNotFound)) => {
+            "A required file is missing from the archive: the container or package document (OPF)".to_string()
+        }
+        DocError::ArchiveError(e) => format!("The zip archive is damaged or incomplete ({})", e),
+        DocError::XmlError(e) => format!("The container or package document (OPF) is malformed ({})", e),
+        DocError::IOError(e) => format!("The file could not be read ({})", e),
+        DocError::InvalidEpub => "The package document (OPF) is missing or lacks a manifest/spine".to_string(),
+    }
+}
+
+/// Encryption schemes that make a book unreadable without the vendor's key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Drm {
+    AdobeAdept,
+    Lcp,
+    AppleFairPlay,
+    /// `encryption.xml` encrypts content documents with an unknown scheme
+    Unknown,
+}
+
+impl fmt::Display for Drm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Drm::AdobeAdept => "Adobe ADEPT",
+            Drm::Lcp => "Readium LCP",
+            Drm::AppleFairPlay => "Apple FairPlay",
+            Drm::Unknown => "an unknown",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Font obfuscation algorithms also appear in `encryption.xml` but only
+/// scramble embedded fonts; the text stays readable.
+const FONT_OBFUSCATION: [&str; 2] = ["http://www.idpf.org/2008/embedding", "http://ns.adobe.com/pdf/enc#RC"];
+
+/// Looks for DRM markers in the `META-INF` directory of the zip at `path`.
+pub fn detect_drm(path: &Path) -> Option<Drm> {
+    let file = File::open(path).ok()?;
+    let mut archive = ZipArchive::new(BufReader::new(file)).ok()?;
+    let mut read = |name: &str| -> Option<String> {
+        let mut entry = archive.by_name(name).ok()?;
+        let mut content = String::new();
+        entry.read_to_string(&mut content).ok()?;
+        Some(content)
+    };
+
+    if read("META-INF/license.lcpl").is_some() {
+        return Some(Drm::Lcp);
+    }
+    if read("META-INF/sinf.xml").is_some() {
+        return Some(Drm::AppleFairPlay);
+    }
+    if read("META-INF/rights.xml").is_some_and(|rights| rights.contains("ns.adobe.com/adept")) {
+        return Some(Drm::AdobeAdept);
+    }
+    read("META-INF/encryption.xml").and_then(|encryption| classify_encryption(&encryption))
+}
+
+fn classify_encryption(encryption: &str) -> Option<Drm> {
+    if encryption.contains("ns.adobe.com/adept") {
+        return Some(Drm::AdobeAdept);
+    }
+    if encryption.contains("readium.org/2014/01/lcp") {
+        return Some(Drm::Lcp);
+    }
+    // Each <EncryptedData> block names an algorithm and the file it covers
+    let encrypts_content = encryption.split("<EncryptedData").skip(1).any(|block| {
+        let obfuscated_font = FONT_OBFUSCATION.iter().any(|algorithm| block.contains(algorithm));
+        let target = block
+            .split("URI=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        !obfuscated_font && (crate::book::is_content_document(&target) || target.is_empty())
+    });
+    encrypts_content.then_some(Drm::Unknown)
+}
+
+fn format_ranges(numbers: &[usize]) -> String {
+    let mut ranges: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < numbers.len() {
+        let start = numbers[i];
+        let mut end = start;
+        while i + 1 < numbers.len() && numbers[i + 1] == end + 1 {
+            i += 1;
+            end = numbers[i];
+        }
+        ranges.push(if start == end { start.to_string() } else { format!("{}-{}", start, end) });
+        i += 1;
+    }
+    ranges.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readable_summary() {
+        let diagnostics = Diagnostics {
+            problems: vec!["x".to_string()],
+            total_parts: 7,
+            unreadable: vec![3, 5],
+        };
+        assert_eq!(diagnostics.readable_summary(), "Readable parts: 1-3, 5, 7 of 7");
+
+        let none = Diagnostics {
+            total_parts: 1,
+            unreadable: vec![0],
+            ..Default::default()
+        };
+        assert_eq!(none.readable_summary(), "No parts are readable.");
+    }
+
+    #[test]
+    fn test_has_anchor() {
+        assert!(has_anchor(r#"<h2 id="ch3">Three</h2>"#, "ch3"));
+        assert!(has_anchor("<a name='note1'/>", "note1"));
+        assert!(!has_anchor(r#"<h2 id="ch30">Thirty</h2>"#, "ch3"));
+    }
+
+    #[test]
+    fn test_classify_encryption() {
+        let fonts = r#"<encryption><EncryptedData><EncryptionMethod Algorithm="http://www.idpf.org/2008/embedding"/>
+            <CipherData><CipherReference URI="OEBPS/fonts/a.otf"/></CipherData></EncryptedData></encryption>"#;
+        assert_eq!(classify_encryption(fonts), None);
+
+        let adept = r#"<encryption><EncryptedData><KeyInfo><resource xmlns="http://ns.adobe.com/adept"/></KeyInfo>
+            </EncryptedData></encryption>"#;
+        assert_eq!(classify_encryption(adept), Some(Drm::AdobeAdept));
+
+        let unknown = r#"<encryption><EncryptedData><EncryptionMethod Algorithm="http://www.w3.org/2001/04/xmlenc#aes128-cbc"/>
+            <CipherData><CipherReference URI="OEBPS/ch1.xhtml"/></CipherData></EncryptedData></encryption>"#;
+        assert_eq!(classify_encryption(unknown), Some(Drm::Unknown));
+    }
+}
@@ -0,0 +1,81 @@
+use std::process::Command;
+
+use log::{debug, error};
+
+use crate::config::DictionaryConfig;
+use crate::hooks;
+
+/// Where words are looked up in languages the config has no dictionary
+/// for, `{lang}` being the language's code.
+const WIKTIONARY: &str = "https://{lang}.wiktionary.org/wiki/{word}";
+
+/// Language of the dictionary for books of no known language.
+const DEFAULT_LANGUAGE: &str = "en";
+
+/// The dictionary page for `word` in the language of the book, `language`
+/// when it has one: the config's dictionary for the language, or else its
+/// Wiktionary.
+pub fn url(config: &DictionaryConfig, word: &str, language: Option<&str>) -> String {
+    let language = language.map(|tag| tag.trim().to_lowercase()).filter(|tag| !tag.is_empty());
+    let language = language.as_deref().unwrap_or(DEFAULT_LANGUAGE);
+    let primary = language.split(['-', '_']).next().unwrap_or(language);
+    let template = config
+        .urls
+        .get(language)
+        .or_else(|| config.urls.get(primary))
+        .map_or(WIKTIONARY, String::as_str);
+    template.replace("{lang}", primary).replace("{word}", &encode(word.trim()))
+}
+
+/// Opens `url` with the configured command or the desktop's opener, in the
+/// background; a failure to start it is only logged.
+pub fn open(config: &DictionaryConfig, url: &str) {
+    debug!("Looking up {}", url);
+    let mut command = match config.command.as_deref().filter(|command| !command.trim().is_empty()) {
+        Some(command) => hooks::shell(command),
+        None if cfg!(windows) => {
+            let mut command = Command::new("cmd");
+            command.args(["/C", "start", ""]).arg(url);
+            command
+        }
+        None => {
+            let mut command = Command::new(if cfg!(target_os = "macos") { "open" } else { "xdg-open" });
+            command.arg(url);
+            command
+        }
+    };
+    if let Err(e) = hooks::spawn(command.env("BOOKRAT_URL", url)) {
+        error!("Failed to open the dictionary: {}", e);
+    }
+}
+
+/// `text` percent-encoded to be a part of a URL path.
+fn encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_words_are_looked_up_in_the_language_of_the_book() {
+        let mut config = DictionaryConfig::default();
+        assert_eq!(url(&config, "Haus", Some("de")), "https://de.wiktionary.org/wiki/Haus");
+        assert_eq!(url(&config, "дом", Some("ru")), "https://ru.wiktionary.org/wiki/%D0%B4%D0%BE%D0%BC");
+        assert_eq!(url(&config, "colour", Some("en-GB")), "https://en.wiktionary.org/wiki/colour");
+        assert_eq!(url(&config, "house", None), "https://en.wiktionary.org/wiki/house");
+
+        config.urls.insert("de".to_string(), "https://www.duden.de/suchen/dudenonline/{word}".to_string());
+        assert_eq!(url(&config, " Straße ", Some("de-AT")), "https://www.duden.de/suchen/dudenonline/Stra%C3%9Fe");
+        assert_eq!(url(&config, "rue", Some("fr")), "https://fr.wiktionary.org/wiki/rue");
+    }
+}
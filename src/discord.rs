@@ -0,0 +1,263 @@
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+use log::{debug, info};
+use serde_json::{json, Value};
+
+/// How often to look for Discord again while it isn't running.
+const RETRY_EVERY: Duration = Duration::from_secs(15);
+
+/// Longest Discord accepts for the lines of an activity.
+const MAX_FIELD: usize = 128;
+
+const HANDSHAKE: u32 = 0;
+const FRAME: u32 = 1;
+const CLOSE: u32 = 2;
+
+/// What is being read, for the "now reading" status.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Activity {
+    pub title: String,
+    pub author: Option<String>,
+    /// Percent of the whole book
+    pub progress: u32,
+}
+
+/// Publishes the book being read as the user's Discord Rich Presence.
+/// Discord is talked to from a thread of its own over its local IPC
+/// socket; while it isn't running, updates are kept until it is.
+pub struct Presence {
+    sender: Sender<Option<Activity>>,
+    /// Last activity sent to the thread
+    current: Option<Activity>,
+}
+
+impl Presence {
+    /// Starts publishing as the Discord application `client_id`, whose
+    /// name is shown as what the user is playing.
+    pub fn start(client_id: &str) -> Self {
+        Self::spawn(client_id, socket_paths())
+    }
+
+    fn spawn(client_id: &str, paths: Vec<PathBuf>) -> Self {
+        let (sender, updates) = mpsc::channel::<Option<Activity>>();
+        let client_id = client_id.to_string();
+        std::thread::spawn(move || {
+            let mut connection: Option<Connection> = None;
+            let mut activity: Option<Activity> = None;
+            let mut started = 0;
+            loop {
+                match updates.recv_timeout(RETRY_EVERY) {
+                    Ok(update) => {
+                        if update.as_ref().map(|a| &a.title) != activity.as_ref().map(|a| &a.title) {
+                            started = chrono::Utc::now().timestamp();
+                        }
+                        activity = update;
+                    }
+                    // Nothing changed: only worth going on to reconnect
+                    Err(RecvTimeoutError::Timeout) if connection.is_some() || activity.is_none() => continue,
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                if connection.is_none() {
+                    connection = Connection::open(&paths, &client_id);
+                }
+                if let Some(open) = &mut connection {
+                    if let Err(e) = open.set_activity(activity.as_ref(), started) {
+                        info!("Lost the connection to Discord: {}", e);
+                        connection = None;
+                    }
+                }
+            }
+        });
+        Self { sender, current: None }
+    }
+
+    /// Shows `activity`, or clears the status with `None`. Discord is only
+    /// told when it changes.
+    pub fn set(&mut self, activity: Option<Activity>) {
+        if activity != self.current {
+            self.current = activity.clone();
+            // The thread only ends with the sender
+            let _ = self.sender.send(activity);
+        }
+    }
+}
+
+#[cfg(unix)]
+type Stream = std::os::unix::net::UnixStream;
+#[cfg(windows)]
+type Stream = std::fs::File;
+
+/// Where the Discord client listens: `discord-ipc-0` to `-9` in the
+/// runtime or temporary directory, also inside Flatpak and Snap sandboxes.
+fn socket_paths() -> Vec<PathBuf> {
+    if cfg!(windows) {
+        return (0..10).map(|i| PathBuf::from(format!(r"\\.\pipe\discord-ipc-{}", i))).collect();
+    }
+    let mut dirs: Vec<PathBuf> = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"]
+        .iter()
+        .filter_map(|var| std::env::var_os(var).map(PathBuf::from))
+        .collect();
+    dirs.push(PathBuf::from("/tmp"));
+    let sandboxed = ["app/com.discordapp.Discord", "snap.discord"];
+    let dirs: Vec<PathBuf> = dirs
+        .iter()
+        .flat_map(|dir| std::iter::once(dir.clone()).chain(sandboxed.iter().map(|sub| dir.join(sub))))
+        .collect();
+    (0..10).flat_map(|i| dirs.iter().map(move |dir| dir.join(format!("discord-ipc-{}", i)))).collect()
+}
+
+struct Connection {
+    stream: Stream,
+    nonce: u64,
+}
+
+impl Connection {
+    /// Connects to the first Discord socket that answers the handshake.
+    fn open(paths: &[PathBuf], client_id: &str) -> Option<Self> {
+        paths.iter().find_map(|path| {
+            let stream = connect(path).ok()?;
+            let mut connection = Self { stream, nonce: 0 };
+            match connection.handshake(client_id) {
+                Ok(()) => {
+                    info!("Connected to Discord at {:?}", path);
+                    Some(connection)
+                }
+                Err(e) => {
+                    debug!("No Discord handshake at {:?}: {}", path, e);
+                    None
+                }
+            }
+        })
+    }
+
+    fn handshake(&mut self, client_id: &str) -> io::Result<()> {
+        self.send(HANDSHAKE, &json!({ "v": 1, "client_id": client_id }))?;
+        self.receive().map(|_| ())
+    }
+
+    fn set_activity(&mut self, activity: Option<&Activity>, started: i64) -> io::Result<()> {
+        self.nonce += 1;
+        let payload = json!({
+            "cmd": "SET_ACTIVITY",
+            "args": { "pid": std::process::id(), "activity": activity.map(|activity| activity_json(activity, started)) },
+            "nonce": self.nonce.to_string(),
+        });
+        self.send(FRAME, &payload)?;
+        // Read the reply so replies never pile up unread
+        let reply = self.receive()?;
+        if reply["evt"] == "ERROR" {
+            info!("Discord rejected the activity: {}", reply["data"]["message"]);
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, op: u32, payload: &Value) -> io::Result<()> {
+        self.stream.write_all(&frame(op, payload))?;
+        self.stream.flush()
+    }
+
+    fn receive(&mut self) -> io::Result<Value> {
+        let mut header = [0; 8];
+        self.stream.read_exact(&mut header)?;
+        let op = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let mut body = vec![0; len];
+        self.stream.read_exact(&mut body)?;
+        let reply: Value = serde_json::from_slice(&body)?;
+        if op == CLOSE {
+            return Err(io::Error::new(io::ErrorKind::ConnectionAborted, reply["message"].to_string()));
+        }
+        Ok(reply)
+    }
+}
+
+#[cfg(unix)]
+fn connect(path: &std::path::Path) -> io::Result<Stream> {
+    let stream = Stream::connect(path)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    Ok(stream)
+}
+
+#[cfg(windows)]
+fn connect(path: &std::path::Path) -> io::Result<Stream> {
+    std::fs::OpenOptions::new().read(true).write(true).open(path)
+}
+
+/// An IPC message: opcode and length, both little-endian, then the JSON.
+fn frame(op: u32, payload: &Value) -> Vec<u8> {
+    let body = payload.to_string();
+    let mut frame = Vec::with_capacity(8 + body.len());
+    frame.extend_from_slice(&op.to_le_bytes());
+    frame.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    frame.extend_from_slice(body.as_bytes());
+    frame
+}
+
+/// The title on the first line; author and progress on the second, with
+/// the time since the book was opened.
+fn activity_json(activity: &Activity, started: i64) -> Value {
+    let state = match &activity.author {
+        Some(author) => format!("by {} · {}%", author, activity.progress),
+        None => format!("{}% read", activity.progress),
+    };
+    json!({
+        "details": truncate(&activity.title),
+        "state": truncate(&state),
+        "timestamps": { "start": started },
+    })
+}
+
+fn truncate(text: &str) -> String {
+    if text.chars().count() <= MAX_FIELD {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(MAX_FIELD - 1).collect();
+    cut.push('…');
+    cut
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+
+    fn read_frame(stream: &mut Stream) -> (u32, Value) {
+        let mut header = [0; 8];
+        stream.read_exact(&mut header).unwrap();
+        let mut body = vec![0; u32::from_le_bytes(header[4..].try_into().unwrap()) as usize];
+        stream.read_exact(&mut body).unwrap();
+        (u32::from_le_bytes(header[..4].try_into().unwrap()), serde_json::from_slice(&body).unwrap())
+    }
+
+    #[test]
+    fn test_presence_talks_discord_ipc() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let socket = dir.path().join("discord-ipc-0");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let mut presence = Presence::spawn("1234", vec![dir.path().join("missing"), socket]);
+        let activity = Activity { title: "War and Peace".to_string(), author: Some("Leo Tolstoy".to_string()), progress: 42 };
+        presence.set(Some(activity.clone()));
+        presence.set(Some(activity));
+        presence.set(None);
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let (op, handshake) = read_frame(&mut stream);
+        assert_eq!((op, handshake), (HANDSHAKE, json!({ "v": 1, "client_id": "1234" })));
+        stream.write_all(&frame(FRAME, &json!({ "evt": "READY" }))).unwrap();
+
+        let (op, set) = read_frame(&mut stream);
+        assert_eq!((op, set["cmd"].as_str()), (FRAME, Some("SET_ACTIVITY")));
+        assert_eq!(set["args"]["activity"]["details"], "War and Peace");
+        assert_eq!(set["args"]["activity"]["state"], "by Leo Tolstoy · 42%");
+        stream.write_all(&frame(FRAME, &json!({ "cmd": "SET_ACTIVITY" }))).unwrap();
+
+        // The repeated activity isn't sent again; clearing it is
+        let (_, cleared) = read_frame(&mut stream);
+        assert!(cleared["args"]["activity"].is_null());
+        assert_eq!(truncate(&"x".repeat(200)).chars().count(), MAX_FIELD);
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use anyhow::{bail, Context, Result};
+use log::{error, info};
+
+/// Whether `arg` names a book to download rather than a local file.
+pub fn is_url(arg: &str) -> bool {
+    arg.starts_with("http://") || arg.starts_with("https://")
+}
+
+/// File name to save the book at `url` under: the last path segment,
+/// without query or fragment, ending in `.epub`.
+pub fn file_name_for(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let after_host = path.splitn(4, '/').nth(3).unwrap_or_default();
+    let segment = after_host.rsplit('/').next().unwrap_or_default();
+    let stem: String = segment
+        .trim_end_matches(".epub")
+        .chars()
+        .map(|c| if c.is_alphanumeric() || "-_. ".contains(c) { c } else { '_' })
+        .collect();
+    let stem = stem.trim_matches(['.', ' ']);
+    format!("{}.epub", if stem.is_empty() { "download" } else { stem })
+}
+
+/// A path for `name` in `dir` that doesn't overwrite an existing book.
+pub fn unused_path(dir: &Path, name: &str) -> PathBuf {
+    let stem = name.trim_end_matches(".epub");
+    let mut path = dir.join(name);
+    let mut n = 1;
+    while path.exists() {
+        path = dir.join(format!("{}-{}.epub", stem, n));
+        n += 1;
+    }
+    path
+}
+
+/// What the download thread reports to the UI loop.
+#[derive(Debug, Clone, PartialEq)]
+enum Progress {
+    /// Bytes received so far, out of the length the server announced
+    Received(u64, Option<u64>),
+    /// Saved at this path
+    Done(String),
+    Failed(String),
+}
+
+/// A book being downloaded into the library on a background thread.
+pub struct Download {
+    pub url: String,
+    pub name: String,
+    received: u64,
+    total: Option<u64>,
+    progress: Receiver<Progress>,
+}
+
+impl Download {
+    pub fn start(url: &str, books_dir: &str) -> Self {
+        let name = file_name_for(url);
+        let (sender, progress) = mpsc::channel();
+        let (thread_url, dir) = (url.to_string(), PathBuf::from(books_dir));
+        let thread_name = name.clone();
+        thread::spawn(move || {
+            let report = |progress| {
+                // The app may have quit; the download is abandoned then
+                let _ = sender.send(progress);
+            };
+            match fetch(&thread_url, &dir, &thread_name, &report) {
+                Ok(path) => {
+                    info!("Downloaded {} to {:?}", thread_url, path);
+                    report(Progress::Done(path.to_string_lossy().to_string()));
+                }
+                Err(e) => {
+                    error!("Failed to download {}: {:?}", thread_url, e);
+                    report(Progress::Failed(format!("{:#}", e)));
+                }
+            }
+        });
+        Self {
+            url: url.to_string(),
+            name,
+            received: 0,
+            total: None,
+            progress,
+        }
+    }
+
+    /// Takes in the progress reported so far. Once the download is over,
+    /// gives the path it was saved at or why it failed.
+    pub fn poll(&mut self) -> Option<Result<String, String>> {
+        loop {
+            match self.progress.try_recv() {
+                Ok(Progress::Received(received, total)) => {
+                    self.received = received;
+                    self.total = total;
+                }
+                Ok(Progress::Done(path)) => return Some(Ok(path)),
+                Ok(Progress::Failed(reason)) => return Some(Err(reason)),
+                Err(mpsc::TryRecvError::Empty) => return None,
+                Err(mpsc::TryRecvError::Disconnected) => return Some(Err("The download stopped".to_string())),
+            }
+        }
+    }
+
+    /// A status line such as `Downloading war.epub: 45% of 1.2 MB`.
+    pub fn describe(&self) -> String {
+        match self.total {
+            Some(total) if total > 0 => format!(
+                "Downloading {}: {}% of {}",
+                self.name,
+                self.received * 100 / total,
+                format_size(total)
+            ),
+            _ => format!("Downloading {}: {}", self.name, format_size(self.received)),
+        }
+    }
+}
+
+/// Downloads `url` to a partial file in `dir`, renamed to `name` (or a
+/// numbered variant of it) once complete.
+fn fetch(url: &str, dir: &Path, name: &str, report: &dyn Fn(Progress)) -> Result<PathBuf> {
+    let response = ureq::get(url).call().with_context(|| format!("Failed to fetch {}", url))?;
+    let total = response.header("Content-Length").and_then(|length| length.parse::<u64>().ok());
+    let partial = dir.join(format!(".{}.part", name));
+    let mut file = File::create(&partial).with_context(|| format!("Failed to create {:?}", partial))?;
+    let mut reader = response.into_reader();
+    let mut buffer = [0; 64 * 1024];
+    let mut received = 0;
+    let result = (|| -> Result<()> {
+        loop {
+            let n = reader.read(&mut buffer).context("The download was interrupted")?;
+            if n == 0 {
+                break;
+            }
+            file.write_all(&buffer[..n]).context("Failed to write the download")?;
+            received += n as u64;
+            report(Progress::Received(received, total));
+        }
+        if total.is_some_and(|total| received < total) {
+            bail!("The download ended after {} of {} bytes", received, total.unwrap_or_default());
+        }
+        Ok(())
+    })();
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&partial);
+        return Err(e);
+    }
+    let path = unused_path(dir, name);
+    std::fs::rename(&partial, &path).with_context(|| format!("Failed to move the download to {:?}", path))?;
+    Ok(path)
+}
+
+/// `bytes` as a short size such as `340 KB` or `1.2 MB`.
+pub fn format_size(bytes: u64) -> String {
+    match bytes {
+        b if b < 1024 => format!("{} B", b),
+        b if b < 1024 * 1024 => format!("{} KB", b / 1024),
+        b => format!("{:.1} MB", b as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_name_for_url() {
+        assert_eq!(file_name_for("https://example.com/books/war-and-peace.epub?dl=1"), "war-and-peace.epub");
+        assert_eq!(file_name_for("http://example.com/get/1234"), "1234.epub");
+        assert_eq!(file_name_for("https://example.com/"), "download.epub");
+        assert_eq!(file_name_for("https://example.com"), "download.epub");
+        assert_eq!(file_name_for("https://example.com/a/..%2F..%2Fx.epub"), "_2F.._2Fx.epub");
+        assert!(is_url("https://example.com/x.epub"));
+        assert!(!is_url("books/x.epub"));
+    }
+
+    #[test]
+    fn test_unused_path_and_size() {
+        let dir = tempfile::TempDir::new().unwrap();
+        assert_eq!(unused_path(dir.path(), "a.epub"), dir.path().join("a.epub"));
+        std::fs::write(dir.path().join("a.epub"), "").unwrap();
+        assert_eq!(unused_path(dir.path(), "a.epub"), dir.path().join("a-1.epub"));
+        assert_eq!(format_size(2048), "2 KB");
+        assert_eq!(format_size(1536 * 1024), "1.5 MB");
+    }
+}
@@ -0,0 +1,165 @@
+use std::{ops::RangeInclusive, path::Path};
+
+use anyhow::{bail, Context, Result};
+use epub::doc::EpubDoc;
+use log::{info, warn};
+
+use crate::app::App;
+use crate::blocks;
+use crate::book;
+use crate::layout;
+use crate::regex_patterns::RegexPatterns;
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ExportFormat {
+    #[value(name = "md")]
+    Markdown,
+    #[value(name = "txt")]
+    Text,
+}
+
+/// Parses a 1-based part range such as `3` or `2-5`, matching the
+/// "Part N/M" numbering shown in the reader.
+pub fn parse_part_range(spec: &str) -> Result<RangeInclusive<usize>> {
+    let parse = |s: &str| -> Result<usize> {
+        let n: usize = s.trim().parse().with_context(|| format!("Invalid part number: {:?}", s))?;
+        if n == 0 {
+            bail!("Part numbers start at 1");
+        }
+        Ok(n)
+    };
+    let (start, end) = match spec.split_once('-') {
+        Some((start, end)) => (parse(start)?, parse(end)?),
+        None => {
+            let n = parse(spec)?;
+            (n, n)
+        }
+    };
+    if start > end {
+        bail!("Invalid part range: {}", spec);
+    }
+    Ok(start..=end)
+}
+
+/// Runs every spine item of the book (or just `parts`) through the reader's
+/// content pipeline and returns the whole text in the requested format.
+pub fn export_book(path: &Path, format: ExportFormat, parts: Option<RangeInclusive<usize>>) -> Result<String> {
+    let regex = RegexPatterns::new().context("Failed to compile regex patterns")?;
+    let mut doc = EpubDoc::new(path).with_context(|| format!("Failed to open EPUB: {:?}", path))?;
+    let total = doc.get_num_chapters();
+    let parts = parts.unwrap_or(1..=total);
+    if *parts.start() > total {
+        bail!("Book only has {} parts", total);
+    }
+    info!("Exporting {:?} parts {:?} as {:?}", path, parts, format);
+
+    let titles = book::toc_titles(&mut doc);
+    let mut sections = Vec::new();
+    for part in parts.clone() {
+        if part > total {
+            break;
+        }
+        let index = part - 1;
+        doc.set_current_chapter(index);
+        let Some((content, _mime)) = doc.get_current_str() else {
+            warn!("Failed to read part {} of {:?}", part, path);
+            continue;
+        };
+        let text = App::process_html_content(&content, &regex);
+        if text.is_empty() {
+            continue;
+        }
+        sections.push(format_section(titles.get(&index).map(String::as_str), &text, format));
+    }
+
+    let mut output = sections.join("\n\n");
+    output.push('\n');
+    Ok(output)
+}
+
+fn format_section(title: Option<&str>, text: &str, format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Markdown => {
+            // Indented lines would turn into code blocks, so paragraphs are
+            // separated by blank lines instead.
+            let body = blocks::split_blocks(text)
+                .into_iter()
+                .flat_map(|(block, lines)| {
+                    if block {
+                        vec![format!("{}\n{}\n{}", blocks::FENCE, lines.join("\n"), blocks::FENCE)]
+                    } else {
+                        lines
+                            .into_iter()
+                            .map(str::trim_start)
+                            .filter(|line| !line.is_empty())
+                            .map(String::from)
+                            .collect()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n\n");
+            match title {
+                Some(title) => format!("## {}\n\n{}", title, body),
+                None => body,
+            }
+        }
+        ExportFormat::Text => {
+            let body = blocks::split_blocks(text)
+                .into_iter()
+                .map(|(block, lines)| {
+                    let lines = lines.join("\n");
+                    if block {
+                        lines
+                    } else {
+                        layout::parse_markup(&lines)
+                            .iter()
+                            .map(|runs| runs.iter().map(|run| run.text.as_str()).collect::<String>())
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            match title {
+                Some(title) => format!("{}\n{}\n\n{}", title, "=".repeat(title.chars().count()), body),
+                None => body,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_part_range() {
+        assert_eq!(parse_part_range("3").unwrap(), 3..=3);
+        assert_eq!(parse_part_range("2-5").unwrap(), 2..=5);
+        assert!(parse_part_range("0").is_err());
+        assert!(parse_part_range("5-2").is_err());
+        assert!(parse_part_range("a-b").is_err());
+    }
+
+    #[test]
+    fn test_format_section() {
+        let text = "First _italic_ line\n    Second **bold** line\n```\nlet _x = 1;\n```";
+        assert_eq!(
+            format_section(Some("One"), text, ExportFormat::Markdown),
+            "## One\n\nFirst _italic_ line\n\nSecond **bold** line\n\n```\nlet _x = 1;\n```"
+        );
+        assert_eq!(
+            format_section(Some("One"), text, ExportFormat::Text),
+            "One\n===\n\nFirst italic line\n    Second bold line\nlet _x = 1;"
+        );
+    }
+
+    #[test]
+    fn test_format_section_keeps_literal_underscores() {
+        let text = "Call _read_to_end_ on a file_name";
+        assert_eq!(
+            format_section(None, text, ExportFormat::Text),
+            "Call read_to_end on a file_name"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::path::Path;
+
+use crate::blocks::strip_tags;
+use crate::book::{anchor_position, text_length};
+use crate::page_list::{attribute, resolve};
+use crate::sanitize::decode_entities;
+
+/// Longest link text taken for a note number when the link isn't marked
+/// as a note reference.
+const MAX_LABEL_CHARS: usize = 4;
+
+/// Fewest characters of text a preview is cut off at the end of a block
+/// with, so a glossary term brings its definition along.
+const MIN_PREVIEW_CHARS: usize = 40;
+
+/// Most characters of a note shown in a preview.
+pub const MAX_PREVIEW_CHARS: usize = 400;
+
+/// Closing tags of the blocks a note ends with.
+const BLOCK_ENDS: [&str; 7] = ["</p>", "</li>", "</aside>", "</div>", "</dd>", "</dt>", "</section>"];
+
+/// A link to a footnote or endnote, where it is in the text of its part.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoteRef {
+    /// The link text: `3`, `[iv]`, `*`
+    pub label: String,
+    /// Archive path of the note, with its `#fragment`
+    pub target: String,
+    /// Share of the part's text before the reference
+    pub fraction: f64,
+}
+
+/// The note references of `html`, the part at archive path `base`: links
+/// marked as `noteref`, and links to a fragment with a label short enough
+/// to be a note number.
+pub fn note_refs(html: &str, base: &Path) -> Vec<NoteRef> {
+    let total = text_length(html).max(1);
+    html.match_indices("<a ")
+        .filter_map(|(at, _)| {
+            let (tag, rest) = html[at + 3..].split_once('>')?;
+            let label = decode_entities(&strip_tags(rest.split("</a>").next()?)).trim().to_string();
+            let href = attribute(tag, "href").filter(|href| href.contains('#') && !href.contains("://"))?;
+            let marked = tag.contains("noteref");
+            if !marked && (label.is_empty() || label.chars().count() > MAX_LABEL_CHARS || label.contains(' ')) {
+                return None;
+            }
+            Some(NoteRef {
+                label,
+                target: resolve(base, &href),
+                fraction: text_length(&html[..at]) as f64 / total as f64,
+            })
+        })
+        .collect()
+}
+
+/// The text of the note at `fragment` of `html`, for a preview: up to
+/// the end of its block, or of the blocks after it while that is too
+/// short, cut to `MAX_PREVIEW_CHARS`.
+pub fn note_text(html: &str, fragment: &str) -> Option<String> {
+    let at = anchor_position(html, fragment)?;
+    let start = html[..at].rfind('<').unwrap_or(at);
+    let rest = &html[start..];
+    let text_until = |end: usize| {
+        // Blocks are kept apart by a space
+        let html = BLOCK_ENDS.iter().fold(rest[..end].to_string(), |html, tag| html.replace(tag, &format!("{} ", tag)));
+        let text = decode_entities(&strip_tags(&html));
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    };
+    let mut ends: Vec<usize> = BLOCK_ENDS
+        .iter()
+        .flat_map(|tag| rest.match_indices(tag).map(|(i, tag)| i + tag.len()))
+        .collect();
+    ends.sort_unstable();
+    let text = ends
+        .into_iter()
+        .map(text_until)
+        .find(|text| text.chars().count() >= MIN_PREVIEW_CHARS)
+        .unwrap_or_else(|| text_until(rest.len()));
+    if text.chars().count() <= MAX_PREVIEW_CHARS {
+        return Some(text);
+    }
+    let cut: String = text.chars().take(MAX_PREVIEW_CHARS).collect();
+    let cut = cut.rsplit_once(' ').map_or(cut.as_str(), |(words, _)| words);
+    Some(format!("{}…", cut))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_note_refs_are_short_or_marked_links() {
+        let html = r#"<p>Text<a href="notes.xhtml#n1" id="r1"><sup>1</sup></a> and
+            <a epub:type="noteref" href="../notes/end.xhtml#note-two">see the note</a>,
+            <a href="ch2.xhtml">2</a> <a href="http://example.com/#x">Site link</a></p>"#;
+        let refs = note_refs(html, Path::new("OEBPS/text/ch1.xhtml"));
+        let found: Vec<(&str, &str)> = refs.iter().map(|note| (note.label.as_str(), note.target.as_str())).collect();
+        assert_eq!(found, vec![("1", "OEBPS/text/notes.xhtml#n1"), ("see the note", "OEBPS/notes/end.xhtml#note-two")]);
+        assert!(refs[0].fraction < refs[1].fraction);
+    }
+
+    #[test]
+    fn test_note_text_ends_with_its_block() {
+        let html = r#"<ol><li id="n1"><p>First note, long enough to be worth a preview on its own.</p></li>
+            <li id="n2">Short.</li><li id="n3">Third &amp; last note, which runs on to the very end.</li></ol>
+            <dl><dt id="term">Word</dt><dd>What the word means, at some length here.</dd></dl>"#;
+        assert_eq!(note_text(html, "n1").unwrap(), "First note, long enough to be worth a preview on its own.");
+        assert_eq!(note_text(html, "n2").unwrap(), "Short. Third & last note, which runs on to the very end.");
+        assert_eq!(note_text(html, "term").unwrap(), "Word What the word means, at some length here.");
+        assert_eq!(note_text(html, "missing"), None);
+
+        let long = format!("<p id=\"long\">{}</p>", "word ".repeat(200));
+        let text = note_text(&long, "long").unwrap();
+        assert!(text.ends_with("word…") && text.chars().count() <= MAX_PREVIEW_CHARS + 1);
+    }
+}
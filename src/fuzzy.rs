@@ -0,0 +1,142 @@
+/// Points for every matched character.
+const SCORE_MATCH: i64 = 16;
+/// Extra for a character matched right after the previous one.
+const BONUS_CONSECUTIVE: i64 = 8;
+/// Extra for a character starting a word.
+const BONUS_BOUNDARY: i64 = 10;
+/// Extra for an uppercase letter after a lowercase one, as in `ePub`.
+const BONUS_CAMEL: i64 = 7;
+/// The bonus of the first character of a word of the pattern counts this
+/// many times, so words are found by their starts.
+const FIRST_MULTIPLIER: i64 = 2;
+/// Cost of skipping characters between two matched ones, and of every
+/// character skipped after the first.
+const GAP_START: i64 = 3;
+const GAP_EXTENSION: i64 = 1;
+
+/// How well a pattern matched a text, and where.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub score: i64,
+    /// Character indices in the text of the matched characters, in order
+    pub positions: Vec<usize>,
+}
+
+/// Matches `pattern` against `text` the way fuzzy finders do: every word
+/// of the pattern has to appear in the text in order, though not
+/// necessarily together, case ignored. Matches that keep characters
+/// together and start at word starts score higher. `None` when some
+/// word doesn't appear.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<Match> {
+    let chars: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = chars.iter().map(|c| c.to_lowercase().next().unwrap_or(*c)).collect();
+    let bonuses: Vec<i64> = (0..chars.len()).map(|j| bonus(&chars, j)).collect();
+    let mut total = Match { score: 0, positions: Vec::new() };
+    for word in pattern.split_whitespace() {
+        let word: Vec<char> = word.chars().map(|c| c.to_lowercase().next().unwrap_or(c)).collect();
+        let found = match_word(&word, &lower, &bonuses)?;
+        total.score += found.score;
+        total.positions.extend(found.positions);
+    }
+    total.positions.sort_unstable();
+    total.positions.dedup();
+    Some(total)
+}
+
+/// The bonus for matching the character at `j` of `chars`.
+fn bonus(chars: &[char], j: usize) -> i64 {
+    match j.checked_sub(1).map(|i| chars[i]) {
+        None => BONUS_BOUNDARY,
+        Some(before) if !before.is_alphanumeric() && chars[j].is_alphanumeric() => BONUS_BOUNDARY,
+        Some(before) if before.is_lowercase() && chars[j].is_uppercase() => BONUS_CAMEL,
+        _ => 0,
+    }
+}
+
+/// The best alignment of the lowercase `word` in the lowercase `text`,
+/// found by dynamic programming over where each character of the word
+/// matches.
+fn match_word(word: &[char], text: &[char], bonuses: &[i64]) -> Option<Match> {
+    const NONE: i64 = i64::MIN / 4;
+    let (m, n) = (word.len(), text.len());
+    if m == 0 {
+        return Some(Match { score: 0, positions: Vec::new() });
+    }
+    if m > n {
+        return None;
+    }
+    // `scores[i][j]`: the best score with `word[..=i]` matched and the
+    // last of them at `text[j]`; `from[i][j]` where `word[i - 1]` was
+    let mut scores = vec![vec![NONE; n]; m];
+    let mut from = vec![vec![0; n]; m];
+    for j in 0..n {
+        if text[j] == word[0] {
+            scores[0][j] = SCORE_MATCH + bonuses[j] * FIRST_MULTIPLIER;
+        }
+    }
+    for i in 1..m {
+        // The best earlier match of `word[i - 1]` with a gap before `j`,
+        // the gap already paid for
+        let mut gapped = (NONE, 0);
+        for j in i..n {
+            if j >= 2 {
+                let k = j - 2;
+                gapped.0 = gapped.0.saturating_sub(GAP_EXTENSION);
+                if scores[i - 1][k] > NONE && scores[i - 1][k] - GAP_START > gapped.0 {
+                    gapped = (scores[i - 1][k] - GAP_START, k);
+                }
+            }
+            if text[j] != word[i] {
+                continue;
+            }
+            let consecutive = scores[i - 1][j - 1];
+            let (before, k) = if consecutive > NONE && consecutive + BONUS_CONSECUTIVE >= gapped.0 {
+                (consecutive + BONUS_CONSECUTIVE, j - 1)
+            } else {
+                gapped
+            };
+            if before > NONE {
+                scores[i][j] = before + SCORE_MATCH + bonuses[j];
+                from[i][j] = k;
+            }
+        }
+    }
+
+    let (mut j, score) = scores[m - 1].iter().copied().enumerate().max_by_key(|&(j, score)| (score, std::cmp::Reverse(j)))?;
+    if score <= NONE {
+        return None;
+    }
+    let mut positions = vec![0; m];
+    for i in (0..m).rev() {
+        positions[i] = j;
+        j = from[i][j];
+    }
+    Some(Match { score, positions })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_matches_prefer_word_starts_and_runs() {
+        let war = fuzzy_match("wp", "War and Peace — Leo Tolstoy").unwrap();
+        assert_eq!(war.positions, [0, 8]);
+        assert!(fuzzy_match("war peace", "War and Peace").is_some());
+        assert!(fuzzy_match("pw", "War and Peace").is_none());
+        assert!(fuzzy_match("warx", "War").is_none());
+        assert_eq!(fuzzy_match("", "anything").unwrap().score, 0);
+
+        // A run beats scattered letters, and a word start beats the middle
+        let run = fuzzy_match("tol", "Tolstoy").unwrap();
+        let scattered = fuzzy_match("tol", "The Old Man").unwrap();
+        assert!(run.score > scattered.score, "{:?} {:?}", run, scattered);
+        let start = fuzzy_match("pea", "Peace").unwrap();
+        let middle = fuzzy_match("pea", "Appear").unwrap();
+        assert!(start.score > middle.score);
+
+        // The best alignment is found, not the first
+        assert_eq!(fuzzy_match("ab", "xaxb ab").unwrap().positions, [5, 6]);
+        assert_eq!(fuzzy_match("émi", "Zola, Émile").unwrap().positions, [6, 7, 8]);
+    }
+}
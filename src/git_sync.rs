@@ -0,0 +1,163 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, info};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::bookmark::Bookmarks;
+use crate::collections::Collections;
+use crate::history::History;
+use crate::queue::ReadingQueue;
+use crate::reading_time::ReadingTime;
+
+/// Files of reading state kept in the repository, all beside the
+/// bookmarks file.
+pub const STATE_FILES: [&str; 5] = ["bookmarks.json", "collections.json", "history.json", "queue.json", "reading_time.json"];
+
+/// File at the top of a repository that holds reading state, committed
+/// with it. Sync only works in repositories that have it, so it never
+/// commits to or pushes someone else's project.
+pub const MARKER: &str = ".bookrat-sync";
+
+/// Keeps the reading state in a git repository: local changes are
+/// committed, and merged with the remote's on start and pushed on exit
+/// when the repository has the configured remote.
+pub struct GitSync {
+    dir: PathBuf,
+    remote: String,
+}
+
+impl GitSync {
+    /// Syncs the state files in `dir`, which becomes a repository of its
+    /// own if it isn't in one. A repository bookrat didn't make, one
+    /// without the marker file, is refused, and so is a `dir` inside one.
+    pub fn new(dir: &Path, remote: &str) -> Result<Self> {
+        let sync = Self {
+            dir: if dir.as_os_str().is_empty() { PathBuf::from(".") } else { dir.to_path_buf() },
+            remote: remote.to_string(),
+        };
+        let here = sync.dir.canonicalize().with_context(|| format!("No directory {:?}", sync.dir))?;
+        match sync.git(&["rev-parse", "--show-toplevel"]) {
+            Ok(top) if Path::new(top.trim()) != here => {
+                bail!("{:?} is inside the git repository {}; keep the reading state elsewhere to sync it", sync.dir, top.trim())
+            }
+            Ok(_) if !sync.dir.join(MARKER).exists() => {
+                bail!("{:?} is a git repository bookrat didn't make; add an empty {} file to sync into it", sync.dir, MARKER)
+            }
+            Ok(_) => {}
+            Err(_) => {
+                info!("Creating a git repository for the reading state in {:?}", sync.dir);
+                sync.git(&["init"])?;
+                std::fs::write(sync.dir.join(MARKER), "").with_context(|| format!("Failed to write {}", MARKER))?;
+            }
+        }
+        Ok(sync)
+    }
+
+    /// Commits what changed locally, then merges in the remote's state.
+    /// Conflicting state files are merged entry by entry.
+    pub fn pull(&self) -> Result<()> {
+        self.commit()?;
+        let Some(branch) = self.remote_branch()? else {
+            return Ok(());
+        };
+        // Machines that each started their own history merge the first time
+        let pulled = self.git(&["pull", "--no-rebase", "--no-edit", "--allow-unrelated-histories", &self.remote, &branch]);
+        let Err(e) = pulled else {
+            return Ok(());
+        };
+        let conflicts = self.git(&["diff", "--name-only", "--diff-filter=U"])?;
+        let conflicts: Vec<&str> = conflicts.lines().collect();
+        if conflicts.is_empty() {
+            // Nothing pushed from anywhere yet is no failure
+            if e.to_string().contains("couldn't find remote ref") {
+                return Ok(());
+            }
+            return Err(e);
+        }
+        if let Some(other) = conflicts.iter().find(|file| !STATE_FILES.contains(file)) {
+            self.git(&["merge", "--abort"])?;
+            bail!("Can't merge {} from {}; resolve it by hand in {:?}", other, self.remote, self.dir);
+        }
+        for file in &conflicts {
+            let merged = match *file {
+                "bookmarks.json" => self.merge::<Bookmarks>(file, Bookmarks::merge)?,
+                "collections.json" => self.merge::<Collections>(file, Collections::merge)?,
+                "history.json" => self.merge::<History>(file, History::merge)?,
+                "queue.json" => self.merge::<ReadingQueue>(file, ReadingQueue::merge)?,
+                _ => self.merge::<ReadingTime>(file, ReadingTime::merge)?,
+            };
+            std::fs::write(self.dir.join(file), merged).with_context(|| format!("Failed to write {}", file))?;
+            self.git(&["add", "--", file])?;
+        }
+        self.git(&["commit", "--no-edit"])?;
+        info!("Merged the reading state in {} from {}", conflicts.join(", "), self.remote);
+        Ok(())
+    }
+
+    /// Commits the state files if they changed, then pushes them.
+    pub fn push(&self) -> Result<()> {
+        self.commit()?;
+        if let Some(branch) = self.remote_branch()? {
+            self.git(&["push", &self.remote, &format!("HEAD:{}", branch)])?;
+        }
+        Ok(())
+    }
+
+    fn commit(&self) -> Result<()> {
+        let files: Vec<&str> = STATE_FILES.iter().chain([&MARKER]).copied().filter(|file| self.dir.join(file).exists()).collect();
+        if files.is_empty() {
+            return Ok(());
+        }
+        let mut add = vec!["add", "--"];
+        add.extend(&files);
+        self.git(&add)?;
+        if self.git(&["diff", "--cached", "--quiet"]).is_ok() {
+            return Ok(());
+        }
+        let message = format!("Reading state of {}", chrono::Local::now().format("%Y-%m-%d %H:%M"));
+        self.git(&["commit", "-m", &message])?;
+        Ok(())
+    }
+
+    /// Branch to sync with on the remote, named like the local one; `None`
+    /// without the remote.
+    fn remote_branch(&self) -> Result<Option<String>> {
+        if !self.git(&["remote"])?.lines().any(|remote| remote == self.remote) {
+            return Ok(None);
+        }
+        let branch = self.git(&["symbolic-ref", "--short", "HEAD"])?;
+        Ok(Some(branch.trim().to_string()))
+    }
+
+    /// Both sides of the conflicted `file`, merged with `merge`.
+    fn merge<T: Serialize + DeserializeOwned>(&self, file: &str, merge: fn(&mut T, T)) -> Result<String> {
+        let side = |stage: u8| -> Result<T> {
+            let content = self.git(&["show", &format!(":{}:{}", stage, file)])?;
+            serde_json::from_str(&content).with_context(|| format!("Failed to parse {} from the merge", file))
+        };
+        // Stage 2 is the local side, 3 the remote's
+        let mut ours = side(2)?;
+        merge(&mut ours, side(3)?);
+        Ok(serde_json::to_string_pretty(&ours)?)
+    }
+
+    /// Runs git in the directory and gives back its output.
+    fn git(&self, args: &[&str]) -> Result<String> {
+        let Output { status, stdout, stderr } = Command::new("git")
+            .arg("-C")
+            .arg(&self.dir)
+            .args(args)
+            // Never wait on a password prompt nobody can see
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .output()
+            .context("Failed to run git")?;
+        if !status.success() {
+            let stderr = String::from_utf8_lossy(&stderr);
+            debug!("git {} failed: {}", args.join(" "), stderr.trim());
+            return Err(anyhow!("git {} failed: {}", args[0], stderr.trim()));
+        }
+        Ok(String::from_utf8_lossy(&stdout).into_owned())
+    }
+}
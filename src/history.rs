@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Oldest visits are dropped once the history grows past this many.
+const MAX_VISITS: usize = 500;
+
+/// A place the reader has been, updated while they stay in it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Visit {
+    pub book: String,
+    pub chapter: usize,
+    pub scroll_offset: usize,
+    pub time: chrono::DateTime<chrono::Utc>,
+}
+
+/// Reading history, oldest visit first, stored as `history.json` next to
+/// the bookmarks file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    visits: Vec<Visit>,
+    #[serde(skip)]
+    path: PathBuf,
+    /// Changed since the file was last written
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl History {
+    pub fn with_path<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            visits: Vec::new(),
+            path: path.as_ref().to_path_buf(),
+            dirty: false,
+        }
+    }
+
+    /// Loads the history kept beside `bookmarks_path`.
+    pub fn beside(bookmarks_path: &Path) -> anyhow::Result<Self> {
+        let dir = bookmarks_path.parent().unwrap_or(Path::new(""));
+        Self::load_from(dir.join("history.json"))
+    }
+
+    pub fn load_from<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if path.exists() {
+            let content = fs::read_to_string(path)?;
+            let mut history: Self = serde_json::from_str(&content)?;
+            history.path = path.to_path_buf();
+            Ok(history)
+        } else {
+            Ok(Self::with_path(path))
+        }
+    }
+
+    pub fn save(&mut self) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(&self.path, content)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Writes the file if anything changed since it was last written.
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        if self.dirty {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Newest visit first.
+    pub fn visits(&self) -> impl Iterator<Item = &Visit> {
+        self.visits.iter().rev()
+    }
+
+    pub fn len(&self) -> usize {
+        self.visits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.visits.is_empty()
+    }
+
+    /// Takes in the visits of another copy of the history, keeping them
+    /// all in time order.
+    pub fn merge(&mut self, other: History) {
+        self.visits.extend(other.visits);
+        self.visits.sort_by_key(|visit| visit.time);
+        self.visits.dedup();
+        if self.visits.len() > MAX_VISITS {
+            self.visits.drain(..self.visits.len() - MAX_VISITS);
+        }
+        self.dirty = true;
+    }
+
+    /// Records being at `chapter`/`scroll_offset` of `book`. Moving within
+    /// the part of the latest visit updates it; anything else starts a new
+    /// visit. Kept in memory until the next `flush` or `save`.
+    pub fn record(&mut self, book: &str, chapter: usize, scroll_offset: usize) {
+        let time = chrono::Utc::now();
+        match self.visits.last_mut() {
+            Some(last) if last.book == book && last.chapter == chapter => {
+                last.scroll_offset = scroll_offset;
+                last.time = time;
+            }
+            _ => {
+                self.visits.push(Visit {
+                    book: book.to_string(),
+                    chapter,
+                    scroll_offset,
+                    time,
+                });
+                if self.visits.len() > MAX_VISITS {
+                    self.visits.drain(..self.visits.len() - MAX_VISITS);
+                }
+            }
+        }
+        self.dirty = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_merges_moves_within_a_part() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("history.json");
+        let mut history = History::with_path(&path);
+
+        history.record("a.epub", 1, 0);
+        history.record("a.epub", 1, 40);
+        history.record("a.epub", 2, 0);
+        history.record("b.epub", 1, 3);
+        history.flush().unwrap();
+
+        let visits: Vec<(&str, usize, usize)> =
+            history.visits().map(|v| (v.book.as_str(), v.chapter, v.scroll_offset)).collect();
+        assert_eq!(visits, vec![("b.epub", 1, 3), ("a.epub", 2, 0), ("a.epub", 1, 40)]);
+
+        let reloaded = History::load_from(&path).unwrap();
+        assert_eq!(reloaded.len(), 3);
+    }
+
+    #[test]
+    fn test_history_is_capped() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut history = History::with_path(dir.path().join("history.json"));
+        for chapter in 0..MAX_VISITS + 10 {
+            history.record("a.epub", chapter, 0);
+        }
+        assert_eq!(history.len(), MAX_VISITS);
+        assert_eq!(history.visits().last().unwrap().chapter, 10);
+    }
+}
@@ -0,0 +1,95 @@
+use std::process::{Command, Stdio};
+
+use log::{debug, error};
+
+use crate::config::HooksConfig;
+
+/// Reading events a shell command can be run on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    BookOpened,
+    /// Read past the end of a part onto the next
+    ChapterFinished,
+    /// Reached the end of the last part
+    BookFinished,
+    HighlightCreated,
+}
+
+impl Event {
+    /// Name of the event in the config and in `BOOKRAT_EVENT`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Event::BookOpened => "book_opened",
+            Event::ChapterFinished => "chapter_finished",
+            Event::BookFinished => "book_finished",
+            Event::HighlightCreated => "highlight_created",
+        }
+    }
+}
+
+/// What a hook is told about the event, as `BOOKRAT_*` variables.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EventContext {
+    pub book: String,
+    pub title: String,
+    pub author: Option<String>,
+    /// 1-based, like the title bar
+    pub chapter: usize,
+    pub total_chapters: usize,
+    pub highlight: Option<String>,
+    pub note: Option<String>,
+}
+
+impl EventContext {
+    fn variables(&self, event: Event) -> Vec<(&'static str, String)> {
+        let mut variables = vec![
+            ("BOOKRAT_EVENT", event.name().to_string()),
+            ("BOOKRAT_BOOK", self.book.clone()),
+            ("BOOKRAT_TITLE", self.title.clone()),
+            ("BOOKRAT_AUTHOR", self.author.clone().unwrap_or_default()),
+            ("BOOKRAT_CHAPTER", self.chapter.to_string()),
+            ("BOOKRAT_TOTAL_CHAPTERS", self.total_chapters.to_string()),
+        ];
+        if let Some(highlight) = &self.highlight {
+            variables.push(("BOOKRAT_HIGHLIGHT", highlight.clone()));
+            variables.push(("BOOKRAT_NOTE", self.note.clone().unwrap_or_default()));
+        }
+        variables
+    }
+}
+
+/// Runs the command configured for `event`, if any, through the shell.
+/// It runs in the background with no terminal, since the screen belongs
+/// to the reader; a failure to start it is only logged.
+pub fn run(hooks: &HooksConfig, event: Event, context: &EventContext) {
+    let Some(command) = hooks.command(event) else {
+        return;
+    };
+    debug!("Running the {} hook: {}", event.name(), command);
+    if let Err(e) = spawn(shell(command).envs(context.variables(event))) {
+        error!("Failed to run the {} hook: {}", event.name(), e);
+    }
+}
+
+/// `command` to be run through `sh -c`, or `cmd /C` on Windows.
+pub(crate) fn shell(command: &str) -> Command {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    shell
+}
+
+/// Starts `command` in the background, with no terminal.
+pub(crate) fn spawn(command: &mut Command) -> std::io::Result<()> {
+    let mut child = command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn()?;
+    // Waited for on a thread of its own so it never lingers as a zombie
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
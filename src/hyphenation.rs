@@ -0,0 +1,230 @@
+/// How words of a language divide into syllables, for the wrapper to break
+/// long words between them.
+struct Rules {
+    vowels: &'static str,
+    /// Consonant pairs that begin a syllable together
+    onsets: &'static [&'static str],
+    /// Consonant pairs that end a syllable together
+    codas: &'static [&'static str],
+    /// Letters that close a syllable and never begin the next one
+    closing: &'static str,
+    /// Fewest letters left on either side of a break
+    min_prefix: usize,
+    min_suffix: usize,
+}
+
+const RULES: &[(&str, Rules)] = &[
+    (
+        "en",
+        Rules {
+            vowels: "aeiouy",
+            onsets: &["bl", "br", "cl", "cr", "dr", "fl", "fr", "gl", "gr", "pl", "pr", "tr", "ch", "ph", "wh"],
+            codas: &["ck", "gh", "sh", "th"],
+            closing: "",
+            min_prefix: 2,
+            min_suffix: 3,
+        },
+    ),
+    (
+        "fr",
+        Rules {
+            vowels: "aeiouyàâäéèêëîïôöùûüÿœæ",
+            onsets: &["bl", "br", "cl", "cr", "dr", "fl", "fr", "gl", "gr", "pl", "pr", "tr", "ch", "ph", "th", "gn"],
+            codas: &[],
+            closing: "",
+            min_prefix: 2,
+            min_suffix: 3,
+        },
+    ),
+    (
+        "de",
+        Rules {
+            vowels: "aeiouyäöü",
+            onsets: &["bl", "br", "cl", "cr", "dr", "fl", "fr", "gl", "gr", "pl", "pr", "tr", "ch", "ck", "ph", "th"],
+            codas: &[],
+            closing: "",
+            min_prefix: 2,
+            min_suffix: 2,
+        },
+    ),
+    (
+        "es",
+        Rules {
+            vowels: "aeiouáéíóúü",
+            onsets: &["bl", "br", "cl", "cr", "dr", "fl", "fr", "gl", "gr", "pl", "pr", "tr", "ch", "ll", "rr"],
+            codas: &[],
+            closing: "",
+            min_prefix: 2,
+            min_suffix: 2,
+        },
+    ),
+    (
+        "it",
+        Rules {
+            vowels: "aeiouàèéìíòóù",
+            onsets: &["bl", "br", "cl", "cr", "dr", "fl", "fr", "gl", "gr", "pl", "pr", "tr", "ch", "gh", "gn", "sc", "sp", "st"],
+            codas: &[],
+            closing: "",
+            min_prefix: 2,
+            min_suffix: 2,
+        },
+    ),
+    (
+        "pt",
+        Rules {
+            vowels: "aeiouáàâãéêíóôõú",
+            onsets: &["bl", "br", "cl", "cr", "dr", "fl", "fr", "gl", "gr", "pl", "pr", "tr", "ch", "lh", "nh"],
+            codas: &[],
+            closing: "",
+            min_prefix: 2,
+            min_suffix: 2,
+        },
+    ),
+    (
+        "nl",
+        Rules {
+            vowels: "aeiouyäëïöü",
+            onsets: &["bl", "br", "cl", "cr", "dr", "fl", "fr", "gl", "gr", "pl", "pr", "tr", "ch", "ph", "th"],
+            codas: &[],
+            closing: "",
+            min_prefix: 2,
+            min_suffix: 2,
+        },
+    ),
+    (
+        "ru",
+        Rules {
+            vowels: "аеёиоуыэюя",
+            onsets: &[],
+            codas: &[],
+            closing: "ьъй",
+            min_prefix: 2,
+            min_suffix: 2,
+        },
+    ),
+    (
+        "uk",
+        Rules {
+            vowels: "аеєиіїоуюя",
+            onsets: &[],
+            codas: &[],
+            closing: "ьй",
+            min_prefix: 2,
+            min_suffix: 2,
+        },
+    ),
+];
+
+/// Shortest word worth breaking.
+const MIN_WORD_LETTERS: usize = 6;
+
+/// U+00AD, the optional break the wrapper shows as a hyphen.
+const SOFT_HYPHEN: char = '\u{AD}';
+
+/// The rules for `language`, a language tag such as `en-GB`.
+fn rules(language: &str) -> Option<&'static Rules> {
+    let primary = language.split(['-', '_']).next()?.trim().to_lowercase();
+    RULES.iter().find(|(tag, _)| *tag == primary).map(|(_, rules)| rules)
+}
+
+/// `text` with soft hyphens between the syllables of its long words, by
+/// the rules of `language`. Text in a language without rules is left
+/// alone, and so is text that has soft hyphens already, as its publisher
+/// hyphenated it.
+pub fn hyphenate(text: &str, language: &str) -> String {
+    let Some(rules) = rules(language).filter(|_| !text.contains(SOFT_HYPHEN)) else {
+        return text.to_string();
+    };
+    let mut hyphenated = String::with_capacity(text.len());
+    let mut word = Vec::new();
+    for c in text.chars() {
+        if c.is_alphabetic() {
+            word.push(c);
+            continue;
+        }
+        push_word(&mut hyphenated, &word, rules);
+        word.clear();
+        hyphenated.push(c);
+    }
+    push_word(&mut hyphenated, &word, rules);
+    hyphenated
+}
+
+fn push_word(hyphenated: &mut String, word: &[char], rules: &Rules) {
+    let breaks = if word.len() >= MIN_WORD_LETTERS { syllable_breaks(word, rules) } else { Vec::new() };
+    for (i, c) in word.iter().enumerate() {
+        if breaks.contains(&i) {
+            hyphenated.push(SOFT_HYPHEN);
+        }
+        hyphenated.push(*c);
+    }
+}
+
+/// Letter indices of `word` that begin a new syllable. Vowels next to each
+/// other are one nucleus; of the consonants between two nuclei the last
+/// one, or the last two when they begin syllables together, go with the
+/// second.
+fn syllable_breaks(word: &[char], rules: &Rules) -> Vec<usize> {
+    let lower: Vec<char> = word.iter().flat_map(|c| c.to_lowercase()).collect();
+    if lower.len() != word.len() {
+        return Vec::new();
+    }
+    let is_vowel = |i: usize| rules.vowels.contains(lower[i]);
+    let pair = |i: usize| lower[i..i + 2].iter().collect::<String>();
+
+    let mut breaks = Vec::new();
+    let mut previous_nucleus_end = None;
+    let mut i = 0;
+    while i < lower.len() {
+        if !is_vowel(i) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < lower.len() && is_vowel(i) {
+            i += 1;
+        }
+        if let Some(end) = previous_nucleus_end {
+            let mut at = if start - end >= 2 && rules.onsets.contains(&pair(start - 2).as_str()) {
+                start - 2
+            } else {
+                start - 1
+            };
+            if at > end && rules.codas.contains(&pair(at - 1).as_str()) {
+                at += 1;
+            }
+            if rules.closing.contains(lower[at]) {
+                at += 1;
+            }
+            if at >= rules.min_prefix && word.len() - at >= rules.min_suffix {
+                breaks.push(at);
+            }
+        }
+        previous_nucleus_end = Some(i);
+    }
+    breaks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shown(text: &str, language: &str) -> String {
+        hyphenate(text, language).replace(SOFT_HYPHEN, "-")
+    }
+
+    #[test]
+    fn test_words_are_broken_between_syllables() {
+        assert_eq!(shown("Remember the chickens, mother", "en-GB"), "Re-mem-ber the chick-ens, mother");
+        assert_eq!(
+            shown("Together, without washing, laughing at mathematics by the telephone in the kitchen", "en"),
+            "To-gether, with-out wash-ing, laugh-ing at math-e-ma-tics by the te-le-phone in the kit-chen"
+        );
+        assert_eq!(shown("Wunderbares Gebirge", "de"), "Wun-der-ba-res Ge-bir-ge");
+        assert_eq!(shown("большой праздник", "ru"), "боль-шой празд-ник");
+        // Short words, other languages and hyphenated text are left alone
+        assert_eq!(shown("Short words", "en"), "Short words");
+        assert_eq!(shown("remember", "ja"), "remember");
+        assert_eq!(hyphenate("re\u{AD}member", "en"), "re\u{AD}member");
+    }
+}
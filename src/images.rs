@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use std::io::Cursor;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::{imageops::FilterType, ImageFormat, Rgb, RgbImage};
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+
+/// Longest side pictures are kept at once decoded; covers are shown far
+/// smaller than they are stored.
+const MAX_SIDE: u32 = 600;
+/// Pixels of a terminal cell when the terminal doesn't say.
+const DEFAULT_CELL: (u32, u32) = (10, 20);
+/// Base64 characters sent per kitty graphics escape, as the protocol asks.
+const KITTY_CHUNK: usize = 4096;
+
+/// How pictures are drawn, as chosen in the config.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Graphics {
+    /// The best the terminal is known to support
+    #[default]
+    Auto,
+    Kitty,
+    Sixel,
+    /// Coloured half blocks, which every terminal with true colour shows
+    Blocks,
+    /// No pictures
+    Off,
+}
+
+impl Graphics {
+    /// The protocol pictures are drawn with, `None` for none at all.
+    pub fn protocol(self) -> Option<Protocol> {
+        match self {
+            Self::Auto => Some(detect(|name| std::env::var(name).ok())),
+            Self::Kitty => Some(Protocol::Kitty),
+            Self::Sixel => Some(Protocol::Sixel),
+            Self::Blocks => Some(Protocol::Blocks),
+            Self::Off => None,
+        }
+    }
+}
+
+/// A way of drawing pictures in the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// The kitty graphics protocol, also spoken by WezTerm and Ghostty
+    Kitty,
+    Sixel,
+    /// Two pixels per cell, drawn with `▀` in the text itself
+    Blocks,
+}
+
+/// The protocol the terminal described by the environment supports,
+/// reading variables with `var`. Terminals are told apart by the names
+/// they set, as asking them would mean reading their answer among the keys.
+pub fn detect(var: impl Fn(&str) -> Option<String>) -> Protocol {
+    let term = var("TERM").unwrap_or_default();
+    let program = var("TERM_PROGRAM").unwrap_or_default();
+    // Multiplexers pass neither protocol through by default
+    if var("TMUX").is_some() || term.starts_with("screen") || term.starts_with("tmux") {
+        return Protocol::Blocks;
+    }
+    if var("KITTY_WINDOW_ID").is_some() || term.contains("kitty") || term.contains("ghostty") || ["WezTerm", "ghostty"].contains(&program.as_str()) {
+        Protocol::Kitty
+    } else if ["foot", "mlterm", "yaft"].iter().any(|name| term.starts_with(name)) || term.contains("sixel") || program == "iTerm.app" {
+        Protocol::Sixel
+    } else {
+        Protocol::Blocks
+    }
+}
+
+/// Decodes the PNG, JPEG, GIF or WebP image in `bytes`, shrunk to no more
+/// than `MAX_SIDE` pixels a side. `None` when it can't be decoded.
+pub fn decode(bytes: &[u8]) -> Option<RgbImage> {
+    decode_within(bytes, MAX_SIDE)
+}
+
+/// Like `decode`, shrinking the image to `side` pixels a side at most.
+pub fn decode_within(bytes: &[u8], side: u32) -> Option<RgbImage> {
+    let image = image::load_from_memory(bytes).map_err(|e| log::warn!("Failed to decode an image: {}", e)).ok()?;
+    let image = if image.width().max(image.height()) > side { image.thumbnail(side, side) } else { image };
+    Some(image.to_rgb8())
+}
+
+/// The largest area of `area`, anchored at its top left, that shows
+/// `image` without stretching it, taking a cell to be twice as tall as it
+/// is wide.
+pub fn fit(image: &RgbImage, area: Rect) -> Rect {
+    let (width, height) = (image.width().max(1) as u64, image.height().max(1) as u64);
+    let rows_for = |columns: u64| (columns * height).div_ceil(2 * width);
+    let mut columns = area.width as u64;
+    while columns > 1 && rows_for(columns) > area.height as u64 {
+        columns -= 1;
+    }
+    let rows = rows_for(columns).clamp(1, area.height.max(1) as u64);
+    Rect::new(area.x, area.y, columns as u16, rows.min(area.height as u64) as u16)
+}
+
+/// Draws `image` into `area` of `buffer` with half blocks: each cell
+/// shows a pixel above another, as the foreground and background of `▀`.
+pub fn render_blocks(image: &RgbImage, area: Rect, buffer: &mut Buffer) {
+    let area = fit(image, area);
+    if area.is_empty() {
+        return;
+    }
+    let pixels = image::imageops::resize(image, area.width as u32, area.height as u32 * 2, FilterType::Triangle);
+    let color = |pixel: &Rgb<u8>| Color::Rgb(pixel[0], pixel[1], pixel[2]);
+    for row in 0..area.height {
+        for column in 0..area.width {
+            let top = pixels.get_pixel(column as u32, row as u32 * 2);
+            let bottom = pixels.get_pixel(column as u32, row as u32 * 2 + 1);
+            buffer.get_mut(area.x + column, area.y + row).set_symbol("▀").set_fg(color(top)).set_bg(color(bottom));
+        }
+    }
+}
+
+/// The escapes that draw `image` over `area` of the screen with
+/// `protocol`, to be written after the frame. Empty for `Blocks`, which
+/// draws into the frame itself.
+pub fn escape(image: &RgbImage, area: Rect, protocol: Protocol) -> String {
+    let area = fit(image, area);
+    if area.is_empty() {
+        return String::new();
+    }
+    let mut out = format!("\x1b7\x1b[{};{}H", area.y + 1, area.x + 1);
+    match protocol {
+        Protocol::Kitty => out.push_str(&kitty(image, area.width, area.height)),
+        Protocol::Sixel => {
+            let (cell_width, cell_height) = cell_size();
+            let pixels = image::imageops::resize(image, area.width as u32 * cell_width, area.height as u32 * cell_height, FilterType::Triangle);
+            out.push_str(&sixel(&pixels));
+        }
+        Protocol::Blocks => return String::new(),
+    }
+    out.push_str("\x1b8");
+    out
+}
+
+/// What to write to the terminal after a frame, as the pictures on
+/// screen changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Update {
+    /// The frame has to be drawn again in full first, to paint over a
+    /// sixel picture, which is made of pixels rather than cells
+    pub redraw: bool,
+    pub sequence: String,
+}
+
+/// Escape taking away every picture kitty shows.
+pub const KITTY_DELETE: &str = "\x1b_Ga=d,q=2\x1b\\";
+
+/// Sends `image` as a PNG in kitty's graphics protocol, scaled by the
+/// terminal to `columns` by `rows` cells at the cursor.
+fn kitty(image: &RgbImage, columns: u16, rows: u16) -> String {
+    let mut png = Vec::new();
+    if let Err(e) = image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png) {
+        log::warn!("Failed to encode an image: {}", e);
+        return String::new();
+    }
+    let data = STANDARD.encode(png);
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut out = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+        if i == 0 {
+            out.push_str(&format!("\x1b_Ga=T,f=100,q=2,C=1,c={},r={},m={};{}\x1b\\", columns, rows, more, chunk));
+        } else {
+            out.push_str(&format!("\x1b_Gm={};{}\x1b\\", more, chunk));
+        }
+    }
+    out
+}
+
+/// Encodes `image` as sixels, its colours rounded to a 6×6×6 cube.
+fn sixel(image: &RgbImage) -> String {
+    let level = |value: u8| (value as usize * 5 + 127) / 255;
+    let index = |pixel: &Rgb<u8>| level(pixel[0]) * 36 + level(pixel[1]) * 6 + level(pixel[2]);
+    let (width, height) = image.dimensions();
+    let mut out = format!("\x1bPq\"1;1;{};{}", width, height);
+    let mut used = [false; 216];
+    for pixel in image.pixels() {
+        used[index(pixel)] = true;
+    }
+    for color in (0..216).filter(|color| used[*color]) {
+        let percent = |level: usize| level * 100 / 5;
+        out.push_str(&format!("#{};2;{};{};{}", color, percent(color / 36), percent(color / 6 % 6), percent(color % 6)));
+    }
+    for band in (0..height).step_by(6) {
+        // Which of the six rows of the band each colour covers, by column
+        let mut rows: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+        for y in band..(band + 6).min(height) {
+            for x in 0..width {
+                let columns = rows.entry(index(image.get_pixel(x, y))).or_insert_with(|| vec![0; width as usize]);
+                columns[x as usize] |= 1 << (y - band);
+            }
+        }
+        for (i, (color, columns)) in rows.iter().enumerate() {
+            if i > 0 {
+                out.push('$');
+            }
+            out.push_str(&format!("#{}", color));
+            let mut start = 0;
+            for end in 1..=columns.len() {
+                if end == columns.len() || columns[end] != columns[start] {
+                    push_run(&mut out, char::from(63 + columns[start]), end - start);
+                    start = end;
+                }
+            }
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+/// Appends `count` of the sixel `c`, run-length encoded when that is shorter.
+fn push_run(out: &mut String, c: char, count: usize) {
+    if count > 3 {
+        out.push_str(&format!("!{}{}", count, c));
+    } else {
+        out.extend(std::iter::repeat_n(c, count));
+    }
+}
+
+/// Pixels of a terminal cell, as the terminal reports its size.
+fn cell_size() -> (u32, u32) {
+    match crossterm::terminal::window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => {
+            ((size.width / size.columns) as u32, (size.height / size.rows) as u32)
+        }
+        _ => DEFAULT_CELL,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_terminals_are_told_apart_by_their_environment() {
+        let detect_with = |vars: &[(&str, &str)]| {
+            let vars: Vec<(String, String)> = vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+            detect(|name| vars.iter().find(|(var, _)| var == name).map(|(_, value)| value.clone()))
+        };
+        assert_eq!(detect_with(&[("TERM", "xterm-kitty")]), Protocol::Kitty);
+        assert_eq!(detect_with(&[("TERM_PROGRAM", "WezTerm")]), Protocol::Kitty);
+        assert_eq!(detect_with(&[("TERM", "foot")]), Protocol::Sixel);
+        assert_eq!(detect_with(&[("TERM", "xterm-256color")]), Protocol::Blocks);
+        assert_eq!(detect_with(&[("TERM", "xterm-kitty"), ("TMUX", "/tmp/tmux")]), Protocol::Blocks);
+    }
+
+    #[test]
+    fn test_pictures_keep_their_shape_in_every_protocol() {
+        // Red above blue, twice as tall as wide
+        let image = RgbImage::from_fn(4, 8, |_, y| if y < 4 { Rgb([255, 0, 0]) } else { Rgb([0, 0, 255]) });
+        assert_eq!(fit(&image, Rect::new(2, 1, 10, 4)), Rect::new(2, 1, 4, 4));
+        assert_eq!(fit(&image, Rect::new(0, 0, 3, 20)), Rect::new(0, 0, 3, 3));
+
+        let mut buffer = Buffer::empty(Rect::new(0, 0, 10, 4));
+        render_blocks(&image, buffer.area, &mut buffer);
+        let cell = buffer.get(0, 0);
+        assert_eq!((cell.symbol(), cell.fg, cell.bg), ("▀", Color::Rgb(255, 0, 0), Color::Rgb(255, 0, 0)));
+        assert_eq!(buffer.get(3, 3).bg, Color::Rgb(0, 0, 255));
+        assert_eq!(buffer.get(4, 0).symbol(), " ");
+
+        let kitty = escape(&image, Rect::new(2, 1, 10, 4), Protocol::Kitty);
+        assert!(kitty.starts_with("\x1b7\x1b[2;3H\x1b_Ga=T,f=100,q=2,C=1,c=4,r=4,m=0;"), "{:?}", kitty);
+        let sixels = sixel(&image);
+        // Red is colour 180 of the cube and blue 5; the top band is red
+        // for four rows and blue for two, the second all blue
+        assert!(sixels.starts_with("\x1bPq\"1;1;4;8#5;2;0;0;100#180;2;100;0;0#5!4o$#180!4N-"), "{:?}", sixels);
+        assert!(sixels.ends_with("-#5!4B-\x1b\\"), "{:?}", sixels);
+        assert!(escape(&image, Rect::new(0, 0, 4, 4), Protocol::Blocks).is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+use epub::doc::EpubVersion;
+use ratatui::{
+    style::{Color, Style, Stylize},
+    text::Line,
+};
+
+use crate::book::Book;
+
+fn heading(text: String) -> Line<'static> {
+    Line::from(text).style(Style::default().fg(Color::Yellow).bold())
+}
+
+fn item(text: String) -> Line<'static> {
+    Line::from(format!("  {}", text))
+}
+
+/// Describes the internals of `book` for the inspector panel: the current
+/// spine item, the package metadata, the spine in reading order and the
+/// manifest. Spine items that are non-linear or missing from the manifest
+/// are flagged, since those are the usual reasons a part looks wrong.
+pub fn describe(book: &Book) -> Vec<Line<'static>> {
+    let doc = match book {
+        Book::Epub(doc) => doc,
+        Book::Salvaged(salvaged) => {
+            let mut lines = vec![
+                heading("Salvaged book".to_string()),
+                item("The package document is missing or unreadable; parts are the HTML files in the archive".to_string()),
+                Line::from(""),
+                heading(format!("Parts ({})", salvaged.entries().len())),
+            ];
+            let current = book.current_chapter();
+            lines.extend(salvaged.entries().iter().enumerate().map(|(i, name)| {
+                let marker = if i == current { "▶" } else { " " };
+                item(format!("{} {}. {}", marker, i + 1, name))
+            }));
+            return lines;
+        }
+        Book::Document(_) => {
+            return vec![
+                heading("Piped document".to_string()),
+                item("Read from standard input as a single part; it has no package document".to_string()),
+            ];
+        }
+    };
+
+    let current = doc.get_current_chapter();
+    let mut lines = vec![heading("Current item".to_string())];
+    lines.push(item(format!(
+        "Part {} of {}: id {}, href {}, {}",
+        current + 1,
+        doc.spine.len(),
+        doc.get_current_id().unwrap_or_else(|| "?".to_string()),
+        doc.get_current_path().map_or("?".to_string(), |path| path.display().to_string()),
+        doc.get_current_mime().unwrap_or_else(|| "?".to_string()),
+    )));
+
+    lines.push(Line::from(""));
+    lines.push(heading("Package".to_string()));
+    let version = match &doc.version {
+        EpubVersion::Version2_0 => "2.0".to_string(),
+        EpubVersion::Version3_0 => "3.0".to_string(),
+        EpubVersion::Unknown(version) => version.clone(),
+    };
+    lines.push(item(format!("EPUB {}, package document {}", version, doc.root_file.display())));
+    if let Some(id) = &doc.unique_identifier {
+        lines.push(item(format!("unique identifier: {}", id)));
+    }
+
+    lines.push(Line::from(""));
+    lines.push(heading(format!("Metadata ({})", doc.metadata.len())));
+    lines.extend(doc.metadata.iter().map(|meta| {
+        let lang = meta.lang.as_deref().map(|lang| format!(" [{}]", lang)).unwrap_or_default();
+        item(format!("{}: {}{}", meta.property, meta.value.trim(), lang))
+    }));
+
+    lines.push(Line::from(""));
+    lines.push(heading(format!("Spine ({})", doc.spine.len())));
+    for (i, spine_item) in doc.spine.iter().enumerate() {
+        let marker = if i == current { "▶" } else { " " };
+        let target = match doc.resources.get(&spine_item.idref) {
+            Some(resource) => format!("{} ({})", resource.path.display(), resource.mime),
+            None => "not in the manifest".to_string(),
+        };
+        let linear = if spine_item.linear { "" } else { " [non-linear]" };
+        lines.push(item(format!("{} {}. {} → {}{}", marker, i + 1, spine_item.idref, target, linear)));
+    }
+
+    lines.push(Line::from(""));
+    lines.push(heading(format!("Manifest ({})", doc.resources.len())));
+    let mut resources: Vec<_> = doc.resources.iter().collect();
+    resources.sort_by(|a, b| a.1.path.cmp(&b.1.path));
+    lines.extend(resources.into_iter().map(|(id, resource)| {
+        let properties = resource.properties.as_deref().map(|p| format!(" [{}]", p)).unwrap_or_default();
+        item(format!("{}  {}  {}{}", id, resource.path.display(), resource.mime, properties))
+    }));
+    lines
+}
@@ -0,0 +1,257 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use epub::doc::EpubDoc;
+use log::{info, warn};
+use rusqlite::{params, Connection, OpenFlags};
+
+use crate::bookmark::{Bookmarks, Highlight};
+use crate::catalog::Entry;
+
+/// The reader's database, relative to the root of a mounted Kobo.
+const DATABASE: &str = ".kobo/KoboReader.sqlite";
+
+/// Where Linux and macOS mount removable drives.
+fn mount_roots() -> Vec<PathBuf> {
+    let mut roots = vec![PathBuf::from("/Volumes"), PathBuf::from("/media")];
+    if let Ok(user) = std::env::var("USER") {
+        roots.push(Path::new("/media").join(&user));
+        roots.push(Path::new("/run/media").join(&user));
+    }
+    roots
+}
+
+/// Mounted drives that look like a Kobo.
+pub fn find_mounts() -> Vec<PathBuf> {
+    mount_roots()
+        .iter()
+        .filter_map(|root| std::fs::read_dir(root).ok())
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.join(DATABASE).is_file())
+        .collect()
+}
+
+/// A book as the Kobo's database knows it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KoboBook {
+    pub content_id: String,
+    pub title: String,
+    /// Part of the book the reader was last at, as a path inside the EPUB
+    pub chapter: Option<String>,
+    pub last_read: Option<DateTime<Utc>>,
+    pub highlights: Vec<KoboHighlight>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KoboHighlight {
+    /// Path inside the EPUB of the part the passage is in
+    pub chapter: String,
+    pub text: String,
+    pub note: Option<String>,
+    pub created: Option<DateTime<Utc>>,
+}
+
+/// What a sync did.
+#[derive(Debug, Default, PartialEq)]
+pub struct SyncReport {
+    /// Positions taken from the Kobo, where it was read last
+    pub positions: usize,
+    /// Highlights new to bookrat, with the path of their book
+    pub highlights: Vec<(String, Highlight)>,
+    /// Positions written to the Kobo, where bookrat was read last
+    pub written_back: usize,
+    /// Kobo books not found in the library
+    pub unmatched: Vec<String>,
+}
+
+/// Reads the sideloaded books of the Kobo mounted at `mount`, with their
+/// positions and highlights.
+pub fn read_books(mount: &Path) -> Result<Vec<KoboBook>> {
+    let connection = Connection::open_with_flags(mount.join(DATABASE), OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open the Kobo database in {:?}", mount))?;
+    let mut books = Vec::new();
+    {
+        let mut statement = connection.prepare(
+            "SELECT ContentID, Title, ChapterIDBookmarked, DateLastRead FROM content
+             WHERE ContentType = 6 AND ContentID LIKE 'file://%'",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok(KoboBook {
+                content_id: row.get(0)?,
+                title: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                chapter: row.get::<_, Option<String>>(2)?.filter(|chapter| !chapter.is_empty()).map(|c| strip_fragment(&c)),
+                last_read: row.get::<_, Option<String>>(3)?.as_deref().and_then(parse_date),
+                highlights: Vec::new(),
+            })
+        })?;
+        for book in rows {
+            books.push(book?);
+        }
+    }
+    let mut statement = connection.prepare(
+        "SELECT ContentID, Text, Annotation, DateCreated FROM Bookmark
+         WHERE VolumeID = ?1 AND Text IS NOT NULL AND Text != '' ORDER BY DateCreated",
+    )?;
+    for book in &mut books {
+        let volume = book.content_id.clone();
+        let rows = statement.query_map(params![volume], |row| {
+            Ok(KoboHighlight {
+                chapter: chapter_of_content_id(&row.get::<_, String>(0)?, &volume),
+                text: row.get::<_, String>(1)?.trim().to_string(),
+                note: row.get::<_, Option<String>>(2)?.map(|note| note.trim().to_string()).filter(|note| !note.is_empty()),
+                created: row.get::<_, Option<String>>(3)?.as_deref().and_then(parse_date),
+            })
+        })?;
+        for highlight in rows {
+            book.highlights.push(highlight?);
+        }
+    }
+    info!("Read {} books from the Kobo at {:?}", books.len(), mount);
+    Ok(books)
+}
+
+/// Brings the Kobo at `mount` and `bookmarks` together for the books of
+/// `library`: positions go whichever way is older, and highlights are
+/// imported. The Kobo is only written to with `write_back`.
+pub fn sync(mount: &Path, library: &[String], bookmarks: &mut Bookmarks, write_back: bool) -> Result<SyncReport> {
+    let mut report = SyncReport::default();
+    let books = read_books(mount)?;
+    let connection = if write_back {
+        Some(Connection::open(mount.join(DATABASE)).context("Failed to open the Kobo database for writing")?)
+    } else {
+        None
+    };
+    for kobo in books {
+        let Some(path) = find_in_library(&kobo, library) else {
+            report.unmatched.push(kobo.title.clone());
+            continue;
+        };
+        let doc = match EpubDoc::new(path) {
+            Ok(doc) => doc,
+            Err(e) => {
+                warn!("Skipping {}: {}", path, e);
+                continue;
+            }
+        };
+        let spine = spine_paths(&doc);
+
+        let ours = bookmarks.get_bookmark(path).map(|bookmark| (bookmark.chapter, bookmark.last_read));
+        let theirs = kobo.chapter.as_deref().and_then(|chapter| spine_index(&spine, chapter)).zip(kobo.last_read);
+        if let Some((chapter, theirs)) = theirs {
+            if ours.is_none_or(|(_, ours)| ours < theirs) {
+                bookmarks.set_position(path, chapter, 0, theirs);
+                report.positions += 1;
+            }
+        }
+        if let (Some(connection), Some((chapter, last_read))) = (&connection, ours) {
+            if kobo.last_read.is_none_or(|theirs| theirs < last_read) && chapter < spine.len() {
+                let progress = (chapter * 100 / spine.len()) as i64;
+                connection.execute(
+                    "UPDATE content SET ChapterIDBookmarked = ?1, DateLastRead = ?2, ___PercentRead = ?3,
+                     ReadStatus = MAX(ReadStatus, 1) WHERE ContentID = ?4",
+                    params![spine[chapter], last_read.format("%Y-%m-%dT%H:%M:%SZ").to_string(), progress, kobo.content_id],
+                )?;
+                report.written_back += 1;
+            }
+        }
+
+        for highlight in &kobo.highlights {
+            let chapter = spine_index(&spine, &highlight.chapter).unwrap_or(0);
+            let highlight = Highlight {
+                chapter,
+                text: highlight.text.clone(),
+                note: highlight.note.clone(),
+                created: highlight.created.unwrap_or_else(Utc::now),
+            };
+            if bookmarks.add_highlight(path, highlight.clone()) {
+                report.highlights.push((path.clone(), highlight));
+            }
+        }
+    }
+    bookmarks.flush()?;
+    info!("Kobo sync: {:?}", report);
+    Ok(report)
+}
+
+/// The library book a Kobo book is a copy of: the same file name (a
+/// `.kepub.epub` matches its `.epub`), or else the same title.
+fn find_in_library<'a>(kobo: &KoboBook, library: &'a [String]) -> Option<&'a String> {
+    let stem = |path: &str| {
+        let name = path.rsplit('/').next().unwrap_or_default().to_lowercase();
+        name.trim_end_matches(".epub").trim_end_matches(".kepub").to_string()
+    };
+    let kobo_stem = stem(&kobo.content_id);
+    library.iter().find(|file| stem(file) == kobo_stem).or_else(|| {
+        let title = kobo.title.trim().to_lowercase();
+        (!title.is_empty()).then(|| library.iter().find(|file| Entry::read(file).title.to_lowercase() == title)).flatten()
+    })
+}
+
+/// Paths inside the archive of the spine items, in reading order.
+fn spine_paths<R: std::io::Read + std::io::Seek>(doc: &EpubDoc<R>) -> Vec<String> {
+    doc.spine
+        .iter()
+        .map(|item| doc.resources.get(&item.idref).map(|resource| resource.path.to_string_lossy().to_string()).unwrap_or_default())
+        .collect()
+}
+
+/// Spine index of the part at `chapter`, a path inside the EPUB that may
+/// be relative to a different folder than the package document.
+fn spine_index(spine: &[String], chapter: &str) -> Option<usize> {
+    let chapter = chapter.trim_start_matches('/');
+    spine
+        .iter()
+        .position(|path| path == chapter || path.ends_with(&format!("/{}", chapter)) || chapter.ends_with(&format!("/{}", path)))
+        .or_else(|| {
+            let name = chapter.rsplit('/').next()?;
+            spine.iter().position(|path| path.rsplit('/').next() == Some(name))
+        })
+}
+
+/// The part a highlight's `content_id` points into: what follows the
+/// book's own id, written `#(N)OEBPS/ch1.xhtml` or `!OEBPS!ch1.xhtml`.
+fn chapter_of_content_id(content_id: &str, volume: &str) -> String {
+    let volume = volume.trim_start_matches("file://");
+    let content_id = content_id.trim_start_matches("file://");
+    let rest = content_id.strip_prefix(volume).unwrap_or(content_id);
+    let rest = rest.trim_start_matches(['#', '!', '/']);
+    let rest = match rest.strip_prefix('(') {
+        Some(numbered) => numbered.split_once(')').map_or(numbered, |(_, path)| path),
+        None => rest,
+    };
+    strip_fragment(&rest.replace('!', "/"))
+}
+
+fn strip_fragment(path: &str) -> String {
+    path.split('#').next().unwrap_or_default().to_string()
+}
+
+/// Kobo dates look like `2024-03-01T20:15:00Z` or `2024-03-01T20:15:00.000`.
+fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(date)
+        .map(|date| date.with_timezone(&Utc))
+        .ok()
+        .or_else(|| NaiveDateTime::parse_from_str(date.trim_end_matches('Z'), "%Y-%m-%dT%H:%M:%S%.f").ok().map(|date| date.and_utc()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chapter_paths() {
+        let volume = "file:///mnt/onboard/Books/war.epub";
+        assert_eq!(chapter_of_content_id("/mnt/onboard/Books/war.epub#(2)OEBPS/ch2.xhtml", volume), "OEBPS/ch2.xhtml");
+        assert_eq!(chapter_of_content_id("/mnt/onboard/Books/war.epub!OEBPS!Text/ch3.xhtml#kobo.1.1", volume), "OEBPS/Text/ch3.xhtml");
+        let spine = vec!["OEBPS/title.xhtml".to_string(), "OEBPS/Text/ch1.xhtml".to_string()];
+        assert_eq!(spine_index(&spine, "Text/ch1.xhtml"), Some(1));
+        assert_eq!(spine_index(&spine, "/OEBPS/Text/ch1.xhtml"), Some(1));
+        assert_eq!(spine_index(&spine, "elsewhere/ch1.xhtml"), Some(1));
+        assert_eq!(spine_index(&spine, "ch9.xhtml"), None);
+        assert!(parse_date("2024-03-01T20:15:00Z").is_some());
+        assert_eq!(parse_date("2024-03-01T20:15:00.000"), parse_date("2024-03-01T20:15:00Z"));
+    }
+}
@@ -0,0 +1,103 @@
+/// Short, frequent words of the languages told apart by their words, the
+/// ones written in the Latin script.
+const COMMON_WORDS: &[(&str, &[&str])] = &[
+    ("en", &["the", "and", "of", "to", "is", "was", "that", "with", "he", "it", "you", "for"]),
+    ("fr", &["le", "la", "les", "et", "est", "une", "des", "que", "il", "dans", "pas", "du"]),
+    ("de", &["der", "die", "und", "ist", "das", "nicht", "ein", "ich", "sie", "zu", "mit", "den"]),
+    ("es", &["el", "los", "las", "y", "que", "en", "una", "por", "con", "del", "se", "no"]),
+    ("it", &["il", "che", "di", "e", "non", "un", "per", "una", "sono", "della", "gli", "è"]),
+    ("pt", &["o", "os", "que", "não", "uma", "com", "do", "da", "em", "um", "para", "é"]),
+    ("nl", &["de", "het", "een", "en", "van", "niet", "is", "dat", "ik", "je", "op", "zijn"]),
+];
+
+/// Fewest common words for a guess from the Latin script to be trusted.
+const MIN_COMMON_WORDS: usize = 5;
+
+/// Whether `tag` names a language, rather than being empty or one of the
+/// codes for an undetermined or mixed one.
+pub fn is_declared(tag: &str) -> bool {
+    let tag = tag.trim().to_lowercase();
+    !tag.is_empty() && !["und", "mul", "zxx", "mis"].contains(&tag.as_str())
+}
+
+/// The language `text` is written in, as a primary subtag (`fr`, `ru`), or
+/// `None` when there is too little of it to tell.
+pub fn guess(text: &str) -> Option<&'static str> {
+    let mut scripts = [0usize; 8];
+    let mut letters = 0;
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        letters += 1;
+        let script = match c as u32 {
+            0x0400..=0x04FF => 1,
+            0x0370..=0x03FF => 2,
+            0x3040..=0x30FF => 3,
+            0xAC00..=0xD7AF | 0x1100..=0x11FF => 4,
+            0x4E00..=0x9FFF | 0x3400..=0x4DBF => 5,
+            0x0600..=0x06FF => 6,
+            0x0590..=0x05FF => 7,
+            _ => 0,
+        };
+        scripts[script] += 1;
+    }
+    if letters == 0 {
+        return None;
+    }
+    // Japanese mixes kana into its Han characters
+    if scripts[3] * 10 > letters {
+        return Some("ja");
+    }
+    let (script, count) = scripts.iter().enumerate().max_by_key(|(_, count)| **count)?;
+    if count * 2 < letters {
+        return None;
+    }
+    match script {
+        1 if text.chars().any(|c| matches!(c, 'ї' | 'є' | 'ґ' | 'і')) => Some("uk"),
+        1 => Some("ru"),
+        2 => Some("el"),
+        3 => Some("ja"),
+        4 => Some("ko"),
+        5 => Some("zh"),
+        6 => Some("ar"),
+        7 => Some("he"),
+        _ => by_common_words(text),
+    }
+}
+
+/// The Latin-script language whose common words `text` uses most.
+fn by_common_words(text: &str) -> Option<&'static str> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let mut scores: Vec<(&str, usize)> = COMMON_WORDS
+        .iter()
+        .map(|(language, common)| (*language, words.iter().filter(|word| common.contains(&word.as_str())).count()))
+        .collect();
+    scores.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+    match scores.as_slice() {
+        [(language, best), (_, second), ..] if *best >= MIN_COMMON_WORDS && best > second => Some(language),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_languages_are_told_from_the_text() {
+        assert_eq!(guess("It was the best of times, it was the worst of times, and he knew it."), Some("en"));
+        assert_eq!(guess("Il ne savait pas que la mer est une chose que les enfants aiment dans le Midi."), Some("fr"));
+        assert_eq!(guess("Ich weiß nicht, was soll es bedeuten, dass ich so traurig bin und die Zeit vergeht."), Some("de"));
+        assert_eq!(guess("Все счастливые семьи похожи друг на друга."), Some("ru"));
+        assert_eq!(guess("Садок вишневий коло хати, хрущі над вишнями гудуть."), Some("uk"));
+        assert_eq!(guess("吾輩は猫である。名前はまだ無い。"), Some("ja"));
+        assert_eq!(guess("Chapter One"), None);
+        assert_eq!(guess("1984"), None);
+
+        assert!(is_declared("en-GB"));
+        assert!(!is_declared(" und "));
+        assert!(!is_declared(""));
+    }
+}
@@ -0,0 +1,1082 @@
+use std::ops::Range;
+
+use ratatui::{
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+};
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::blocks::split_blocks;
+use crate::hyphenation;
+
+/// How paragraphs are set apart in the content view.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParagraphStyle {
+    /// Classic first-line indentation, paragraphs on consecutive lines
+    #[default]
+    Indent,
+    /// No indentation, a blank line between paragraphs
+    Block,
+}
+
+/// Widest first-line indent that can be configured
+pub const MAX_INDENT: usize = 8;
+
+/// Indent the formatter puts before every paragraph. The layout swaps it
+/// for the configured one.
+pub const PARAGRAPH_INDENT: &str = "    ";
+
+/// Widest line of text in dyslexia-friendly mode, in columns
+pub const SPACED_LINE_WIDTH: usize = 60;
+
+/// Gap between words in dyslexia-friendly mode. A terminal can't widen
+/// the gaps between letters by less than a whole cell, so only words are
+/// set further apart.
+const SPACED_WORD_GAP: &str = "   ";
+
+/// How paragraphs are laid out: the style and, for `Indent`, the number of
+/// spaces before the first line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParagraphFormat {
+    pub style: ParagraphStyle,
+    pub indent: usize,
+    /// Dyslexia-friendly: wider gaps between words and a blank line
+    /// between paragraphs, whatever the style
+    pub spaced: bool,
+}
+
+impl Default for ParagraphFormat {
+    fn default() -> Self {
+        Self {
+            style: ParagraphStyle::default(),
+            indent: PARAGRAPH_INDENT.len(),
+            spaced: false,
+        }
+    }
+}
+
+impl ParagraphFormat {
+    /// This format in dyslexia-friendly mode, or not.
+    pub fn spaced(self, spaced: bool) -> Self {
+        Self { spaced, ..self }
+    }
+}
+
+/// Rewrites the paragraph starts of processed prose for `format`.
+fn apply_paragraph_format(lines: &[&str], format: ParagraphFormat) -> String {
+    let indent = " ".repeat(format.indent.min(MAX_INDENT));
+    let mut out = String::with_capacity(lines.iter().map(|line| line.len() + 1).sum());
+    let style = if format.spaced { ParagraphStyle::Block } else { format.style };
+    let mut after_text = false;
+    for (i, line) in lines.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        match line.strip_prefix(PARAGRAPH_INDENT) {
+            Some(text) if !text.trim().is_empty() => {
+                match style {
+                    ParagraphStyle::Indent => out.push_str(&indent),
+                    ParagraphStyle::Block if after_text => out.push('\n'),
+                    ParagraphStyle::Block => {}
+                }
+                out.push_str(&spaced_words(text, format.spaced));
+            }
+            _ => out.push_str(&spaced_words(line, format.spaced)),
+        }
+        after_text = !line.trim().is_empty();
+    }
+    out
+}
+
+/// `line` with `SPACED_WORD_GAP` between its words when `spaced`, its
+/// indentation kept.
+fn spaced_words(line: &str, spaced: bool) -> String {
+    let words = line.trim_start();
+    if !spaced || words.is_empty() {
+        return line.to_string();
+    }
+    let indent = &line[..line.len() - words.len()];
+    format!("{}{}", indent, words.split_whitespace().collect::<Vec<_>>().join(SPACED_WORD_GAP))
+}
+
+/// A piece of a source line with uniform emphasis.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Run {
+    pub text: String,
+    pub italic: bool,
+    pub bold: bool,
+    /// Foreground color, white when unset
+    pub color: Option<Color>,
+}
+
+/// Splits processed chapter text into source lines of styled runs,
+/// consuming the `_italic_` and `**bold**` markers. Emphasis carries over
+/// line breaks, so a marker left open styles the following lines too. An
+/// underscore between two letters or digits, as in `snake_case`, is kept
+/// as text.
+pub fn parse_markup(text: &str) -> Vec<Vec<Run>> {
+    let mut lines = Vec::new();
+    let mut italic = false;
+    let mut bold = false;
+
+    for line in text.lines() {
+        let mut runs = Vec::new();
+        let mut current = String::new();
+        let mut previous = None;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            let toggles_bold = c == '*' && chars.peek() == Some(&'*');
+            let intraword = previous.is_some_and(char::is_alphanumeric)
+                && chars.peek().is_some_and(|next| next.is_alphanumeric());
+            previous = Some(c);
+            if (c == '_' && !intraword) || toggles_bold {
+                if toggles_bold {
+                    chars.next();
+                }
+                if !current.is_empty() {
+                    runs.push(Run { text: std::mem::take(&mut current), italic, bold, color: None });
+                }
+                if toggles_bold {
+                    bold = !bold;
+                } else {
+                    italic = !italic;
+                }
+            } else {
+                current.push(c);
+            }
+        }
+        if !current.is_empty() {
+            runs.push(Run { text: current, italic, bold, color: None });
+        }
+        lines.push(runs);
+    }
+    lines
+}
+
+/// Share of processed chapter `text` before the line where `passage`
+/// starts, or `None` when no line holds its first words. Whitespace is
+/// compared loosely, as a passage copied elsewhere may be wrapped
+/// differently.
+pub fn fraction_of(text: &str, passage: &str) -> Option<f64> {
+    let loose = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let start: String = loose(passage).chars().take(PASSAGE_START_CHARS).collect();
+    if start.is_empty() {
+        return None;
+    }
+    let lines: Vec<String> = parse_markup(text)
+        .iter()
+        .map(|runs| runs.iter().map(|run| run.text.as_str()).collect::<String>().replace(SOFT_HYPHEN, ""))
+        .collect();
+    let total: usize = lines.iter().map(|line| line.chars().count()).sum();
+    let found = lines.iter().position(|line| loose(line).contains(&start))?;
+    let before: usize = lines[..found].iter().map(|line| line.chars().count()).sum();
+    Some(before as f64 / total.max(1) as f64)
+}
+
+/// Characters of a passage's start `fraction_of` looks for.
+const PASSAGE_START_CHARS: usize = 40;
+
+#[derive(Clone, Copy, PartialEq)]
+enum HtmlState {
+    Text,
+    Entity,
+    Comment,
+    TagName,
+    Attributes,
+    Value(char),
+}
+
+/// Splits raw (X)HTML into source lines of colored runs for debug mode:
+/// tag names, attribute names and values, entities and comments each get
+/// a color. Tags and comments may span lines.
+pub fn highlight_html(text: &str) -> Vec<Vec<Run>> {
+    let mut lines = Vec::new();
+    let mut runs: Vec<Run> = Vec::new();
+    let mut state = HtmlState::Text;
+    let chars: Vec<char> = text.chars().collect();
+
+    let push = |runs: &mut Vec<Run>, c: char, color: Option<Color>| match runs.last_mut() {
+        Some(run) if run.color == color => run.text.push(c),
+        _ => runs.push(Run {
+            text: c.to_string(),
+            color,
+            ..Default::default()
+        }),
+    };
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        match c {
+            '\r' => continue,
+            '\n' => {
+                lines.push(std::mem::take(&mut runs));
+                if state == HtmlState::Entity {
+                    state = HtmlState::Text;
+                }
+                continue;
+            }
+            '\t' => {
+                let color = runs.last().and_then(|run| run.color);
+                for _ in 0..4 {
+                    push(&mut runs, ' ', color);
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let punctuation = Some(Color::DarkGray);
+        state = match state {
+            HtmlState::Text | HtmlState::Entity if c == '<' => {
+                if chars[i..].starts_with(&['!', '-', '-']) {
+                    push(&mut runs, c, punctuation);
+                    HtmlState::Comment
+                } else {
+                    push(&mut runs, c, punctuation);
+                    HtmlState::TagName
+                }
+            }
+            HtmlState::Text if c == '&' => {
+                push(&mut runs, c, Some(Color::Magenta));
+                HtmlState::Entity
+            }
+            HtmlState::Text => {
+                push(&mut runs, c, None);
+                HtmlState::Text
+            }
+            HtmlState::Entity => {
+                if c == ';' {
+                    push(&mut runs, c, Some(Color::Magenta));
+                    HtmlState::Text
+                } else if c.is_ascii_alphanumeric() || c == '#' {
+                    push(&mut runs, c, Some(Color::Magenta));
+                    HtmlState::Entity
+                } else {
+                    push(&mut runs, c, None);
+                    HtmlState::Text
+                }
+            }
+            HtmlState::Comment => {
+                push(&mut runs, c, punctuation);
+                if c == '>' && chars[..i - 1].ends_with(&['-', '-']) {
+                    HtmlState::Text
+                } else {
+                    HtmlState::Comment
+                }
+            }
+            HtmlState::TagName | HtmlState::Attributes if c == '>' => {
+                push(&mut runs, c, punctuation);
+                HtmlState::Text
+            }
+            HtmlState::TagName => {
+                if c.is_whitespace() {
+                    push(&mut runs, c, None);
+                    HtmlState::Attributes
+                } else if c == '/' || c == '?' || c == '!' {
+                    push(&mut runs, c, punctuation);
+                    HtmlState::TagName
+                } else {
+                    push(&mut runs, c, Some(Color::Cyan));
+                    HtmlState::TagName
+                }
+            }
+            HtmlState::Attributes => match c {
+                '"' | '\'' => {
+                    push(&mut runs, c, Some(Color::Green));
+                    HtmlState::Value(c)
+                }
+                '=' | '/' | '?' => {
+                    push(&mut runs, c, punctuation);
+                    HtmlState::Attributes
+                }
+                c if c.is_whitespace() => {
+                    push(&mut runs, c, None);
+                    HtmlState::Attributes
+                }
+                c => {
+                    push(&mut runs, c, Some(Color::Yellow));
+                    HtmlState::Attributes
+                }
+            },
+            HtmlState::Value(quote) => {
+                push(&mut runs, c, Some(Color::Green));
+                if c == quote {
+                    HtmlState::Attributes
+                } else {
+                    HtmlState::Value(quote)
+                }
+            }
+        };
+    }
+    if !runs.is_empty() || lines.is_empty() {
+        lines.push(runs);
+    }
+    lines
+}
+
+/// One row on screen: the graphemes `start..end` of source line `source`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VisualLine {
+    pub source: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// U+00AD, an optional break point inside a word. It is never drawn; a row
+/// that breaks at one ends in a hyphen instead.
+const SOFT_HYPHEN: &str = "\u{AD}";
+
+/// Greedy word wrap of a single line, given as grapheme clusters, into
+/// `(start, end)` grapheme ranges at most `width` terminal columns wide.
+/// Breaks at spaces (which are dropped at the break) or after soft hyphens
+/// (leaving room for the hyphen shown there) and splits words wider than
+/// the line; a grapheme wider than the whole line gets a row of its own.
+/// Non-breaking spaces are not spaces here, so `Mr.\u{A0}Smith` stays on
+/// one row.
+pub fn wrap_line(graphemes: &[&str], width: usize) -> Vec<(usize, usize)> {
+    let len = graphemes.len();
+    if len == 0 || width == 0 {
+        return vec![(0, len)];
+    }
+
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while start < len {
+        // First grapheme that no longer fits on the row
+        let mut limit = start;
+        let mut columns = 0;
+        while limit < len && columns + graphemes[limit].width() <= width {
+            columns += graphemes[limit].width();
+            limit += 1;
+        }
+        if limit == start {
+            limit += 1;
+        }
+        if limit == len {
+            ranges.push((start, len));
+            break;
+        }
+        // Last space that ends a word, so leading indentation is never a break
+        let space = (start + 1..=limit)
+            .rev()
+            .find(|&i| graphemes[i] == " " && graphemes[i - 1] != " ");
+        // Last soft hyphen that leaves a column for the hyphen
+        let hyphen = (start + 1..limit).rev().find(|&i| {
+            graphemes[i] == SOFT_HYPHEN && graphemes[start..i].iter().map(|g| g.width()).sum::<usize>() < width
+        });
+        match (space, hyphen) {
+            (Some(i), hyphen) if hyphen.is_none_or(|j| j < i) => {
+                ranges.push((start, i));
+                start = i;
+                while start < len && graphemes[start] == " " {
+                    start += 1;
+                }
+            }
+            (_, Some(j)) => {
+                ranges.push((start, j + 1));
+                start = j + 1;
+            }
+            _ => {
+                ranges.push((start, limit));
+                start = limit;
+            }
+        }
+    }
+    ranges
+}
+
+/// What is drawn for `grapheme`: soft hyphens are hidden, or shown as a
+/// hyphen where the row was broken at them.
+fn shown_grapheme(grapheme: &str, row_end: bool) -> &str {
+    match grapheme {
+        SOFT_HYPHEN if row_end => "-",
+        SOFT_HYPHEN => "",
+        grapheme => grapheme,
+    }
+}
+
+fn graphemes(runs: &[Run]) -> Vec<&str> {
+    runs.iter().flat_map(|run| run.text.graphemes(true)).collect()
+}
+
+/// Rows styled on either side of the requested range, so scrolling by a
+/// few lines reuses them instead of styling the viewport again.
+const RENDER_MARGIN: usize = 32;
+
+/// Characters of text kept to recognise a saved place by.
+pub const SNIPPET_CHARS: usize = 100;
+
+/// Columns taken by the minimap strip, including a blank separator.
+pub const MINIMAP_WIDTH: usize = 3;
+
+/// Density shades, from an empty stretch to rows filled edge to edge.
+const SHADES: [&str; 5] = [" ", "░", "▒", "▓", "█"];
+
+/// Guesses whether a processed source line is a heading: headings come out
+/// of the formatter unindented and short, without closing punctuation.
+fn is_heading(runs: &[Run]) -> bool {
+    let text: String = runs.iter().map(|run| run.text.as_str()).collect();
+    let text = text.trim_end();
+    !text.is_empty()
+        && !text.starts_with(' ')
+        && text.graphemes(true).count() <= 60
+        && !text.ends_with(['.', ',', ';', ':', '!', '?', '"', '\u{201D}'])
+}
+
+/// A chapter parsed into styled source lines and wrapped to a width. The
+/// mapping between rows and source positions lets a scroll offset survive
+/// a change of width.
+pub struct ChapterLayout {
+    width: usize,
+    lines: Vec<Vec<Run>>,
+    headings: Vec<bool>,
+    /// Source lines from fenced blocks, shown unwrapped
+    preformatted: Vec<bool>,
+    /// Wrap prose to the width; when off every line is cut at the edge
+    wrap: bool,
+    /// Columns of preformatted lines scrolled out on the left
+    hscroll: usize,
+    visual: Vec<VisualLine>,
+    /// First row of `rendered` and the styled rows around the last viewport
+    rendered_from: usize,
+    rendered: Vec<Line<'static>>,
+}
+
+impl ChapterLayout {
+    /// Lays out processed text. Lines between fences are kept as they are,
+    /// without markup, and cut at the edge instead of wrapped.
+    pub fn new(text: &str, width: usize) -> Self {
+        Self::with_format(text, width, ParagraphFormat::default())
+    }
+
+    /// Like `new`, setting paragraphs apart as `format` says.
+    pub fn with_format(text: &str, width: usize, format: ParagraphFormat) -> Self {
+        let mut lines = Vec::new();
+        let mut preformatted = Vec::new();
+        for (block, segment) in split_blocks(text) {
+            let parsed = if block {
+                segment
+                    .iter()
+                    .map(|line| {
+                        vec![Run {
+                            text: line.to_string(),
+                            ..Default::default()
+                        }]
+                    })
+                    .collect()
+            } else {
+                parse_markup(&apply_paragraph_format(&segment, format))
+            };
+            preformatted.extend(std::iter::repeat_n(block, parsed.len()));
+            lines.extend(parsed);
+        }
+
+        let headings = lines
+            .iter()
+            .zip(&preformatted)
+            .map(|(runs, &preformatted)| !preformatted && is_heading(runs))
+            .collect();
+        Self::from_lines(lines, headings, preformatted, width)
+    }
+
+    /// Lays out raw (X)HTML with syntax highlighting instead of markup.
+    pub fn html(text: &str, width: usize) -> Self {
+        let lines = highlight_html(text);
+        let headings = vec![false; lines.len()];
+        let preformatted = vec![false; lines.len()];
+        Self::from_lines(lines, headings, preformatted, width)
+    }
+
+    fn from_lines(lines: Vec<Vec<Run>>, headings: Vec<bool>, preformatted: Vec<bool>, width: usize) -> Self {
+        let mut layout = Self {
+            width,
+            headings,
+            preformatted,
+            wrap: true,
+            hscroll: 0,
+            lines,
+            visual: Vec::new(),
+            rendered_from: 0,
+            rendered: Vec::new(),
+        };
+        layout.rewrap(width);
+        layout
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn rewrap(&mut self, width: usize) {
+        self.width = width;
+        self.rendered.clear();
+        self.visual = self
+            .lines
+            .iter()
+            .enumerate()
+            .flat_map(|(source, runs)| {
+                let graphemes = graphemes(runs);
+                let ranges = if self.unwrapped(source) {
+                    vec![(0, graphemes.len())]
+                } else {
+                    wrap_line(&graphemes, width)
+                };
+                ranges.into_iter().map(move |(start, end)| VisualLine { source, start, end })
+            })
+            .collect();
+        self.hscroll = self.hscroll.min(self.max_hscroll());
+    }
+
+    /// Number of rows the chapter occupies.
+    pub fn len(&self) -> usize {
+        self.visual.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.visual.is_empty()
+    }
+
+    /// Source line and grapheme offset shown at row `index`, clamped to the
+    /// last row.
+    pub fn position_of(&self, index: usize) -> (usize, usize) {
+        match self.visual.get(index).or(self.visual.last()) {
+            Some(line) => (line.source, line.start),
+            None => (0, 0),
+        }
+    }
+
+    /// Share of the text before row `index`, counted in graphemes of the
+    /// source lines so that it is the same at any width.
+    pub fn fraction_before(&self, index: usize) -> f64 {
+        let lengths = self.source_lengths();
+        let total: usize = lengths.iter().sum();
+        if total == 0 {
+            return 0.0;
+        }
+        let (source, offset) = self.position_of(index);
+        let before: usize = lengths[..source].iter().sum::<usize>() + offset;
+        before as f64 / total as f64
+    }
+
+    /// The row `fraction_before` puts at `fraction` of the text: the last
+    /// one starting at or before it.
+    pub fn index_at_fraction(&self, fraction: f64) -> usize {
+        let lengths = self.source_lengths();
+        let target = (lengths.iter().sum::<usize>() as f64 * fraction.clamp(0.0, 1.0)) as usize;
+        let mut starts = Vec::with_capacity(lengths.len());
+        let mut before = 0;
+        for length in &lengths {
+            starts.push(before);
+            before += length;
+        }
+        self.visual
+            .iter()
+            .rposition(|line| starts[line.source] + line.start <= target)
+            .unwrap_or(0)
+    }
+
+    /// Graphemes of every source line.
+    fn source_lengths(&self) -> Vec<usize> {
+        let mut lengths = vec![0; self.lines.len()];
+        for line in &self.visual {
+            lengths[line.source] = lengths[line.source].max(line.end);
+        }
+        lengths
+    }
+
+    /// Row that shows grapheme `offset` of source line `source`.
+    pub fn index_of(&self, source: usize, offset: usize) -> usize {
+        self.visual
+            .iter()
+            .rposition(|line| line.source == source && line.start <= offset)
+            .or_else(|| self.visual.iter().position(|line| line.source >= source))
+            .unwrap_or(self.visual.len().saturating_sub(1))
+    }
+
+    /// Styled rows for `range`, ready to hand to a `Paragraph`. Only rows
+    /// near the range are ever styled, so the cost depends on the viewport
+    /// size rather than the length of the chapter.
+    pub fn render(&mut self, range: Range<usize>) -> Vec<Line<'static>> {
+        let end = range.end.min(self.visual.len());
+        let start = range.start.min(end);
+        let cached_end = self.rendered_from + self.rendered.len();
+        if start < self.rendered_from || end > cached_end || self.rendered.is_empty() {
+            let from = start.saturating_sub(RENDER_MARGIN);
+            let to = (end + RENDER_MARGIN).min(self.visual.len());
+            self.rendered = self.visual[from..to]
+                .iter()
+                .map(|line| self.render_line(line))
+                .collect();
+            self.rendered_from = from;
+        }
+        self.rendered[start - self.rendered_from..end - self.rendered_from].to_vec()
+    }
+
+    /// A strip `height` rows tall summarizing the whole chapter: each cell
+    /// stands for an equal share of its rows, shaded by how much of them is
+    /// text, with headings marked and the cells of `viewport` highlighted.
+    pub fn minimap(&self, height: usize, viewport: Range<usize>) -> Vec<Line<'static>> {
+        let len = self.visual.len();
+        if len == 0 || height == 0 {
+            return Vec::new();
+        }
+        let cells = height.min(len);
+        (0..cells)
+            .map(|cell| {
+                let rows = cell * len / cells..((cell + 1) * len / cells).max(cell * len / cells + 1);
+                let covered = &self.visual[rows.clone()];
+                let heading = covered.iter().any(|line| line.start == 0 && self.headings[line.source]);
+                let filled: usize = covered.iter().map(|line| line.end - line.start).sum();
+                let capacity = (covered.len() * self.width.max(1)) as f32;
+                let shade = ((filled as f32 / capacity) * (SHADES.len() - 1) as f32).ceil() as usize;
+                let in_view = rows.start < viewport.end && viewport.start < rows.end;
+
+                let (symbol, mut style) = if heading {
+                    ("━━".to_string(), Style::default().fg(Color::Yellow))
+                } else {
+                    (SHADES[shade.min(SHADES.len() - 1)].repeat(2), Style::default().fg(Color::Gray))
+                };
+                if in_view {
+                    style = style.bg(Color::DarkGray);
+                }
+                Line::from(vec![Span::raw(" "), Span::styled(symbol, style)])
+            })
+            .collect()
+    }
+
+    /// Plain text of the paragraph shown at row `index`, without its
+    /// indentation. A blank row gives the next paragraph below it.
+    pub fn paragraph_at(&self, index: usize) -> Option<String> {
+        let (source, _) = self.position_of(index);
+        self.lines[source.min(self.lines.len())..]
+            .iter()
+            .map(|runs| runs.iter().map(|run| run.text.as_str()).collect::<String>())
+            .find(|text| !text.trim().is_empty())
+            .map(|text| text.trim().replace(SOFT_HYPHEN, ""))
+    }
+
+    /// Plain text of the rows in `range`, one row per line, as they appear
+    /// on screen, except that a word broken at a soft hyphen is put back
+    /// together on the first of its rows.
+    pub fn text_of(&self, range: Range<usize>) -> String {
+        let end = range.end.min(self.visual.len());
+        let start = range.start.min(end);
+        let mut rows: Vec<String> = Vec::new();
+        let mut broken = false;
+        for line in &self.visual[start..end] {
+            let row = &graphemes(&self.lines[line.source])[line.start..line.end];
+            let mut text = row.concat().replace(SOFT_HYPHEN, "");
+            if let Some(last) = rows.last_mut().filter(|_| broken) {
+                let rest = text.find(char::is_whitespace).unwrap_or(text.len());
+                last.push_str(&text[..rest]);
+                text = text[rest..].trim_start().to_string();
+            }
+            broken = row.last() == Some(&SOFT_HYPHEN) && !self.unwrapped(line.source);
+            rows.push(text);
+        }
+        if let Some(last) = rows.last_mut().filter(|_| broken) {
+            last.push('-');
+        }
+        rows.iter().map(|row| row.trim_end()).collect::<Vec<_>>().join("\n")
+    }
+
+    /// About the first `SNIPPET_CHARS` characters of text from row
+    /// `index` on, in one line, to recognise a place by.
+    pub fn snippet_at(&self, index: usize) -> Option<String> {
+        let mut words: Vec<String> = Vec::new();
+        let mut len = 0;
+        for word in self.text_of(index..index + SNIPPET_CHARS + 1).split_whitespace() {
+            if len >= SNIPPET_CHARS {
+                return Some(words.join(" ") + "\u{2026}");
+            }
+            len += word.chars().count() + 1;
+            words.push(word.to_string());
+        }
+        (!words.is_empty()).then(|| words.join(" "))
+    }
+
+    pub fn wraps(&self) -> bool {
+        self.wrap
+    }
+
+    /// Switches between wrapping prose and cutting every line at the edge,
+    /// to be scrolled sideways like preformatted text.
+    pub fn set_wrap(&mut self, wrap: bool) {
+        if wrap != self.wrap {
+            self.wrap = wrap;
+            self.rewrap(self.width);
+        }
+    }
+
+    /// Puts soft hyphens between the syllables of long words in the prose,
+    /// by the rules of `language`, for the wrapper to break them at.
+    pub fn hyphenate(&mut self, language: &str) {
+        for (runs, _) in self.lines.iter_mut().zip(&self.preformatted).filter(|(_, preformatted)| !**preformatted) {
+            for run in runs {
+                run.text = hyphenation::hyphenate(&run.text, language);
+            }
+        }
+        self.rewrap(self.width);
+    }
+
+    /// Columns the widest unwrapped line reaches past the right edge.
+    pub fn max_hscroll(&self) -> usize {
+        (0..self.lines.len())
+            .filter(|&source| self.unwrapped(source))
+            .map(|source| self.lines[source].iter().map(|run| run.text.width()).sum::<usize>())
+            .max()
+            .unwrap_or(0)
+            .saturating_sub(self.width)
+    }
+
+    pub fn hscroll(&self) -> usize {
+        self.hscroll
+    }
+
+    /// Scrolls unwrapped lines sideways by `columns`, within the widest of
+    /// them. Wrapped prose never moves.
+    pub fn scroll_horizontally(&mut self, columns: isize) {
+        let hscroll = self.hscroll.saturating_add_signed(columns).min(self.max_hscroll());
+        if hscroll != self.hscroll {
+            self.hscroll = hscroll;
+            self.rendered.clear();
+        }
+    }
+
+    /// Number of rows currently styled and cached.
+    pub fn rendered_len(&self) -> usize {
+        self.rendered.len()
+    }
+
+    /// Whether source line `source` is cut at the edge rather than wrapped.
+    fn unwrapped(&self, source: usize) -> bool {
+        !self.wrap || self.preformatted[source]
+    }
+
+    /// Graphemes of an unwrapped row that fit between the horizontal scroll
+    /// offset and the right edge.
+    fn visible_part(&self, line: &VisualLine) -> VisualLine {
+        let mut column = 0;
+        let (mut start, mut end) = (None, line.start);
+        for (i, grapheme) in graphemes(&self.lines[line.source]).into_iter().enumerate() {
+            let grapheme_start = column;
+            column += grapheme.width();
+            if grapheme_start < self.hscroll {
+                continue;
+            }
+            if column > self.hscroll + self.width {
+                break;
+            }
+            start.get_or_insert(i);
+            end = i + 1;
+        }
+        let start = start.unwrap_or(line.end);
+        VisualLine {
+            source: line.source,
+            start,
+            end: end.max(start),
+        }
+    }
+
+    fn render_line(&self, line: &VisualLine) -> Line<'static> {
+        let unwrapped = self.unwrapped(line.source);
+        let line = if unwrapped { self.visible_part(line) } else { *line };
+        let mut spans = Vec::new();
+        let mut offset = 0;
+        for run in &self.lines[line.source] {
+            let run_len = run.text.graphemes(true).count();
+            let (run_start, run_end) = (offset, offset + run_len);
+            offset = run_end;
+            let from = line.start.max(run_start);
+            let to = line.end.min(run_end);
+            if from >= to {
+                continue;
+            }
+            let text: String = run
+                .text
+                .graphemes(true)
+                .enumerate()
+                .skip(from - run_start)
+                .take(to - from)
+                .map(|(i, grapheme)| shown_grapheme(grapheme, !unwrapped && run_start + i + 1 == line.end))
+                .collect();
+            let mut style = Style::default().fg(run.color.unwrap_or(Color::White));
+            if run.italic {
+                style = style.italic();
+            }
+            if run.bold {
+                style = style.bold();
+            }
+            spans.push(Span::styled(text, style));
+        }
+        Line::from(spans)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrap(text: &str, width: usize) -> Vec<String> {
+        let graphemes: Vec<&str> = text.graphemes(true).collect();
+        wrap_line(&graphemes, width)
+            .into_iter()
+            .map(|(s, e)| graphemes[s..e].concat())
+            .collect()
+    }
+
+    #[test]
+    fn test_wrap_line() {
+        assert_eq!(wrap("the quick brown fox", 10), vec!["the quick", "brown fox"]);
+        assert_eq!(wrap("    indented paragraph", 12), vec!["    indented", "paragraph"]);
+        assert_eq!(wrap("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+        assert_eq!(wrap("", 4), vec![""]);
+        assert_eq!(wrap("exactly ten", 11), vec!["exactly ten"]);
+        assert_eq!(wrap("paid to Mr.\u{A0}Smith", 12), vec!["paid to", "Mr.\u{A0}Smith"]);
+    }
+
+    #[test]
+    fn test_soft_hyphens_are_break_points() {
+        assert_eq!(wrap("an extra\u{AD}ordinary day", 12), vec!["an extra\u{AD}", "ordinary day"]);
+        // The hyphen needs a column of its own
+        assert_eq!(wrap("an extra\u{AD}ordinary", 8), vec!["an", "extra\u{AD}", "ordinary"]);
+        // A space further along wins over an earlier soft hyphen
+        assert_eq!(wrap("ex\u{AD}tra ordinary", 10), vec!["ex\u{AD}tra", "ordinary"]);
+
+        let mut layout = ChapterLayout::new("an extra\u{AD}ordinary day", 12);
+        let rows: Vec<String> = layout.render(0..2).iter().map(|line| line.to_string()).collect();
+        assert_eq!(rows, vec!["an extra-", "ordinary day"]);
+        assert_eq!(layout.text_of(0..2), "an extraordinary\nday");
+        assert_eq!(layout.text_of(0..1), "an extra-");
+        assert_eq!(layout.snippet_at(0).unwrap(), "an extraordinary day");
+        let mut layout = ChapterLayout::new("an extra\u{AD}ordinary day", 40);
+        assert_eq!(layout.render(0..1)[0].to_string(), "an extraordinary day");
+        assert_eq!(layout.paragraph_at(0).unwrap(), "an extraordinary day");
+    }
+
+    #[test]
+    fn test_wrap_line_counts_columns() {
+        // Double-width CJK: three characters fill six columns
+        assert_eq!(wrap("日本語の本", 6), vec!["日本語", "の本"]);
+        assert_eq!(wrap("日本", 1), vec!["日", "本"]);
+        // A combining accent belongs to its base letter and takes no column
+        assert_eq!(wrap("cafe\u{301} noir", 4), vec!["cafe\u{301}", "noir"]);
+        // A family emoji is one grapheme, two columns wide
+        assert_eq!(wrap("ab \u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467} cd", 5), vec!["ab \u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}", "cd"]);
+    }
+
+    #[test]
+    fn test_parse_markup_carries_emphasis() {
+        let lines = parse_markup("a _b\nc_ **d**");
+        assert_eq!(
+            lines[0],
+            vec![
+                Run { text: "a ".into(), italic: false, bold: false, color: None },
+                Run { text: "b".into(), italic: true, bold: false, color: None },
+            ]
+        );
+        assert_eq!(
+            lines[1],
+            vec![
+                Run { text: "c".into(), italic: true, bold: false, color: None },
+                Run { text: " ".into(), italic: false, bold: false, color: None },
+                Run { text: "d".into(), italic: false, bold: true, color: None },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_markup_keeps_intraword_underscores() {
+        let lines = parse_markup("_call_ snake_case");
+        assert_eq!(
+            lines[0],
+            vec![
+                Run { text: "call".into(), italic: true, bold: false, color: None },
+                Run { text: " snake_case".into(), italic: false, bold: false, color: None },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rewrap_keeps_position() {
+        let text = "one two three four five six seven eight\nnine ten eleven twelve";
+        let mut layout = ChapterLayout::new(text, 10);
+        let len = layout.len();
+        let row = layout.render(0..len).iter().position(|l| l.to_string().contains("seven")).unwrap();
+        let (source, offset) = layout.position_of(row);
+
+        layout.rewrap(25);
+        let new_row = layout.index_of(source, offset);
+        assert!(layout.render(new_row..new_row + 1)[0].to_string().contains("seven"));
+        assert_eq!(layout.position_of(99), (1, 0));
+    }
+
+    #[test]
+    fn test_fractions_of_the_text_ignore_the_width() {
+        let mut layout = ChapterLayout::new("aaaa bbbb\ncccc dddd", 4);
+        assert_eq!(layout.fraction_before(1), 5.0 / 18.0);
+        assert_eq!(layout.fraction_before(2), 0.5);
+        assert_eq!(layout.index_at_fraction(0.5), 2);
+        assert_eq!(layout.index_at_fraction(0.4), 1);
+        assert_eq!(layout.index_at_fraction(1.0), 3);
+
+        layout.rewrap(20);
+        assert_eq!(layout.fraction_before(1), 0.5);
+        assert_eq!(layout.index_at_fraction(0.4), 0);
+    }
+
+    #[test]
+    fn test_render_splits_runs_across_rows() {
+        let mut layout = ChapterLayout::new("plain **bold words here**", 11);
+        let rows = layout.render(0..3);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].to_string(), "plain bold");
+        assert_eq!(rows[1].to_string(), "words here");
+        assert!(rows[1].spans[0].style.add_modifier.contains(ratatui::style::Modifier::BOLD));
+    }
+
+    #[test]
+    fn test_render_styles_only_rows_near_the_viewport() {
+        let text: Vec<String> = (0..1000).map(|i| format!("line {}", i)).collect();
+        let mut layout = ChapterLayout::new(&text.join("\n"), 40);
+
+        let rows = layout.render(500..520);
+        assert_eq!(rows[0].to_string(), "line 500");
+        assert_eq!(layout.rendered_len(), 20 + 2 * RENDER_MARGIN);
+
+        // Scrolling within the margin reuses the styled rows
+        assert_eq!(layout.render(510..530)[19].to_string(), "line 529");
+        assert_eq!(layout.rendered_len(), 20 + 2 * RENDER_MARGIN);
+
+        assert_eq!(layout.render(990..1010).len(), 10);
+        assert_eq!(layout.render(0..0).len(), 0);
+    }
+
+    #[test]
+    fn test_minimap_marks_headings_and_viewport() {
+        let body = format!("    {}", "word ".repeat(40));
+        let text = format!("Chapter One\n{}\n\n{}", body, body);
+        let layout = ChapterLayout::new(&text, 20);
+        let map = layout.minimap(4, 0..2);
+        assert_eq!(map.len(), 4);
+
+        let symbols: Vec<String> = map.iter().map(|line| line.to_string()).collect();
+        assert_eq!(symbols[0], " ━━");
+        assert!(symbols[1].contains('█'));
+        assert_eq!(map[0].spans[1].style.bg, Some(Color::DarkGray));
+        assert_eq!(map[3].spans[1].style.bg, None);
+
+        // Short chapters get one cell per row
+        assert_eq!(ChapterLayout::new("a\nb", 20).minimap(10, 0..2).len(), 2);
+    }
+
+    #[test]
+    fn test_paragraph_and_screen_text() {
+        let layout = ChapterLayout::new("    first _para_ here\n\n    second one", 10);
+        assert_eq!(layout.paragraph_at(1), Some("first para here".to_string()));
+        assert_eq!(layout.paragraph_at(2), Some("second one".to_string()));
+        assert_eq!(layout.text_of(0..3), "    first\npara here\n");
+        assert_eq!(ChapterLayout::new("", 10).paragraph_at(0), None);
+        assert_eq!(layout.snippet_at(1), Some("para here second one".to_string()));
+        let long = ChapterLayout::new(&"word ".repeat(50), 30).snippet_at(0).unwrap();
+        assert!(long.ends_with("word\u{2026}") && long.chars().count() <= SNIPPET_CHARS + 1);
+        assert_eq!(ChapterLayout::new("", 10).snippet_at(0), None);
+
+        let text = "first line\nthe _marked_   passage\nlast";
+        assert_eq!(fraction_of(text, "marked passage"), Some(10.0 / 34.0));
+        assert_eq!(fraction_of(text, "elsewhere"), None);
+    }
+
+    #[test]
+    fn test_preformatted_lines_scroll_instead_of_wrapping() {
+        let text = "Some prose that wraps\n```\n0123456789abcdef _x_\n```\nEnd";
+        let mut layout = ChapterLayout::new(text, 10);
+        let rows: Vec<String> = layout.render(0..5).iter().map(|line| line.to_string()).collect();
+        assert_eq!(rows, vec!["Some prose", "that wraps", "0123456789", "End"]);
+        assert_eq!(layout.max_hscroll(), 10);
+
+        layout.scroll_horizontally(8);
+        assert_eq!(layout.render(2..3)[0].to_string(), "89abcdef _");
+        layout.scroll_horizontally(100);
+        assert_eq!(layout.hscroll(), 10);
+        assert_eq!(layout.render(2..3)[0].to_string(), "abcdef _x_");
+        assert_eq!(layout.render(0..1)[0].to_string(), "Some prose");
+        layout.scroll_horizontally(-100);
+        assert_eq!(layout.hscroll(), 0);
+    }
+
+    #[test]
+    fn test_paragraph_format_sets_indent_or_blank_lines() {
+        let text = "Chapter One\n\n    First paragraph.\n    Second one.\n```\n    code\n```\n    Third.";
+        let rows = |style, indent| {
+            let mut layout = ChapterLayout::with_format(text, 40, ParagraphFormat { style, indent, spaced: false });
+            let len = layout.len();
+            layout.render(0..len).iter().map(|line| line.to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            rows(ParagraphStyle::Indent, 4),
+            vec!["Chapter One", "", "    First paragraph.", "    Second one.", "    code", "    Third."]
+        );
+        assert_eq!(
+            rows(ParagraphStyle::Indent, 2),
+            vec!["Chapter One", "", "  First paragraph.", "  Second one.", "    code", "  Third."]
+        );
+        assert_eq!(rows(ParagraphStyle::Indent, 0)[2], "First paragraph.");
+        assert_eq!(rows(ParagraphStyle::Indent, 20)[2], format!("{}First paragraph.", " ".repeat(MAX_INDENT)));
+        assert_eq!(
+            rows(ParagraphStyle::Block, 4),
+            vec!["Chapter One", "", "First paragraph.", "", "Second one.", "    code", "Third."]
+        );
+
+        let spaced = ParagraphFormat::default().spaced(true);
+        let mut layout = ChapterLayout::with_format(text, 14, spaced);
+        let rows: Vec<String> = layout.render(0..layout.len()).iter().map(|line| line.to_string()).collect();
+        assert_eq!(rows, vec!["Chapter   One", "", "First", "paragraph.", "", "Second   one.", "    code", "Third."]);
+    }
+
+    #[test]
+    fn test_unwrapped_prose_keeps_its_styles() {
+        let mut layout = ChapterLayout::new("a long **line** of prose", 10);
+        assert_eq!(layout.len(), 3);
+        layout.set_wrap(false);
+        assert_eq!(layout.len(), 1);
+        assert_eq!(layout.render(0..1)[0].to_string(), "a long lin");
+        layout.scroll_horizontally(4);
+        let row = &layout.render(0..1)[0];
+        assert_eq!(row.to_string(), "ng line of");
+        assert!(row.spans[1].style.add_modifier.contains(ratatui::style::Modifier::BOLD));
+        layout.set_wrap(true);
+        assert_eq!(layout.hscroll(), 0);
+        assert_eq!(layout.len(), 3);
+    }
+
+    #[test]
+    fn test_highlight_html() {
+        let lines = highlight_html("<p class=\"a\">x &amp; y</p>\n<!-- c -->");
+        let colored: Vec<(&str, Option<Color>)> = lines[0].iter().map(|run| (run.text.as_str(), run.color)).collect();
+        assert_eq!(
+            colored,
+            vec![
+                ("<", Some(Color::DarkGray)),
+                ("p", Some(Color::Cyan)),
+                (" ", None),
+                ("class", Some(Color::Yellow)),
+                ("=", Some(Color::DarkGray)),
+                ("\"a\"", Some(Color::Green)),
+                (">", Some(Color::DarkGray)),
+                ("x ", None),
+                ("&amp;", Some(Color::Magenta)),
+                (" y", None),
+                ("</", Some(Color::DarkGray)),
+                ("p", Some(Color::Cyan)),
+                (">", Some(Color::DarkGray)),
+            ]
+        );
+        assert_eq!(lines[1], vec![Run { text: "<!-- c -->".into(), color: Some(Color::DarkGray), ..Default::default() }]);
+    }
+}
@@ -0,0 +1,53 @@
+pub mod app;
+pub mod archive;
+pub mod big_print;
+pub mod blocks;
+pub mod boilerplate;
+pub mod book;
+pub mod bookmark;
+pub mod calibre;
+pub mod catalog;
+pub mod clipboard;
+pub mod collections;
+pub mod config;
+pub mod diagnostics;
+pub mod dictionary;
+pub mod discord;
+pub mod download;
+pub mod export;
+pub mod footnotes;
+pub mod fuzzy;
+pub mod git_sync;
+pub mod history;
+pub mod hyphenation;
+pub mod images;
+pub mod hooks;
+pub mod inspector;
+pub mod kobo;
+pub mod language;
+pub mod layout;
+pub mod library;
+pub mod library_index;
+pub mod library_stats;
+pub mod logging;
+pub mod notifications;
+pub mod opds;
+pub mod page_list;
+pub mod paths;
+pub mod perf;
+pub mod plugins;
+pub mod popup;
+pub mod prompt_history;
+pub mod queue;
+pub mod readability;
+pub mod reading_time;
+pub mod regex_patterns;
+pub mod relative_time;
+pub mod remote;
+pub mod sanitize;
+pub mod sort;
+pub mod state_file;
+pub mod status_file;
+pub mod view;
+pub mod watcher;
+pub mod web;
@@ -0,0 +1,217 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+
+use crate::archive;
+use crate::paths;
+
+/// The library directory when none is configured.
+pub const DEFAULT_ROOT: &str = "./books";
+
+/// Levels of subdirectories read below a library root by default, enough
+/// for `author/series/book.epub` and then some.
+pub const DEFAULT_MAX_DEPTH: usize = 8;
+
+/// How the library directories are read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanOptions {
+    /// Levels of subdirectories to read below each root; 0 reads only the
+    /// root itself
+    pub max_depth: usize,
+    /// Read symlinked directories too. However a directory is reached, it
+    /// is read once, so links that loop back are harmless
+    pub follow_symlinks: bool,
+    /// List hidden files and read hidden directories, whose names start
+    /// with a dot
+    pub hidden: bool,
+    /// Where the books in archives are unpacked
+    pub unpack_dir: PathBuf,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: DEFAULT_MAX_DEPTH,
+            follow_symlinks: true,
+            hidden: false,
+            unpack_dir: paths::default_cache_dir().join("archives"),
+        }
+    }
+}
+
+/// Returns the paths of all `.epub` files in `books_dir` and its
+/// subdirectories, as `scan_roots` does with the default options.
+pub fn scan_books(books_dir: &str) -> Result<Vec<String>> {
+    scan_roots(&[books_dir.to_string()], &ScanOptions::default())
+}
+
+/// Returns the paths of all `.epub` files in the `roots` directories and
+/// their subdirectories, sorted by file name. Books in `.zip` bundles and
+/// `.epub.gz` files are unpacked into `options.unpack_dir` and listed from
+/// there. A root that can't be read is skipped with a warning; it is an
+/// error only when none can be.
+pub fn scan_roots(roots: &[String], options: &ScanOptions) -> Result<Vec<String>> {
+    let mut epub_files = Vec::new();
+    let mut visited = HashSet::new();
+    let mut failures = Vec::new();
+    for root in roots {
+        if let Err(e) = scan_dir(Path::new(root), 0, options, &mut visited, &mut epub_files) {
+            warn!("Failed to read the library directory {}: {}", root, e);
+            failures.push(format!("{}: {}", root, e));
+        }
+    }
+    if !roots.is_empty() && failures.len() == roots.len() {
+        anyhow::bail!("Failed to read directory: {}", failures.join(", "));
+    }
+
+    // Unpacked books live elsewhere, but sort in among the others
+    epub_files.sort_by(|a, b| (Path::new(a).file_name(), a).cmp(&(Path::new(b).file_name(), b)));
+    info!("Found EPUB files: {:?}", epub_files);
+
+    Ok(epub_files)
+}
+
+/// Adds the books in `dir`, `depth` levels below its root, to `books`.
+fn scan_dir(dir: &Path, depth: usize, options: &ScanOptions, visited: &mut HashSet<PathBuf>, books: &mut Vec<String>) -> io::Result<()> {
+    let entries = std::fs::read_dir(dir)?;
+    if !visited.insert(dir.canonicalize()?) {
+        return Ok(());
+    }
+
+    for entry_result in entries {
+        let entry = match entry_result {
+            Ok(e) => e,
+            Err(e) => {
+                warn!("Failed to process directory entry: {}", e);
+                continue;
+            }
+        };
+
+        let path = entry.path();
+        if !options.hidden && entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let linked = entry.file_type().is_ok_and(|file_type| file_type.is_symlink());
+
+        if path.is_dir() {
+            if depth < options.max_depth && (options.follow_symlinks || !linked) {
+                if let Err(e) = scan_dir(&path, depth + 1, options, visited, books) {
+                    warn!("Failed to read directory {:?}: {}", path, e);
+                }
+            }
+        } else if path.is_file() && archive::is_archive(&path) {
+            match archive::unpack(&path, &options.unpack_dir) {
+                Ok(unpacked) => books.extend(unpacked.iter().filter_map(|book| book.to_str()).map(str::to_string)),
+                Err(e) => warn!("Failed to unpack {:?}: {:#}", path, e),
+            }
+        } else if path.is_file() {
+            if let Some(ext) = path.extension() {
+                if ext == "epub" {
+                    if let Some(path_str) = path.to_str() {
+                        books.push(path_str.to_string());
+                    } else {
+                        warn!("Failed to convert path to string: {:?}", path);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Moves the book at `from` to `to`, making the folders `to` goes in. A
+/// file already at `to` is never replaced.
+pub fn move_book(from: &Path, to: &Path) -> Result<()> {
+    if to.exists() {
+        bail!("{:?} already exists", to);
+    }
+    if let Some(folder) = to.parent() {
+        fs::create_dir_all(folder).with_context(|| format!("Failed to make the folder {:?}", folder))?;
+    }
+    if let Err(e) = fs::rename(from, to) {
+        // A rename can't cross file systems, but a copy can
+        fs::copy(from, to).with_context(|| format!("Failed to move {:?} to {:?}: {}", from, to, e))?;
+        fs::remove_file(from).with_context(|| format!("Failed to remove {:?} once copied", from))?;
+    }
+    info!("Moved {:?} to {:?}", from, to);
+    Ok(())
+}
+
+/// One of `books` at random, `None` when there are none.
+pub fn pick_random(books: &[String]) -> Option<&String> {
+    if books.is_empty() {
+        return None;
+    }
+    // Every `RandomState` is seeded differently, which is random enough
+    // for choosing what to read
+    let random = RandomState::new().build_hasher().finish();
+    books.get((random % books.len() as u64) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roots_are_scanned_recursively() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().join("books");
+        let other = dir.path().join("more");
+        for folder in ["Le Guin/Earthsea/deep/deeper", ".hidden"] {
+            std::fs::create_dir_all(root.join(folder)).unwrap();
+        }
+        std::fs::create_dir_all(&other).unwrap();
+        for book in ["top.epub", "Le Guin/Earthsea/wizard.epub", "Le Guin/Earthsea/deep/deeper/lost.epub", ".hidden/secret.epub", "notes.txt"] {
+            std::fs::write(root.join(book), "").unwrap();
+        }
+        std::fs::write(other.join("another.epub"), "").unwrap();
+        let names = |books: Vec<String>| -> Vec<String> {
+            books.iter().map(|book| Path::new(book).file_name().unwrap().to_string_lossy().to_string()).collect()
+        };
+        let roots = [root.to_string_lossy().to_string(), other.to_string_lossy().to_string()];
+
+        let books = scan_roots(&roots, &ScanOptions::default()).unwrap();
+        assert_eq!(names(books), ["another.epub", "lost.epub", "top.epub", "wizard.epub"]);
+
+        let shallow = ScanOptions { max_depth: 2, hidden: true, ..ScanOptions::default() };
+        assert_eq!(names(scan_roots(&roots[..1], &shallow).unwrap()), ["secret.epub", "top.epub", "wizard.epub"]);
+
+        // One root missing is only warned about
+        let missing = [roots[1].clone(), dir.path().join("gone").to_string_lossy().to_string()];
+        assert_eq!(names(scan_roots(&missing, &ScanOptions::default()).unwrap()), ["another.epub"]);
+        assert!(scan_roots(&missing[1..], &ScanOptions::default()).is_err());
+
+        #[cfg(unix)]
+        {
+            // A link back up is read once, and not at all unless followed
+            std::os::unix::fs::symlink(&root, root.join("Le Guin/loop")).unwrap();
+            std::os::unix::fs::symlink(&other, root.join("linked")).unwrap();
+            let books = scan_roots(&roots[..1], &ScanOptions::default()).unwrap();
+            assert_eq!(names(books), ["another.epub", "lost.epub", "top.epub", "wizard.epub"]);
+            let unfollowed = ScanOptions { follow_symlinks: false, ..ScanOptions::default() };
+            assert_eq!(names(scan_roots(&roots[..1], &unfollowed).unwrap()), ["lost.epub", "top.epub", "wizard.epub"]);
+        }
+    }
+
+    #[test]
+    fn test_books_are_moved_into_new_folders_but_never_over_others() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let (book, other) = (dir.path().join("book.epub"), dir.path().join("other.epub"));
+        std::fs::write(&book, "book").unwrap();
+        std::fs::write(&other, "other").unwrap();
+
+        let moved = dir.path().join("Austen/Emma/book.epub");
+        move_book(&book, &moved).unwrap();
+        assert!(!book.exists());
+        assert_eq!(std::fs::read_to_string(&moved).unwrap(), "book");
+        assert!(move_book(&moved, &other).is_err());
+        assert_eq!(std::fs::read_to_string(&other).unwrap(), "other");
+        assert!(moved.exists());
+    }
+}
@@ -0,0 +1,478 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use epub::doc::EpubDoc;
+use log::{info, warn};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::app::book_progress;
+use crate::book;
+use crate::bookmark::{Bookmark, Bookmarks};
+use crate::catalog::{Catalog, Entry};
+
+/// Bumped whenever the tables change; an index of another version is
+/// built again from the books, as everything in it can be.
+const SCHEMA_VERSION: i32 = 4;
+
+/// When a file last changed, in seconds since the epoch, and its size.
+type Stamp = (i64, i64);
+
+/// Characters of text in a wrapped row, to tell how far into a part a
+/// bookmark's scroll offset is without laying the part out.
+const CHARS_PER_ROW: usize = 60;
+
+const SCHEMA: &str = "
+CREATE TABLE books (
+    path TEXT PRIMARY KEY,
+    modified INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    authors TEXT NOT NULL,
+    tags TEXT NOT NULL,
+    language TEXT,
+    series TEXT,
+    series_index TEXT,
+    description TEXT,
+    publisher TEXT,
+    date TEXT,
+    identifier TEXT,
+    cover_hash TEXT,
+    part_sizes TEXT,
+    progress INTEGER,
+    last_read TEXT,
+    added TEXT NOT NULL
+);
+";
+
+/// What is known of every book in the library, stored as `library.db`
+/// next to the bookmarks file: its metadata, a hash of its cover, the
+/// text length of its parts and how far it has been read. A book is only
+/// read again once its file changes, so start-up needn't open every book.
+/// The bookmarks stay the record of the reading; the index mirrors them.
+pub struct LibraryIndex {
+    connection: Connection,
+    /// The metadata of the books last refreshed, kept for drawing, with
+    /// the file stamp it was read at
+    entries: HashMap<String, (Stamp, Entry)>,
+    /// How far the books read were read, in percent, for listing
+    progress: HashMap<String, u32>,
+}
+
+impl LibraryIndex {
+    /// Opens the index kept beside `bookmarks_path`.
+    pub fn beside(bookmarks_path: &Path) -> Result<Self> {
+        Self::open(Self::path_beside(bookmarks_path))
+    }
+
+    /// Where the index kept next to `bookmarks_path` is.
+    pub fn path_beside(bookmarks_path: &Path) -> PathBuf {
+        bookmarks_path.parent().unwrap_or(Path::new("")).join("library.db")
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let connection = Connection::open(path).with_context(|| format!("Failed to open the library index {:?}", path))?;
+        // Another bookrat reading the same library may be writing
+        connection.busy_timeout(Duration::from_secs(2))?;
+        Self::with_connection(connection)
+    }
+
+    /// An index that lasts as long as the program, for when the file
+    /// can't be opened.
+    pub fn in_memory() -> Self {
+        let connection = Connection::open_in_memory().expect("SQLite opens an in-memory database");
+        Self::with_connection(connection).expect("an empty in-memory index is set up")
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self> {
+        let version: i32 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version != SCHEMA_VERSION {
+            info!("Building the library index anew (version {} found)", version);
+            connection.execute_batch("DROP TABLE IF EXISTS books;")?;
+            connection.execute_batch(SCHEMA)?;
+            connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        }
+        Ok(Self { connection, entries: HashMap::new(), progress: HashMap::new() })
+    }
+
+    /// Brings the index up to date with the library's `files`: books new
+    /// or changed since they were indexed are read, books no longer among
+    /// them are dropped, and when `bookmarks` were read since, the reading
+    /// progress is taken over. Returns how many books were read.
+    pub fn refresh(&mut self, files: &[String], bookmarks: &Bookmarks) -> Result<usize> {
+        let transaction = self.connection.transaction()?;
+        let mut indexed: HashMap<String, (i64, i64, Option<String>)> = HashMap::new();
+        {
+            let mut statement = transaction.prepare("SELECT path, modified, size, last_read FROM books")?;
+            let rows = statement.query_map([], |row| Ok((row.get::<_, String>(0)?, (row.get(1)?, row.get(2)?, row.get(3)?))))?;
+            for row in rows {
+                let (path, stamp) = row?;
+                indexed.insert(path, stamp);
+            }
+        }
+
+        let listed: HashSet<&String> = files.iter().collect();
+        for gone in indexed.keys().filter(|path| !listed.contains(path)) {
+            transaction.execute("DELETE FROM books WHERE path = ?1", [gone])?;
+        }
+        let mut read = 0;
+        for file in files {
+            let (modified, size) = file_stamp(file);
+            let mut known_read = indexed.get(file).and_then(|(_, _, last_read)| last_read.as_deref());
+            if indexed.get(file).is_none_or(|&(known_modified, known_size, _)| (known_modified, known_size) != (modified, size)) {
+                // All but when it was added is read again, the reading too
+                known_read = None;
+                let (entry, cover_hash) = read_book(file);
+                transaction.execute(
+                    "INSERT INTO books
+                     (path, modified, size, title, authors, tags, language, series, series_index, description, publisher, date, identifier, cover_hash, added)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)
+                     ON CONFLICT (path) DO UPDATE SET
+                     modified = excluded.modified, size = excluded.size, title = excluded.title,
+                     authors = excluded.authors, tags = excluded.tags, language = excluded.language,
+                     series = excluded.series, series_index = excluded.series_index,
+                     description = excluded.description, publisher = excluded.publisher, date = excluded.date,
+                     identifier = excluded.identifier, cover_hash = excluded.cover_hash,
+                     part_sizes = NULL, progress = NULL, last_read = NULL",
+                    params![
+                        file,
+                        modified,
+                        size,
+                        entry.title,
+                        serde_json::to_string(&entry.authors)?,
+                        serde_json::to_string(&entry.tags)?,
+                        entry.language,
+                        entry.series,
+                        entry.series_index,
+                        entry.description,
+                        entry.publisher,
+                        entry.date,
+                        entry.identifier,
+                        cover_hash,
+                        Utc::now().to_rfc3339(),
+                    ],
+                )?;
+                read += 1;
+            }
+            let Some(bookmark) = bookmarks.get_bookmark(file) else {
+                continue;
+            };
+            let last_read = bookmark.last_read.to_rfc3339();
+            if known_read != Some(last_read.as_str()) {
+                let sizes: Option<String> = transaction.query_row("SELECT part_sizes FROM books WHERE path = ?1", [file], |row| row.get(0))?;
+                let progress = sizes
+                    .and_then(|sizes| serde_json::from_str::<Vec<usize>>(&sizes).ok())
+                    .map(|sizes| bookmark_progress(&sizes, bookmark));
+                transaction.execute("UPDATE books SET progress = ?2, last_read = ?3 WHERE path = ?1", params![file, progress, last_read])?;
+            }
+        }
+        transaction.commit()?;
+        if read > 0 {
+            info!("Indexed {} books", read);
+        }
+
+        self.entries = self.load_entries(&listed)?;
+        self.progress = self.load_progress()?;
+        Ok(read)
+    }
+
+    /// Takes over every position in `bookmarks` at once, rather than as
+    /// books are opened: refreshes the index, and measures the parts of the
+    /// books read that never were so their progress is known. Returns the
+    /// positions, most recently read first.
+    pub fn import<'a>(&mut self, files: &[String], bookmarks: &'a Bookmarks) -> Result<Vec<Imported<'a>>> {
+        self.refresh(files, bookmarks)?;
+        let listed: HashSet<&str> = files.iter().map(String::as_str).collect();
+        let mut imported = Vec::new();
+        for file in bookmarks.recently_read() {
+            let Some(bookmark) = bookmarks.get_bookmark(file) else {
+                continue;
+            };
+            let progress = listed.contains(file).then(|| match self.progress(file) {
+                Some(progress) => progress,
+                None => {
+                    let progress = bookmark_progress(&self.part_sizes(file, || book::part_sizes_of(file)), bookmark);
+                    self.record_reading(file, progress, bookmark.last_read);
+                    progress
+                }
+            });
+            imported.push(Imported { file, bookmark, progress });
+        }
+        Ok(imported)
+    }
+
+    fn load_progress(&self) -> Result<HashMap<String, u32>> {
+        let mut statement = self.connection.prepare("SELECT path, progress FROM books WHERE progress IS NOT NULL")?;
+        let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn load_entries(&self, listed: &HashSet<&String>) -> Result<HashMap<String, (Stamp, Entry)>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT path, title, authors, tags, language, series, series_index, description, publisher, date, identifier, modified, size FROM books")?;
+        let rows = statement.query_map([], |row| {
+            let list = |index: usize| -> rusqlite::Result<Vec<String>> {
+                Ok(serde_json::from_str(&row.get::<_, String>(index)?).unwrap_or_default())
+            };
+            let entry = Entry {
+                title: row.get(1)?,
+                authors: list(2)?,
+                tags: list(3)?,
+                language: row.get(4)?,
+                series: row.get(5)?,
+                series_index: row.get(6)?,
+                description: row.get(7)?,
+                publisher: row.get(8)?,
+                date: row.get(9)?,
+                identifier: row.get(10)?,
+            };
+            Ok((row.get::<_, String>(0)?, ((row.get(11)?, row.get(12)?), entry)))
+        })?;
+        let mut entries = HashMap::new();
+        for row in rows {
+            let (path, entry) = row?;
+            if listed.contains(&path) {
+                entries.insert(path, entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// The metadata of `file`, unless the file changed since the last
+    /// refresh.
+    pub fn entry(&self, file: &str) -> Option<&Entry> {
+        self.entries.get(file).filter(|(stamp, _)| *stamp == file_stamp(file)).map(|(_, entry)| entry)
+    }
+
+    /// The metadata of every book as of the last refresh, for filtering.
+    pub fn catalog(&self) -> Catalog {
+        Catalog::from_entries(self.entries.iter().map(|(file, (_, entry))| (file.clone(), entry.clone())).collect())
+    }
+
+    /// `Title — Author` for `file`, with every author when it has several,
+    /// as of the last refresh. Books not indexed go by their file name.
+    pub fn label(&self, file: &str) -> String {
+        match self.entries.get(file).map(|(_, entry)| entry) {
+            Some(entry) if entry.authors.is_empty() => entry.title.clone(),
+            Some(entry) => format!("{} — {}", entry.title, entry.authors.join(", ")),
+            None => Path::new(file).file_stem().unwrap_or_default().to_string_lossy().to_string(),
+        }
+    }
+
+    /// Hex hash of the cover image of `file`, `None` when it has none.
+    pub fn cover_hash(&self, file: &str) -> Option<String> {
+        self.connection
+            .query_row("SELECT cover_hash FROM books WHERE path = ?1", [file], |row| row.get(0))
+            .optional()
+            .ok()
+            .flatten()
+            .flatten()
+    }
+
+    /// The text length of every part of `file`, measured with `measure`
+    /// the first time and kept from then on.
+    pub fn part_sizes(&self, file: &str, measure: impl FnOnce() -> Vec<usize>) -> Vec<usize> {
+        let stored: Option<String> = self
+            .connection
+            .query_row("SELECT part_sizes FROM books WHERE path = ?1", [file], |row| row.get(0))
+            .optional()
+            .unwrap_or_else(|e| {
+                warn!("Failed to read the part sizes of {}: {}", file, e);
+                None
+            })
+            .flatten();
+        if let Some(sizes) = stored.and_then(|sizes| serde_json::from_str(&sizes).ok()) {
+            return sizes;
+        }
+        let sizes = measure();
+        self.set_part_sizes(file, &sizes);
+        sizes
+    }
+
+    /// Keeps `sizes` as the text length of the parts of `file`.
+    pub fn set_part_sizes(&self, file: &str, sizes: &[usize]) {
+        let json = serde_json::to_string(sizes).unwrap_or_default();
+        if let Err(e) = self.connection.execute("UPDATE books SET part_sizes = ?2 WHERE path = ?1", params![file, json]) {
+            warn!("Failed to store the part sizes of {}: {}", file, e);
+        }
+    }
+
+    /// Keeps `progress`, in percent, as how far `file` was read at
+    /// `last_read`.
+    pub fn record_reading(&mut self, file: &str, progress: u32, last_read: DateTime<Utc>) {
+        self.progress.insert(file.to_string(), progress);
+        let result = self.connection.execute(
+            "UPDATE books SET progress = ?2, last_read = ?3 WHERE path = ?1",
+            params![file, progress, last_read.to_rfc3339()],
+        );
+        if let Err(e) = result {
+            warn!("Failed to store the progress of {}: {}", file, e);
+        }
+    }
+
+    /// Keeps what is known of `from` as `to`, for a book renamed or moved,
+    /// so it isn't read again and keeps when it was added.
+    pub fn rename(&mut self, from: &str, to: &str) {
+        let result = self
+            .connection
+            .execute("DELETE FROM books WHERE path = ?1", [to])
+            .and_then(|_| self.connection.execute("UPDATE books SET path = ?2 WHERE path = ?1", [from, to]));
+        if let Err(e) = result {
+            warn!("Failed to rename {} in the library index: {}", from, e);
+        }
+        if let Some(entry) = self.entries.remove(from) {
+            self.entries.insert(to.to_string(), entry);
+        }
+        if let Some(progress) = self.progress.remove(from) {
+            self.progress.insert(to.to_string(), progress);
+        }
+    }
+
+    /// How far `file` was read, in percent, as of the last refresh or
+    /// reading recorded; `None` for books never read or never measured.
+    pub fn progress(&self, file: &str) -> Option<u32> {
+        self.progress.get(file).copied()
+    }
+
+    /// When `file` was first indexed.
+    pub fn added(&self, file: &str) -> Option<DateTime<Utc>> {
+        let added: String = self
+            .connection
+            .query_row("SELECT added FROM books WHERE path = ?1", [file], |row| row.get(0))
+            .optional()
+            .ok()
+            .flatten()?;
+        Some(DateTime::parse_from_rfc3339(&added).ok()?.with_timezone(&Utc))
+    }
+
+    /// How far `file` was read, in percent, and when, as last recorded.
+    pub fn reading(&self, file: &str) -> Option<(Option<u32>, DateTime<Utc>)> {
+        let (progress, last_read): (Option<u32>, Option<String>) = self
+            .connection
+            .query_row("SELECT progress, last_read FROM books WHERE path = ?1", [file], |row| Ok((row.get(0)?, row.get(1)?)))
+            .optional()
+            .ok()
+            .flatten()?;
+        let last_read = DateTime::parse_from_rfc3339(&last_read?).ok()?.with_timezone(&Utc);
+        Some((progress, last_read))
+    }
+}
+
+/// A position `LibraryIndex::import` took over from the bookmarks.
+pub struct Imported<'a> {
+    pub file: &'a str,
+    pub bookmark: &'a Bookmark,
+    /// How far into the book the position is, in percent; `None` for a
+    /// book no longer in the library, which is left out
+    pub progress: Option<u32>,
+}
+
+/// Imports the positions in `bookmarks` into the index kept beside them,
+/// or with `dry_run` into one in memory, so nothing is written.
+pub fn migrate<'a>(files: &[String], bookmarks: &'a Bookmarks, dry_run: bool) -> Result<Vec<Imported<'a>>> {
+    let mut index = if dry_run { LibraryIndex::in_memory() } else { LibraryIndex::beside(bookmarks.path())? };
+    index.import(files, bookmarks)
+}
+
+/// How far into a book with parts of `sizes` the bookmark is, in percent,
+/// its scroll offset taken as rows of `CHARS_PER_ROW` characters.
+fn bookmark_progress(sizes: &[usize], bookmark: &Bookmark) -> u32 {
+    let size = sizes.get(bookmark.chapter).copied().unwrap_or(0);
+    let chapter_progress = match size {
+        0 => 0,
+        size => (bookmark.scroll_offset * CHARS_PER_ROW * 100 / size).min(100) as u32,
+    };
+    book_progress(sizes, bookmark.chapter, chapter_progress)
+}
+
+/// The stamp of `file`, with zeros for what can't be told.
+fn file_stamp(file: &str) -> Stamp {
+    let Ok(metadata) = fs::metadata(file) else {
+        return (0, 0);
+    };
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs() as i64);
+    (modified, metadata.len() as i64)
+}
+
+/// The metadata of the book at `file` and the hash of its cover.
+fn read_book(file: &str) -> (Entry, Option<String>) {
+    let Ok(mut doc) = EpubDoc::new(file) else {
+        return (Entry::read(file), None);
+    };
+    let cover_hash = doc.get_cover().map(|(image, _)| format!("{:016x}", fnv1a(&image)));
+    (Entry::from_doc(&doc, file).detect_language(file), cover_hash)
+}
+
+/// 64-bit FNV-1a: stable across builds, unlike the standard hasher, so
+/// the stored hashes stay comparable.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_books_are_indexed_until_they_change() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("library.db");
+        let broken = dir.path().join("broken.epub").to_string_lossy().to_string();
+        fs::write(&broken, "not a book").unwrap();
+        let files = vec![broken.clone()];
+        let mut bookmarks = Bookmarks::with_path(dir.path().join("bookmarks.json"));
+
+        let mut index = LibraryIndex::open(&path).unwrap();
+        assert_eq!(index.refresh(&files, &bookmarks).unwrap(), 1);
+        assert_eq!(index.label(&broken), "broken");
+        assert_eq!(index.label("elsewhere/unknown.epub"), "unknown");
+        assert_eq!(index.cover_hash(&broken), None);
+
+        // Sizes are measured once, and the progress follows the bookmarks
+        assert_eq!(index.part_sizes(&broken, || vec![100, 300]), [100, 300]);
+        assert_eq!(index.part_sizes(&broken, || unreachable!()), [100, 300]);
+        let read_at = Utc::now();
+        bookmarks.set_position(&broken, 1, 0, read_at);
+        let mut index = LibraryIndex::open(&path).unwrap();
+        assert_eq!(index.refresh(&files, &bookmarks).unwrap(), 0);
+        let (progress, last_read) = index.reading(&broken).unwrap();
+        assert_eq!((progress, last_read.timestamp()), (Some(25), read_at.timestamp()));
+        assert_eq!(index.progress(&broken), Some(25));
+        index.record_reading(&broken, 40, read_at);
+        assert_eq!(index.progress(&broken), Some(40));
+        index.refresh(&files, &bookmarks).unwrap();
+        assert_eq!(index.reading(&broken).unwrap().0, Some(40));
+
+        // A changed file is read again, but keeps when it was added
+        let added = index.added(&broken).unwrap();
+        fs::write(&broken, "still not a book").unwrap();
+        assert_eq!(index.refresh(&files, &bookmarks).unwrap(), 1);
+        assert_eq!(index.added(&broken), Some(added));
+        assert_eq!(index.progress(&broken), None, "the parts are measured again first");
+        assert_eq!(index.catalog().get(&broken).unwrap().title, "broken");
+
+        // A book moved keeps its record
+        let renamed = dir.path().join("renamed.epub").to_string_lossy().to_string();
+        fs::rename(&broken, &renamed).unwrap();
+        index.rename(&broken, &renamed);
+        assert_eq!(index.added(&renamed), Some(added));
+        assert_eq!(index.refresh(std::slice::from_ref(&renamed), &bookmarks).unwrap(), 0, "not read again");
+        assert_eq!(index.label(&renamed), "broken");
+
+        index.refresh(&[], &bookmarks).unwrap();
+        assert_eq!(index.entry(&broken), None);
+        assert_eq!(index.reading(&broken), None);
+
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+
+use ratatui::{
+    style::{Color, Style, Stylize},
+    text::Line,
+};
+
+use crate::catalog::Entry;
+
+/// Letters in an average word, as `book::text_length` counts them
+/// (whitespace left out).
+pub const CHARS_PER_WORD: usize = 5;
+/// Words on a printed page, for the page estimate.
+pub const WORDS_PER_PAGE: usize = 250;
+/// Characters of a virtual page, so page numbers don't depend on the
+/// size of the terminal.
+pub const CHARS_PER_PAGE: usize = CHARS_PER_WORD * WORDS_PER_PAGE;
+
+/// What the library is made of: how many books, how much text, and how
+/// they divide up by author, language and whether they were read.
+#[derive(Debug, Default, PartialEq)]
+pub struct LibraryStats {
+    pub books: usize,
+    /// Text characters of all books, whitespace left out
+    pub characters: usize,
+    pub read: usize,
+    authors: BTreeMap<String, usize>,
+    languages: BTreeMap<String, usize>,
+    /// Titles of the books marked finished, by when
+    finished: Vec<(chrono::DateTime<chrono::Utc>, String)>,
+}
+
+impl LibraryStats {
+    /// Counts in a book described by `entry`, holding `characters` of
+    /// text, opened before when `read`.
+    pub fn add(&mut self, entry: &Entry, characters: usize, read: bool) {
+        self.books += 1;
+        self.characters += characters;
+        self.read += usize::from(read);
+        if entry.authors.is_empty() {
+            *self.authors.entry("Unknown author".to_string()).or_default() += 1;
+        }
+        for author in &entry.authors {
+            *self.authors.entry(author.clone()).or_default() += 1;
+        }
+        // `en-GB` and `en-US` are both English
+        let language = entry
+            .language
+            .as_deref()
+            .map(|language| language.split(['-', '_']).next().unwrap_or(language).to_lowercase())
+            .unwrap_or_else(|| "unknown".to_string());
+        *self.languages.entry(language).or_default() += 1;
+    }
+
+    /// Counts in a book described by `entry` as finished `when`.
+    pub fn add_finished(&mut self, entry: &Entry, when: chrono::DateTime<chrono::Utc>) {
+        self.finished.push((when, entry.title.clone()));
+        // Most recently finished first
+        self.finished.sort_by_key(|(when, _)| std::cmp::Reverse(*when));
+    }
+
+    pub fn words(&self) -> usize {
+        self.characters / CHARS_PER_WORD
+    }
+
+    pub fn pages(&self) -> usize {
+        estimated_pages(self.characters)
+    }
+
+    /// Authors with their number of books, most books first.
+    pub fn authors(&self) -> Vec<(&str, usize)> {
+        by_count(&self.authors)
+    }
+
+    /// Primary language subtags with their number of books, most first.
+    pub fn languages(&self) -> Vec<(&str, usize)> {
+        by_count(&self.languages)
+    }
+
+    /// The statistics as lines for the statistics view.
+    pub fn describe(&self) -> Vec<Line<'static>> {
+        let heading = |text: &str| Line::from(text.to_string()).style(Style::default().fg(Color::Yellow).bold());
+        let item = |text: String| Line::from(format!("  {}", text));
+        let share = |count: usize| count * 100 / self.books.max(1);
+
+        let mut lines = vec![
+            heading("Library"),
+            item(format!("{} books", self.books)),
+            item(format!("About {} words, {} pages", self.words(), self.pages())),
+            Line::from(""),
+            heading("Read status"),
+            item(format!("Read: {} ({}%)", self.read, share(self.read))),
+            item(format!("Unread: {} ({}%)", self.books - self.read, share(self.books - self.read))),
+            item(format!("Finished: {} ({}%)", self.finished.len(), share(self.finished.len()))),
+            Line::from(""),
+        ];
+        if !self.finished.is_empty() {
+            lines.push(heading("Finished"));
+            lines.extend(self.finished.iter().map(|(when, title)| {
+                item(format!("{}  {}", when.with_timezone(&chrono::Local).format("%Y-%m-%d"), title))
+            }));
+            lines.push(Line::from(""));
+        }
+        lines.push(heading("Languages"));
+        lines.extend(self.languages().into_iter().map(|(language, count)| item(format!("{}: {}", language, count))));
+        lines.push(Line::from(""));
+        lines.push(heading("Authors"));
+        lines.extend(self.authors().into_iter().map(|(author, count)| item(format!("{}: {}", author, count))));
+        lines
+    }
+}
+
+/// Printed pages that `characters` of text (whitespace left out) fill.
+pub fn estimated_pages(characters: usize) -> usize {
+    (characters / CHARS_PER_WORD).div_ceil(WORDS_PER_PAGE)
+}
+
+/// Virtual page of the reading position and the pages of the whole book,
+/// given the text length of every part and the share of the current one
+/// already read.
+pub fn virtual_page(sizes: &[usize], chapter: usize, fraction: f64) -> (usize, usize) {
+    let total = estimated_pages(sizes.iter().sum()).max(1);
+    let before: usize = sizes.iter().take(chapter).sum();
+    let current = sizes.get(chapter).copied().unwrap_or(0) as f64 * fraction.clamp(0.0, 1.0);
+    let page = (before + current as usize) / CHARS_PER_PAGE + 1;
+    (page.min(total), total)
+}
+
+/// Part and share of its text where virtual page `page` (from 1) starts,
+/// or `None` past the end of the book.
+pub fn page_start(sizes: &[usize], page: usize) -> Option<(usize, f64)> {
+    let mut offset = page.checked_sub(1)? * CHARS_PER_PAGE;
+    for (chapter, size) in sizes.iter().enumerate() {
+        if offset < *size {
+            return Some((chapter, offset as f64 / *size as f64));
+        }
+        offset -= size;
+    }
+    None
+}
+
+fn by_count(counts: &BTreeMap<String, usize>) -> Vec<(&str, usize)> {
+    let mut counts: Vec<(&str, usize)> = counts.iter().map(|(name, count)| (name.as_str(), *count)).collect();
+    // Stable, so equal counts stay in alphabetical order
+    counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(authors: &[&str], language: Option<&str>) -> Entry {
+        Entry {
+            authors: authors.iter().map(|author| author.to_string()).collect(),
+            language: language.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_library_stats_break_down() {
+        let mut stats = LibraryStats::default();
+        stats.add(&entry(&["Tolstoy"], Some("en-GB")), 3000, true);
+        stats.add(&entry(&["Gogol", "Tolstoy"], Some("EN")), 1000, false);
+        stats.add(&entry(&[], Some("ru")), 1, false);
+        stats.add(&entry(&["Chekhov"], None), 0, false);
+
+        assert_eq!((stats.books, stats.read, stats.words(), stats.pages()), (4, 1, 800, 4));
+        assert_eq!(stats.authors(), vec![("Tolstoy", 2), ("Chekhov", 1), ("Gogol", 1), ("Unknown author", 1)]);
+        assert_eq!(stats.languages(), vec![("en", 2), ("ru", 1), ("unknown", 1)]);
+        assert_eq!(stats.describe()[5].to_string(), "  Read: 1 (25%)");
+        assert_eq!(stats.describe()[7].to_string(), "  Finished: 0 (0%)");
+
+        let finished = Entry { title: "War and Peace".to_string(), ..Default::default() };
+        let when = chrono::Utc::now();
+        stats.add_finished(&finished, when);
+        let lines: Vec<String> = stats.describe().iter().map(|line| line.to_string()).collect();
+        assert_eq!(lines[7], "  Finished: 1 (25%)");
+        assert_eq!(lines[10], format!("  {}  War and Peace", when.with_timezone(&chrono::Local).format("%Y-%m-%d")));
+    }
+
+    #[test]
+    fn test_virtual_pages_count_the_whole_book() {
+        let sizes = [CHARS_PER_PAGE * 2, CHARS_PER_PAGE * 3 + 5];
+        assert_eq!(virtual_page(&sizes, 0, 0.0), (1, 6));
+        assert_eq!(virtual_page(&sizes, 0, 0.75), (2, 6));
+        assert_eq!(virtual_page(&sizes, 1, 0.0), (3, 6));
+        assert_eq!(virtual_page(&sizes, 1, 1.0), (6, 6));
+        assert_eq!(virtual_page(&[], 0, 0.5), (1, 1));
+
+        assert_eq!(page_start(&sizes, 1), Some((0, 0.0)));
+        assert_eq!(page_start(&sizes, 2), Some((0, 0.5)));
+        assert_eq!(page_start(&sizes, 3), Some((1, 0.0)));
+        assert_eq!(page_start(&sizes, 7), None);
+        assert_eq!(page_start(&sizes, 0), None);
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use simplelog::{Config as LogConfig, LevelFilter, WriteLogger};
+
+use crate::config::LoggingConfig;
+
+pub fn parse_level(level: &str) -> Result<LevelFilter> {
+    level
+        .parse()
+        .map_err(|_| anyhow!("Unknown log level {:?} (expected off, error, warn, info, debug or trace)", level))
+}
+
+/// Installs the global logger described by `config`. Does nothing when the
+/// level is `off`.
+pub fn init(config: &LoggingConfig) -> Result<()> {
+    let level = parse_level(&config.level)?;
+    if level == LevelFilter::Off {
+        return Ok(());
+    }
+    let path = config.path.as_ref().ok_or_else(|| anyhow!("No state directory for the log file; set logging.path"))?;
+    let writer = RotatingFile::open(path, config.max_size_kb.saturating_mul(1024), config.keep)?;
+    WriteLogger::init(level, LogConfig::default(), writer).context("Failed to install logger")
+}
+
+/// Log file that is rotated to `<name>.1`, `<name>.2`, ... when it grows
+/// past `max_bytes`, keeping at most `keep` old files.
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    max_bytes: u64,
+    keep: usize,
+}
+
+impl RotatingFile {
+    pub fn open(path: &Path, max_bytes: u64, keep: usize) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("Failed to create log directory: {:?}", parent))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open log file: {:?}", path))?;
+        let written = file.metadata().map(|m| m.len()).unwrap_or(0);
+        let mut rotating = Self {
+            path: path.to_path_buf(),
+            file,
+            written,
+            max_bytes,
+            keep,
+        };
+        if rotating.written >= rotating.max_bytes {
+            rotating.rotate()?;
+        }
+        Ok(rotating)
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(self.rotated_path(self.keep));
+            for n in (1..self.keep).rev() {
+                let from = self.rotated_path(n);
+                if from.exists() {
+                    fs::rename(&from, self.rotated_path(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.max_bytes > 0 && self.written + buf.len() as u64 > self.max_bytes && self.written > 0 {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_level() {
+        assert_eq!(parse_level("debug").unwrap(), LevelFilter::Debug);
+        assert_eq!(parse_level("OFF").unwrap(), LevelFilter::Off);
+        assert!(parse_level("loud").is_err());
+    }
+
+    #[test]
+    fn test_rotation_keeps_limited_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("logs").join("bookrat.log");
+        let mut log = RotatingFile::open(&path, 10, 2).unwrap();
+
+        for line in ["first line\n", "second line\n", "third line\n", "fourth\n"] {
+            log.write_all(line.as_bytes()).unwrap();
+        }
+        log.flush().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(fs::read_to_string(dir.path().join("logs/bookrat.log.1")).unwrap(), "third line\n");
+        assert_eq!(fs::read_to_string(dir.path().join("logs/bookrat.log.2")).unwrap(), "second line\n");
+        assert!(!dir.path().join("logs/bookrat.log.3").exists());
+    }
+}
@@ -1,536 +1,173 @@
-mod bookmark;
-mod regex_patterns;
-
 use std::{
-    fs::File,
-    io::{stdout, BufReader, Stdout},
-    time::Duration,
-    path::Path,
+    collections::HashSet,
+    io::{stdout, Read, Stdout, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
 };
 
 use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+    event::{self, DisableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use epub::doc::EpubDoc;
-use log::{debug, error, info, warn};
-use ratatui::{
-    backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
-    style::{Color, Style, Stylize},
-    text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
-    Terminal,
+use log::{error, info};
+use ratatui::{backend::CrosstermBackend, Terminal};
+
+use bookrat::{
+    app::App,
+    book::{self, Document},
+    bookmark::Bookmarks,
+    calibre::{self, CalibreClient, RemoteBook},
+    clipboard,
+    catalog::{self, CatalogFormat, Entry, Query, Record},
+    config::{CalibreConfig, Config},
+    diagnostics,
+    discord::Presence,
+    download,
+    export::{self, ExportFormat},
+    git_sync::GitSync,
+    history::History,
+    hooks::{self, EventContext},
+    kobo,
+    library::{self, ScanOptions},
+    library_index::{self, Imported, LibraryIndex},
+    library_stats,
+    logging,
+    opds::OpdsServer,
+    paths::Paths,
+    plugins::Plugins,
+    reading_time::ReadingTime,
+    remote::RemoteServer,
+    status_file::StatusWriter,
+    web::WebServer,
 };
-use simplelog::{Config, LevelFilter, WriteLogger};
-use regex::{self};
-use textwrap::{fill, wrap_algorithms::Penalties, Options, WrapAlgorithm};
-
-use crate::bookmark::Bookmarks;
-use crate::regex_patterns::RegexPatterns;
-
-struct App {
-    epub_files: Vec<String>,
-    selected: usize,
-    current_content: Option<String>,
-    list_state: ListState,
-    current_epub: Option<EpubDoc<BufReader<std::fs::File>>>,
-    current_chapter: usize,
-    total_chapters: usize,
-    scroll_offset: usize,
-    mode: Mode,
-    bookmarks: Bookmarks,
-    current_file: Option<String>,
-    content_length: usize,
-    last_scroll_time: std::time::Instant,
-    scroll_speed: usize,
-    regex: RegexPatterns,
-    debug_mode: bool,
-}
 
-#[derive(PartialEq)]
-enum Mode {
-    FileList,
-    Content,
+#[derive(Parser)]
+#[command(name = "bookrat", version, about = "A TUI EPUB reader")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// A book to open, a directory to read the library from for this
+    /// session, an http(s) URL of a book to download into the library and
+    /// open, or `-` to read HTML or text piped to standard input
+    #[arg(value_name = "BOOK|DIR|URL|-")]
+    source: Option<String>,
+    /// Serve the remote-control API on `host:port` or `unix:/path/to/socket`
+    #[arg(long, value_name = "ADDR")]
+    remote: Option<String>,
+    /// Keep a JSON status of the book being read in this file, or serve it
+    /// on `unix:/path/to/socket`, for tmux and desktop bars
+    #[arg(long, value_name = "PATH")]
+    status: Option<String>,
+    /// Keep the config, bookmarks and log in `bookrat-data` beside the
+    /// executable; on by default when that directory exists
+    #[arg(long, global = true)]
+    portable: bool,
+    /// Use the bookmarks, reading statistics and settings of this profile
+    #[arg(long, value_name = "NAME", global = true)]
+    profile: Option<String>,
+    /// Log level: off, error, warn, info, debug or trace
+    #[arg(long, value_name = "LEVEL")]
+    log_level: Option<String>,
+    /// Write the log here instead of the state directory
+    #[arg(long, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+    /// Disable logging entirely
+    #[arg(long, conflicts_with_all = ["log_level", "log_file"])]
+    no_log: bool,
 }
 
-impl App {
-    fn new() -> Result<Self> {
-        let bookmarks = Bookmarks::load().context("Failed to load bookmarks")?;
-
-        let mut epub_files = Vec::new();
-        let books_dir = "./books";
-        
-        let entries = std::fs::read_dir(books_dir)
-            .with_context(|| format!("Failed to read directory: {}", books_dir))?;
-
-        for entry_result in entries {
-            let entry = match entry_result {
-                Ok(e) => e,
-                Err(e) => {
-                    warn!("Failed to process directory entry: {}", e);
-                    continue;
-                }
-            };
-
-            let path = entry.path();
-
-            if path.is_file() {
-                if let Some(ext) = path.extension() {
-                    if ext == "epub" {
-                        if let Some(path_str) = path.to_str() {
-                            epub_files.push(path_str.to_string());
-                        } else {
-                            warn!("Failed to convert path to string: {:?}", path);
-                        }
-                    }
-                }
-            }
-        }
-
-        epub_files.sort();
-        info!("Found EPUB files: {:?}", epub_files);
-
-        let mut list_state = ListState::default();
-        if !epub_files.is_empty() {
-            list_state.select(Some(0));
-        }
-        
-        let regex = RegexPatterns::new()
-            .context("Failed to compile regex patterns")?;
-
-        Ok(Self {
-            mode: Mode::FileList,
-            list_state,
-            epub_files,
-            selected: 0,
-            current_epub: None,
-            current_file: None,
-            current_content: None,
-            content_length: 0,
-            scroll_offset: 0,
-            current_chapter: 0,
-            total_chapters: 0,
-            bookmarks,
-            last_scroll_time: std::time::Instant::now(),
-            scroll_speed: 1,
-            debug_mode: false,
-            regex,
-        })
-    }
-
-    fn process_html_content(content: &str, regex: &RegexPatterns) -> String {
-        let text = content.replace('\n', "");
-        
-        let text = regex.css_rule.replace_all(&text, "").to_string();
-
-        let text = regex.h_open.replace_all(&text, "\n").to_string();
-        let text = regex.h_close.replace_all(&text, "\n").to_string();
-
-        let text = regex.multi_space.replace_all(&text, " ").to_string();
-        let text = regex.leading_space.replace_all(&text, "").to_string();
-        let text = regex.line_leading_space.replace_all(&text, "\n").to_string();
-
-        let mut first_paragraph = true;
-        let text = regex.p_tag.replace_all(&text, |_caps: &regex::Captures| {
-            if first_paragraph {
-                first_paragraph = false;
-                ""
-            } else {
-                "\n    "
-            }
-        }).to_string();
-        
-        let text = text
-            .replace("</p>", "")
-            .replace("<br>", "\n")
-            .replace("<br/>", "\n")
-            .replace("<br />", "\n")
-            .replace("<blockquote>", "\n    ")
-            .replace("</blockquote>", "\n")
-            .replace("<em>", "_")
-            .replace("</em>", "_")
-            .replace("<i>", "_")
-            .replace("</i>", "_")
-            .replace("<strong>", "**")
-            .replace("</strong>", "**")
-            .replace("<b>", "**")
-            .replace("</b>", "**");
-
-        let text = regex.remaining_tags.replace_all(&text, "").to_string();
-
-        let text = text
-            .replace("&nbsp;", " ")
-            .replace("&amp;", "&")
-            .replace("&lt;", "<")
-            .replace("&gt;", ">")
-            .replace("&quot;", "\"")
-            .replace("&apos;", "'")
-            .replace("&mdash;", "—")
-            .replace("&ndash;", "–")
-            .replace("&hellip;", "...")
-            .replace("&ldquo;", "\u{201C}")
-            .replace("&rdquo;", "\u{201D}")
-            .replace("&lsquo;", "\u{2018}")
-            .replace("&rsquo;", "\u{2019}");
-
-        let text = regex.empty_lines.replace_all(&text, "\n").to_string();
-        let mut text = regex.multi_newline.replace_all(&text, "\n").to_string();
-        
-        if text.starts_with("\n    ") {
-            text.replace_range(1..5, "");
-        }
-
-        text.trim().to_string()
-    }
-
-    fn load_epub(&mut self, path: &str) {
-        info!("Attempting to load EPUB: {}", path);
-        match EpubDoc::new(path) {
-            Ok(mut doc) => {
-                info!("Successfully created EPUB document");
-                self.total_chapters = doc.get_num_pages();
-                info!("Total chapters: {}", self.total_chapters);
-
-                if let Some(bookmark) = self.bookmarks.get_bookmark(path) {
-                    info!("Found bookmark: chapter {}, offset {}", bookmark.chapter, bookmark.scroll_offset);
-                    if bookmark.chapter > 0 {
-                        doc.set_current_page(0);
-                        for _ in 0..bookmark.chapter {
-                            if !doc.go_next() {
-                                error!("Failed to navigate to bookmarked chapter at index {}", bookmark.chapter);
-                                doc.set_current_page(0);
-                                self.current_chapter = 0;
-                                self.scroll_offset = 0;
-                                break;
-                            }
-                        }
-                        if doc.get_current_page() == bookmark.chapter {
-                            self.current_chapter = bookmark.chapter;
-                            self.scroll_offset = bookmark.scroll_offset;
-                        } else {
-                             error!("Could not reach bookmarked chapter index {}", bookmark.chapter);
-                             if self.total_chapters > 1 {
-                                 doc.set_current_page(1);
-                                 self.current_chapter = 1;
-                             } else {
-                                 doc.set_current_page(0);
-                                 self.current_chapter = 0;
-                             }
-                             self.scroll_offset = 0;
-                        }
-                    }
-                } else {
-                    if self.total_chapters > 1 {
-                        if doc.go_next() {
-                            self.current_chapter = 1;
-                            info!("Skipped potential metadata page, moved to chapter 1 (index 1)");
-                        } else {
-                            error!("Failed to move to chapter 1");
-                            self.current_chapter = 0;
-                        }
-                    }
-                }
-
-                self.current_epub = Some(doc);
-                self.current_file = Some(path.to_string());
-                self.update_content();
-                self.mode = Mode::Content;
-            }
-            Err(e) => {
-                error!("Failed to load EPUB: {}: {}", path, e);
-            }
-        }
-    }
-
-    fn save_bookmark(&mut self) {
-        if let Some(path) = &self.current_file {
-            self.bookmarks.update_bookmark(path, self.current_chapter, self.scroll_offset);
-            if let Err(e) = self.bookmarks.save() {
-                error!("Failed to save bookmark: {}", e);
-            }
-        }
-    }
-
-    fn update_content(&mut self) {
-        if let Some(doc) = &mut self.current_epub {
-            if let Some((content, _mime)) = doc.get_current_str() {
-                debug!("Raw content length: {} bytes", content.len());
-
-                if self.debug_mode {
-                    self.content_length = content.len();
-                    self.current_content = Some(content);
-                } else {
-                    let text = Self::process_html_content(&content, &self.regex);
-                    debug!("Processed text length: {} bytes", text.len());
-                    debug!("Text after HTML cleanup: {}", text.chars().take(100).collect::<String>());
-
-                    if text.is_empty() {
-                        warn!("Converted text is empty");
-                        self.current_content = Some("No content available in this chapter.".to_string());
-                        self.content_length = 0;
-                    } else {
-                        self.content_length = text.len(); 
-                        self.current_content = Some(text);
-                    }
-                }
-            } else {
-                error!("Failed to get current chapter content for index {}", self.current_chapter);
-                self.current_content = Some("Error reading chapter content.".to_string());
-                self.content_length = 0;
-            }
-        } else {
-            error!("No EPUB document loaded");
-            self.current_content = Some("No EPUB document loaded.".to_string());
-            self.content_length = 0;
-        }
-    }
-
-    fn next_chapter(&mut self) {
-        if let Some(doc) = &mut self.current_epub {
-            if self.current_chapter < self.total_chapters.saturating_sub(1) {
-                if doc.go_next() {
-                    self.current_chapter += 1;
-                    info!("Moving to next chapter: {}", self.current_chapter);
-                    self.update_content();
-                    self.scroll_offset = 0;
-                    self.save_bookmark();
-                } else {
-                    error!("Failed to move to next chapter from {}", self.current_chapter);
-                }
-            } else {
-                info!("Already at last chapter {}", self.current_chapter);
-            }
-        }
-    }
-
-    fn prev_chapter(&mut self) {
-        if let Some(doc) = &mut self.current_epub {
-            if self.current_chapter > 0 {
-                if doc.go_prev() {
-                    self.current_chapter -= 1;
-                    info!("Moving to previous chapter: {}", self.current_chapter);
-                    self.update_content();
-                    self.scroll_offset = 0;
-                    self.save_bookmark();
-                } else {
-                    error!("Failed to move to previous chapter from {}", self.current_chapter);
-                }
-            } else {
-                info!("Already at first chapter (0)");
-            }
-        }
-    }
-
-    fn scroll_down(&mut self) {
-        if let Some(content) = &self.current_content {
-            let now = std::time::Instant::now();
-            if now.duration_since(self.last_scroll_time) < std::time::Duration::from_millis(100) {
-                self.scroll_speed = (self.scroll_speed + 1).min(10);
-            } else {
-                self.scroll_speed = 1;
-            }
-            self.last_scroll_time = now;
-
-            self.scroll_offset = self.scroll_offset.saturating_add(self.scroll_speed);
-            let total_lines = content.lines().count();
-            debug!("Scrolling down to offset: {}/{} (speed: {})", self.scroll_offset, total_lines, self.scroll_speed);
-            self.save_bookmark();
-        }
-    }
-
-    fn scroll_up(&mut self) {
-        if let Some(content) = &self.current_content {
-            let now = std::time::Instant::now();
-            if now.duration_since(self.last_scroll_time) < std::time::Duration::from_millis(100) {
-                self.scroll_speed = (self.scroll_speed + 1).min(10);
-            } else {
-                self.scroll_speed = 1;
-            }
-            self.last_scroll_time = now;
-
-            self.scroll_offset = self.scroll_offset.saturating_sub(self.scroll_speed);
-            let total_lines = content.lines().count();
-            debug!("Scrolling up to offset: {}/{} (speed: {})", self.scroll_offset, total_lines, self.scroll_speed);
-            self.save_bookmark();
-        }
-    }
-
-    fn draw(&mut self, f: &mut ratatui::Frame) {
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([
-                Constraint::Min(0),
-                Constraint::Length(3),
-            ])
-            .split(f.size());
-
-        let main_chunks = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([
-                Constraint::Percentage(30),
-                Constraint::Percentage(70),
-            ])
-            .split(chunks[0]);
-
-        let items: Vec<ListItem> = self
-            .epub_files
-            .iter()
-            .map(|file| {
-                let bookmark = self.bookmarks.get_bookmark(file);
-                let last_read = bookmark
-                    .map(|b| b.last_read.format("%Y-%m-%d %H:%M").to_string())
-                    .unwrap_or_else(|| "Never".to_string());
-                
-                let display_name = Path::new(file)
-                    .file_stem()
-                    .unwrap_or_default()
-                    .to_string_lossy()
-                    .to_string();
-                
-                let content = Line::from(vec![
-                    Span::styled(
-                        display_name,
-                        Style::default(),
-                    ),
-                    Span::styled(
-                        format!(" ({})", last_read),
-                        Style::default().fg(Color::DarkGray),
-                    ),
-                ]);
-                ListItem::new(content)
-            })
-            .collect();
-
-        let files = List::new(items)
-            .block(Block::default().borders(Borders::ALL).title("Books"))
-            .highlight_style(Style::default().bg(Color::White).fg(Color::Black));
-
-        f.render_stateful_widget(files, main_chunks[0], &mut self.list_state.clone());
-
-        let content_display_text = self
-            .current_content
-            .as_deref()
-            .unwrap_or("Select a file to view its content");
-
-        let title = if self.current_epub.is_some() && !self.debug_mode {
-            let chapter_progress = if let Some(ref content) = self.current_content {
-                if !content.is_empty() {
-                    let visible_width = main_chunks[1].width.saturating_sub(2);
-                    if visible_width > 0 {
-                        let options = Options::new(visible_width as usize)
-                            .word_separator(textwrap::WordSeparator::AsciiSpace)
-                            .wrap_algorithm(WrapAlgorithm::OptimalFit(Penalties::default()));
-                        let wrapped_lines = fill(content, &options);
-                        let total_lines = wrapped_lines.lines().count();
-                        
-                        let visible_height = main_chunks[1].height.saturating_sub(2);
-                        let max_scroll_offset = total_lines.saturating_sub(visible_height as usize);
-                        
-                        if max_scroll_offset > 0 {
-                            let current_scroll = self.scroll_offset;
-                            ((current_scroll as f32 / max_scroll_offset as f32) * 100.0).min(100.0) as u32
-                        } else {
-                            100
-                        }
-                    } else {
-                        0 
-                    }
-                } else {
-                    0 
-                }
-            } else {
-                0
-            };
-            format!(
-                "Part {}/{} | Progress: {}%",
-                self.current_chapter + 1,
-                self.total_chapters,
-                chapter_progress
-            )
-        } else if self.debug_mode && self.current_epub.is_some() {
-            format!(
-                "Part {}/{} [DEBUG MODE]",
-                self.current_chapter + 1,
-                self.total_chapters
-            )
-        } else {
-            "Content".to_string()
-        };
-
-        let styled_content: Vec<Line> = if let Some(ref content_str) = self.current_content {
-            let mut styled_lines = Vec::new();
-            let mut is_italic = false;
-            let mut is_bold = false;
-            
-            for line in content_str.lines() {
-                let mut current_line_spans = Vec::new();
-                let mut current_text = String::new();
-                let mut chars = line.chars().peekable();
-                
-                while let Some(c) = chars.next() {
-                    if c == '_' {
-                        if !current_text.is_empty() {
-                            let mut style = Style::default().fg(Color::White);
-                            if is_italic { style = style.italic(); }
-                            if is_bold { style = style.bold(); }
-                            current_line_spans.push(Span::styled(current_text.clone(), style));
-                            current_text.clear();
-                        }
-                        is_italic = !is_italic;
-                    } else if c == '*' && chars.peek() == Some(&'*') {
-                        chars.next(); 
-                        if !current_text.is_empty() {
-                            let mut style = Style::default().fg(Color::White);
-                            if is_italic { style = style.italic(); }
-                            if is_bold { style = style.bold(); }
-                            current_line_spans.push(Span::styled(current_text.clone(), style));
-                            current_text.clear();
-                        }
-                        is_bold = !is_bold;
-                    } else {
-                        current_text.push(c);
-                    }
-                }
-                
-                if !current_text.is_empty() {
-                    let mut style = Style::default().fg(Color::White);
-                    if is_italic { style = style.italic(); }
-                    if is_bold { style = style.bold(); }
-                    current_line_spans.push(Span::styled(current_text, style));
-                }
-                
-                styled_lines.push(Line::from(current_line_spans));
-            }
-            styled_lines
-        } else {
-            vec![Line::from(content_display_text)]
-        };
-
-        let content_paragraph = Paragraph::new(styled_content)
-            .block(Block::default().borders(Borders::ALL).title(title))
-            .wrap(Wrap { trim: false })
-            .scroll((self.scroll_offset as u16, 0));
-
-        f.render_widget(content_paragraph, main_chunks[1]);
+#[derive(Subcommand)]
+enum Command {
+    /// Convert a book to Markdown or plain text without starting the TUI
+    Export {
+        book: PathBuf,
+        #[arg(long, value_enum, default_value = "md")]
+        format: ExportFormat,
+        /// Parts to export, e.g. `3` or `2-5` (as shown in the reader's title bar)
+        #[arg(long, visible_alias = "chapters")]
+        parts: Option<String>,
+        /// Output file; defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Check a book for structural problems that make it render badly
+    Validate { book: PathBuf },
+    /// List the whole library with its metadata and reading progress
+    Catalog {
+        #[arg(long, value_enum, default_value = "csv")]
+        format: CatalogFormat,
+        /// Output file; defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Serve the library and reading positions to a web browser
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: String,
+    },
+    /// Browse and fetch books from a calibre content server
+    Calibre {
+        #[command(subcommand)]
+        action: CalibreAction,
+        /// Server address; defaults to calibre.url in the config
+        #[arg(long, global = true)]
+        url: Option<String>,
+        /// Library id; defaults to calibre.library or the server's default
+        #[arg(long, global = true)]
+        library: Option<String>,
+    },
+    /// Import positions and highlights from a mounted Kobo e-reader
+    Kobo {
+        /// Where the Kobo is mounted; found among removable drives by default
+        #[arg(long, value_name = "PATH")]
+        mount: Option<PathBuf>,
+        /// Also move the Kobo's position on for books read further here
+        #[arg(long)]
+        write_back: bool,
+    },
+    /// Import the reading positions in bookmarks.json into the library
+    /// index, listing each book's position and when it was last read
+    Migrate {
+        /// Only list what would be imported
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Open a random book never opened before
+    Pick {
+        /// Only pick among books matching this library filter, such as
+        /// `tag:scifi`
+        query: Vec<String>,
+        /// Only pick books of at most this many estimated pages
+        #[arg(long, value_name = "N")]
+        max_pages: Option<usize>,
+    },
+    /// Serve the library as an OPDS catalog for reading apps
+    OpdsServe {
+        /// Listens on all interfaces by default, so phones on the same
+        /// network can reach it
+        #[arg(long, default_value = "0.0.0.0:8081")]
+        addr: String,
+    },
+}
 
-        let help_text = match self.mode {
-            Mode::FileList => "j/k: Navigate | Enter: Select | Tab: Switch View | q: Quit",
-            Mode::Content => {
-                if self.debug_mode {
-                    "j/k: Scroll | h/l: Change Part | Tab: Switch View | d: Toggle Debug | q: Quit"
-                } else {
-                    "j/k: Scroll | h/l: Change Part | Tab: Switch View | d: Toggle Debug | q: Quit"
-                }
-            },
-        };
-        let help = Paragraph::new(help_text)
-            .block(Block::default().borders(Borders::ALL))
-            .style(Style::default().fg(Color::DarkGray));
-        f.render_widget(help, chunks[1]);
-    }
+#[derive(Subcommand)]
+enum CalibreAction {
+    /// List the books matching a calibre search (all books without one)
+    List { query: Vec<String> },
+    /// Download a book into the library
+    Download { id: u64 },
+    /// Read a book without adding it to the library
+    Open { id: u64 },
+    /// Set the book's read column on the server
+    MarkRead {
+        id: u64,
+        /// Mark it unread instead
+        #[arg(long)]
+        unread: bool,
+    },
 }
 
 fn setup_terminal() -> Result<Terminal<CrosstermBackend<Stdout>>> {
@@ -551,23 +188,165 @@ fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> Result
     terminal.show_cursor().context("Failed to show cursor")
 }
 
+/// Ctrl-Z: raw mode keeps the terminal from stopping bookrat itself, so
+/// the terminal is handed back to the shell and the process stopped here.
+/// On `fg` the screen is set up again and redrawn from scratch.
+fn suspend<B: ratatui::backend::Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<()> {
+    app.flush();
+    disable_raw_mode().context("Failed to disable raw mode")?;
+    execute!(stdout(), LeaveAlternateScreen).context("Failed to leave alternate screen")?;
+    terminal.show_cursor().context("Failed to show cursor")?;
+    info!("Suspending");
+    #[cfg(unix)]
+    signal_hook::low_level::raise(signal_hook::consts::SIGSTOP).context("Failed to stop")?;
+    info!("Resumed");
+    enable_raw_mode().context("Failed to enable raw mode")?;
+    execute!(stdout(), EnterAlternateScreen).context("Failed to enter alternate screen")?;
+    terminal.clear().context("Failed to clear the screen")
+}
+
+fn is_suspend(key: &KeyEvent) -> bool {
+    cfg!(unix) && key.code == KeyCode::Char('z') && key.modifiers.contains(KeyModifiers::CONTROL)
+}
+
 fn main() -> Result<()> {
-    WriteLogger::init(
-        LevelFilter::Debug,
-        Config::default(),
-        File::create("bookrat.log")?,
-    )?;
+    let cli = Cli::parse();
+    let paths = if cli.portable || Paths::portable_dir_exists() {
+        Paths::portable(cli.profile.as_deref())?
+    } else {
+        Paths::new(cli.profile.as_deref())?
+    };
+    let mut config = Config::load_layers(&paths.config_files())?;
+    if config.logging.path.is_none() {
+        config.logging.path = paths.log_file().map(PathBuf::from);
+    }
+    if let Some(level) = &cli.log_level {
+        config.logging.level = level.clone();
+    }
+    if let Some(path) = &cli.log_file {
+        config.logging.path = Some(path.clone());
+    }
+    if cli.no_log {
+        config.logging.level = "off".to_string();
+    }
+    logging::init(&config.logging)?;
+
+    let mut open_file = None;
+    match cli.command {
+        Some(Command::Calibre { action, url, library }) => {
+            let mut settings = config.calibre.clone();
+            settings.url = url.or(settings.url);
+            settings.library = library.or(settings.library);
+            match run_calibre(action, &settings, &config.library.roots(), &paths)? {
+                Some(path) => open_file = Some(path),
+                None => return Ok(()),
+            }
+        }
+        Some(Command::Export { book, format, parts, output }) => {
+            return run_export(&book, format, parts.as_deref(), output);
+        }
+        Some(Command::Validate { book }) => {
+            return run_validate(&book);
+        }
+        Some(Command::Catalog { format, output }) => {
+            return run_catalog(format, output, &config, &paths);
+        }
+        Some(Command::Serve { addr }) => {
+            let server = WebServer::bind(&addr, &config.library.roots(), scan_options(&config, &paths), &paths.bookmarks_file())?;
+            println!("Serving the library on http://{}", addr);
+            server.run();
+            return Ok(());
+        }
+        Some(Command::Kobo { mount, write_back }) => {
+            return run_kobo(mount, write_back, &config, &paths);
+        }
+        Some(Command::Migrate { dry_run }) => {
+            return run_migrate(dry_run, &config, &paths);
+        }
+        Some(Command::Pick { query, max_pages }) => {
+            open_file = Some(run_pick(&query.join(" "), max_pages, &config, &paths)?);
+        }
+        Some(Command::OpdsServe { addr }) => {
+            let server = OpdsServer::bind(&addr, &config.library.roots(), scan_options(&config, &paths))?;
+            println!("Serving the OPDS catalog on http://{}/opds", addr);
+            server.run();
+            return Ok(());
+        }
+        None => {}
+    }
+
+    let mut roots = config.library.roots();
+    let mut piped = None;
+    let mut url = None;
+    match cli.source.as_deref() {
+        Some("-") => piped = Some(read_stdin()?),
+        Some(source) if download::is_url(source) => url = Some(source),
+        Some(dir) if Path::new(dir).is_dir() => roots = vec![dir.to_string()],
+        Some(book) if Path::new(book).is_file() => open_file = Some(book.to_string()),
+        Some(source) => anyhow::bail!("{} is neither a book, a directory nor an http:// or https:// URL", source),
+        None => {}
+    }
 
     info!("Starting BookRat EPUB reader");
 
-    let mut app = App::new()?;
+    let sync = config.git_sync.enabled.then(|| start_git_sync(&paths, &config.git_sync.remote)).flatten();
+
+    let mut app = App::new(&paths.bookmarks_file(), &roots, scan_options(&config, &paths))?;
+    if let Some(dir) = paths.plugins_dir() {
+        app.set_plugins(Plugins::load_dir(&dir));
+    }
+    app.set_paragraph_format(config.reading.paragraph_format());
+    app.set_hooks(config.hooks.clone());
+    app.set_dictionary(config.dictionary.clone());
+    app.set_library_preview(config.library.preview);
+    app.set_graphics(config.library.graphics.protocol());
+    app.set_continue_reading(config.library.continue_reading);
+    app.set_last_read_format(&config.library.last_read_format);
+    app.set_reminders(config.reading.reminders());
+    app.set_skip_boilerplate(config.reading.skip_boilerplate);
+    if config.bookmarks.sidecars {
+        app.use_sidecars();
+    }
+    match (piped, url) {
+        (Some(input), _) => app.open_document(Document::new("stdin", &input)),
+        (None, Some(url)) => app.open_url(url),
+        (None, None) => {}
+    }
+    if let Some(path) = &open_file {
+        app.open_file(path);
+    }
+    let (wake, wakeups) = mpsc::channel();
+    let remote = match cli.remote.as_deref() {
+        Some(addr) => {
+            let wake = wake.clone();
+            Some(RemoteServer::start_waking(addr, move || {
+                let _ = wake.send(Wake::Remote);
+            })?)
+        }
+        None => None,
+    };
+    if config.library.watch {
+        let wake = wake.clone();
+        app.watch_library(move || {
+            let _ = wake.send(Wake::Library);
+        });
+    }
+    let mut presence = config.discord.client_id.as_deref().map(Presence::start);
+    let mut status = cli.status.as_deref().map(StatusWriter::open).transpose()?;
 
     let mut terminal = setup_terminal()?;
 
-    let result = run_app(&mut terminal, &mut app);
+    let result = run_app(&mut terminal, &mut app, (wake, wakeups), remote.as_ref(), presence.as_mut(), status.as_mut());
 
     restore_terminal(&mut terminal)?;
 
+    if let Some(sync) = &sync {
+        app.flush();
+        if let Err(e) = sync.push() {
+            eprintln!("Warning: reading state not synced: {:#}", e);
+        }
+    }
+
     if let Err(err) = result {
         eprintln!("Error: {:?}", err);
         error!("Application runtime error: {:?}", err);
@@ -578,181 +357,327 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn run_app<B: ratatui::backend::Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<()> {
-    let tick_rate = Duration::from_millis(250);
-    let mut last_tick = std::time::Instant::now();
-
-    loop {
-        terminal.draw(|f| app.draw(f))?;
-        let timeout = tick_rate
-            .checked_sub(last_tick.elapsed())
-            .unwrap_or_else(|| Duration::from_secs(0));
-        if crossterm::event::poll(timeout)? {
-            if let Event::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Char('q') => return Ok(()),
-                    KeyCode::Char('j') => {
-                        if app.mode == Mode::FileList {
-                            if app.selected < app.epub_files.len().saturating_sub(1) {
-                                app.selected += 1;
-                                app.list_state.select(Some(app.selected));
-                            }
-                        } else {
-                            app.scroll_down();
-                        }
-                    }
-                    KeyCode::Char('k') => {
-                        if app.mode == Mode::FileList {
-                            if app.selected > 0 {
-                                app.selected -= 1;
-                                app.list_state.select(Some(app.selected));
-                            }
-                        } else {
-                            app.scroll_up();
-                        }
-                    }
-                    KeyCode::Char('h') => {
-                        if app.mode == Mode::Content {
-                            app.prev_chapter();
-                        }
-                    }
-                    KeyCode::Char('l') => {
-                        if app.mode == Mode::Content {
-                            app.next_chapter();
-                        }
-                    }
-                    KeyCode::Enter => {
-                        if app.mode == Mode::FileList {
-                            if let Some(path) = app.epub_files.get(app.selected).cloned() {
-                                app.load_epub(&path);
-                            }
-                        }
-                    }
-                    KeyCode::Tab => {
-                        app.mode = if app.mode == Mode::FileList {
-                            Mode::Content
-                        } else {
-                            if let Some(current_file) = &app.current_file {
-                                if let Some(pos) = app.epub_files.iter().position(|f| f == current_file) {
-                                    app.selected = pos;
-                                    app.list_state.select(Some(pos));
-                                }
-                            }
-                            Mode::FileList
-                        };
-                    }
-                    KeyCode::Char('d') => {
-                        if app.mode == Mode::Content {
-                            app.debug_mode = !app.debug_mode;
-                            app.update_content();
-                        }
-                    }
-                    _ => {}
-                }
+/// Carries out a calibre action. Downloads go into the first of the
+/// library `roots`; opening a book gives the path of the downloaded copy
+/// for the reader to show.
+fn run_calibre(action: CalibreAction, settings: &CalibreConfig, roots: &[String], paths: &Paths) -> Result<Option<String>> {
+    let client = CalibreClient::connect(settings)?;
+    let book = |id: u64| -> Result<RemoteBook> {
+        match client.search(&format!("id:{}", id))?.into_iter().next() {
+            Some(book) if book.has_epub() => Ok(book),
+            Some(book) => anyhow::bail!("\"{}\" has no EPUB format (only {})", book.title, book.formats.join(", ")),
+            None => anyhow::bail!("No book {} in library {}", id, client.library()),
+        }
+    };
+    match action {
+        CalibreAction::List { query } => {
+            for book in client.search(&query.join(" "))? {
+                let formats = if book.has_epub() { String::new() } else { " (no EPUB)".to_string() };
+                println!("{:>6}  {} - {}{}", book.id, book.title, book.authors.join(" & "), formats);
             }
+            Ok(None)
+        }
+        CalibreAction::Download { id } => {
+            let book = book(id)?;
+            let root = roots.first().map_or(library::DEFAULT_ROOT, String::as_str);
+            let path = client.download_into(id, Path::new(root), &calibre::file_name(&book))?;
+            println!("Saved {:?}", path);
+            Ok(None)
+        }
+        CalibreAction::Open { id } => {
+            book(id)?;
+            let path = client.cache_path(&paths.calibre_dir(), id)?;
+            client.download(id, &path)?;
+            Ok(Some(path.to_string_lossy().to_string()))
         }
-        if last_tick.elapsed() >= tick_rate {
-            last_tick = std::time::Instant::now();
+        CalibreAction::MarkRead { id, unread } => {
+            client.set_read(id, !unread)?;
+            println!("Marked book {} as {}", id, if unread { "unread" } else { "read" });
+            Ok(None)
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Brings in the reading state from the git remote before it is loaded.
+/// Failing to sync is reported but doesn't keep the reader from starting.
+fn start_git_sync(paths: &Paths, remote: &str) -> Option<GitSync> {
+    let bookmarks = paths.bookmarks_file();
+    let dir = bookmarks.parent().unwrap_or(std::path::Path::new("."));
+    let sync = match GitSync::new(dir, remote) {
+        Ok(sync) => sync,
+        Err(e) => {
+            eprintln!("Warning: reading state not synced: {:#}", e);
+            return None;
+        }
+    };
+    // Local changes are still committed at exit
+    if let Err(e) = sync.pull() {
+        eprintln!("Warning: reading state not synced: {:#}", e);
+        error!("Git sync failed: {:?}", e);
+    }
+    Some(sync)
+}
 
-    fn get_test_regex() -> RegexPatterns {
-        RegexPatterns::new()
+fn run_kobo(mount: Option<PathBuf>, write_back: bool, config: &Config, paths: &Paths) -> Result<()> {
+    let mount = match mount {
+        Some(mount) => mount,
+        None => match kobo::find_mounts().as_slice() {
+            [mount] => mount.clone(),
+            [] => anyhow::bail!("No Kobo found; connect it or give its mount point with --mount"),
+            mounts => anyhow::bail!("Several Kobos are mounted ({:?}); pick one with --mount", mounts),
+        },
+    };
+    let library = library::scan_roots(&config.library.roots(), &scan_options(config, paths))?;
+    let mut bookmarks = Bookmarks::load_from(paths.bookmarks_file())?;
+    if config.bookmarks.sidecars {
+        bookmarks.use_sidecars(&library);
+    }
+    let report = kobo::sync(&mount, &library, &mut bookmarks, write_back)?;
+    println!(
+        "Imported {} positions and {} highlights from {:?}",
+        report.positions,
+        report.highlights.len(),
+        mount
+    );
+    for (path, highlight) in &report.highlights {
+        let entry = Entry::read(path);
+        let total_chapters = epub::doc::EpubDoc::new(path).map(|doc| doc.get_num_chapters()).unwrap_or(0);
+        let context = EventContext {
+            book: path.clone(),
+            title: entry.title,
+            author: entry.authors.into_iter().next(),
+            chapter: highlight.chapter + 1,
+            total_chapters,
+            highlight: Some(highlight.text.clone()),
+            note: highlight.note.clone(),
+        };
+        hooks::run(&config.hooks, hooks::Event::HighlightCreated, &context);
     }
+    if write_back {
+        println!("Wrote {} positions back to the Kobo", report.written_back);
+    }
+    if !report.unmatched.is_empty() {
+        println!("Not in the library: {}", report.unmatched.join(", "));
+    }
+    Ok(())
+}
 
-    #[test]
-    fn test_html_formatting() {
-        let regex = get_test_regex();
-        let test_content = r#"
-            <h1>Header 1</h1>
-            <p>First paragraph with <em>italic</em> text.</p>
-            <p>Second paragraph with <strong>bold</strong> text.</p>
-            <blockquote>A blockquote</blockquote>
-            <p>Third paragraph with <br/> line break.</p>
-            <p>Fourth paragraph with &quot;quotes&quot; and &mdash; dash.</p>
-        "#;
-
-        let content = App::process_html_content(test_content, &regex);
-
-        assert!(content.contains("Header 1"));
-        assert!(content.contains("First paragraph with _italic_ text."));
-        assert!(content.contains("Second paragraph with **bold** text."));
-        assert!(content.contains("Third paragraph with \n line break."));
-        assert!(content.contains("Fourth paragraph with \"quotes\" and — dash."));
-        let paragraphs: Vec<&str> = content.split('\n').collect();
-        assert!(paragraphs.len() >= 5);
-        assert!(content.contains("    A blockquote"));
-        assert!(!content.contains("&quot;"));
-        assert!(!content.contains("&mdash;"));
-        assert!(!content.contains("<em>"));
-        assert!(!content.contains("<strong>"));
+/// How the configured library is read, unpacking archives where `paths`
+/// keeps them.
+fn scan_options(config: &Config, paths: &Paths) -> ScanOptions {
+    ScanOptions {
+        unpack_dir: paths.archives_dir(),
+        ..config.library.scan_options()
     }
+}
 
-    #[test]
-    fn test_empty_content() {
-        let regex = get_test_regex();
-        let test_content = "";
-        let content = App::process_html_content(test_content, &regex);
-        assert!(content.is_empty());
+/// Everything piped to standard input. Keys are still read from the
+/// terminal, which crossterm opens directly when stdin isn't one.
+fn read_stdin() -> Result<String> {
+    let mut bytes = Vec::new();
+    std::io::stdin().read_to_end(&mut bytes).context("Failed to read standard input")?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn run_export(book: &std::path::Path, format: ExportFormat, parts: Option<&str>, output: Option<PathBuf>) -> Result<()> {
+    let parts = parts.map(export::parse_part_range).transpose()?;
+    let text = export::export_book(book, format, parts)?;
+    match output {
+        Some(path) => std::fs::write(&path, text).with_context(|| format!("Failed to write {:?}", path)),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
     }
+}
 
-    #[test]
-    fn test_html_entities() {
-        let regex = get_test_regex();
-        let test_content = r#"<p>&amp; &lt; &gt; &apos; &ldquo; &rdquo; &lsquo; &rsquo;</p>"#;
-        let content = App::process_html_content(test_content, &regex);
-
-        assert!(content.contains("&"));
-        assert!(content.contains("<"));
-        assert!(content.contains(">"));
-        assert!(content.contains("'"));
-        assert!(content.contains("\u{201C}"));
-        assert!(content.contains("\u{201D}"));
-        assert!(content.contains("\u{2018}"));
-        assert!(content.contains("\u{2019}"));
-        let expected = format!("& < > ' {} {} {} {}", 
-            '\u{201C}', '\u{201D}', '\u{2018}', '\u{2019}');
-        assert_eq!(content, expected);
+/// Prints the validation report of `book`; fails when problems were found,
+/// so scripts can tell.
+fn run_validate(book: &std::path::Path) -> Result<()> {
+    let validation = diagnostics::validate(book);
+    for line in validation.describe() {
+        println!("{}", line);
+    }
+    if !validation.is_clean() {
+        anyhow::bail!("{:?} has {} structural problems", book, validation.problem_count());
     }
+    Ok(())
+}
 
-    #[test]
-    fn test_paragraph_indentation() {
-        let regex = get_test_regex();
-        let test_content = r#"<p>First paragraph</p><p>Second paragraph</p><p>Third paragraph</p>"#;
-        let content = App::process_html_content(test_content, &regex);
-
-        let paragraphs: Vec<&str> = content.split('\n').collect();
-        assert!(paragraphs.len() == 3);
-        assert!(content.starts_with("First paragraph"));
-        assert!(content.contains("\n    Second paragraph"));
-        assert!(content.contains("\n    Third paragraph"));
+/// Lists every position in the bookmarks, most recently read first, and,
+/// unless `dry_run`, keeps it in the library index with how far into the
+/// book it is. Books no longer in the library are listed but left out.
+fn run_migrate(dry_run: bool, config: &Config, paths: &Paths) -> Result<()> {
+    let library = library::scan_roots(&config.library.roots(), &scan_options(config, paths))?;
+    let mut bookmarks = Bookmarks::load_from(paths.bookmarks_file())?;
+    if config.bookmarks.sidecars {
+        bookmarks.use_sidecars(&library);
+    }
+    let imported = library_index::migrate(&library, &bookmarks, dry_run)?;
+    for Imported { file, bookmark, progress } in &imported {
+        let last_read = bookmark.last_read.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M");
+        let position = format!("part {}, line {}, last read {}", bookmark.chapter + 1, bookmark.scroll_offset + 1, last_read);
+        match progress {
+            Some(progress) => println!("{}: {}, {}% read", file, position, progress),
+            None => println!("{}: {} (not in the library, left out)", file, position),
+        }
     }
+    let left_out = imported.iter().filter(|imported| imported.progress.is_none()).count();
+    println!(
+        "{} {} positions into {}; {} not in the library",
+        if dry_run { "Would import" } else { "Imported" },
+        imported.len() - left_out,
+        LibraryIndex::path_beside(bookmarks.path()).display(),
+        left_out,
+    );
+    Ok(())
+}
 
-    #[test]
-    fn test_paragraphs_with_empty_lines() {
-        let regex = get_test_regex();
-        let test_content = r#"<p>First paragraph</p>
+/// Chooses the book for `bookrat pick`.
+fn run_pick(query: &str, max_pages: Option<usize>, config: &Config, paths: &Paths) -> Result<String> {
+    let library = library::scan_roots(&config.library.roots(), &scan_options(config, paths))?;
+    let mut bookmarks = Bookmarks::load_from(paths.bookmarks_file())?;
+    if config.bookmarks.sidecars {
+        bookmarks.use_sidecars(&library);
+    }
+    let history = History::beside(bookmarks.path())?;
+    let mut index = LibraryIndex::beside(bookmarks.path())?;
+    index.refresh(&library, &bookmarks)?;
+    let opened: HashSet<&str> = history.visits().map(|visit| visit.book.as_str()).collect();
+    let query = Query::parse(query);
+    let candidates: Vec<String> = library
+        .into_iter()
+        .filter(|file| !opened.contains(file.as_str()) && bookmarks.get_bookmark(file).is_none())
+        .filter(|file| {
+            let pages = (query.needs_pages() || max_pages.is_some())
+                .then(|| library_stats::estimated_pages(index.part_sizes(file, || book::part_sizes_of(file)).iter().sum()));
+            let entry = index.entry(file).cloned().unwrap_or_else(|| Entry::read(file));
+            query.matches(file, &entry, false, pages) && max_pages.is_none_or(|max| pages.is_some_and(|pages| pages <= max))
+        })
+        .collect();
+    let path = library::pick_random(&candidates).context("No unread book matches")?;
+    info!("Picked {} out of {} candidates", path, candidates.len());
+    Ok(path.clone())
+}
 
-        <p>Second paragraph</p>
+fn run_catalog(format: CatalogFormat, output: Option<PathBuf>, config: &Config, paths: &Paths) -> Result<()> {
+    let library = library::scan_roots(&config.library.roots(), &scan_options(config, paths))?;
+    let mut bookmarks = Bookmarks::load_from(paths.bookmarks_file())?;
+    if config.bookmarks.sidecars {
+        bookmarks.use_sidecars(&library);
+    }
+    let time = ReadingTime::beside(bookmarks.path())?;
+    let records: Vec<Record> = library.iter().map(|file| Record::read(file, &bookmarks, &time)).collect();
+    let text = catalog::format_catalog(&records, format)?;
+    match output {
+        Some(path) => std::fs::write(&path, text).with_context(|| format!("Failed to write {:?}", path)),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
 
+/// What wakes the UI loop from waiting.
+enum Wake {
+    Input(Event),
+    /// A stop sent from outside, as with `kill -TSTP`, taken like Ctrl-Z
+    Stop,
+    /// A remote-control request is waiting
+    Remote,
+    /// A book was added to or removed from the library
+    Library,
+}
 
-        <p>Third paragraph</p>
+/// Reads terminal input on a thread of its own, handing it to the UI loop.
+fn forward_input(wake: Sender<Wake>) {
+    std::thread::spawn(move || loop {
+        match event::read() {
+            Ok(event) => {
+                if wake.send(Wake::Input(event)).is_err() {
+                    break;
+                }
+            }
+            Err(e) => {
+                error!("Failed to read terminal input: {}", e);
+                break;
+            }
+        }
+    });
+}
 
-        <p>Fourth paragraph</p>"#;
-        let content = App::process_html_content(test_content, &regex);
+/// Runs the UI until it quits. Between draws the loop sleeps until a key,
+/// a resize, a stop signal or a remote request arrives, or until the app
+/// has something due (`App::next_wakeup`), so an open page costs no CPU.
+fn run_app<B: ratatui::backend::Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    (wake, wakeups): (Sender<Wake>, Receiver<Wake>),
+    remote: Option<&RemoteServer>,
+    mut presence: Option<&mut Presence>,
+    mut status: Option<&mut StatusWriter>,
+) -> Result<()> {
+    #[cfg(unix)]
+    {
+        let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGTSTP]).context("Failed to handle SIGTSTP")?;
+        let wake = wake.clone();
+        std::thread::spawn(move || {
+            for _ in signals.forever() {
+                if wake.send(Wake::Stop).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    forward_input(wake);
 
-        let paragraphs: Vec<&str> = content.split("\n").collect();
-        assert!(paragraphs.len() == 4, "Expected 4 paragraphs, got {}", paragraphs.len());
-        assert!(content.contains("First paragraph"));
-        assert!(content.contains("    Second paragraph"));
-        assert!(content.contains("    Third paragraph"));
-        assert!(content.contains("    Fourth paragraph"));
-        let expected = "First paragraph\n    Second paragraph\n    Third paragraph\n    Fourth paragraph";
-        assert_eq!(content, expected, "Content does not match expected format");
+    loop {
+        app.tick();
+        terminal.draw(|f| app.draw(f))?;
+        if let Some(update) = app.take_graphics() {
+            if update.redraw {
+                terminal.clear()?;
+                terminal.draw(|f| app.draw(f))?;
+            }
+            let mut out = stdout();
+            if let Err(e) = out.write_all(update.sequence.as_bytes()).and_then(|()| out.flush()) {
+                error!("Failed to draw a picture: {}", e);
+            }
+        }
+        if let Some(presence) = presence.as_mut() {
+            presence.set(app.now_reading());
+        }
+        if let Some(status) = status.as_mut() {
+            status.update(&app.reading_status());
+        }
+        let woken = match wakeups.recv_timeout(app.next_wakeup()) {
+            Ok(woken) => Some(woken),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => anyhow::bail!("Terminal input stopped"),
+        };
+        match woken {
+            Some(Wake::Input(Event::Key(key))) if is_suspend(&key) => suspend(terminal, app)?,
+            Some(Wake::Input(Event::Key(key))) => {
+                if app.handle_key(key) {
+                    return Ok(());
+                }
+                if let Some(text) = app.take_clipboard() {
+                    if let Err(e) = clipboard::copy(&mut stdout(), &text) {
+                        error!("Failed to copy to the clipboard: {}", e);
+                    }
+                }
+            }
+            Some(Wake::Stop) => suspend(terminal, app)?,
+            // The next draw rewraps to a new size and keeps the reading
+            // position; remote requests are taken below, and library
+            // changes on the next tick
+            Some(Wake::Input(_) | Wake::Remote | Wake::Library) | None => {}
+        }
+        if let Some(remote) = remote {
+            while let Some(request) = remote.try_recv() {
+                let status = app.apply_remote_command(request.command.clone());
+                request.reply(status);
+            }
+        }
     }
 }
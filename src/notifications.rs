@@ -0,0 +1,16 @@
+use log::{debug, warn};
+use notify_rust::Notification;
+
+/// Shows a desktop notification, so it is seen even with the terminal in
+/// the background. Sent from a thread of its own because talking to the
+/// notification daemon can take a while, and failures are only logged.
+pub fn send(summary: &str, body: &str) {
+    let mut notification = Notification::new();
+    notification.appname("bookrat").summary(summary).body(body);
+    debug!("Notifying: {}", summary);
+    std::thread::spawn(move || {
+        if let Err(e) = notification.show() {
+            warn!("Failed to show a desktop notification: {}", e);
+        }
+    });
+}
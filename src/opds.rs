@@ -0,0 +1,200 @@
+use std::{net::SocketAddr, path::Path};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use epub::doc::EpubDoc;
+use log::{error, info, warn};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::catalog::Entry;
+use crate::library::{self, ScanOptions};
+use crate::web::escape;
+
+const FEED_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=acquisition";
+const EPUB_TYPE: &str = "application/epub+zip";
+
+/// Serves the library as an OPDS catalog, the feed format reading apps
+/// browse to download books. Books are listed with their metadata and
+/// covers and downloaded as the original files.
+pub struct OpdsServer {
+    server: Server,
+    roots: Vec<String>,
+    scan: ScanOptions,
+}
+
+/// What a request is answered with.
+struct Reply {
+    status: u16,
+    content_type: String,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn ok(content_type: &str, body: Vec<u8>) -> Self {
+        Self {
+            status: 200,
+            content_type: content_type.to_string(),
+            body,
+        }
+    }
+
+    fn text(status: u16, message: &str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8".to_string(),
+            body: message.as_bytes().to_vec(),
+        }
+    }
+}
+
+impl OpdsServer {
+    /// Listens on `addr` for the library in the `roots` directories, read
+    /// as `scan` says.
+    pub fn bind(addr: &str, roots: &[String], scan: ScanOptions) -> Result<Self> {
+        let server = Server::http(addr).map_err(|e| anyhow!("Failed to listen on {}: {}", addr, e))?;
+        Ok(Self {
+            server,
+            roots: roots.to_vec(),
+            scan,
+        })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Handles requests until the process is stopped.
+    pub fn run(&self) {
+        info!("OPDS catalog listening on {:?}", self.local_addr());
+        for request in self.server.incoming_requests() {
+            let reply = if *request.method() != Method::Get {
+                Reply::text(405, "Method not allowed")
+            } else {
+                match self.route(&request) {
+                    Ok(Some(reply)) => reply,
+                    Ok(None) => Reply::text(404, "Not found"),
+                    Err(e) => {
+                        error!("OPDS request {} failed: {:?}", request.url(), e);
+                        Reply::text(500, &e.to_string())
+                    }
+                }
+            };
+            let header = Header::from_bytes("Content-Type", reply.content_type.as_bytes()).expect("content types are valid headers");
+            let response = Response::from_data(reply.body).with_header(header).with_status_code(reply.status);
+            if let Err(e) = request.respond(response) {
+                warn!("Failed to send OPDS response: {}", e);
+            }
+        }
+    }
+
+    fn route(&self, request: &Request) -> Result<Option<Reply>> {
+        let path = request.url().split('?').next().unwrap_or_default();
+        if path == "/" || path == "/opds" {
+            // The client names the host; anything but a host and port is
+            // dropped rather than written into the links
+            let base = request
+                .headers()
+                .iter()
+                .find(|header| header.field.equiv("Host"))
+                .map(|header| header.value.as_str())
+                .filter(|host| host.chars().all(|c| c.is_ascii_alphanumeric() || "-.:[]".contains(c)))
+                .map(|host| format!("http://{}", host))
+                .unwrap_or_default();
+            return self.feed(&base).map(|feed| Some(Reply::ok(FEED_TYPE, feed.into_bytes())));
+        }
+        let books = library::scan_roots(&self.roots, &self.scan)?;
+        let book = |id: &str| books.iter().find(|book| self.id_of(book) == id);
+        if let Some(id) = path.strip_prefix("/book/").and_then(|rest| rest.strip_suffix(".epub")) {
+            let Some(book) = book(id) else {
+                return Ok(None);
+            };
+            return Ok(Some(Reply::ok(EPUB_TYPE, std::fs::read(book)?)));
+        }
+        if let Some(id) = path.strip_prefix("/cover/") {
+            let cover = book(id).and_then(|book| EpubDoc::new(book).ok()).and_then(|mut doc| doc.get_cover());
+            return Ok(cover.map(|(image, mime)| Reply::ok(&mime, image)));
+        }
+        Ok(None)
+    }
+
+    /// The acquisition feed listing every book. Links are absolute under
+    /// `base`, as some reading apps don't resolve relative ones.
+    fn feed(&self, base: &str) -> Result<String> {
+        let books = library::scan_roots(&self.roots, &self.scan)?;
+        let base = escape(base);
+        let mut entries = String::new();
+        for book in &books {
+            entries.push_str(&feed_entry(&base, &self.id_of(book), book));
+        }
+        Ok(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <feed xmlns=\"http://www.w3.org/2005/Atom\" xmlns:dc=\"http://purl.org/dc/terms/\" xmlns:opds=\"http://opds-spec.org/2010/catalog\">\
+             <id>urn:bookrat:library</id><title>BookRat library</title><updated>{}</updated>\
+             <link rel=\"self\" href=\"{base}/opds\" type=\"{FEED_TYPE}\"/>\
+             <link rel=\"start\" href=\"{base}/opds\" type=\"{FEED_TYPE}\"/>{entries}</feed>\n",
+            Utc::now().to_rfc3339(),
+        ))
+    }
+
+    /// The id `book` is linked by: that of its path in the library root
+    /// it is in.
+    fn id_of(&self, book: &str) -> String {
+        let path = Path::new(book);
+        let relative = self.roots.iter().find_map(|root| path.strip_prefix(root).ok()).unwrap_or(path);
+        book_id(relative)
+    }
+}
+
+/// An id for the book at `relative` path in the library, which stays the
+/// same as other books come and go: a hash of the path, FNV-1a since the
+/// standard hasher may change between builds.
+pub fn book_id(relative: &Path) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in relative.to_string_lossy().bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// The feed entry for `book`, linked under `base` (escaped already) by
+/// its `id`.
+fn feed_entry(base: &str, id: &str, book: &str) -> String {
+    let doc = EpubDoc::new(book).ok();
+    let entry = doc.as_ref().map_or_else(|| Entry::read(book), |doc| Entry::from_doc(doc, book));
+    let has_cover = doc.is_some_and(|doc| doc.get_cover_id().is_some());
+    let updated: DateTime<Utc> = std::fs::metadata(book)
+        .and_then(|metadata| metadata.modified())
+        .map(DateTime::from)
+        .unwrap_or_else(|_| Utc::now());
+    let file = Path::new(book).file_name().unwrap_or_default().to_string_lossy();
+
+    let mut xml = format!(
+        "<entry><id>urn:bookrat:book:{}</id><title>{}</title><updated>{}</updated>",
+        id,
+        escape(&entry.title),
+        updated.to_rfc3339()
+    );
+    for author in &entry.authors {
+        xml.push_str(&format!("<author><name>{}</name></author>", escape(author)));
+    }
+    if let Some(language) = &entry.language {
+        xml.push_str(&format!("<dc:language>{}</dc:language>", escape(language)));
+    }
+    for tag in &entry.tags {
+        xml.push_str(&format!("<category term=\"{0}\" label=\"{0}\"/>", escape(tag)));
+    }
+    if has_cover {
+        for rel in ["http://opds-spec.org/image", "http://opds-spec.org/image/thumbnail"] {
+            xml.push_str(&format!("<link rel=\"{}\" href=\"{}/cover/{}\"/>", rel, base, id));
+        }
+    }
+    xml.push_str(&format!(
+        "<link rel=\"http://opds-spec.org/acquisition\" href=\"{}/book/{}.epub\" type=\"{}\" title=\"{}\"/></entry>",
+        base,
+        id,
+        EPUB_TYPE,
+        escape(&file)
+    ));
+    xml
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+use std::path::{Component, Path, PathBuf};
+
+use epub::doc::EpubDoc;
+
+use crate::blocks::strip_tags;
+use crate::book::anchor_fraction;
+use crate::sanitize::decode_entities;
+
+/// A page of the printed edition, where the book marks its start.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrintPage {
+    /// As printed: `12`, `xiv`
+    pub label: String,
+    /// 0-based spine index of the part the page starts in
+    pub chapter: usize,
+    /// Share of the part's text before the start of the page
+    pub fraction: f64,
+}
+
+/// The print pages of a book in reading order, from the `page-list` of
+/// its EPUB 3 navigation document or else the `pageList` of its NCX.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PageList {
+    pages: Vec<PrintPage>,
+}
+
+impl PageList {
+    /// Reads the page list of `doc`, placing every page in its part. Parts
+    /// with page markers are each read once.
+    pub fn read<R: Read + Seek>(doc: &mut EpubDoc<R>) -> Self {
+        let mut parts: HashMap<usize, Option<String>> = HashMap::new();
+        let mut pages = Vec::new();
+        for (label, target) in targets(doc) {
+            let (file, fragment) = match target.split_once('#') {
+                Some((file, fragment)) => (file, Some(fragment)),
+                None => (target.as_str(), None),
+            };
+            let Some(chapter) = doc.resource_uri_to_chapter(&PathBuf::from(file)) else {
+                continue;
+            };
+            let html = parts.entry(chapter).or_insert_with(|| {
+                let id = doc.spine[chapter].idref.clone();
+                doc.get_resource_str(&id).map(|(content, _mime)| content)
+            });
+            let fraction = match (html.as_deref(), fragment) {
+                (Some(html), Some(fragment)) => anchor_fraction(html, fragment).unwrap_or(0.0),
+                _ => 0.0,
+            };
+            pages.push(PrintPage { label, chapter, fraction });
+        }
+        Self { pages }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    pub fn pages(&self) -> &[PrintPage] {
+        &self.pages
+    }
+
+    /// The page labelled `label`, in any case.
+    pub fn find(&self, label: &str) -> Option<&PrintPage> {
+        let label = label.trim();
+        self.pages.iter().find(|page| page.label.eq_ignore_ascii_case(label))
+    }
+
+    /// The page being read in part `chapter`: the last one to have
+    /// `started`, given where in the part it starts, or to start in an
+    /// earlier part.
+    pub fn current(&self, chapter: usize, started: impl Fn(f64) -> bool) -> Option<&PrintPage> {
+        self.pages
+            .iter()
+            .take_while(|page| page.chapter < chapter || (page.chapter == chapter && started(page.fraction)))
+            .last()
+    }
+}
+
+/// Labels and archive paths (with any `#fragment`) of the page list.
+fn targets<R: Read + Seek>(doc: &mut EpubDoc<R>) -> Vec<(String, String)> {
+    let nav = doc
+        .resources
+        .values()
+        .find(|resource| resource.properties.as_deref().is_some_and(|properties| properties.split_whitespace().any(|p| p == "nav")))
+        .map(|resource| resource.path.clone());
+    let ncx = doc
+        .resources
+        .values()
+        .find(|resource| resource.mime == "application/x-dtbncx+xml")
+        .map(|resource| resource.path.clone());
+    let lists = [(nav, nav_page_list as fn(&str) -> Vec<(String, String)>), (ncx, ncx_page_list)];
+    for (path, parse) in lists {
+        let Some(path) = path else {
+            continue;
+        };
+        let Some(content) = doc.get_resource_str_by_path(&path) else {
+            continue;
+        };
+        let targets = parse(&content);
+        if !targets.is_empty() {
+            return targets.into_iter().map(|(label, href)| (label, resolve(&path, &href))).collect();
+        }
+    }
+    Vec::new()
+}
+
+/// Labels and hrefs of the links in the `page-list` nav of an EPUB 3
+/// navigation document.
+fn nav_page_list(html: &str) -> Vec<(String, String)> {
+    let Some(list) = html.split("<nav").skip(1).find(|nav| {
+        let tag = nav.split('>').next().unwrap_or_default();
+        tag.contains("page-list") || tag.contains("doc-pagelist")
+    }) else {
+        return Vec::new();
+    };
+    let list = list.split("</nav>").next().unwrap_or_default();
+    list.split("<a ")
+        .skip(1)
+        .filter_map(|link| {
+            let (tag, rest) = link.split_once('>')?;
+            let label = rest.split("</a>").next().unwrap_or_default();
+            Some((clean_label(label), attribute(tag, "href")?))
+        })
+        .filter(|(label, _)| !label.is_empty())
+        .collect()
+}
+
+/// Labels and sources of the `pageTarget`s of an NCX.
+fn ncx_page_list(xml: &str) -> Vec<(String, String)> {
+    let Some((_, list)) = xml.split_once("<pageList") else {
+        return Vec::new();
+    };
+    let list = list.split("</pageList>").next().unwrap_or_default();
+    list.split("<pageTarget")
+        .skip(1)
+        .filter_map(|target| {
+            let label = target.split_once("<text>")?.1.split("</text>").next()?;
+            let content = target.split_once("<content")?.1.split('>').next()?;
+            Some((clean_label(label), attribute(content, "src")?))
+        })
+        .filter(|(label, _)| !label.is_empty())
+        .collect()
+}
+
+fn clean_label(label: &str) -> String {
+    decode_entities(&strip_tags(label)).split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Value of the attribute `name` in the inside of a tag.
+pub fn attribute(tag: &str, name: &str) -> Option<String> {
+    let at = tag.split_whitespace().find(|part| part.starts_with(&format!("{}=", name)))?;
+    let quoted = &at[name.len() + 1..];
+    let quote = quoted.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = quoted[1..].split(quote).next()?;
+    Some(decode_entities(value))
+}
+
+/// Archive path of `href`, relative to the document at `base`.
+pub fn resolve(base: &Path, href: &str) -> String {
+    let (file, fragment) = match href.split_once('#') {
+        Some((file, fragment)) => (file, Some(fragment)),
+        None => (href, None),
+    };
+    let mut path = Vec::new();
+    let joined = base.parent().unwrap_or(Path::new("")).join(percent_decode(file));
+    for component in joined.components() {
+        match component {
+            Component::ParentDir => {
+                path.pop();
+            }
+            Component::Normal(name) => path.push(name.to_string_lossy().to_string()),
+            _ => {}
+        }
+    }
+    let path = path.join("/");
+    match fragment {
+        Some(fragment) => format!("{}#{}", path, fragment),
+        None => path,
+    }
+}
+
+/// `%20` and the like in hrefs, as file names.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_lists_are_parsed() {
+        let nav = r#"<nav epub:type="toc"><ol><li><a href="ch1.xhtml">One</a></li></ol></nav>
+            <nav epub:type="page-list" hidden=""><ol>
+            <li><a href="text/ch1.xhtml#p1">1</a></li><li><a href='text/ch%201.xhtml#p2'><span>ii</span></a></li>
+            </ol></nav>"#;
+        assert_eq!(
+            nav_page_list(nav),
+            vec![("1".to_string(), "text/ch1.xhtml#p1".to_string()), ("ii".to_string(), "text/ch%201.xhtml#p2".to_string())]
+        );
+
+        let ncx = r#"<navMap/><pageList><navLabel><text>Pages</text></navLabel>
+            <pageTarget id="p7" type="normal" value="7"><navLabel><text>7</text></navLabel><content src="ch2.xhtml#page7"/></pageTarget>
+            </pageList>"#;
+        assert_eq!(ncx_page_list(ncx), vec![("7".to_string(), "ch2.xhtml#page7".to_string())]);
+        assert!(ncx_page_list("<navMap/>").is_empty());
+    }
+
+    #[test]
+    fn test_hrefs_resolve_against_the_document() {
+        assert_eq!(resolve(Path::new("OEBPS/nav/nav.xhtml"), "../text/ch%201.xhtml#p2"), "OEBPS/text/ch 1.xhtml#p2");
+        assert_eq!(resolve(Path::new("toc.ncx"), "ch1.xhtml"), "ch1.xhtml");
+    }
+
+    #[test]
+    fn test_current_page_is_the_last_started() {
+        let page = |label: &str, chapter: usize, fraction: f64| PrintPage {
+            label: label.to_string(),
+            chapter,
+            fraction,
+        };
+        let list = PageList {
+            pages: vec![page("1", 1, 0.0), page("2", 1, 0.5), page("iii", 2, 0.2)],
+        };
+        let at = |fraction: f64| move |start: f64| start <= fraction;
+        assert_eq!(list.current(0, at(0.9)), None);
+        assert_eq!(list.current(1, at(0.6)).map(|page| page.label.as_str()), Some("2"));
+        assert_eq!(list.current(2, at(0.1)).map(|page| page.label.as_str()), Some("2"));
+        assert_eq!(list.find("III").map(|page| page.chapter), Some(2));
+    }
+}
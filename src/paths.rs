@@ -0,0 +1,165 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+/// Where bookrat keeps its settings and reading state. With a profile,
+/// the bookmarks, history and reading time are the profile's own, and its
+/// settings are layered over the shared ones.
+#[derive(Debug, Clone)]
+pub struct Paths {
+    /// Directory of `config.json`, when the platform has one
+    config_dir: Option<PathBuf>,
+    /// Directory of `bookmarks.json` and the files kept beside it
+    state_dir: PathBuf,
+    /// The log file, when there is a place for it
+    log_file: Option<PathBuf>,
+    /// Directory of files that can be made again, such as unpacked books
+    cache_dir: PathBuf,
+    profile: Option<String>,
+}
+
+/// Directory beside the executable that portable mode keeps everything in.
+pub const PORTABLE_DIR: &str = "bookrat-data";
+
+impl Paths {
+    /// The usual places: the platform config directory for settings and
+    /// the current directory for reading state. A profile's state is kept
+    /// in `profiles/<name>`, created when first used.
+    pub fn new(profile: Option<&str>) -> Result<Self> {
+        let mut paths = Self::with_dirs(dirs::config_dir().map(|dir| dir.join("bookrat")), PathBuf::from("."), profile)?;
+        paths.log_file = dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .map(|dir| dir.join("bookrat").join("bookrat.log"));
+        Ok(paths)
+    }
+
+    /// Everything in `bookrat-data` beside the executable, for running from
+    /// a USB stick: the config, the reading state and the log.
+    pub fn portable(profile: Option<&str>) -> Result<Self> {
+        let exe = std::env::current_exe().context("Can't tell where the executable is")?;
+        let dir = exe.parent().unwrap_or(Path::new(".")).join(PORTABLE_DIR);
+        Self::in_dir(&dir, profile)
+    }
+
+    /// Whether a `bookrat-data` directory beside the executable asks for
+    /// portable mode without `--portable`.
+    pub fn portable_dir_exists() -> bool {
+        std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.join(PORTABLE_DIR).is_dir()))
+            .unwrap_or(false)
+    }
+
+    /// Everything in `dir`.
+    pub fn in_dir(dir: &Path, profile: Option<&str>) -> Result<Self> {
+        let mut paths = Self::with_dirs(Some(dir.to_path_buf()), dir.to_path_buf(), profile)?;
+        paths.log_file = Some(dir.join("bookrat.log"));
+        paths.cache_dir = dir.join("cache");
+        Ok(paths)
+    }
+
+    pub fn with_dirs(config_dir: Option<PathBuf>, state_dir: PathBuf, profile: Option<&str>) -> Result<Self> {
+        if let Some(name) = profile {
+            if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+                bail!("Profile names may only have letters, digits, '-' and '_', not {:?}", name);
+            }
+        }
+        let paths = Self {
+            config_dir,
+            state_dir,
+            log_file: None,
+            cache_dir: default_cache_dir(),
+            profile: profile.map(str::to_string),
+        };
+        if let Some(dir) = paths.bookmarks_file().parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+        }
+        Ok(paths)
+    }
+
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// Config files in the order they apply: the shared `config.json`,
+    /// then the profile's `profiles/<name>.json` beside it.
+    pub fn config_files(&self) -> Vec<PathBuf> {
+        let Some(dir) = &self.config_dir else {
+            return Vec::new();
+        };
+        let mut files = vec![dir.join("config.json")];
+        if let Some(profile) = &self.profile {
+            files.push(dir.join("profiles").join(format!("{}.json", profile)));
+        }
+        files
+    }
+
+    /// Where to log when the config doesn't say, if anywhere.
+    pub fn log_file(&self) -> Option<&Path> {
+        self.log_file.as_deref()
+    }
+
+    /// Directory of the user's Rhai scripts, beside the config.
+    pub fn plugins_dir(&self) -> Option<PathBuf> {
+        self.config_dir.as_ref().map(|dir| dir.join("plugins"))
+    }
+
+    pub fn bookmarks_file(&self) -> PathBuf {
+        self.profile_dir(&self.state_dir).join("bookmarks.json")
+    }
+
+    /// Where books taken out of archives are unpacked: outside the library,
+    /// so they aren't listed twice, at the same path every time so their
+    /// bookmarks hold.
+    pub fn archives_dir(&self) -> PathBuf {
+        self.profile_dir(&self.cache_dir).join("archives")
+    }
+
+    /// Where books streamed from calibre are kept, for the same reasons.
+    pub fn calibre_dir(&self) -> PathBuf {
+        self.profile_dir(&self.cache_dir).join("calibre")
+    }
+
+    fn profile_dir(&self, dir: &Path) -> PathBuf {
+        match &self.profile {
+            Some(profile) => dir.join("profiles").join(profile),
+            None => dir.to_path_buf(),
+        }
+    }
+}
+
+/// The platform's cache directory for bookrat.
+pub fn default_cache_dir() -> PathBuf {
+    dirs::cache_dir().unwrap_or_else(std::env::temp_dir).join("bookrat")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles_have_their_own_state() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = dir.path().join("config");
+        let shared = Paths::with_dirs(Some(config.clone()), dir.path().to_path_buf(), None).unwrap();
+        assert_eq!(shared.bookmarks_file(), dir.path().join("bookmarks.json"));
+        assert_eq!(shared.config_files(), vec![config.join("config.json")]);
+
+        let alice = Paths::with_dirs(Some(config.clone()), dir.path().to_path_buf(), Some("alice")).unwrap();
+        assert_eq!(alice.bookmarks_file(), dir.path().join("profiles/alice/bookmarks.json"));
+        assert!(dir.path().join("profiles/alice").is_dir());
+        assert_eq!(alice.config_files(), vec![config.join("config.json"), config.join("profiles/alice.json")]);
+
+        assert!(Paths::with_dirs(None, dir.path().to_path_buf(), Some("../bob")).is_err());
+
+        let stick = dir.path().join("stick");
+        let portable = Paths::in_dir(&stick, Some("alice")).unwrap();
+        assert_eq!(portable.config_files(), vec![stick.join("config.json"), stick.join("profiles/alice.json")]);
+        assert_eq!(portable.bookmarks_file(), stick.join("profiles/alice/bookmarks.json"));
+        assert_eq!(portable.log_file(), Some(stick.join("bookrat.log").as_path()));
+        assert_eq!(portable.archives_dir(), stick.join("cache/profiles/alice/archives"));
+        assert_eq!(portable.calibre_dir(), stick.join("cache/profiles/alice/calibre"));
+        assert_eq!(portable.plugins_dir(), Some(stick.join("plugins")));
+    }
+}
@@ -0,0 +1,91 @@
+use std::time::{Duration, Instant};
+
+use ratatui::{
+    layout::Rect,
+    style::{Color, Style},
+    text::Line,
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+
+/// Timings shown by the debug overlay. Each value is the most recent
+/// measurement, not an average.
+#[derive(Debug, Default, Clone)]
+pub struct PerfStats {
+    pub frame: Duration,
+    pub processing: Duration,
+    pub wrap: Duration,
+}
+
+/// Starts a timer; the returned closure yields the elapsed time.
+pub fn start_timer() -> impl Fn() -> Duration {
+    let start = Instant::now();
+    move || start.elapsed()
+}
+
+/// Resident set size of this process in kilobytes, where the platform
+/// exposes it cheaply.
+pub fn resident_memory_kb() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        parse_vm_rss(&status)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_vm_rss(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|rest| rest.trim().trim_end_matches("kB").trim().parse().ok())
+}
+
+fn format_duration(d: Duration) -> String {
+    format!("{:.2} ms", d.as_secs_f64() * 1000.0)
+}
+
+/// Draws the overlay in the top-right corner of `area`.
+pub fn render_overlay(f: &mut Frame, area: Rect, stats: &PerfStats) {
+    let memory = resident_memory_kb()
+        .map(|kb| format!("{:.1} MB", kb as f64 / 1024.0))
+        .unwrap_or_else(|| "n/a".to_string());
+    let lines = vec![
+        Line::from(format!("Frame:   {}", format_duration(stats.frame))),
+        Line::from(format!("Process: {}", format_duration(stats.processing))),
+        Line::from(format!("Wrap:    {}", format_duration(stats.wrap))),
+        Line::from(format!("Memory:  {}", memory)),
+    ];
+
+    let width = 24.min(area.width);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let overlay = Rect {
+        x: area.x + area.width.saturating_sub(width + 1),
+        y: area.y + 1.min(area.height),
+        width,
+        height,
+    };
+    f.render_widget(Clear, overlay);
+    f.render_widget(
+        Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title("Perf"))
+            .style(Style::default().fg(Color::Yellow)),
+        overlay,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_vm_rss() {
+        let status = "Name:\tbookrat\nVmPeak:\t  9000 kB\nVmRSS:\t  4321 kB\nThreads:\t1\n";
+        assert_eq!(parse_vm_rss(status), Some(4321));
+        assert_eq!(parse_vm_rss("Name:\tbookrat\n"), None);
+    }
+}
@@ -0,0 +1,254 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use log::{error, info, warn};
+use rhai::{CallFnOptions, Dynamic, Engine, FuncArgs, Scope, AST};
+
+/// Something a script asked the reader to do. Scripts never touch `App`
+/// directly; their requests are queued and applied after the call returns.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PluginAction {
+    NextChapter,
+    PrevChapter,
+    GoToChapter(usize),
+    ScrollDown(usize),
+    ScrollUp(usize),
+}
+
+/// Events scripts can react to by defining a function with the same name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hook {
+    /// `fn on_book_opened(path)`
+    BookOpened,
+    /// `fn on_chapter_changed(chapter, total)`, chapter is 1-based
+    ChapterChanged,
+    /// `fn on_selection_changed(path)`, fired when the file list cursor moves
+    SelectionChanged,
+}
+
+impl Hook {
+    fn fn_name(self) -> &'static str {
+        match self {
+            Hook::BookOpened => "on_book_opened",
+            Hook::ChapterChanged => "on_chapter_changed",
+            Hook::SelectionChanged => "on_selection_changed",
+        }
+    }
+}
+
+struct Script {
+    path: PathBuf,
+    ast: AST,
+    scope: Scope<'static>,
+}
+
+pub struct Plugins {
+    engine: Engine,
+    scripts: Vec<Script>,
+    bindings: HashMap<char, (usize, String)>,
+    pending_bindings: Rc<RefCell<Vec<(char, String)>>>,
+    actions: Rc<RefCell<Vec<PluginAction>>>,
+}
+
+impl Default for Plugins {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Plugins {
+    pub fn new() -> Self {
+        let actions: Rc<RefCell<Vec<PluginAction>>> = Rc::default();
+        let pending_bindings: Rc<RefCell<Vec<(char, String)>>> = Rc::default();
+        let mut engine = Engine::new();
+
+        // stdout belongs to the TUI, so route script output to the log
+        engine.on_print(|s| info!("[plugin] {}", s));
+        engine.on_debug(|s, _, pos| info!("[plugin] {:?} {}", pos, s));
+
+        let queue = actions.clone();
+        engine.register_fn("next_chapter", move || queue.borrow_mut().push(PluginAction::NextChapter));
+        let queue = actions.clone();
+        engine.register_fn("prev_chapter", move || queue.borrow_mut().push(PluginAction::PrevChapter));
+        let queue = actions.clone();
+        engine.register_fn("go_to_chapter", move |n: i64| {
+            // Scripts count chapters from 1 like the title bar does
+            let index = usize::try_from(n.max(1) - 1).unwrap_or(0);
+            queue.borrow_mut().push(PluginAction::GoToChapter(index));
+        });
+        let queue = actions.clone();
+        engine.register_fn("scroll_down", move |n: i64| {
+            queue.borrow_mut().push(PluginAction::ScrollDown(n.max(0) as usize));
+        });
+        let queue = actions.clone();
+        engine.register_fn("scroll_up", move |n: i64| {
+            queue.borrow_mut().push(PluginAction::ScrollUp(n.max(0) as usize));
+        });
+        let pending = pending_bindings.clone();
+        engine.register_fn("bind_key", move |key: &str, fn_name: &str| {
+            let mut chars = key.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => pending.borrow_mut().push((c, fn_name.to_string())),
+                _ => warn!("[plugin] bind_key expects a single character, got {:?}", key),
+            }
+        });
+
+        Self {
+            engine,
+            scripts: Vec::new(),
+            bindings: HashMap::new(),
+            pending_bindings,
+            actions,
+        }
+    }
+
+    /// Loads every `*.rhai` file in `dir`, in file name order. Scripts that
+    /// fail to compile or run are logged and skipped.
+    pub fn load_dir(dir: &Path) -> Self {
+        let mut plugins = Self::new();
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return plugins,
+        };
+
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "rhai"))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            if let Err(e) = plugins.load_script(&path) {
+                error!("Failed to load plugin {:?}: {}", path, e);
+            }
+        }
+        plugins
+    }
+
+    pub fn load_script(&mut self, path: &Path) -> anyhow::Result<()> {
+        let ast = self
+            .engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let mut scope = Scope::new();
+        let result = self.engine.run_ast_with_scope(&mut scope, &ast);
+
+        // Top-level code may only register bindings; discard anything else
+        // it queued so loading a script never moves the reader.
+        self.actions.borrow_mut().clear();
+        let pending: Vec<_> = self.pending_bindings.borrow_mut().drain(..).collect();
+        result.map_err(|e| anyhow::anyhow!("{}", e))?;
+
+        let index = self.scripts.len();
+        for (key, fn_name) in pending {
+            info!("Plugin {:?} bound '{}' to {}", path, key, fn_name);
+            self.bindings.insert(key, (index, fn_name));
+        }
+        info!("Loaded plugin {:?}", path);
+        self.scripts.push(Script {
+            path: path.to_path_buf(),
+            ast,
+            scope,
+        });
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scripts.is_empty()
+    }
+
+    pub fn is_bound(&self, key: char) -> bool {
+        self.bindings.contains_key(&key)
+    }
+
+    /// Calls `hook` in every script that defines it and returns the
+    /// actions they queued.
+    pub fn fire(&mut self, hook: Hook, args: impl FuncArgs + Clone) -> Vec<PluginAction> {
+        let name = hook.fn_name();
+        for index in 0..self.scripts.len() {
+            let defined = self.scripts[index].ast.iter_functions().any(|f| f.name == name);
+            if defined {
+                self.call(index, name, args.clone());
+            }
+        }
+        self.take_actions()
+    }
+
+    /// Runs the script function bound to `key`, if any.
+    pub fn run_binding(&mut self, key: char) -> Option<Vec<PluginAction>> {
+        let (index, fn_name) = self.bindings.get(&key).cloned()?;
+        self.call(index, &fn_name, ());
+        Some(self.take_actions())
+    }
+
+    fn call(&mut self, index: usize, name: &str, args: impl FuncArgs) {
+        let script = &mut self.scripts[index];
+        let options = CallFnOptions::new().eval_ast(false).rewind_scope(false);
+        let result =
+            self.engine
+                .call_fn_with_options::<Dynamic>(options, &mut script.scope, &script.ast, name, args);
+        if let Err(e) = result {
+            error!("Plugin {:?} failed in {}: {}", script.path, name, e);
+        }
+    }
+
+    fn take_actions(&mut self) -> Vec<PluginAction> {
+        self.actions.borrow_mut().drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plugins_with(source: &str) -> (tempfile::TempDir, Plugins) {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join("test.rhai"), source).unwrap();
+        let plugins = Plugins::load_dir(dir.path());
+        (dir, plugins)
+    }
+
+    #[test]
+    fn test_hooks_queue_actions() {
+        let (_dir, mut plugins) = plugins_with(
+            r#"
+            fn on_chapter_changed(chapter, total) {
+                if chapter == 1 { next_chapter(); }
+            }
+            "#,
+        );
+        assert!(!plugins.is_empty());
+        assert_eq!(plugins.fire(Hook::ChapterChanged, (1_i64, 5_i64)), vec![PluginAction::NextChapter]);
+        assert!(plugins.fire(Hook::ChapterChanged, (2_i64, 5_i64)).is_empty());
+        assert!(plugins.fire(Hook::BookOpened, ("book.epub".to_string(),)).is_empty());
+    }
+
+    #[test]
+    fn test_key_bindings() {
+        let (_dir, mut plugins) = plugins_with(
+            r#"
+            bind_key("g", "jump_start");
+            bind_key("too long", "ignored");
+            fn jump_start() { go_to_chapter(1); scroll_down(3); }
+            "#,
+        );
+        assert!(plugins.is_bound('g'));
+        assert_eq!(
+            plugins.run_binding('g'),
+            Some(vec![PluginAction::GoToChapter(0), PluginAction::ScrollDown(3)])
+        );
+        assert_eq!(plugins.run_binding('x'), None);
+    }
+
+    #[test]
+    fn test_broken_script_is_skipped() {
+        let (_dir, plugins) = plugins_with("fn oops( {");
+        assert!(plugins.is_empty());
+    }
+}
@@ -0,0 +1,86 @@
+use ratatui::{
+    layout::Rect,
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+    Frame,
+};
+
+/// An error shown to the user until they dismiss it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorPopup {
+    pub title: String,
+    pub message: String,
+    pub details: Vec<String>,
+    pub hint: String,
+}
+
+impl ErrorPopup {
+    pub fn new(title: impl Into<String>, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            message: message.into(),
+            details: Vec::new(),
+            hint: hint.into(),
+        }
+    }
+
+    /// Adds a bulleted list shown between the message and the hint.
+    pub fn with_details(mut self, details: Vec<String>) -> Self {
+        self.details = details;
+        self
+    }
+
+    pub fn render(&self, f: &mut Frame, area: Rect) {
+        let width = centered_rect(60, 0, area).width;
+        let inner_width = width.saturating_sub(2).max(1) as usize;
+        let wrapped = |text: &str| textwrap::wrap(text, inner_width).len() as u16;
+        let details: Vec<String> = self.details.iter().map(|d| format!("• {}", d)).collect();
+        let details_height: u16 = details.iter().map(|d| wrapped(d)).sum();
+        let height = wrapped(&self.message) + details_height + wrapped(&self.hint) + 3;
+
+        let mut lines = vec![Line::from(self.message.clone())];
+        lines.extend(details.into_iter().map(Line::from));
+        lines.push(Line::from(""));
+        lines.push(Line::from(self.hint.clone()).fg(Color::Gray));
+        let popup = centered_rect(60, height, area);
+        f.render_widget(Clear, popup);
+        f.render_widget(
+            Paragraph::new(lines)
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(self.title.clone())
+                        .title_bottom(Line::from(" Press any key to dismiss ").fg(Color::DarkGray))
+                        .border_style(Style::default().fg(Color::Red)),
+                )
+                .wrap(Wrap { trim: true }),
+            popup,
+        );
+    }
+}
+
+/// A rectangle `percent_x` percent as wide as `area` and `height` rows
+/// tall, centered in it.
+pub fn centered_rect(percent_x: u16, height: u16, area: Rect) -> Rect {
+    let width = (area.width as u32 * percent_x.min(100) as u32 / 100) as u16;
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_centered_rect() {
+        let area = Rect::new(0, 0, 100, 40);
+        assert_eq!(centered_rect(60, 10, area), Rect::new(20, 15, 60, 10));
+        assert_eq!(centered_rect(50, 80, Rect::new(10, 5, 20, 10)), Rect::new(15, 5, 10, 10));
+    }
+}
@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Entries kept for each prompt; older ones are forgotten.
+const MAX_ENTRIES: usize = 100;
+
+/// A prompt whose entries are remembered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prompt {
+    /// `/`, filtering the library
+    Filter,
+    /// `o`, downloading a book
+    Url,
+}
+
+/// What was entered in the prompts, oldest first, stored as
+/// `prompts.json` next to the bookmarks file. Written on every entry.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PromptHistory {
+    filters: Vec<String>,
+    urls: Vec<String>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl PromptHistory {
+    pub fn with_path<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            ..Self::default()
+        }
+    }
+
+    /// Loads the prompt history kept beside `bookmarks_path`.
+    pub fn beside(bookmarks_path: &Path) -> anyhow::Result<Self> {
+        let dir = bookmarks_path.parent().unwrap_or(Path::new(""));
+        Self::load_from(dir.join("prompts.json"))
+    }
+
+    pub fn load_from<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if path.exists() {
+            let content = fs::read_to_string(path)?;
+            let mut history: Self = serde_json::from_str(&content)?;
+            history.path = path.to_path_buf();
+            Ok(history)
+        } else {
+            Ok(Self::with_path(path))
+        }
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(&self.path, content)?;
+        Ok(())
+    }
+
+    pub fn entries(&self, prompt: Prompt) -> &[String] {
+        match prompt {
+            Prompt::Filter => &self.filters,
+            Prompt::Url => &self.urls,
+        }
+    }
+
+    /// Remembers `entry` as the newest of `prompt`, moving it up when it
+    /// was entered before. Blank entries aren't kept.
+    pub fn record(&mut self, prompt: Prompt, entry: &str) {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            return;
+        }
+        let entries = match prompt {
+            Prompt::Filter => &mut self.filters,
+            Prompt::Url => &mut self.urls,
+        };
+        entries.retain(|earlier| earlier != entry);
+        entries.push(entry.to_string());
+        let excess = entries.len().saturating_sub(MAX_ENTRIES);
+        entries.drain(..excess);
+        if let Err(e) = self.save() {
+            log::error!("Failed to save the prompt history: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_are_kept_newest_last_without_repeats() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("prompts.json");
+        let mut history = PromptHistory::load_from(&path).unwrap();
+        history.record(Prompt::Filter, "tag:scifi");
+        history.record(Prompt::Filter, "  ");
+        history.record(Prompt::Filter, "unread");
+        history.record(Prompt::Filter, "tag:scifi ");
+        history.record(Prompt::Url, "https://example.com/a.epub");
+
+        let loaded = PromptHistory::load_from(&path).unwrap();
+        assert_eq!(loaded.entries(Prompt::Filter), ["unread", "tag:scifi"]);
+        assert_eq!(loaded.entries(Prompt::Url), ["https://example.com/a.epub"]);
+
+        for i in 0..MAX_ENTRIES + 5 {
+            history.record(Prompt::Url, &i.to_string());
+        }
+        assert_eq!(history.entries(Prompt::Url).len(), MAX_ENTRIES);
+        assert_eq!(history.entries(Prompt::Url)[0], "5");
+    }
+}
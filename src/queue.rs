@@ -0,0 +1,202 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::state_file;
+
+/// Books to read next, in order, stored as `queue.json` next to the
+/// bookmarks file. Written on every change.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ReadingQueue {
+    books: Vec<String>,
+    /// When each book was last queued
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    queued: BTreeMap<String, DateTime<Utc>>,
+    /// When each book was last taken out, so that merging in a copy
+    /// that still has it doesn't queue it again
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    removed: BTreeMap<String, DateTime<Utc>>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl ReadingQueue {
+    pub fn with_path<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            ..Self::default()
+        }
+    }
+
+    /// Loads the queue kept beside `bookmarks_path`.
+    pub fn beside(bookmarks_path: &Path) -> anyhow::Result<Self> {
+        Self::load_from(state_file::beside(bookmarks_path, "queue.json"))
+    }
+
+    pub fn load_from<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let mut queue: Self = state_file::load(path)?.unwrap_or_default();
+        queue.path = path.to_path_buf();
+        Ok(queue)
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        state_file::save(self, &self.path)
+    }
+
+    fn changed(&self) {
+        state_file::save_changed(self, &self.path, "the reading queue");
+    }
+
+    pub fn books(&self) -> &[String] {
+        &self.books
+    }
+
+    pub fn len(&self) -> usize {
+        self.books.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.books.is_empty()
+    }
+
+    pub fn contains(&self, book: &str) -> bool {
+        self.books.iter().any(|queued| queued == book)
+    }
+
+    /// Puts `book` at the end of the queue, unless it is queued already.
+    pub fn push(&mut self, book: &str) {
+        if !self.contains(book) {
+            self.books.push(book.to_string());
+            self.queued.insert(book.to_string(), Utc::now());
+            self.changed();
+        }
+    }
+
+    /// Takes `book` out of the queue; whether it was in it.
+    pub fn remove(&mut self, book: &str) -> bool {
+        let before = self.books.len();
+        self.books.retain(|queued| queued != book);
+        let removed = self.books.len() != before;
+        if removed {
+            self.removed.insert(book.to_string(), Utc::now());
+            self.changed();
+        }
+        removed
+    }
+
+    /// Keeps `from` queued where it was as `to`, for a book renamed or
+    /// moved.
+    pub fn rename(&mut self, from: &str, to: &str) {
+        if let Some(queued) = self.books.iter_mut().find(|queued| *queued == from) {
+            *queued = to.to_string();
+            let now = Utc::now();
+            self.queued.insert(to.to_string(), now);
+            self.removed.insert(from.to_string(), now);
+            self.changed();
+        }
+    }
+
+    /// Swaps the book at `index` with its neighbour `up` or down; the
+    /// index it ends up at.
+    pub fn shift(&mut self, index: usize, up: bool) -> usize {
+        let target = if up { index.checked_sub(1) } else { Some(index + 1) };
+        match target.filter(|target| *target < self.books.len() && index < self.books.len()) {
+            Some(target) => {
+                self.books.swap(index, target);
+                self.changed();
+                target
+            }
+            None => index,
+        }
+    }
+
+    /// First book of the queue other than `current`.
+    pub fn next(&self, current: Option<&str>) -> Option<&String> {
+        self.books.iter().find(|book| Some(book.as_str()) != current)
+    }
+
+    /// Takes in another copy of the queue: our order, followed by the
+    /// books only queued there. A book either copy took out since it was
+    /// last queued stays out.
+    pub fn merge(&mut self, other: ReadingQueue) {
+        state_file::merge_largest(&mut self.queued, other.queued);
+        state_file::merge_largest(&mut self.removed, other.removed);
+        for book in other.books {
+            if !self.contains(&book) {
+                self.books.push(book);
+            }
+        }
+        let (queued, removed) = (&self.queued, &self.removed);
+        self.books.retain(|book| {
+            removed.get(book).is_none_or(|removed| queued.get(book).is_some_and(|queued| queued > removed))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue_keeps_its_order() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("queue.json");
+        let mut queue = ReadingQueue::with_path(&path);
+        queue.push("a.epub");
+        queue.push("b.epub");
+        queue.push("c.epub");
+        queue.push("a.epub");
+        assert_eq!(queue.books(), ["a.epub", "b.epub", "c.epub"]);
+
+        assert_eq!(queue.shift(2, true), 1);
+        assert_eq!(queue.shift(0, true), 0);
+        assert_eq!(queue.shift(2, false), 2);
+        assert_eq!(queue.books(), ["a.epub", "c.epub", "b.epub"]);
+        assert_eq!(queue.next(Some("a.epub")).map(String::as_str), Some("c.epub"));
+
+        assert!(queue.remove("c.epub"));
+        assert!(!queue.remove("c.epub"));
+        assert_eq!(ReadingQueue::load_from(&path).unwrap().books(), ["a.epub", "b.epub"]);
+        queue.rename("a.epub", "moved/a.epub");
+        queue.rename("gone.epub", "c.epub");
+        assert_eq!(ReadingQueue::load_from(&path).unwrap().books(), ["moved/a.epub", "b.epub"]);
+        queue.rename("moved/a.epub", "a.epub");
+
+        let other = ReadingQueue {
+            books: vec!["d.epub".to_string(), "b.epub".to_string()],
+            ..Default::default()
+        };
+        queue.merge(other);
+        assert_eq!(queue.books(), ["a.epub", "b.epub", "d.epub"]);
+    }
+
+    #[test]
+    fn test_books_taken_out_elsewhere_stay_out() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut queue = ReadingQueue::with_path(dir.path().join("queue.json"));
+        let mut other = ReadingQueue::with_path(dir.path().join("other.json"));
+        for book in ["a.epub", "b.epub", "c.epub"] {
+            queue.push(book);
+            other.push(book);
+        }
+        // Finished here, and taken out and queued again over there
+        queue.remove("a.epub");
+        other.remove("b.epub");
+        queue.remove("c.epub");
+        other.remove("c.epub");
+        other.push("c.epub");
+
+        let copy = ReadingQueue::load_from(dir.path().join("other.json")).unwrap();
+        queue.merge(copy);
+        assert_eq!(queue.books(), ["c.epub"]);
+        other.merge(ReadingQueue::load_from(dir.path().join("queue.json")).unwrap());
+        assert_eq!(other.books(), ["c.epub"]);
+
+        // Files from before books were timed merge as they used to
+        let mut old = ReadingQueue { books: vec!["d.epub".to_string()], ..Default::default() };
+        old.merge(ReadingQueue { books: vec!["e.epub".to_string()], ..Default::default() });
+        assert_eq!(old.books(), ["d.epub", "e.epub"]);
+    }
+}
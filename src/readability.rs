@@ -0,0 +1,150 @@
+use std::collections::HashSet;
+
+use ratatui::{
+    style::{Color, Style, Stylize},
+    text::Line,
+};
+
+/// What makes a text hard or easy to read: how long its sentences and
+/// words are, and how varied its vocabulary.
+#[derive(Debug, Default, PartialEq)]
+pub struct Readability {
+    pub words: usize,
+    pub sentences: usize,
+    pub syllables: usize,
+    /// Different words, case ignored
+    pub unique_words: usize,
+}
+
+impl Readability {
+    /// Measures plain `text`.
+    pub fn measure(text: &str) -> Self {
+        let words: Vec<String> = text
+            .split(|c: char| !c.is_alphanumeric() && c != '\'' && c != '\u{2019}')
+            .map(|word| word.trim_matches(['\'', '\u{2019}']))
+            .filter(|word| word.chars().any(char::is_alphabetic))
+            .map(str::to_lowercase)
+            .collect();
+        // A run of `.`, `!` or `?` ends a sentence, and so does the end of
+        // the text
+        let mut sentences = 0;
+        let mut in_sentence = false;
+        for c in text.chars() {
+            if matches!(c, '.' | '!' | '?' | '\u{2026}') {
+                sentences += usize::from(in_sentence);
+                in_sentence = false;
+            } else if c.is_alphanumeric() {
+                in_sentence = true;
+            }
+        }
+        sentences += usize::from(in_sentence);
+        Self {
+            words: words.len(),
+            sentences,
+            syllables: words.iter().map(|word| syllables(word)).sum(),
+            unique_words: words.iter().collect::<HashSet<_>>().len(),
+        }
+    }
+
+    pub fn words_per_sentence(&self) -> f64 {
+        self.words as f64 / self.sentences.max(1) as f64
+    }
+
+    pub fn syllables_per_word(&self) -> f64 {
+        self.syllables as f64 / self.words.max(1) as f64
+    }
+
+    /// Flesch reading ease: about 100 for the easiest text, 0 and below
+    /// for the hardest.
+    pub fn flesch_reading_ease(&self) -> f64 {
+        206.835 - 1.015 * self.words_per_sentence() - 84.6 * self.syllables_per_word()
+    }
+
+    /// Flesch-Kincaid grade level: the US school grade the text suits.
+    pub fn flesch_kincaid_grade(&self) -> f64 {
+        0.39 * self.words_per_sentence() + 11.8 * self.syllables_per_word() - 15.59
+    }
+
+    /// Share of the words that are different, in percent.
+    pub fn unique_word_ratio(&self) -> f64 {
+        self.unique_words as f64 * 100.0 / self.words.max(1) as f64
+    }
+
+    /// The statistics as lines for the statistics view.
+    pub fn describe(&self) -> Vec<Line<'static>> {
+        let heading = |text: &str| Line::from(text.to_string()).style(Style::default().fg(Color::Yellow).bold());
+        let item = |text: String| Line::from(format!("  {}", text));
+        let ease = self.flesch_reading_ease();
+        vec![
+            heading("Text"),
+            item(format!("{} words, {} sentences", self.words, self.sentences)),
+            item(format!("Average sentence: {:.1} words", self.words_per_sentence())),
+            item(format!("Unique words: {} ({:.0}%)", self.unique_words, self.unique_word_ratio())),
+            Line::from(""),
+            heading("Readability"),
+            item(format!("Flesch reading ease: {:.0} ({})", ease, ease_band(ease))),
+            item(format!("Flesch-Kincaid grade: {:.1}", self.flesch_kincaid_grade())),
+            Line::from("  The Flesch scores are made for English text").style(Style::default().fg(Color::DarkGray)),
+        ]
+    }
+}
+
+/// How a Flesch reading ease score is usually read.
+fn ease_band(ease: f64) -> &'static str {
+    match ease {
+        e if e >= 90.0 => "very easy",
+        e if e >= 80.0 => "easy",
+        e if e >= 70.0 => "fairly easy",
+        e if e >= 60.0 => "plain English",
+        e if e >= 50.0 => "fairly difficult",
+        e if e >= 30.0 => "difficult",
+        _ => "very difficult",
+    }
+}
+
+/// Syllables of lowercase `word`, estimated from its groups of vowels,
+/// with a silent final `e` left out.
+fn syllables(word: &str) -> usize {
+    let letters: Vec<char> = word.chars().filter(|c| c.is_alphabetic()).collect();
+    let is_vowel = |c: &char| "aeiouyàáâäèéêëìíîïòóôöùúûü".contains(*c);
+    let mut groups = 0;
+    let mut previous_vowel = false;
+    for c in &letters {
+        let vowel = is_vowel(c);
+        groups += usize::from(vowel && !previous_vowel);
+        previous_vowel = vowel;
+    }
+    // "make", but not "the" or "table"
+    if let [.., before, 'e'] = letters.as_slice() {
+        if groups > 1 && !is_vowel(before) && *before != 'l' {
+            groups -= 1;
+        }
+    }
+    groups.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readability_of_simple_and_long_winded_text() {
+        let simple = Readability::measure("The cat sat on the mat. It was glad! Was it?");
+        assert_eq!((simple.words, simple.sentences, simple.syllables, simple.unique_words), (11, 3, 11, 8));
+        assert!(simple.flesch_reading_ease() > 90.0);
+        assert_eq!(simple.describe()[6].to_string(), "  Flesch reading ease: 119 (very easy)");
+
+        let hard = Readability::measure(
+            "Notwithstanding considerable institutional opposition, the administration implemented comprehensive regulatory modifications",
+        );
+        assert_eq!(hard.sentences, 1);
+        assert!(hard.flesch_reading_ease() < 0.0 && hard.flesch_kincaid_grade() > 20.0);
+
+        assert_eq!(syllables("make"), 1);
+        assert_eq!(syllables("table"), 2);
+        assert_eq!(syllables("the"), 1);
+        assert_eq!(syllables("reading"), 2);
+        assert_eq!(Readability::measure(""), Readability::default());
+        assert_eq!(Readability::measure("Don't stop. Don't."), Readability { words: 3, sentences: 2, syllables: 3, unique_words: 2 });
+    }
+}
@@ -0,0 +1,251 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::state_file;
+
+/// Time spent reading today, and in total in each book, stored as
+/// `reading_time.json` next to the bookmarks file so it adds up across
+/// sessions.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ReadingTime {
+    date: Option<NaiveDate>,
+    seconds: f64,
+    /// Seconds spent in each book, by path
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    books: BTreeMap<String, f64>,
+    #[serde(skip)]
+    path: PathBuf,
+    /// Time added since the last save
+    #[serde(skip)]
+    unsaved: bool,
+}
+
+impl ReadingTime {
+    pub fn with_path<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            ..Default::default()
+        }
+    }
+
+    /// Loads the reading time kept beside `bookmarks_path`.
+    pub fn beside(bookmarks_path: &Path) -> anyhow::Result<Self> {
+        Self::load_from(state_file::beside(bookmarks_path, "reading_time.json"))
+    }
+
+    pub fn load_from<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let mut time: Self = state_file::load(path)?.unwrap_or_default();
+        time.path = path.to_path_buf();
+        Ok(time)
+    }
+
+    pub fn save(&mut self) -> anyhow::Result<()> {
+        state_file::save(self, &self.path)?;
+        self.unsaved = false;
+        Ok(())
+    }
+
+    /// Saves the time added since the last save, if any.
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        if self.unsaved {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Adds `elapsed` of reading `book` on `today`, starting from zero on
+    /// a new day. Saved whenever another whole minute is reached, and
+    /// otherwise on `flush`.
+    pub fn add(&mut self, today: NaiveDate, book: Option<&str>, elapsed: Duration) {
+        if self.date != Some(today) {
+            self.date = Some(today);
+            self.seconds = 0.0;
+        }
+        let before = self.minutes(today);
+        self.seconds += elapsed.as_secs_f64();
+        if let Some(book) = book {
+            *self.books.entry(book.to_string()).or_default() += elapsed.as_secs_f64();
+        }
+        self.unsaved = true;
+        if self.minutes(today) != before {
+            if let Err(e) = self.save() {
+                log::error!("Failed to save reading time: {}", e);
+            }
+        }
+    }
+
+    /// Takes in another copy of the file: the later day, or for the same
+    /// day the longer time, since each copy only ever adds to it. The same
+    /// goes for the time in each book.
+    pub fn merge(&mut self, other: ReadingTime) {
+        if other.date > self.date || (other.date == self.date && other.seconds > self.seconds) {
+            self.date = other.date;
+            self.seconds = other.seconds;
+        }
+        state_file::merge_largest(&mut self.books, other.books);
+    }
+
+    /// Whole minutes spent reading `book`, over all days.
+    pub fn book_minutes(&self, book: &str) -> u64 {
+        self.books.get(book).map_or(0, |seconds| (*seconds / 60.0) as u64)
+    }
+
+    /// Whole minutes read on `today`.
+    pub fn minutes(&self, today: NaiveDate) -> u64 {
+        if self.date == Some(today) {
+            (self.seconds / 60.0) as u64
+        } else {
+            0
+        }
+    }
+}
+
+/// Something worth telling the reader about their reading time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reminder {
+    /// Today's reading reached the goal of this many minutes
+    GoalReached(u64),
+    /// A pomodoro of reading is over; a break of this many minutes begins
+    BreakStarts(u64),
+    BreakOver,
+}
+
+impl Reminder {
+    pub fn message(&self) -> String {
+        match self {
+            Reminder::GoalReached(goal) => format!("Daily goal of {} min reached", goal),
+            Reminder::BreakStarts(minutes) => format!("Time for a {} min break", minutes),
+            Reminder::BreakOver => "Break over".to_string(),
+        }
+    }
+}
+
+/// Keeps track of the daily reading goal and of pomodoros: after
+/// `pomodoro` of reading, a `pause` long break.
+#[derive(Debug, Default)]
+pub struct Reminders {
+    /// Minutes to read every day
+    pub goal: Option<u64>,
+    pomodoro: Option<Duration>,
+    pause: Duration,
+    /// Reading since the last break
+    worked: Duration,
+    break_until: Option<Instant>,
+    /// Tell the desktop too, not only the help bar
+    pub notify: bool,
+}
+
+impl Reminders {
+    pub fn new(goal: Option<u64>, pomodoro: Option<Duration>, pause: Duration, notify: bool) -> Self {
+        Self {
+            goal,
+            pomodoro,
+            pause,
+            notify,
+            ..Default::default()
+        }
+    }
+
+    pub fn on_break(&self, now: Instant) -> bool {
+        self.break_until.is_some_and(|until| now < until)
+    }
+
+    /// How long from `now` until a break starts or ends, if one is due:
+    /// a break starts only while `reading`.
+    pub fn next_due(&self, now: Instant, reading: bool) -> Option<Duration> {
+        match (self.break_until, self.pomodoro.filter(|pomodoro| !pomodoro.is_zero())) {
+            (Some(until), _) => Some(until.saturating_duration_since(now)),
+            (None, Some(pomodoro)) if reading => Some(pomodoro.saturating_sub(self.worked)),
+            (None, _) => None,
+        }
+    }
+
+    /// Takes in `reading`, time spent reading since the last call, which
+    /// took today's total from `before` to `after` whole minutes.
+    pub fn update(&mut self, now: Instant, reading: Duration, before: u64, after: u64) -> Vec<Reminder> {
+        let mut reminders = Vec::new();
+        if let Some(goal) = self.goal.filter(|goal| *goal > 0) {
+            if before < goal && after >= goal {
+                reminders.push(Reminder::GoalReached(goal));
+            }
+        }
+        match self.break_until {
+            Some(until) if now >= until => {
+                self.break_until = None;
+                reminders.push(Reminder::BreakOver);
+            }
+            // Reading during a break doesn't count toward the next pomodoro
+            Some(_) => {}
+            None => {
+                if let Some(pomodoro) = self.pomodoro.filter(|pomodoro| !pomodoro.is_zero()) {
+                    self.worked += reading;
+                    if self.worked >= pomodoro {
+                        self.worked = Duration::ZERO;
+                        self.break_until = Some(now + self.pause);
+                        reminders.push(Reminder::BreakStarts(self.pause.as_secs() / 60));
+                    }
+                }
+            }
+        }
+        reminders
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reading_time_adds_up_per_day() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("reading_time.json");
+        let day = NaiveDate::from_ymd_opt(2026, 3, 1).unwrap();
+        let mut time = ReadingTime::with_path(&path);
+
+        time.add(day, Some("a.epub"), Duration::from_secs(50));
+        assert_eq!(time.minutes(day), 0);
+        time.add(day, None, Duration::from_secs(100));
+        assert_eq!(time.minutes(day), 2);
+        assert_eq!(ReadingTime::load_from(&path).unwrap().minutes(day), 2);
+        // Less than a minute more is only written when flushed
+        time.add(day, None, Duration::from_secs(20));
+        assert_eq!(ReadingTime::load_from(&path).unwrap().seconds, 150.0);
+        time.flush().unwrap();
+        assert_eq!(ReadingTime::load_from(&path).unwrap().seconds, 170.0);
+
+        let next = day.succ_opt().unwrap();
+        assert_eq!(time.minutes(next), 0);
+        time.add(next, Some("a.epub"), Duration::from_secs(61));
+        assert_eq!(time.minutes(next), 1);
+        assert_eq!(time.minutes(day), 0);
+        assert_eq!(time.book_minutes("a.epub"), 1);
+        assert_eq!(time.book_minutes("b.epub"), 0);
+    }
+
+    #[test]
+    fn test_reminders_for_goal_and_pomodoro() {
+        let minute = Duration::from_secs(60);
+        let start = Instant::now();
+        let mut reminders = Reminders::new(Some(30), Some(25 * minute), 5 * minute, true);
+        assert_eq!(reminders.update(start, 24 * minute, 5, 29), vec![]);
+        assert_eq!(reminders.next_due(start, true), Some(minute));
+        assert_eq!(reminders.next_due(start, false), None);
+        assert_eq!(
+            reminders.update(start, minute, 29, 30),
+            vec![Reminder::GoalReached(30), Reminder::BreakStarts(5)]
+        );
+        assert!(reminders.on_break(start + minute));
+        assert_eq!(reminders.next_due(start + minute, false), Some(4 * minute));
+        assert_eq!(reminders.update(start + minute, minute, 30, 31), vec![]);
+        assert_eq!(reminders.update(start + 5 * minute, Duration::ZERO, 31, 31), vec![Reminder::BreakOver]);
+        // The minute read on the break didn't count
+        assert_eq!(reminders.update(start + 6 * minute, 24 * minute, 31, 55), vec![]);
+
+        let mut off = Reminders::default();
+        assert_eq!(off.update(start, 60 * minute, 0, 60), vec![]);
+    }
+}
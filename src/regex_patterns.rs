@@ -1,4 +1,3 @@
-use regex;
 use anyhow::{Context, Result};
 
 pub struct RegexPatterns {
@@ -17,7 +16,7 @@ pub struct RegexPatterns {
 
 impl RegexPatterns {
     pub fn new() -> Result<Self> {
-        let p_tag = regex::Regex::new(r"<p[^>]*>")
+        let p_tag = regex::Regex::new(r"[ \t]*<p[^>]*>")
             .context("Failed to compile paragraph tag regex")?;
         let h_open = regex::Regex::new(r"<h[1-6][^>]*>")
             .context("Failed to compile header open tag regex")?;
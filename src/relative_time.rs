@@ -0,0 +1,71 @@
+use chrono::{DateTime, Local, Utc};
+use chrono::format::{Item, StrftimeItems};
+
+/// `last_read_format` that shows times relative to now.
+pub const RELATIVE: &str = "relative";
+
+/// How long ago `then` was, roughly, at `now`: `5 min ago`, `yesterday`.
+pub fn relative(then: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let minutes = (now - then).num_minutes();
+    let hours = minutes / 60;
+    let days = hours / 24;
+    if minutes < 1 {
+        "just now".to_string()
+    } else if minutes < 60 {
+        format!("{} min ago", minutes)
+    } else if hours < 24 {
+        format!("{} h ago", hours)
+    } else if days < 2 {
+        "yesterday".to_string()
+    } else if days < 30 {
+        format!("{} days ago", days)
+    } else if days < 60 {
+        "a month ago".to_string()
+    } else if days < 365 {
+        format!("{} months ago", days / 30)
+    } else if days < 730 {
+        "a year ago".to_string()
+    } else {
+        format!("{} years ago", days / 365)
+    }
+}
+
+/// Whether `format` is `relative` or a strftime pattern chrono can use.
+pub fn is_valid(format: &str) -> bool {
+    format == RELATIVE || !StrftimeItems::new(format).any(|item| item == Item::Error)
+}
+
+/// `then` as `format` says: relative to `now`, or in local time with a
+/// strftime pattern. Patterns are checked with `is_valid` beforehand.
+pub fn format(format: &str, then: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    if format == RELATIVE {
+        relative(then, now)
+    } else {
+        then.with_timezone(&Local).format(format).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_times_are_rounded_down_to_a_friendly_unit() {
+        let now = Utc::now();
+        let ago = |duration: Duration| relative(now - duration, now);
+        assert_eq!(ago(Duration::seconds(20)), "just now");
+        assert_eq!(ago(Duration::seconds(-90)), "just now");
+        assert_eq!(ago(Duration::minutes(5)), "5 min ago");
+        assert_eq!(ago(Duration::minutes(150)), "2 h ago");
+        assert_eq!(ago(Duration::hours(30)), "yesterday");
+        assert_eq!(ago(Duration::days(3)), "3 days ago");
+        assert_eq!(ago(Duration::days(95)), "3 months ago");
+        assert_eq!(ago(Duration::days(400)), "a year ago");
+        assert_eq!(ago(Duration::days(3000)), "8 years ago");
+
+        assert!(is_valid(RELATIVE) && is_valid("%d.%m.%Y"));
+        assert!(!is_valid("%Q"));
+        assert_eq!(format("%Y", now, now), now.with_timezone(&Local).format("%Y").to_string());
+    }
+}
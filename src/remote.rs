@@ -0,0 +1,202 @@
+use std::{
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+use serde::Serialize;
+use tiny_http::{Header, Method, Response, Server};
+
+/// A command sent to the reader by an external tool.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemoteCommand {
+    Status,
+    NextChapter,
+    PrevChapter,
+    /// 1-based chapter, as shown in the title bar, and an optional scroll offset
+    Jump { chapter: usize, offset: Option<usize> },
+}
+
+/// Snapshot of the reader returned for every remote request.
+#[derive(Debug, Clone, Serialize)]
+pub struct RemoteStatus {
+    pub book: Option<String>,
+    pub mode: String,
+    pub chapter: usize,
+    pub total_chapters: usize,
+    pub scroll_offset: usize,
+}
+
+pub struct RemoteRequest {
+    pub command: RemoteCommand,
+    reply: Sender<RemoteStatus>,
+}
+
+impl RemoteRequest {
+    pub fn reply(self, status: RemoteStatus) {
+        // The HTTP thread may have given up waiting; nothing to do then
+        let _ = self.reply.send(status);
+    }
+}
+
+/// Local control API. Requests are accepted on a background thread and
+/// handed to the UI loop, which owns the `App`, through a channel.
+pub struct RemoteServer {
+    requests: Receiver<RemoteRequest>,
+}
+
+impl RemoteServer {
+    /// Listens on `addr`, either `host:port` or `unix:/path/to/socket`.
+    pub fn start(addr: &str) -> Result<Self> {
+        Self::start_waking(addr, || {})
+    }
+
+    /// Like `start`, calling `wake` whenever a request is waiting, so a
+    /// UI loop blocked on input can take it at once.
+    pub fn start_waking(addr: &str, wake: impl Fn() + Send + 'static) -> Result<Self> {
+        let server = match addr.strip_prefix("unix:") {
+            #[cfg(unix)]
+            Some(path) => {
+                // A stale socket from a previous run would make bind fail
+                let _ = std::fs::remove_file(path);
+                Server::http_unix(std::path::Path::new(path))
+            }
+            #[cfg(not(unix))]
+            Some(_) => return Err(anyhow!("Unix sockets are not supported on this platform")),
+            None => Server::http(addr),
+        }
+        .map_err(|e| anyhow!("Failed to start remote API on {}: {}", addr, e))?;
+        info!("Remote API listening on {}", addr);
+
+        let (sender, requests) = mpsc::channel();
+        thread::spawn(move || serve(server, sender, wake));
+        Ok(Self { requests })
+    }
+
+    pub fn try_recv(&self) -> Option<RemoteRequest> {
+        self.requests.try_recv().ok()
+    }
+}
+
+fn serve(server: Server, sender: Sender<RemoteRequest>, wake: impl Fn()) {
+    for request in server.incoming_requests() {
+        let command = match parse_route(request.method(), request.url()) {
+            Ok(command) => command,
+            Err((code, message)) => {
+                let _ = request.respond(json_response(format!("{{\"error\":{:?}}}", message)).with_status_code(code));
+                continue;
+            }
+        };
+
+        let (reply, response) = mpsc::channel();
+        if sender.send(RemoteRequest { command, reply }).is_err() {
+            // The UI loop has exited
+            break;
+        }
+        wake();
+        let result = match response.recv_timeout(Duration::from_secs(2)) {
+            Ok(status) => match serde_json::to_string(&status) {
+                Ok(body) => request.respond(json_response(body)),
+                Err(e) => {
+                    error!("Failed to serialize remote status: {}", e);
+                    request.respond(Response::from_string("").with_status_code(500))
+                }
+            },
+            Err(_) => request.respond(json_response("{\"error\":\"timeout\"}".to_string()).with_status_code(503)),
+        };
+        if let Err(e) = result {
+            warn!("Failed to send remote API response: {}", e);
+        }
+    }
+}
+
+fn json_response(body: String) -> Response<std::io::Cursor<Vec<u8>>> {
+    let header = Header::from_bytes("Content-Type", "application/json").expect("static header is valid");
+    Response::from_string(body).with_header(header)
+}
+
+/// Maps an HTTP request to a command:
+///
+/// - `GET /status`
+/// - `POST /next`, `POST /prev`
+/// - `POST /jump?chapter=N[&offset=M]`
+fn parse_route(method: &Method, url: &str) -> Result<RemoteCommand, (u16, String)> {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let param = |name: &str| -> Result<Option<usize>, (u16, String)> {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.parse().map_err(|_| (400, format!("invalid {}: {}", name, value))))
+            .transpose()
+    };
+
+    match (method, path) {
+        (Method::Get, "/status") => Ok(RemoteCommand::Status),
+        (Method::Post, "/next") => Ok(RemoteCommand::NextChapter),
+        (Method::Post, "/prev") => Ok(RemoteCommand::PrevChapter),
+        (Method::Post, "/jump") => {
+            let chapter = param("chapter")?.ok_or((400, "missing chapter".to_string()))?;
+            Ok(RemoteCommand::Jump { chapter, offset: param("offset")? })
+        }
+        (_, "/status" | "/next" | "/prev" | "/jump") => Err((405, "method not allowed".to_string())),
+        _ => Err((404, "not found".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_route() {
+        assert_eq!(parse_route(&Method::Get, "/status"), Ok(RemoteCommand::Status));
+        assert_eq!(parse_route(&Method::Post, "/next"), Ok(RemoteCommand::NextChapter));
+        assert_eq!(
+            parse_route(&Method::Post, "/jump?chapter=3&offset=10"),
+            Ok(RemoteCommand::Jump { chapter: 3, offset: Some(10) })
+        );
+        assert_eq!(
+            parse_route(&Method::Post, "/jump?chapter=3"),
+            Ok(RemoteCommand::Jump { chapter: 3, offset: None })
+        );
+        assert_eq!(parse_route(&Method::Post, "/jump").unwrap_err().0, 400);
+        assert_eq!(parse_route(&Method::Post, "/jump?chapter=x").unwrap_err().0, 400);
+        assert_eq!(parse_route(&Method::Get, "/next").unwrap_err().0, 405);
+        assert_eq!(parse_route(&Method::Get, "/nope").unwrap_err().0, 404);
+    }
+
+    #[test]
+    fn test_server_round_trip() {
+        use std::io::{Read, Write};
+
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        let (sender, requests) = mpsc::channel();
+        thread::spawn(move || serve(server, sender, || {}));
+
+        let client = thread::spawn(move || {
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            stream.write_all(b"POST /next HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        });
+
+        let request = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(request.command, RemoteCommand::NextChapter);
+        request.reply(RemoteStatus {
+            book: Some("a.epub".to_string()),
+            mode: "Content".to_string(),
+            chapter: 2,
+            total_chapters: 4,
+            scroll_offset: 0,
+        });
+
+        let response = client.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains("\"chapter\":2"));
+    }
+}
@@ -0,0 +1,197 @@
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+/// Bounds on the work done for one part before the regex passes run. The
+/// regex engine matches in linear time, so capping the input size and
+/// nesting here bounds the whole pipeline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    /// Bytes of HTML read from a part; the rest is skipped
+    pub max_bytes: usize,
+    /// Tags nested deeper than this are dropped, their text is kept
+    pub max_depth: usize,
+    pub timeout: Duration,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_bytes: 8 * 1024 * 1024,
+            max_depth: 256,
+            timeout: Duration::from_secs(2),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sanitized {
+    pub html: String,
+    /// Shown after the text when part of it was skipped
+    pub notice: Option<String>,
+}
+
+/// Elements whose content is never text
+const SKIPPED: [&str; 3] = ["script", "style", "svg"];
+/// Elements that never have a closing tag
+const VOID: [&str; 11] = ["area", "base", "br", "col", "hr", "img", "input", "link", "meta", "source", "wbr"];
+
+/// Rewrites `html` into a small, well-bounded subset for the formatter:
+/// drops comments, `<script>`, `<style>` and `<svg>` blocks, strips every
+/// attribute (inline CSS included) and flattens nesting past the depth
+/// limit. Input past the size limit or the deadline is skipped.
+pub fn sanitize(html: &str, limits: &Limits) -> Sanitized {
+    let deadline = Instant::now() + limits.timeout;
+    let mut notice = None;
+    let mut input = html;
+    if input.len() > limits.max_bytes {
+        let mut end = limits.max_bytes;
+        while !input.is_char_boundary(end) {
+            end -= 1;
+        }
+        input = &input[..end];
+        warn!("Part is {} bytes; only the first {} are shown", html.len(), end);
+        notice = Some(format!(
+            "[The rest of this part was skipped: it is larger than {} KB]",
+            limits.max_bytes / 1024
+        ));
+    }
+
+    let mut out = String::with_capacity(input.len());
+    let mut depth = 0usize;
+    let mut pos = 0;
+    let mut steps = 0u32;
+    while let Some(found) = input[pos..].find('<') {
+        steps = steps.wrapping_add(1);
+        if steps.is_multiple_of(1024) && Instant::now() > deadline {
+            warn!("Sanitizing a part took longer than {:?}; skipping the rest", limits.timeout);
+            notice = Some("[The rest of this part was skipped: it took too long to process]".to_string());
+            pos = input.len();
+            break;
+        }
+        let start = pos + found;
+        out.push_str(&input[pos..start]);
+        let rest = &input[start..];
+
+        if rest.starts_with("<!--") {
+            pos = rest.find("-->").map_or(input.len(), |end| start + end + 3);
+            continue;
+        }
+        let Some(close) = rest.find('>') else {
+            // No tag is closed after this point; keep the rest as text
+            // rather than searching again from every later `<`
+            out.push_str(&rest.replace('<', "&lt;"));
+            pos = input.len();
+            break;
+        };
+        pos = start + close + 1;
+
+        let tag = &rest[1..close];
+        let closing = tag.starts_with('/');
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        if name.is_empty() {
+            continue;
+        }
+        let self_closing = tag.ends_with('/') || VOID.contains(&name.as_str());
+
+        if !closing && !self_closing && SKIPPED.contains(&name.as_str()) {
+            pos = find_closing(input, pos, &name).unwrap_or(input.len());
+            continue;
+        }
+
+        if closing {
+            if depth <= limits.max_depth {
+                out.push_str("</");
+                out.push_str(&name);
+                out.push('>');
+            }
+            depth = depth.saturating_sub(1);
+        } else {
+            if self_closing || depth < limits.max_depth {
+                out.push('<');
+                out.push_str(&name);
+                out.push('>');
+            }
+            if !self_closing {
+                depth += 1;
+            }
+        }
+    }
+    out.push_str(&input[pos..]);
+
+    Sanitized { html: out, notice }
+}
+
+/// Replaces the named and numeric character references the formatter
+/// knows with the characters they stand for. Non-breaking spaces stay
+/// U+00A0, which the wrapper never breaks at.
+pub fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", "\u{A0}")
+        .replace("&#160;", "\u{A0}")
+        .replace("&#xa0;", "\u{A0}")
+        .replace("&#xA0;", "\u{A0}")
+        .replace("&shy;", "\u{AD}")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&mdash;", "—")
+        .replace("&ndash;", "–")
+        .replace("&hellip;", "...")
+        .replace("&ldquo;", "\u{201C}")
+        .replace("&rdquo;", "\u{201D}")
+        .replace("&lsquo;", "\u{2018}")
+        .replace("&rsquo;", "\u{2019}")
+        .replace("&amp;", "&")
+}
+
+/// Byte offset just past `</name ...>`, searching from `from`.
+fn find_closing(input: &str, from: usize, name: &str) -> Option<usize> {
+    let rest = &input[from..];
+    let start = rest.match_indices("</").map(|(i, _)| i).find(|&i| {
+        rest.as_bytes()[i + 2..]
+            .get(..name.len())
+            .is_some_and(|candidate| candidate.eq_ignore_ascii_case(name.as_bytes()))
+    })?;
+    let end = rest[start..].find('>')?;
+    Some(from + start + end + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strips_attributes_and_skipped_elements() {
+        let html = r#"<style>p { color: red }</style><p class="x" style="font: big">Hi <!-- note --><em id="e">there</em></p><script>alert(1)</script><br />"#;
+        let sanitized = sanitize(html, &Limits::default());
+        assert_eq!(sanitized.html, "<p>Hi <em>there</em></p><br>");
+        assert_eq!(sanitized.notice, None);
+    }
+
+    #[test]
+    fn test_depth_limit_keeps_text() {
+        let limits = Limits {
+            max_depth: 2,
+            ..Default::default()
+        };
+        let html = "<div><div><div><b>deep</b></div></div></div>";
+        assert_eq!(sanitize(html, &limits).html, "<div><div>deep</div></div>");
+    }
+
+    #[test]
+    fn test_unclosed_tags_and_size_limit() {
+        let limits = Limits {
+            max_bytes: 16,
+            ..Default::default()
+        };
+        let sanitized = sanitize("a <b>c <unclosed <tag forever", &limits);
+        assert_eq!(sanitized.html, "a <b>c &lt;unclosed");
+        assert!(sanitized.notice.unwrap().contains("skipped"));
+    }
+}
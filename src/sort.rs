@@ -0,0 +1,111 @@
+use std::cmp::Reverse;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bookmark::Bookmarks;
+use crate::library_index::LibraryIndex;
+
+/// The order the library lists its books in, switched with `s`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Title,
+    /// By the first author, then the title
+    Author,
+    /// Most recently read first, never read last
+    LastRead,
+    /// Furthest read first
+    Progress,
+    /// Newest to the library first
+    Added,
+}
+
+impl SortOrder {
+    /// The order after this one, wrapping around.
+    pub fn next(self) -> Self {
+        match self {
+            Self::Title => Self::Author,
+            Self::Author => Self::LastRead,
+            Self::LastRead => Self::Progress,
+            Self::Progress => Self::Added,
+            Self::Added => Self::Title,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Title => "title",
+            Self::Author => "author",
+            Self::LastRead => "last read",
+            Self::Progress => "progress",
+            Self::Added => "date added",
+        }
+    }
+}
+
+/// Sorts `books` in `order`, with what `index` and `bookmarks` know of
+/// them. Books the order can't tell apart stay sorted by title, and then
+/// by path.
+pub fn sort(books: &mut [String], order: SortOrder, index: &LibraryIndex, bookmarks: &Bookmarks) {
+    let title = |book: &str| index.entry(book).map_or_else(|| index.label(book), |entry| entry.title.clone()).to_lowercase();
+    let by_title = |book: &String| (title(book), book.clone());
+    match order {
+        SortOrder::Title => books.sort_by_cached_key(by_title),
+        SortOrder::Author => books.sort_by_cached_key(|book| {
+            // Books without an author come last
+            let author = index.entry(book).and_then(|entry| entry.authors.first()).map(|author| author.to_lowercase());
+            (author.is_none(), author, by_title(book))
+        }),
+        SortOrder::LastRead => books.sort_by_cached_key(|book| {
+            (Reverse(bookmarks.get_bookmark(book).map(|bookmark| bookmark.last_read)), by_title(book))
+        }),
+        SortOrder::Progress => books.sort_by_cached_key(|book| {
+            let progress = index.reading(book).and_then(|(progress, _)| progress);
+            (Reverse(progress), by_title(book))
+        }),
+        SortOrder::Added => books.sort_by_cached_key(|book| (Reverse(index.added(book)), by_title(book))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_books_are_sorted_in_every_order() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let book = |name: &str| {
+            let path = dir.path().join(name).to_string_lossy().to_string();
+            std::fs::write(&path, name).unwrap();
+            path
+        };
+        let (walden, emma, dune) = (book("Walden.epub"), book("emma.epub"), book("dune.epub"));
+        let mut bookmarks = Bookmarks::with_path(dir.path().join("bookmarks.json"));
+        let mut index = LibraryIndex::open(dir.path().join("library.db")).unwrap();
+        index.refresh(std::slice::from_ref(&walden), &bookmarks).unwrap();
+        let now = chrono::Utc::now();
+        bookmarks.set_position(&emma, 0, 0, now - chrono::Duration::days(2));
+        bookmarks.set_position(&dune, 0, 0, now);
+        let mut books = vec![walden.clone(), emma.clone(), dune.clone()];
+        index.refresh(&books, &bookmarks).unwrap();
+        index.record_reading(&emma, 80, now - chrono::Duration::days(2));
+        index.record_reading(&dune, 10, now);
+
+        sort(&mut books, SortOrder::Title, &index, &bookmarks);
+        assert_eq!(books, [dune.clone(), emma.clone(), walden.clone()]);
+        sort(&mut books, SortOrder::LastRead, &index, &bookmarks);
+        assert_eq!(books, [dune.clone(), emma.clone(), walden.clone()]);
+        sort(&mut books, SortOrder::Progress, &index, &bookmarks);
+        assert_eq!(books, [emma.clone(), dune.clone(), walden.clone()]);
+        // Walden was indexed first, the others since
+        sort(&mut books, SortOrder::Added, &index, &bookmarks);
+        assert_eq!(books.last(), Some(&walden));
+
+        let mut order = SortOrder::default();
+        for _ in 0..5 {
+            order = order.next();
+        }
+        assert_eq!(order, SortOrder::Title);
+    }
+}
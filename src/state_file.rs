@@ -0,0 +1,48 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where the state file `name` is kept beside `bookmarks_path`.
+pub fn beside(bookmarks_path: &Path, name: &str) -> PathBuf {
+    bookmarks_path.parent().unwrap_or(Path::new("")).join(name)
+}
+
+/// The state read from the JSON file at `path`, `None` when there is no
+/// such file yet.
+pub fn load<T: DeserializeOwned>(path: &Path) -> anyhow::Result<Option<T>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path)?;
+    Ok(Some(serde_json::from_str(&content)?))
+}
+
+/// Writes `state` to `path` as JSON.
+pub fn save<T: Serialize>(state: &T, path: &Path) -> anyhow::Result<()> {
+    let content = serde_json::to_string_pretty(state)?;
+    fs::write(path, content)?;
+    Ok(())
+}
+
+/// Saves `state` after a change, logging rather than returning a failure;
+/// `what` names it in the log.
+pub fn save_changed<T: Serialize>(state: &T, path: &Path, what: &str) {
+    if let Err(e) = save(state, path) {
+        log::error!("Failed to save {}: {}", what, e);
+    }
+}
+
+/// Takes in another copy's `theirs`, keeping the larger value of each key
+/// found in both: for values that only ever grow, such as counts or the
+/// time something last happened.
+pub fn merge_largest<K: Ord, V: PartialOrd>(ours: &mut BTreeMap<K, V>, theirs: BTreeMap<K, V>) {
+    for (key, value) in theirs {
+        match ours.get(&key) {
+            Some(kept) if *kept >= value => {}
+            _ => {
+                ours.insert(key, value);
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Context, Result};
+use log::{error, info};
+use serde::Serialize;
+
+/// Characters of text read in a minute, for the time left: about 250
+/// words of five letters and a space.
+pub const CHARS_PER_MINUTE: usize = 1500;
+
+/// What is being read, as written for status bars.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ReadingStatus {
+    pub book: Option<String>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// 1-based; 0 without a book
+    pub chapter: usize,
+    pub total_chapters: usize,
+    /// Of the whole book
+    pub percent: u32,
+    /// At `CHARS_PER_MINUTE`, when the book's length is known
+    pub minutes_left: Option<u64>,
+    /// One line ready to show, such as `War and Peace 42% (1h 35m left)`
+    pub text: String,
+}
+
+impl ReadingStatus {
+    pub fn new(book: &str, title: String, author: Option<String>, chapter: usize, total_chapters: usize, percent: u32, minutes_left: Option<u64>) -> Self {
+        let left = minutes_left.map(|minutes| format!(" ({} left)", format_minutes(minutes))).unwrap_or_default();
+        Self {
+            text: format!("{} {}%{}", title, percent, left),
+            book: Some(book.to_string()),
+            title: Some(title),
+            author,
+            chapter,
+            total_chapters,
+            percent,
+            minutes_left,
+        }
+    }
+}
+
+/// `95` as `1h 35m`, `20` as `20m`.
+pub fn format_minutes(minutes: u64) -> String {
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{}m", minutes),
+        (hours, minutes) => format!("{}h {}m", hours, minutes),
+    }
+}
+
+/// Where the status goes: a file rewritten whenever it changes, or a Unix
+/// socket that hands every client the current status and hangs up, for
+/// `socat - UNIX-CONNECT:/path`.
+pub struct StatusWriter {
+    target: Target,
+    last: Option<String>,
+}
+
+enum Target {
+    File(PathBuf),
+    #[cfg(unix)]
+    Socket(PathBuf, Arc<Mutex<String>>),
+}
+
+impl StatusWriter {
+    /// Writes to `target`, a path or `unix:/path/to/socket`.
+    pub fn open(target: &str) -> Result<Self> {
+        let target = match target.strip_prefix("unix:") {
+            #[cfg(unix)]
+            Some(path) => {
+                let current = Arc::new(Mutex::new(String::new()));
+                serve(Path::new(path), current.clone())?;
+                Target::Socket(PathBuf::from(path), current)
+            }
+            #[cfg(not(unix))]
+            Some(_) => return Err(anyhow!("Unix sockets are not supported on this platform")),
+            None => Target::File(PathBuf::from(target)),
+        };
+        Ok(Self { target, last: None })
+    }
+
+    /// Publishes `status` as a line of JSON, unless it is what was
+    /// published last.
+    pub fn update(&mut self, status: &ReadingStatus) {
+        let json = match serde_json::to_string(status) {
+            Ok(json) => json + "\n",
+            Err(e) => return error!("Failed to encode the status: {}", e),
+        };
+        if self.last.as_ref() == Some(&json) {
+            return;
+        }
+        match &self.target {
+            Target::File(path) => {
+                if let Err(e) = write_atomically(path, &json) {
+                    error!("Failed to write the status to {:?}: {}", path, e);
+                }
+            }
+            #[cfg(unix)]
+            Target::Socket(_, current) => *current.lock().unwrap() = json.clone(),
+        }
+        self.last = Some(json);
+    }
+}
+
+impl Drop for StatusWriter {
+    /// Leaves nothing behind to show a book that isn't being read any more.
+    fn drop(&mut self) {
+        let path = match &self.target {
+            Target::File(path) => path,
+            #[cfg(unix)]
+            Target::Socket(path, _) => path,
+        };
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Writes a temporary file and renames it into place, so a bar reading the
+/// file never sees half a status.
+fn write_atomically(path: &Path, content: &str) -> Result<()> {
+    let name = path.file_name().ok_or_else(|| anyhow!("{:?} is not a file path", path))?;
+    let partial = path.with_file_name(format!(".{}.tmp", name.to_string_lossy()));
+    fs::write(&partial, content)?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn serve(path: &Path, current: Arc<Mutex<String>>) -> Result<()> {
+    use std::io::Write;
+    // A stale socket from a previous run would make bind fail
+    let _ = fs::remove_file(path);
+    let listener = std::os::unix::net::UnixListener::bind(path).with_context(|| format!("Failed to listen on {:?}", path))?;
+    info!("Serving the status on {:?}", path);
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let status = current.lock().unwrap().clone();
+            // The client may be gone already; nothing to do then
+            let _ = stream.write_all(status.as_bytes());
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_is_written_as_json() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("status.json");
+        let mut writer = StatusWriter::open(path.to_str().unwrap()).unwrap();
+        let status = ReadingStatus::new("books/war.epub", "War and Peace".to_string(), None, 3, 12, 42, Some(95));
+        assert_eq!(status.text, "War and Peace 42% (1h 35m left)");
+        writer.update(&status);
+        let written: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["percent"], 42);
+        assert_eq!(written["minutes_left"], 95);
+        assert_eq!(written["book"], "books/war.epub");
+
+        writer.update(&ReadingStatus::default());
+        assert_eq!(fs::read_to_string(&path).unwrap().trim(), serde_json::to_string(&ReadingStatus::default()).unwrap());
+        drop(writer);
+        assert!(!path.exists());
+        assert_eq!(format_minutes(20), "20m");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_status_socket_answers_every_client() {
+        use std::io::Read;
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("status.sock");
+        let mut writer = StatusWriter::open(&format!("unix:{}", path.display())).unwrap();
+        writer.update(&ReadingStatus::new("a.epub", "A".to_string(), None, 1, 1, 100, Some(0)));
+        for _ in 0..2 {
+            let mut reply = String::new();
+            std::os::unix::net::UnixStream::connect(&path).unwrap().read_to_string(&mut reply).unwrap();
+            assert!(reply.contains(r#""text":"A 100% (0m left)""#), "{}", reply);
+        }
+    }
+}
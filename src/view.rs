@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::sort::SortOrder;
+
+/// Share of the screen the library takes by default, in percent.
+pub const DEFAULT_LIBRARY_WIDTH: u16 = 30;
+/// Widest the library can be made, leaving room for the text.
+pub const MAX_LIBRARY_WIDTH: u16 = 70;
+/// Percent the library grows or shrinks by with one key.
+pub const LIBRARY_WIDTH_STEP: u16 = 5;
+
+/// How the screen was last laid out: the width and order of the library
+/// and which panels and reading modes were on, stored as `view.json` next to the
+/// bookmarks file. Left out of sync, as it suits one machine's terminal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct View {
+    /// Percent of the screen, 0 for the library hidden while reading
+    pub library_width: u16,
+    pub chapter_sidebar: bool,
+    pub minimap: bool,
+    pub line_numbers: bool,
+    pub wrap: bool,
+    pub large_print: bool,
+    pub sort: SortOrder,
+    /// Leave books marked finished out of the library
+    pub hide_finished: bool,
+    /// Show the library as a shelf of covers rather than a list
+    pub shelf: bool,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self {
+            library_width: DEFAULT_LIBRARY_WIDTH,
+            chapter_sidebar: false,
+            minimap: false,
+            line_numbers: false,
+            wrap: true,
+            large_print: false,
+            sort: SortOrder::default(),
+            hide_finished: false,
+            shelf: false,
+            path: PathBuf::new(),
+        }
+    }
+}
+
+impl View {
+    /// Loads the view kept beside `bookmarks_path`.
+    pub fn beside(bookmarks_path: &Path) -> anyhow::Result<Self> {
+        let dir = bookmarks_path.parent().unwrap_or(Path::new(""));
+        Self::load_from(dir.join("view.json"))
+    }
+
+    pub fn load_from<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let mut view: Self = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(path)?)?
+        } else {
+            Self::default()
+        };
+        view.library_width = view.library_width.min(MAX_LIBRARY_WIDTH);
+        view.path = path.to_path_buf();
+        Ok(view)
+    }
+
+    /// Makes `change` and writes the view out, if that changed it.
+    pub fn update(&mut self, change: impl FnOnce(&mut View)) {
+        let before = self.clone();
+        change(self);
+        if *self == before {
+            return;
+        }
+        if let Err(e) = self.save() {
+            log::error!("Failed to save the view: {}", e);
+        }
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(&self.path, content)?;
+        Ok(())
+    }
+
+    /// The library width one step wider, or narrower down to hidden.
+    pub fn resized(width: u16, wider: bool) -> u16 {
+        if wider {
+            (width + LIBRARY_WIDTH_STEP).min(MAX_LIBRARY_WIDTH)
+        } else {
+            width.saturating_sub(LIBRARY_WIDTH_STEP)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_view_is_kept_when_changed() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("view.json");
+        let mut view = View::load_from(&path).unwrap();
+        assert_eq!(view.library_width, DEFAULT_LIBRARY_WIDTH);
+
+        view.update(|view| {
+            view.minimap = true;
+            view.library_width = 45;
+        });
+        let loaded = View::load_from(&path).unwrap();
+        assert!(loaded.minimap && loaded.wrap);
+        assert_eq!(loaded.library_width, 45);
+
+        assert_eq!(View::resized(MAX_LIBRARY_WIDTH, true), MAX_LIBRARY_WIDTH);
+        assert_eq!(View::resized(3, false), 0);
+    }
+}
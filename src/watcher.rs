@@ -0,0 +1,92 @@
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+use notify::event::{AccessKind, AccessMode, ModifyKind};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::archive;
+
+/// Watches the library roots for books being added, removed or renamed
+/// while bookrat runs.
+pub struct LibraryWatcher {
+    // Watching stops when it is dropped
+    _watcher: RecommendedWatcher,
+    changes: Receiver<()>,
+}
+
+impl LibraryWatcher {
+    /// Starts watching `roots`, and their subdirectories when `recursive`.
+    /// Roots that can't be watched are skipped; it fails only when none
+    /// can.
+    pub fn start(roots: &[String], recursive: bool) -> Result<Self> {
+        Self::start_waking(roots, recursive, || {})
+    }
+
+    /// Like `start`, calling `wake` whenever a book changed, so a UI loop
+    /// blocked on input can take the change at once.
+    pub fn start_waking(roots: &[String], recursive: bool, wake: impl Fn() + Send + 'static) -> Result<Self> {
+        let (sender, changes) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+            Ok(event) if concerns_books(&event) => {
+                // The app may have quit; the change is of no use then
+                if sender.send(()).is_ok() {
+                    wake();
+                }
+            }
+            Ok(_) => {}
+            Err(e) => error!("Failed to watch the library: {}", e),
+        })?;
+        let mode = if recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+        let mut watched = 0;
+        for root in roots {
+            match watcher.watch(Path::new(root), mode) {
+                Ok(()) => watched += 1,
+                Err(e) => warn!("Failed to watch the library directory {}: {}", root, e),
+            }
+        }
+        if watched == 0 && !roots.is_empty() {
+            return Err(anyhow!("None of the library directories could be watched"));
+        }
+        info!("Watching {} library directories", watched);
+        Ok(Self { _watcher: watcher, changes })
+    }
+
+    /// Whether any book changed since the last call.
+    pub fn changed(&self) -> bool {
+        self.changes.try_iter().count() > 0
+    }
+}
+
+/// Whether `event` may have added, removed or finished writing a book:
+/// opening one to read it doesn't count. Directories, which have no
+/// extension, count, as a folder of books may have been moved in.
+fn concerns_books(event: &Event) -> bool {
+    let relevant = matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_)) | EventKind::Access(AccessKind::Close(AccessMode::Write))
+    );
+    relevant
+        && event.paths.iter().any(|path| {
+            let hidden = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            !hidden && (path.extension().is_none_or(|ext| ext == "epub") || archive::is_archive(path))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, RemoveKind};
+
+    #[test]
+    fn test_only_changes_to_books_are_reported() {
+        let event = |kind, path: &str| Event::new(kind).add_path(path.into());
+        assert!(concerns_books(&event(EventKind::Create(CreateKind::File), "/books/emma.epub")));
+        assert!(concerns_books(&event(EventKind::Create(CreateKind::Folder), "/books/Austen")));
+        assert!(concerns_books(&event(EventKind::Remove(RemoveKind::File), "/books/emma.epub")));
+        assert!(!concerns_books(&event(EventKind::Access(AccessKind::Open(AccessMode::Read)), "/books/emma.epub")));
+        assert!(!concerns_books(&event(EventKind::Create(CreateKind::File), "/books/notes.txt")));
+        assert!(!concerns_books(&event(EventKind::Create(CreateKind::File), "/books/.emma.epub.part")));
+    }
+}
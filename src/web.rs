@@ -0,0 +1,240 @@
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use epub::doc::EpubDoc;
+use log::{error, info, warn};
+use tiny_http::{Header, Method, Response, Server};
+
+use crate::app::App;
+use crate::blocks;
+use crate::bookmark::Bookmarks;
+use crate::layout;
+use crate::library::{self, ScanOptions};
+use crate::regex_patterns::RegexPatterns;
+
+const STYLE: &str = "body{max-width:40em;margin:2em auto;padding:0 1em;font-family:Georgia,serif;line-height:1.6;color:#222}\
+a{color:#06c}nav{display:flex;justify-content:space-between;margin:1.5em 0}li{margin:.3em 0}.meta{color:#888}";
+
+/// Serves the library over HTTP. Reading a part in the browser updates the
+/// same bookmarks file the TUI uses, so positions carry over between them.
+pub struct WebServer {
+    server: Server,
+    roots: Vec<String>,
+    scan: ScanOptions,
+    bookmarks_path: PathBuf,
+    regex: RegexPatterns,
+}
+
+impl WebServer {
+    /// Listens on `addr` for the library in the `roots` directories, read
+    /// as `scan` says.
+    pub fn bind(addr: &str, roots: &[String], scan: ScanOptions, bookmarks_path: &Path) -> Result<Self> {
+        let server = Server::http(addr).map_err(|e| anyhow!("Failed to listen on {}: {}", addr, e))?;
+        Ok(Self {
+            server,
+            roots: roots.to_vec(),
+            scan,
+            bookmarks_path: bookmarks_path.to_path_buf(),
+            regex: RegexPatterns::new().context("Failed to compile regex patterns")?,
+        })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Handles requests until the process is stopped.
+    pub fn run(&self) {
+        info!("Web reader listening on {:?}", self.local_addr());
+        for request in self.server.incoming_requests() {
+            let (status, body) = if *request.method() != Method::Get {
+                (405, page("Error", "<p>Method not allowed</p>"))
+            } else {
+                match self.route(request.url()) {
+                    Ok(Some(body)) => (200, body),
+                    Ok(None) => (404, page("Not found", "<p>Not found. <a href=\"/\">Library</a></p>")),
+                    Err(e) => {
+                        error!("Web request {} failed: {:?}", request.url(), e);
+                        (500, page("Error", &format!("<p>{}</p>", escape(&e.to_string()))))
+                    }
+                }
+            };
+            let header = Header::from_bytes("Content-Type", "text/html; charset=utf-8").expect("static header is valid");
+            if let Err(e) = request.respond(Response::from_string(body).with_header(header).with_status_code(status)) {
+                warn!("Failed to send web response: {}", e);
+            }
+        }
+    }
+
+    fn route(&self, url: &str) -> Result<Option<String>> {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let part = query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == "part")
+            .and_then(|(_, value)| value.parse::<usize>().ok());
+
+        if path == "/" {
+            return self.library_page().map(Some);
+        }
+        match path.strip_prefix("/book/").map(str::parse::<usize>) {
+            Some(Ok(index)) => self.book_page(index, part),
+            _ => Ok(None),
+        }
+    }
+
+    fn library_page(&self) -> Result<String> {
+        let books = library::scan_roots(&self.roots, &self.scan)?;
+        let bookmarks = Bookmarks::load_from(&self.bookmarks_path)?;
+        let mut items = String::new();
+        for (index, book) in books.iter().enumerate() {
+            let name = Path::new(book).file_stem().unwrap_or_default().to_string_lossy();
+            let meta = match bookmarks.get_bookmark(book) {
+                Some(b) => format!("part {}, last read {}", b.chapter + 1, b.last_read.format("%Y-%m-%d %H:%M")),
+                None => "never read".to_string(),
+            };
+            items.push_str(&format!(
+                "<li><a href=\"/book/{}\">{}</a> <span class=\"meta\">({})</span></li>",
+                index,
+                escape(&name),
+                meta
+            ));
+        }
+        if books.is_empty() {
+            items.push_str("<li>No books found</li>");
+        }
+        Ok(page("Library", &format!("<h1>Library</h1><ul>{}</ul>", items)))
+    }
+
+    fn book_page(&self, index: usize, part: Option<usize>) -> Result<Option<String>> {
+        let books = library::scan_roots(&self.roots, &self.scan)?;
+        let Some(book) = books.get(index) else {
+            return Ok(None);
+        };
+        let mut doc = EpubDoc::new(book).with_context(|| format!("Failed to open EPUB: {}", book))?;
+        let total = doc.get_num_chapters();
+        let mut bookmarks = Bookmarks::load_from(&self.bookmarks_path)?;
+        let bookmark = bookmarks.get_bookmark(book).map(|b| (b.chapter, b.scroll_offset));
+
+        // Same starting point as the TUI: the bookmark, or skip the title page
+        let chapter = match (part, bookmark) {
+            (Some(part), _) => part.saturating_sub(1),
+            (None, Some((chapter, _))) => chapter,
+            (None, None) => usize::from(total > 1),
+        };
+        if chapter >= total || !doc.set_current_chapter(chapter) {
+            return Ok(None);
+        }
+        let content = doc
+            .get_current_str()
+            .map(|(content, _)| App::process_html_content(&content, &self.regex))
+            .unwrap_or_default();
+
+        // Keep the TUI's scroll offset when the browser shows the same part
+        let offset = match bookmark {
+            Some((bookmarked, offset)) if bookmarked == chapter => offset,
+            _ => 0,
+        };
+        bookmarks.update_bookmark(book, chapter, offset);
+        if let Err(e) = bookmarks.save() {
+            error!("Failed to save bookmark: {}", e);
+        }
+
+        let title = doc.get_title().unwrap_or_else(|| {
+            Path::new(book).file_stem().unwrap_or_default().to_string_lossy().to_string()
+        });
+        let prev = if chapter > 0 {
+            format!("<a href=\"/book/{}?part={}\">&larr; Previous</a>", index, chapter)
+        } else {
+            "<span></span>".to_string()
+        };
+        let next = if chapter + 1 < total {
+            format!("<a href=\"/book/{}?part={}\">Next &rarr;</a>", index, chapter + 2)
+        } else {
+            "<span></span>".to_string()
+        };
+        let nav = format!("<nav>{}<a href=\"/\">Library</a>{}</nav>", prev, next);
+        let body = format!(
+            "{nav}<h1>{}</h1><p class=\"meta\">Part {}/{}</p>{}{nav}",
+            escape(&title),
+            chapter + 1,
+            total,
+            text_to_html(&content),
+        );
+        Ok(Some(page(&title, &body)))
+    }
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta name=\"viewport\" content=\"width=device-width\">\
+         <title>{} - BookRat</title><style>{}</style></head><body>{}</body></html>",
+        escape(title),
+        STYLE,
+        body
+    )
+}
+
+/// Escapes `text` for HTML and XML.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Turns the reader's processed text back into paragraphs, mapping the
+/// `_italic_` and `**bold**` markers to HTML. Fenced blocks become `<pre>`.
+fn text_to_html(text: &str) -> String {
+    let mut html = String::new();
+    for (block, lines) in blocks::split_blocks(text) {
+        if block {
+            html.push_str(&format!("<pre>{}</pre>", escape(&lines.join("\n"))));
+        } else {
+            html.push_str(&prose_to_html(&lines));
+        }
+    }
+    html
+}
+
+fn prose_to_html(lines: &[&str]) -> String {
+    let text = lines.iter().map(|line| line.trim()).collect::<Vec<_>>().join("\n");
+    let mut html = String::new();
+    for runs in layout::parse_markup(&text) {
+        if runs.iter().all(|run| run.text.trim().is_empty()) {
+            continue;
+        }
+        let mut out = String::new();
+        for run in runs {
+            let mut text = escape(&run.text);
+            if run.italic {
+                text = format!("<em>{}</em>", text);
+            }
+            if run.bold {
+                text = format!("<strong>{}</strong>", text);
+            }
+            out.push_str(&text);
+        }
+        html.push_str(&format!("<p>{}</p>", out));
+    }
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_to_html() {
+        let text = "First _italic_ line\n    Second **bold** <tag>\n\n    Unclosed _em";
+        assert_eq!(
+            text_to_html(text),
+            "<p>First <em>italic</em> line</p><p>Second <strong>bold</strong> &lt;tag&gt;</p><p>Unclosed <em>em</em></p>"
+        );
+        assert_eq!(text_to_html("```\n  a < _b_\n```"), "<pre>  a &lt; _b_</pre>");
+        assert_eq!(text_to_html("Call _read_to_end_ on file_name"), "<p>Call <em>read_to_end</em> on file_name</p>");
+    }
+}
@@ -0,0 +1,112 @@
+use std::sync::mpsc::{self, Receiver};
+
+use bookrat::{calibre::CalibreClient, config::CalibreConfig};
+
+/// A stand-in for calibre's content server. Every request is reported as
+/// `(method path?query, authorization, body)`.
+fn fake_server() -> (String, Receiver<(String, Option<String>, String)>) {
+    let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", server.server_addr().to_ip().unwrap());
+    let (sender, requests) = mpsc::channel();
+    std::thread::spawn(move || {
+        for mut request in server.incoming_requests() {
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body).unwrap();
+            let url = request.url().to_string();
+            let authorization = request
+                .headers()
+                .iter()
+                .find(|header| header.field.equiv("Authorization"))
+                .map(|header| header.value.to_string());
+            let path = url.split('?').next().unwrap().to_string();
+            let reply = match path.as_str() {
+                "/ajax/library-info" => r#"{"library_map": {"Main": "Main"}, "default_library": "Main"}"#.to_string(),
+                "/ajax/search/Main" if url.contains("query=id%3A7") => r#"{"book_ids": [7], "total_num": 1}"#.to_string(),
+                "/ajax/search/Main" => r#"{"book_ids": [9, 7], "total_num": 2}"#.to_string(),
+                "/ajax/books/Main" => r#"{
+                    "7": {"application_id": 7, "title": "Anna Karenina", "authors": ["Leo Tolstoy"], "formats": ["EPUB"]},
+                    "9": {"application_id": 9, "title": "Anna's Notes", "authors": [], "formats": ["PDF"]}
+                }"#
+                .to_string(),
+                "/get/EPUB/7/Main" => "EPUB BYTES".to_string(),
+                "/cdb/set-fields/7/Main" => r#"{"7": {}}"#.to_string(),
+                _ => {
+                    let _ = request.respond(tiny_http::Response::from_string("nope").with_status_code(404));
+                    continue;
+                }
+            };
+            sender.send((format!("{} {}", request.method(), url), authorization, body)).unwrap();
+            let _ = request.respond(tiny_http::Response::from_string(reply));
+        }
+    });
+    (base, requests)
+}
+
+#[test]
+fn calibre_client_searches_downloads_and_marks_read() {
+    let (base, requests) = fake_server();
+    let config = CalibreConfig {
+        url: Some(format!("{}/", base)),
+        username: Some("reader".to_string()),
+        password: Some("secret".to_string()),
+        ..Default::default()
+    };
+    let client = CalibreClient::connect(&config).unwrap();
+    assert_eq!(client.library(), "Main");
+    let (request, authorization, _) = requests.recv().unwrap();
+    assert_eq!(request, "GET /ajax/library-info");
+    assert_eq!(authorization.as_deref(), Some("Basic cmVhZGVyOnNlY3JldA=="));
+
+    let books = client.search("anna").unwrap();
+    let titles: Vec<&str> = books.iter().map(|book| book.title.as_str()).collect();
+    assert_eq!(titles, vec!["Anna's Notes", "Anna Karenina"], "search order is kept");
+    assert!(!books[0].has_epub());
+    assert!(requests.recv().unwrap().0.starts_with("GET /ajax/search/Main?query=anna"));
+    assert_eq!(requests.recv().unwrap().0, "GET /ajax/books/Main?ids=9%2C7");
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("anna.epub");
+    client.download(7, &path).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "EPUB BYTES");
+    assert!(!path.with_extension("part").exists());
+    requests.recv().unwrap();
+
+    client.set_read(7, true).unwrap();
+    let (request, _, body) = requests.recv().unwrap();
+    assert_eq!(request, "POST /cdb/set-fields/7/Main");
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["changes"]["#read"], serde_json::json!(true));
+
+    assert!(client.download(8, &dir.path().join("missing.epub")).is_err());
+    assert!(!dir.path().join("missing.part").exists());
+}
+
+#[test]
+fn calibre_downloads_never_replace_a_book() {
+    let (base, _requests) = fake_server();
+    let config = CalibreConfig { url: Some(base), ..Default::default() };
+    let client = CalibreClient::connect(&config).unwrap();
+    let dir = tempfile::TempDir::new().unwrap();
+    let library = dir.path().join("books");
+    std::fs::create_dir_all(&library).unwrap();
+    std::fs::write(library.join("anna.epub"), "MINE").unwrap();
+
+    let path = client.download_into(7, &library, "anna.epub").unwrap();
+    assert_eq!(path, library.join("anna-1.epub"));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "EPUB BYTES");
+    assert_eq!(std::fs::read_to_string(library.join("anna.epub")).unwrap(), "MINE");
+
+    assert!(client.download_into(8, &library, "missing.epub").is_err());
+    let mut left: Vec<String> = std::fs::read_dir(&library)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    left.sort();
+    assert_eq!(left, ["anna-1.epub", "anna.epub"]);
+}
+
+#[test]
+fn calibre_needs_a_server() {
+    let error = CalibreClient::connect(&CalibreConfig::default()).err().unwrap();
+    assert!(error.to_string().contains("calibre.url"));
+}
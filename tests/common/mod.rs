@@ -0,0 +1,208 @@
+#![allow(dead_code)]
+
+use std::{fs::File, io::Write, path::Path};
+
+use bookrat::{app::App, bookmark::Bookmarks};
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{backend::TestBackend, Terminal};
+use tempfile::TempDir;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+/// Writes a minimal EPUB 2 file. A title page is always placed at spine
+/// index 0, followed by one spine item per entry in `chapters`.
+pub fn write_epub(path: &Path, title: &str, chapters: &[&str]) {
+    let file = File::create(path).expect("Failed to create epub fixture");
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    zip.start_file("mimetype", options).unwrap();
+    zip.write_all(b"application/epub+zip").unwrap();
+
+    zip.start_file("META-INF/container.xml", options).unwrap();
+    zip.write_all(
+        br#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#,
+    )
+    .unwrap();
+
+    let mut manifest = String::from(
+        r#"<item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+    <item id="title" href="title.xhtml" media-type="application/xhtml+xml"/>"#,
+    );
+    let mut spine = String::from(r#"<itemref idref="title"/>"#);
+    let mut nav_points = String::new();
+    for i in 0..chapters.len() {
+        manifest.push_str(&format!(
+            "\n    <item id=\"ch{i}\" href=\"ch{i}.xhtml\" media-type=\"application/xhtml+xml\"/>"
+        ));
+        spine.push_str(&format!("\n    <itemref idref=\"ch{i}\"/>"));
+        nav_points.push_str(&format!(
+            "<navPoint id=\"np{i}\" playOrder=\"{}\"><navLabel><text>Chapter {}</text></navLabel><content src=\"ch{i}.xhtml\"/></navPoint>",
+            i + 1,
+            i + 1
+        ));
+    }
+
+    zip.start_file("OEBPS/content.opf", options).unwrap();
+    zip.write_all(
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="2.0" unique-identifier="bookid">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title>{title}</dc:title>
+    <dc:creator>Ann</dc:creator>
+    <dc:language>en</dc:language>
+    <dc:identifier id="bookid">urn:test:{title}</dc:identifier>
+  </metadata>
+  <manifest>
+    {manifest}
+  </manifest>
+  <spine toc="ncx">
+    {spine}
+  </spine>
+</package>"#
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+
+    zip.start_file("OEBPS/toc.ncx", options).unwrap();
+    zip.write_all(
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+  <head><meta name="dtb:uid" content="urn:test:{title}"/></head>
+  <docTitle><text>{title}</text></docTitle>
+  <navMap>{nav_points}</navMap>
+</ncx>"#
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+
+    zip.start_file("OEBPS/title.xhtml", options).unwrap();
+    zip.write_all(xhtml(&format!("<h1>{title}</h1>")).as_bytes()).unwrap();
+
+    for (i, body) in chapters.iter().enumerate() {
+        zip.start_file(format!("OEBPS/ch{i}.xhtml"), options).unwrap();
+        zip.write_all(xhtml(body).as_bytes()).unwrap();
+    }
+
+    zip.finish().unwrap();
+}
+
+/// Rewrites the zip at `path` without the entry `name`, to simulate a
+/// damaged book.
+pub fn remove_zip_entry(path: &Path, name: &str) {
+    let bytes = std::fs::read(path).unwrap();
+    let mut source = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+    let mut zip = ZipWriter::new(File::create(path).unwrap());
+    for i in 0..source.len() {
+        let entry = source.by_index_raw(i).unwrap();
+        if entry.name() != name {
+            zip.raw_copy_file(entry).unwrap();
+        }
+    }
+    zip.finish().unwrap();
+}
+
+/// Contents of the text entry `name` of the zip at `path`.
+pub fn read_zip_entry(path: &Path, name: &str) -> String {
+    let mut archive = zip::ZipArchive::new(File::open(path).unwrap()).unwrap();
+    let mut content = String::new();
+    std::io::Read::read_to_string(&mut archive.by_name(name).unwrap(), &mut content).unwrap();
+    content
+}
+
+/// Appends a stored entry to the zip at `path`.
+pub fn add_zip_entry(path: &Path, name: &str, content: impl AsRef<[u8]>) {
+    let file = std::fs::OpenOptions::new().read(true).write(true).open(path).unwrap();
+    let mut zip = ZipWriter::new_append(file).unwrap();
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    zip.start_file(name, options).unwrap();
+    zip.write_all(content.as_ref()).unwrap();
+    zip.finish().unwrap();
+}
+
+fn xhtml(body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml"><head></head><body>{body}</body></html>"#
+    )
+}
+
+/// A temporary library directory with a bookmarks file and a test terminal.
+pub struct Harness {
+    pub dir: TempDir,
+    pub app: App,
+    pub terminal: Terminal<TestBackend>,
+}
+
+impl Harness {
+    /// Creates a library containing the given `(file name, chapters)` books.
+    pub fn new(books: &[(&str, &[&str])]) -> Self {
+        let dir = TempDir::new().unwrap();
+        let books_dir = dir.path().join("books");
+        std::fs::create_dir(&books_dir).unwrap();
+        for (name, chapters) in books {
+            let stem = name.trim_end_matches(".epub");
+            write_epub(&books_dir.join(name), stem, chapters);
+        }
+        let app = Self::open_app(&dir);
+        let terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        Self { dir, app, terminal }
+    }
+
+    /// Starts a fresh `App` against the same library and bookmarks file,
+    /// as if bookrat had been restarted.
+    pub fn restart(&mut self) {
+        self.app.flush();
+        self.app = Self::open_app(&self.dir);
+    }
+
+    fn open_app(dir: &TempDir) -> App {
+        let bookmarks = Bookmarks::load_from(dir.path().join("bookmarks.json")).unwrap();
+        let books_dir = dir.path().join("books");
+        App::with_books_dir(books_dir.to_str().unwrap(), bookmarks).unwrap()
+    }
+
+    /// Sends each character of `keys` as a key press. Returns `true` if
+    /// any of them asked the app to quit.
+    pub fn press(&mut self, keys: &str) -> bool {
+        let mut quit = false;
+        for c in keys.chars() {
+            quit |= self.app.handle_key(KeyEvent::from(KeyCode::Char(c)));
+        }
+        quit
+    }
+
+    pub fn press_code(&mut self, code: KeyCode) -> bool {
+        self.app.handle_key(KeyEvent::from(code))
+    }
+
+    pub fn resize(&mut self, width: u16, height: u16) {
+        self.terminal.backend_mut().resize(width, height);
+        self.terminal.resize(ratatui::layout::Rect::new(0, 0, width, height)).unwrap();
+    }
+
+    /// Draws a frame and returns the screen as one string per row.
+    pub fn render(&mut self) -> Vec<String> {
+        let app = &mut self.app;
+        self.terminal.draw(|f| app.draw(f)).unwrap();
+        let buffer = self.terminal.backend().buffer();
+        let width = buffer.area.width as usize;
+        buffer
+            .content()
+            .chunks(width)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+            .collect()
+    }
+
+    pub fn screen(&mut self) -> String {
+        self.render().join("\n")
+    }
+}
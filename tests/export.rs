@@ -0,0 +1,61 @@
+mod common;
+
+use bookrat::bookmark::Bookmarks;
+use bookrat::catalog::{format_catalog, CatalogFormat, Record};
+use bookrat::export::{export_book, ExportFormat};
+use bookrat::reading_time::ReadingTime;
+
+#[test]
+fn export_markdown_with_part_range() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("book.epub");
+    common::write_epub(
+        &path,
+        "book",
+        &[
+            "<p>One <em>first</em>.</p><p>One second.</p>",
+            "<p>Two <strong>only</strong>.</p>",
+            "<p>Three.</p>",
+        ],
+    );
+
+    let md = export_book(&path, ExportFormat::Markdown, Some(2..=3)).unwrap();
+    assert_eq!(
+        md,
+        "## Chapter 1\n\nOne _first_.\n\nOne second.\n\n## Chapter 2\n\nTwo **only**.\n"
+    );
+
+    let txt = export_book(&path, ExportFormat::Text, None).unwrap();
+    assert!(txt.starts_with("book\n"));
+    assert!(txt.contains("Chapter 3\n=========\n\nThree."));
+    assert!(txt.contains("Two only."));
+
+    assert!(export_book(&path, ExportFormat::Text, Some(9..=9)).is_err());
+}
+
+#[test]
+fn export_library_catalog() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let read = dir.path().join("read.epub").to_string_lossy().to_string();
+    let unread = dir.path().join("unread.epub").to_string_lossy().to_string();
+    common::write_epub(read.as_ref(), "Read book", &["<p>First.</p>", "<p>Second.</p>"]);
+    common::write_epub(unread.as_ref(), "Unread book", &["<p>Only.</p>"]);
+
+    let mut bookmarks = Bookmarks::with_path(dir.path().join("bookmarks.json"));
+    bookmarks.update_bookmark(&read, 2, 0);
+    let mut time = ReadingTime::with_path(dir.path().join("reading_time.json"));
+    time.add(chrono::Local::now().date_naive(), Some(&read), std::time::Duration::from_secs(150));
+
+    let records: Vec<Record> = [&read, &unread].iter().map(|file| Record::read(file, &bookmarks, &time)).collect();
+    assert_eq!(records[0].title, "Read book");
+    assert!(records[0].progress > 0 && records[0].progress < 100);
+    assert_eq!(records[0].minutes_read, 2);
+    assert!(records[0].last_read.is_some());
+    assert_eq!((records[1].progress, records[1].minutes_read, records[1].last_read), (0, 0, None));
+
+    let csv = format_catalog(&records, CatalogFormat::Csv).unwrap();
+    assert_eq!(csv.lines().count(), 3);
+    assert!(csv.lines().nth(2).unwrap().contains(",Unread book,"));
+    let json: serde_json::Value = serde_json::from_str(&format_catalog(&records, CatalogFormat::Json).unwrap()).unwrap();
+    assert_eq!(json[1]["title"], "Unread book");
+}
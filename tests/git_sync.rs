@@ -0,0 +1,96 @@
+use std::path::Path;
+use std::process::Command;
+
+use bookrat::{
+    bookmark::Bookmarks,
+    git_sync::{GitSync, MARKER},
+};
+use chrono::{TimeZone, Utc};
+
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output().unwrap();
+    assert!(output.status.success(), "git {:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// A clone of `remote` in `dir`, as on one of the reader's machines,
+/// marked as holding reading state.
+fn machine(remote: &Path, dir: &Path) -> GitSync {
+    git(remote.parent().unwrap(), &["clone", "-q", remote.to_str().unwrap(), dir.to_str().unwrap()]);
+    std::fs::write(dir.join(MARKER), "").unwrap();
+    git(dir, &["config", "user.name", "Reader"]);
+    git(dir, &["config", "user.email", "reader@example.com"]);
+    GitSync::new(dir, "origin").unwrap()
+}
+
+#[test]
+fn reading_state_merges_across_machines() {
+    let root = tempfile::TempDir::new().unwrap();
+    let remote = root.path().join("state.git");
+    git(root.path(), &["init", "-q", "--bare", remote.to_str().unwrap()]);
+    let (laptop_dir, desktop_dir) = (root.path().join("laptop"), root.path().join("desktop"));
+    let laptop = machine(&remote, &laptop_dir);
+    let desktop = machine(&remote, &desktop_dir);
+    let day = |d| Utc.with_ymd_and_hms(2026, 3, d, 20, 0, 0).unwrap();
+
+    // Nothing to pull from an empty remote yet
+    laptop.pull().unwrap();
+    let mut bookmarks = Bookmarks::with_path(laptop_dir.join("bookmarks.json"));
+    bookmarks.set_position("war.epub", 3, 10, day(1));
+    bookmarks.set_slot("war.epub", 1, 2, 0, None);
+    bookmarks.save().unwrap();
+    laptop.push().unwrap();
+
+    // Read further on the desktop, which never pulled the laptop's file
+    let mut bookmarks = Bookmarks::with_path(desktop_dir.join("bookmarks.json"));
+    bookmarks.set_position("war.epub", 5, 0, day(2));
+    bookmarks.set_position("anna.epub", 1, 4, day(2));
+    bookmarks.save().unwrap();
+    desktop.pull().unwrap();
+    let merged = Bookmarks::load_from(desktop_dir.join("bookmarks.json")).unwrap();
+    let war = merged.get_bookmark("war.epub").unwrap();
+    assert_eq!((war.chapter, war.scroll_offset), (5, 0), "the later position wins");
+    assert!(merged.slot("war.epub", 1).is_some(), "the laptop's slot is kept");
+    assert!(merged.get_bookmark("anna.epub").is_some());
+    desktop.push().unwrap();
+
+    laptop.pull().unwrap();
+    let synced = Bookmarks::load_from(laptop_dir.join("bookmarks.json")).unwrap();
+    assert_eq!(synced.get_bookmark("war.epub").unwrap().chapter, 5);
+    assert_eq!(synced.get_bookmark("anna.epub").unwrap().scroll_offset, 4);
+    assert!(git(&laptop_dir, &["status", "--porcelain"]).is_empty());
+    assert!(git(&laptop_dir, &["log", "--oneline"]).lines().count() >= 3);
+}
+
+#[test]
+fn sync_gets_a_repository_of_its_own() {
+    let root = tempfile::TempDir::new().unwrap();
+    let state = root.path().join("state");
+    std::fs::create_dir(&state).unwrap();
+    let sync = GitSync::new(&state, "origin").unwrap();
+    git(&state, &["config", "user.name", "Reader"]);
+    git(&state, &["config", "user.email", "reader@example.com"]);
+    std::fs::write(state.join("bookmarks.json"), r#"{"books": {}}"#).unwrap();
+    sync.push().unwrap();
+    assert_eq!(git(&state, &["rev-parse", "--show-toplevel"]).trim(), state.canonicalize().unwrap().to_str().unwrap());
+    assert_eq!(git(&state, &["ls-files"]).lines().collect::<Vec<_>>(), [MARKER, "bookmarks.json"]);
+    // Made once, the repository is taken up again
+    assert!(GitSync::new(&state, "origin").is_ok());
+}
+
+#[test]
+fn sync_keeps_out_of_other_repositories() {
+    let root = tempfile::TempDir::new().unwrap();
+    let project = root.path().join("project");
+    std::fs::create_dir_all(project.join("docs")).unwrap();
+    git(&project, &["init", "-q"]);
+    std::fs::write(project.join("bookmarks.json"), r#"{"books": {}}"#).unwrap();
+
+    // Neither the top of the project nor a folder in it
+    let error = GitSync::new(&project, "origin").err().unwrap();
+    assert!(error.to_string().contains(MARKER), "{}", error);
+    assert!(GitSync::new(&project.join("docs"), "origin").is_err());
+    assert!(!project.join("docs/.git").exists());
+    assert!(!project.join(MARKER).exists());
+    assert!(git(&project, &["ls-files"]).is_empty(), "nothing committed to the project");
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use bookrat::{bookmark::Bookmarks, kobo};
+use chrono::{TimeZone, Utc};
+use rusqlite::{params, Connection};
+
+/// A mounted Kobo holding sideloaded copies of war.epub (read the day
+/// before the library's copy), anna.kepub.epub (read on the Kobo only) and
+/// a book the library doesn't have.
+fn fake_kobo(mount: &std::path::Path) -> std::path::PathBuf {
+    std::fs::create_dir_all(mount.join(".kobo")).unwrap();
+    let database = mount.join(".kobo/KoboReader.sqlite");
+    let connection = Connection::open(&database).unwrap();
+    connection
+        .execute_batch(
+            "CREATE TABLE content (ContentID TEXT PRIMARY KEY, ContentType TEXT, Title TEXT, Attribution TEXT,
+                ChapterIDBookmarked TEXT, DateLastRead TEXT, ReadStatus INT DEFAULT 0, ___PercentRead INT DEFAULT 0);
+             CREATE TABLE Bookmark (BookmarkID TEXT PRIMARY KEY, VolumeID TEXT, ContentID TEXT, Text TEXT,
+                Annotation TEXT, DateCreated TEXT);",
+        )
+        .unwrap();
+    let books = [
+        ("file:///mnt/onboard/war.epub", "War", "OEBPS/ch0.xhtml", "2026-03-01T10:00:00Z"),
+        ("file:///mnt/onboard/anna.kepub.epub", "Anna", "OEBPS/ch1.xhtml#kobo.3.1", "2026-03-05T10:00:00.000"),
+        ("file:///mnt/onboard/other.epub", "Somewhere Else", "", ""),
+    ];
+    for (id, title, chapter, date) in books {
+        connection
+            .execute(
+                "INSERT INTO content (ContentID, ContentType, Title, ChapterIDBookmarked, DateLastRead) VALUES (?1, 6, ?2, ?3, ?4)",
+                params![id, title, chapter, date],
+            )
+            .unwrap();
+    }
+    connection
+        .execute(
+            "INSERT INTO Bookmark VALUES ('b1', 'file:///mnt/onboard/anna.kepub.epub',
+                '/mnt/onboard/anna.kepub.epub!OEBPS!ch1.xhtml', ' Happy families ', 'All alike', '2026-03-05T09:00:00.000')",
+            [],
+        )
+        .unwrap();
+    database
+}
+
+#[test]
+fn kobo_positions_and_highlights_are_imported_and_written_back() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let books = dir.path().join("books");
+    std::fs::create_dir(&books).unwrap();
+    let war = books.join("war.epub").to_string_lossy().to_string();
+    let anna = books.join("anna.epub").to_string_lossy().to_string();
+    common::write_epub(books.join("war.epub").as_ref(), "War", &["<p>One</p>", "<p>Two</p>", "<p>Three</p>"]);
+    common::write_epub(books.join("anna.epub").as_ref(), "Anna", &["<p>One</p>", "<p>Two</p>"]);
+    let library = vec![war.clone(), anna.clone()];
+
+    let mut bookmarks = Bookmarks::with_path(dir.path().join("bookmarks.json"));
+    let ours = Utc.with_ymd_and_hms(2026, 3, 2, 10, 0, 0).unwrap();
+    bookmarks.set_position(&war, 3, 40, ours);
+
+    let database = fake_kobo(&dir.path().join("KOBOeReader"));
+    let report = kobo::sync(&dir.path().join("KOBOeReader"), &library, &mut bookmarks, true).unwrap();
+    assert_eq!(report.positions, 1);
+    assert_eq!(report.highlights.len(), 1);
+    assert_eq!(report.highlights[0].0, anna);
+    assert_eq!(report.written_back, 1);
+    assert_eq!(report.unmatched, vec!["Somewhere Else".to_string()]);
+
+    // Read last on the Kobo: the title page is 0, so ch1.xhtml is part 2
+    let imported = bookmarks.get_bookmark(&anna).unwrap();
+    assert_eq!((imported.chapter, imported.scroll_offset), (2, 0));
+    let highlights = bookmarks.highlights(&anna);
+    assert_eq!(highlights.len(), 1);
+    assert_eq!((highlights[0].chapter, highlights[0].text.as_str()), (2, "Happy families"));
+    assert_eq!(highlights[0].note.as_deref(), Some("All alike"));
+
+    // Read last here: kept, and the Kobo moves on to it
+    let kept = bookmarks.get_bookmark(&war).unwrap();
+    assert_eq!((kept.chapter, kept.scroll_offset), (3, 40));
+    let connection = Connection::open(&database).unwrap();
+    let (chapter, date): (String, String) = connection
+        .query_row(
+            "SELECT ChapterIDBookmarked, DateLastRead FROM content WHERE ContentID = 'file:///mnt/onboard/war.epub'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!((chapter.as_str(), date.as_str()), ("OEBPS/ch2.xhtml", "2026-03-02T10:00:00Z"));
+
+    // Nothing new the second time round
+    let again = kobo::sync(&dir.path().join("KOBOeReader"), &library, &mut bookmarks, false).unwrap();
+    assert_eq!((again.positions, again.highlights.len(), again.written_back), (0, 0, 0));
+    assert!(Bookmarks::load_from(dir.path().join("bookmarks.json")).unwrap().get_bookmark(&anna).is_some());
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use bookrat::app::book_progress;
+use bookrat::book;
+use bookrat::bookmark::Bookmarks;
+use bookrat::library_index::{self, LibraryIndex};
+
+#[test]
+fn migrate_imports_positions_unless_dry_run() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("book.epub").to_string_lossy().to_string();
+    let long = format!("<p>{}</p>", "word ".repeat(200));
+    common::write_epub(path.as_ref(), "book", &[&long, &long]);
+    let gone = dir.path().join("gone.epub").to_string_lossy().to_string();
+    let mut bookmarks = Bookmarks::with_path(dir.path().join("bookmarks.json"));
+    let read_at = chrono::Utc::now();
+    bookmarks.set_position(&path, 1, 2, read_at);
+    bookmarks.set_position(&gone, 0, 0, read_at - chrono::Duration::hours(1));
+    let files = vec![path.clone()];
+    let db = LibraryIndex::path_beside(bookmarks.path());
+
+    // A dry run reports the same positions, but writes nothing
+    let imported = library_index::migrate(&files, &bookmarks, true).unwrap();
+    let listed: Vec<(&str, Option<u32>)> = imported.iter().map(|imported| (imported.file, imported.progress)).collect();
+    assert_eq!(listed.len(), 2);
+    assert_eq!(listed[1], (gone.as_str(), None), "not in the library");
+    let progress = listed[0].1.unwrap();
+    assert!(!db.exists());
+
+    // The scroll offset counts: the position is into its part, not at its start
+    let sizes = book::part_sizes_of(&path);
+    assert!(book_progress(&sizes, 1, 0) < progress && progress < book_progress(&sizes, 2, 0), "{}", progress);
+
+    let imported = library_index::migrate(&files, &bookmarks, false).unwrap();
+    assert_eq!(imported[0].progress, Some(progress));
+    let index = LibraryIndex::beside(bookmarks.path()).unwrap();
+    let (stored, last_read) = index.reading(&path).unwrap();
+    assert_eq!((stored, last_read.timestamp()), (Some(progress), read_at.timestamp()));
+    assert_eq!(index.reading(&gone), None);
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::path::Path;
+
+use bookrat::{
+    library::ScanOptions,
+    opds::{book_id, OpdsServer},
+};
+
+fn get(addr: std::net::SocketAddr, path: &str) -> Vec<u8> {
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, addr).unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    response
+}
+
+fn body(response: &[u8]) -> &[u8] {
+    let at = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+    &response[at + 4..]
+}
+
+#[test]
+fn opds_feed_lists_books_with_covers_and_downloads() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let books = dir.path().join("books");
+    std::fs::create_dir(&books).unwrap();
+    let alpha = books.join("alpha.epub");
+    common::write_epub(&alpha, "alpha", &["<p>One.</p>"]);
+    common::write_epub(&books.join("beta.epub"), "Beta &amp; Co", &["<p>Two.</p>"]);
+
+    // Give alpha an EPUB 2 cover
+    let opf = common::read_zip_entry(&alpha, "OEBPS/content.opf")
+        .replace("</metadata>", "<meta name=\"cover\" content=\"cover\"/></metadata>")
+        .replace("<manifest>", "<manifest><item id=\"cover\" href=\"cover.png\" media-type=\"image/png\"/>");
+    common::remove_zip_entry(&alpha, "OEBPS/content.opf");
+    common::add_zip_entry(&alpha, "OEBPS/content.opf", &opf);
+    common::add_zip_entry(&alpha, "OEBPS/cover.png", "PNGDATA");
+
+    let server = OpdsServer::bind("127.0.0.1:0", &[books.to_string_lossy().to_string()], ScanOptions::default()).unwrap();
+    let addr = server.local_addr().unwrap();
+    std::thread::spawn(move || server.run());
+
+    let response = get(addr, "/opds");
+    let feed = String::from_utf8_lossy(&response);
+    assert!(feed.starts_with("HTTP/1.1 200"));
+    assert!(feed.contains("profile=opds-catalog;kind=acquisition"));
+    assert!(feed.contains("<title>alpha</title>"));
+    assert!(feed.contains("<author><name>Ann</name></author>"));
+    assert!(feed.contains("<dc:language>en</dc:language>"));
+    let (alpha_id, beta_id) = (book_id(Path::new("alpha.epub")), book_id(Path::new("beta.epub")));
+    assert!(feed.contains(&format!("href=\"http://{}/book/{}.epub\" type=\"application/epub+zip\"", addr, alpha_id)));
+    assert!(feed.contains(&format!("<link rel=\"http://opds-spec.org/image\" href=\"http://{}/cover/{}\"/>", addr, alpha_id)));
+    assert!(feed.contains("<title>Beta &amp; Co</title>"));
+    assert!(!feed.contains(&format!("/cover/{}", beta_id)), "beta has no cover");
+
+    // A book sorted in ahead of them doesn't move their links
+    common::write_epub(&books.join("aardvark.epub"), "aardvark", &["<p>Three.</p>"]);
+    assert_eq!(body(&get(addr, &format!("/book/{}.epub", alpha_id))), std::fs::read(&alpha).unwrap());
+    let cover = get(addr, &format!("/cover/{}", alpha_id));
+    assert!(String::from_utf8_lossy(&cover).contains("Content-Type: image/png"));
+    assert_eq!(body(&cover), b"PNGDATA");
+    assert!(get(addr, &format!("/cover/{}", beta_id)).starts_with(b"HTTP/1.1 404"));
+    assert!(get(addr, "/book/0.epub").starts_with(b"HTTP/1.1 404"));
+}
+
+#[test]
+fn opds_links_ignore_a_forged_host() {
+    let dir = tempfile::TempDir::new().unwrap();
+    common::write_epub(&dir.path().join("alpha.epub"), "alpha", &["<p>One.</p>"]);
+    let server = OpdsServer::bind("127.0.0.1:0", &[dir.path().to_string_lossy().to_string()], ScanOptions::default()).unwrap();
+    let addr = server.local_addr().unwrap();
+    std::thread::spawn(move || server.run());
+
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    write!(stream, "GET /opds HTTP/1.1\r\nHost: x\"/><evil/>\r\nConnection: close\r\n\r\n").unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    let feed = String::from_utf8_lossy(body(&response)).to_string();
+    assert!(!feed.contains("<evil/>"));
+    assert!(feed.contains(&format!("href=\"/book/{}.epub\"", book_id(Path::new("alpha.epub")))));
+}
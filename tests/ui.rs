@@ -0,0 +1,2170 @@
+mod common;
+
+use bookrat::{app::Mode, big_print::GLYPH_HEIGHT, book::Document, bookmark::{Bookmarks, Highlight}, images::{self, Protocol}};
+use ratatui::style::{Color, Modifier};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use common::Harness;
+
+const ALPHA: &[&str] = &[
+    "<p>Alpha chapter one text.</p>",
+    "<p>Alpha chapter two text.</p>",
+    "<p>Alpha chapter three text.</p>",
+];
+const BETA: &[&str] = &["<p>Beta only chapter.</p>"];
+
+#[test]
+fn file_list_shows_books_sorted_by_name() {
+    let mut h = Harness::new(&[("beta.epub", BETA), ("alpha.epub", ALPHA)]);
+    let rows = h.render();
+
+    let alpha_row = rows.iter().position(|r| r.contains("alpha — Ann (Never)")).unwrap();
+    let beta_row = rows.iter().position(|r| r.contains("beta — Ann (Never)")).unwrap();
+    assert!(alpha_row < beta_row);
+    assert!(rows[0].contains("Books"));
+    assert!(rows[rows.len() - 2].contains("Enter: Select"));
+}
+
+#[test]
+fn sort_order_is_cycled_and_kept_across_sessions() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    // Rows of the list itself, below beta pinned under "Continue reading"
+    let row_of = |rows: &[String], text: &str| rows.iter().position(|r| r.contains(text)).unwrap();
+    h.press("j");
+    h.press_code(KeyCode::Enter);
+    h.press("jj");
+    h.press_code(KeyCode::Tab);
+
+    h.press("s");
+    let screen = h.screen();
+    assert!(screen.contains("Books by author") && screen.contains("Sorted by author"), "{}", screen);
+    h.press("s");
+    let rows = h.render();
+    assert!(rows[row_of(&rows, "Books by")].contains("Books by last read"));
+    assert!(row_of(&rows, "beta — Ann (") < row_of(&rows, "alpha — Ann ("), "{}", rows.join("\n"));
+
+    h.restart();
+    let rows = h.render();
+    assert!(rows[row_of(&rows, "Books by")].contains("Books by last read"));
+    assert!(row_of(&rows, "beta — Ann (") < row_of(&rows, "alpha — Ann ("));
+    h.press("sss");
+    let rows = h.render();
+    assert!(rows[0].contains("Continue reading") && rows[3].contains("┌Books─"), "{}", rows.join("\n"));
+    assert!(row_of(&rows, "alpha — Ann (") < row_of(&rows, "beta — Ann ("));
+}
+
+#[test]
+fn library_is_refreshed_and_shows_hidden_books_on_request() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    let books = h.dir.path().join("books");
+    common::write_epub(&books.join("gamma.epub"), "gamma", BETA);
+    common::write_epub(&books.join(".secret.epub"), "secret", BETA);
+    assert!(!h.screen().contains("gamma"));
+
+    h.press_code(KeyCode::F(5));
+    let screen = h.screen();
+    assert!(screen.contains("gamma — Ann (Never)"), "{}", screen);
+    assert!(screen.contains("Library refreshed: 2 books"));
+    assert!(!screen.contains("secret"));
+
+    h.press(".");
+    let screen = h.screen();
+    assert!(screen.contains("secret — Ann (Never)"), "{}", screen);
+    assert!(screen.contains("Hidden files shown"));
+    h.press(".");
+    assert!(!h.screen().contains("secret — Ann (Never)"));
+}
+
+#[test]
+fn books_are_listed_by_title_and_author() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    let books = h.dir.path().join("books");
+    common::write_epub(&books.join("tolstoy-1869.epub"), "War and Peace", BETA);
+    h.press_code(KeyCode::F(5));
+    let screen = h.screen();
+    assert!(screen.contains("War and Peace — Ann"), "{}", screen);
+    assert!(!screen.contains("tolstoy-1869"));
+    assert!(h.dir.path().join("library.db").exists());
+
+    // Titles are kept, so the books needn't be read again after a restart
+    h.restart();
+    assert!(h.screen().contains("War and Peace — Ann"));
+}
+
+#[test]
+fn books_in_subfolders_are_listed_and_opened() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    let series = h.dir.path().join("books").join("Le Guin").join("Earthsea");
+    std::fs::create_dir_all(&series).unwrap();
+    common::write_epub(&series.join("wizard.epub"), "wizard", ALPHA);
+    h.press_code(KeyCode::F(5));
+    let screen = h.screen();
+    assert!(screen.contains("wizard — Ann (Never)"), "{}", screen);
+
+    h.press("j");
+    h.press_code(KeyCode::Enter);
+    assert_eq!(h.app.mode(), &Mode::Content);
+    assert!(h.screen().contains("Alpha chapter one text."));
+}
+
+/// Ticks until the screen shows `text`, or not when `shown` is false, as
+/// the library watcher takes a moment to report a change.
+fn wait_for_screen(h: &mut Harness, text: &str, shown: bool) {
+    for _ in 0..300 {
+        h.app.tick();
+        if h.screen().contains(text) == shown {
+            return;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    panic!("{:?} never {}:\n{}", text, if shown { "appeared" } else { "went away" }, h.screen());
+}
+
+#[test]
+fn books_dropped_into_the_library_are_picked_up_while_running() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    h.app.watch_library(|| {});
+    let books = h.dir.path().join("books");
+    let folder = books.join("Later");
+    std::fs::create_dir(&folder).unwrap();
+    common::write_epub(&folder.join("beta.epub"), "beta", BETA);
+    wait_for_screen(&mut h, "beta — Ann (Never)", true);
+    assert!(h.screen().contains("Library: 1 book added"));
+
+    std::fs::remove_file(books.join("alpha.epub")).unwrap();
+    wait_for_screen(&mut h, "alpha — Ann", false);
+    assert!(h.screen().contains("Library: 1 book removed"));
+}
+
+#[test]
+fn a_book_given_on_the_command_line_opens_as_the_library_lists_it() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    let listed = h.dir.path().join("books").join("beta.epub");
+    let other_name = h.dir.path().join("books").join("..").join("books").join("beta.epub");
+    h.app.open_file(other_name.to_str().unwrap());
+
+    assert_eq!(h.app.mode(), &Mode::Content);
+    assert_eq!(h.app.remote_status().book.as_deref(), listed.to_str());
+    assert!(h.screen().contains("Beta only chapter."));
+    // Its bookmark is kept under the library's name for it
+    h.press("j");
+    h.press_code(KeyCode::Esc);
+    h.resize(120, 24);
+    assert!(h.screen().contains("beta — Ann (100%, just now)"), "{}", h.screen());
+}
+
+#[test]
+fn enter_opens_first_chapter_after_title_page() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    h.press_code(KeyCode::Enter);
+
+    assert_eq!(h.app.mode(), &Mode::Content);
+    let screen = h.screen();
+    assert!(screen.contains("Alpha chapter one text."));
+    assert!(screen.contains("Part 2/4"));
+    assert!(screen.contains("h/l: Change Part"));
+}
+
+#[test]
+fn chapter_navigation_moves_between_parts() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    h.press_code(KeyCode::Enter);
+
+    h.press("l");
+    let screen = h.screen();
+    assert!(screen.contains("Alpha chapter two text."));
+    assert!(screen.contains("Part 3/4"));
+
+    h.press("ll");
+    assert!(h.screen().contains("Part 4/4"), "next_chapter must stop at the last part");
+
+    h.press("h");
+    assert!(h.screen().contains("Alpha chapter two text."));
+    assert!(h.screen().contains("Part 3/4"));
+}
+
+#[test]
+fn selection_moves_with_j_and_k() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    h.press("j");
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("Beta only chapter."));
+
+    h.press_code(KeyCode::Tab);
+    h.press("k");
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("Alpha chapter one text."));
+}
+
+#[test]
+fn bookmark_restores_chapter_after_restart() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    h.press_code(KeyCode::Enter);
+    h.press("l");
+    h.render();
+
+    h.restart();
+    let screen = h.screen();
+    assert!(!screen.contains("alpha — Ann (Never)"), "last read time should be shown");
+
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("Alpha chapter two text."));
+}
+
+#[test]
+fn tab_toggles_views_and_q_quits() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    h.press_code(KeyCode::Tab);
+    assert_eq!(h.app.mode(), &Mode::Content);
+    h.press_code(KeyCode::Tab);
+    assert_eq!(h.app.mode(), &Mode::FileList);
+
+    assert!(!h.press("j"));
+    assert!(h.press("q"));
+}
+
+#[test]
+fn debug_mode_shows_raw_html() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    h.press_code(KeyCode::Enter);
+    h.press("d");
+
+    let screen = h.screen();
+    assert!(screen.contains("[DEBUG MODE]"));
+    assert!(screen.contains("<p>Alpha chapter one"));
+    assert!(screen.contains("text.</p>"));
+
+    // Tag names are highlighted
+    let rows = h.render();
+    let (y, row) = rows.iter().enumerate().find(|(_, r)| r.contains("<p>Alpha")).unwrap();
+    let x = row.chars().position(|c| c == '<').unwrap() + 1;
+    let cell = h.terminal.backend().buffer().get(x as u16, y as u16).clone();
+    assert_eq!(cell.symbol(), "p");
+    assert_eq!(cell.fg, ratatui::style::Color::Cyan);
+}
+
+#[test]
+fn plugin_binding_and_hooks_drive_navigation() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    let plugin_dir = h.dir.path().join("plugins");
+    std::fs::create_dir(&plugin_dir).unwrap();
+    std::fs::write(
+        plugin_dir.join("jump.rhai"),
+        r#"
+        bind_key("g", "to_last");
+        fn to_last() { go_to_chapter(4); }
+        fn on_book_opened(path) { next_chapter(); }
+        "#,
+    )
+    .unwrap();
+    h.app.set_plugins(bookrat::plugins::Plugins::load_dir(&plugin_dir));
+
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("Alpha chapter two text."));
+
+    h.press("g");
+    assert!(h.screen().contains("Part 4/4"));
+}
+
+#[test]
+fn remote_commands_move_the_reader() {
+    use bookrat::remote::RemoteCommand;
+
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    let status = h.app.apply_remote_command(RemoteCommand::Status);
+    assert_eq!(status.book, None);
+    assert_eq!(status.chapter, 0);
+
+    h.press_code(KeyCode::Enter);
+    let status = h.app.apply_remote_command(RemoteCommand::NextChapter);
+    assert_eq!((status.chapter, status.total_chapters), (3, 4));
+    assert!(h.screen().contains("Alpha chapter two text."));
+
+    let status = h.app.apply_remote_command(RemoteCommand::Jump { chapter: 4, offset: Some(0) });
+    assert_eq!(status.chapter, 4);
+    assert!(h.screen().contains("Alpha chapter three text."));
+}
+
+#[test]
+fn perf_overlay_toggles() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    h.press_code(KeyCode::Enter);
+    assert!(!h.screen().contains("Perf"));
+
+    h.press("P");
+    let screen = h.screen();
+    assert!(screen.contains("Perf"));
+    assert!(screen.contains("Wrap:"));
+    assert!(screen.contains("Memory:"));
+
+    h.press("P");
+    assert!(!h.screen().contains("Perf"));
+}
+
+#[test]
+fn broken_book_shows_dismissible_error() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    std::fs::write(h.dir.path().join("books").join("broken.epub"), b"not a zip").unwrap();
+    h.restart();
+
+    h.press("j");
+    h.press_code(KeyCode::Enter);
+    let screen = h.screen();
+    assert!(screen.contains("Could not open book"));
+    assert!(screen.contains("Press any key to dismiss"));
+    assert_eq!(h.app.mode(), &Mode::FileList);
+
+    assert!(!h.press("j"));
+    assert!(h.app.error_popup().is_none());
+    assert!(!h.screen().contains("Could not open book"));
+}
+
+#[test]
+fn empty_part_shows_error() {
+    let mut h = Harness::new(&[("images.epub", &["<p><img src=\"a.png\"/></p>", "<p>Text.</p>"])]);
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("Empty part"));
+
+    h.press_code(KeyCode::Esc);
+    h.press("l");
+    assert!(h.app.error_popup().is_none());
+    assert!(h.screen().contains("Text."));
+}
+
+#[test]
+fn missing_spine_file_shows_diagnostics() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    common::remove_zip_entry(&h.dir.path().join("books").join("alpha.epub"), "OEBPS/ch1.xhtml");
+
+    h.press_code(KeyCode::Enter);
+    let screen = h.screen();
+    assert!(screen.contains("Damaged EPUB"));
+    assert!(screen.contains("Part 3: OEBPS/ch1.xhtml is missing"));
+    assert!(screen.contains("Readable parts: 1-2, 4 of 4"));
+
+    h.press_code(KeyCode::Esc);
+    assert!(h.screen().contains("Alpha chapter one text."));
+}
+
+#[test]
+fn missing_opf_salvages_content_documents() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    common::remove_zip_entry(&h.dir.path().join("books").join("alpha.epub"), "OEBPS/content.opf");
+
+    h.press_code(KeyCode::Enter);
+    let screen = h.screen();
+    assert!(screen.contains("Damaged EPUB"));
+    assert!(screen.contains("package document (OPF)"));
+    assert_eq!(h.app.mode(), &Mode::Content);
+
+    // Salvaged parts are ch0, ch1, ch2 and title in file name order, and
+    // the first of them is shown
+    h.press_code(KeyCode::Esc);
+    let screen = h.screen();
+    assert!(screen.contains("Part 1/4"));
+    assert!(screen.contains("Alpha chapter one text."));
+}
+
+#[test]
+fn drm_protected_book_is_reported() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    let books = h.dir.path().join("books");
+    common::add_zip_entry(
+        &books.join("alpha.epub"),
+        "META-INF/rights.xml",
+        r#"<adept:rights xmlns:adept="http://ns.adobe.com/adept"/>"#,
+    );
+    common::add_zip_entry(
+        &books.join("beta.epub"),
+        "META-INF/encryption.xml",
+        r#"<encryption><EncryptedData><EncryptionMethod Algorithm="http://www.idpf.org/2008/embedding"/>
+        <CipherData><CipherReference URI="OEBPS/font.otf"/></CipherData></EncryptedData></encryption>"#,
+    );
+
+    h.press_code(KeyCode::Enter);
+    let screen = h.screen();
+    assert!(screen.contains("DRM-protected book"));
+    assert!(screen.contains("Adobe ADEPT"));
+    assert_eq!(h.app.mode(), &Mode::FileList);
+
+    // Obfuscated fonts alone are not DRM
+    h.press_code(KeyCode::Esc);
+    h.press("j");
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("Beta only chapter."));
+}
+
+/// Text of the top row of the content pane.
+fn top_content_row(h: &mut Harness) -> String {
+    h.render()[1].split('│').nth(3).unwrap().trim().to_string()
+}
+
+#[test]
+fn resize_keeps_the_same_text_at_the_top() {
+    let words: Vec<String> = (1..=120).map(|i| format!("w{:03}", i)).collect();
+    let chapter = format!("<p>{}</p>", words.join(" "));
+    let mut h = Harness::new(&[("long.epub", &[chapter.as_str()])]);
+    h.press_code(KeyCode::Enter);
+    h.press("jjj");
+    let top = top_content_row(&mut h);
+    let first_word = top.split(' ').next().unwrap().to_string();
+    assert_ne!(first_word, "w001");
+
+    h.resize(120, 24);
+    assert!(top_content_row(&mut h).contains(&first_word));
+    h.resize(60, 24);
+    assert!(top_content_row(&mut h).contains(&first_word));
+}
+
+#[test]
+fn tabs_keep_each_books_position() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    h.press_code(KeyCode::Enter);
+    h.press("l");
+    assert!(!h.screen().contains("1 alpha"), "no tab bar with a single book");
+
+    h.press_code(KeyCode::Tab);
+    h.press("jt");
+    let screen = h.screen();
+    assert!(screen.contains("Beta only chapter."));
+    assert!(screen.contains("1 alpha") && screen.contains("2 beta"));
+    assert_eq!(h.app.active_tab(), 1);
+
+    h.press("gT");
+    let screen = h.screen();
+    assert!(screen.contains("Alpha chapter two text."));
+    assert!(screen.contains("Part 3/4"));
+
+    // Opening a book that is already in a tab switches to it
+    h.press_code(KeyCode::Tab);
+    h.press("j");
+    h.press_code(KeyCode::Enter);
+    assert_eq!(h.app.active_tab(), 1);
+    assert_eq!(h.app.tab_titles(), vec!["alpha", "beta"]);
+
+    h.press("x");
+    assert_eq!(h.app.tab_titles(), vec!["alpha"]);
+    assert!(h.screen().contains("Alpha chapter two text."));
+    h.press("x");
+    assert_eq!(h.app.mode(), &Mode::FileList);
+    // With no book open, the selected one is previewed
+    assert!(h.screen().contains("Preview"));
+}
+
+#[test]
+fn split_view_shows_two_books_side_by_side() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    h.press_code(KeyCode::Enter);
+    h.press_code(KeyCode::Tab);
+    h.press("jv");
+    let (left, right) = h.app.split_files().unwrap();
+    assert!(left.ends_with("alpha.epub") && right.ends_with("beta.epub"));
+    let screen = h.screen();
+    assert!(screen.contains("Alpha chapter one"));
+    assert!(screen.contains("Beta only chapter."));
+
+    // The focus starts on the new pane; w moves it back to the left one
+    h.press("w");
+    h.press("l");
+    let screen = h.screen();
+    assert!(screen.contains("Alpha chapter two"));
+    assert!(screen.contains("Beta only chapter."));
+
+    h.press("S");
+    h.resize(160, 24);
+    assert!(h.screen().contains("[SYNCED]"));
+
+    h.press("v");
+    assert_eq!(h.app.split_files(), None);
+    assert!(!h.screen().contains("Beta only chapter."));
+}
+
+#[test]
+fn synced_split_scrolls_both_panes() {
+    let words: Vec<String> = (1..=300).map(|i| format!("w{:03}", i)).collect();
+    let chapter = format!("<p>{}</p>", words.join(" "));
+    let mut h = Harness::new(&[("long.epub", &[chapter.as_str()])]);
+    h.press_code(KeyCode::Enter);
+    h.press("vS");
+    h.render();
+    for _ in 0..4 {
+        h.app.apply_remote_command(bookrat::remote::RemoteCommand::Jump { chapter: 2, offset: Some(6) });
+    }
+    let rows = h.render();
+    let panes: Vec<&str> = rows[1].split('│').filter(|cell| !cell.trim().is_empty()).collect();
+    assert_eq!(panes.len(), 3, "file list and two panes: {:?}", rows[1]);
+    // Same book in both panes, both scrolled to the same text
+    assert_eq!(panes[1].trim(), panes[2].trim());
+    assert!(!panes[1].contains("w001"));
+}
+
+#[test]
+fn history_jumps_back_to_an_earlier_place() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    h.press_code(KeyCode::Enter);
+    h.press("l");
+    h.press_code(KeyCode::Tab);
+    h.press("j");
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("Beta only chapter."));
+
+    h.press("H");
+    assert_eq!(h.app.mode(), &Mode::History);
+    let rows = h.render();
+    let beta = rows.iter().position(|r| r.contains("beta  Part 2")).unwrap();
+    let alpha_three = rows.iter().position(|r| r.contains("alpha  Part 3")).unwrap();
+    let alpha_two = rows.iter().position(|r| r.contains("alpha  Part 2")).unwrap();
+    assert!(beta < alpha_three && alpha_three < alpha_two, "newest visit first");
+
+    h.press("j");
+    h.press_code(KeyCode::Enter);
+    assert_eq!(h.app.mode(), &Mode::Content);
+    let screen = h.screen();
+    assert!(screen.contains("Alpha chapter two text."));
+    assert!(screen.contains("Part 3/4"));
+
+    // The history survives a restart
+    h.restart();
+    h.press("H");
+    assert!(h.screen().contains("beta  Part 2"));
+    h.press_code(KeyCode::Esc);
+    assert_eq!(h.app.mode(), &Mode::FileList);
+}
+
+#[test]
+fn library_filter_understands_query_terms() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    let filter = |h: &mut Harness, query: &str| {
+        h.press("/");
+        for _ in 0..40 {
+            h.press_code(KeyCode::Backspace);
+        }
+        h.press(query);
+        h.press_code(KeyCode::Tab);
+        h.screen()
+    };
+
+    let screen = filter(&mut h, "title:beta");
+    assert!(screen.contains("Books 1/2: title:beta"), "{}", screen);
+    assert!(screen.contains("beta — Ann (Never)") && !screen.contains("alpha — Ann (Never)"));
+
+    // Open alpha from the filtered list, then look for unread books
+    filter(&mut h, "author:\"ann\" alpha");
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("Alpha chapter one text."));
+    h.press_code(KeyCode::Tab);
+    let screen = filter(&mut h, "unread");
+    assert!(screen.contains("beta — Ann (Never)") && !screen.contains("alpha — Ann ("), "{}", screen);
+    assert!(filter(&mut h, "author:nobody").contains("Books 0/2"));
+
+    // Typing shows the query on the help bar; Esc shows the whole library again
+    h.press("/");
+    assert!(h.screen().contains("Filter: author:nobody_"));
+    h.press_code(KeyCode::Esc);
+    let screen = h.screen();
+    assert!(screen.contains("alpha — Ann (") && screen.contains("beta — Ann (Never)"));
+}
+
+#[test]
+fn filter_narrows_the_list_while_typing_and_enter_opens_the_match() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA), ("gamma.epub", BETA)]);
+    h.press("/");
+    h.press("ma");
+    let screen = h.screen();
+    assert!(screen.contains("Books 1/3: ma") && screen.contains("gamma — Ann"), "{}", screen);
+    h.press_code(KeyCode::Backspace);
+    h.press_code(KeyCode::Backspace);
+    h.press("bet");
+    let rows = h.render();
+    assert!(rows[0].contains("Books 1/3: bet") && rows[1].contains("beta — Ann"), "{}", rows.join("\n"));
+
+    // The matched letters stand out
+    let bold = |x: u16| h.terminal.backend().buffer().get(x, 1).modifier.contains(ratatui::style::Modifier::BOLD);
+    assert!(bold(1) && bold(3) && !bold(4));
+
+    h.press_code(KeyCode::Enter);
+    assert_eq!(h.app.mode(), &Mode::Content);
+    assert!(h.screen().contains("Beta only chapter."));
+    h.press_code(KeyCode::Tab);
+    h.press("/");
+    h.press_code(KeyCode::Esc);
+    assert!(h.screen().contains("alpha — Ann (Never)"));
+}
+
+#[test]
+fn ctrl_p_finds_a_book_fuzzily_from_any_mode() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA), ("gamma.epub", BETA)]);
+    h.press_code(KeyCode::Enter);
+    assert_eq!(h.app.mode(), &Mode::Content);
+
+    let ctrl = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+    h.app.handle_key(ctrl('p'));
+    assert_eq!(h.app.mode(), &Mode::Finder);
+    assert!(h.screen().contains("Open book (3/3)"));
+    h.press("gma");
+    let screen = h.screen();
+    assert!(screen.contains("Open book (1/3)") && screen.contains("> gma_") && screen.contains("gamma — Ann  gamma"), "{}", screen);
+
+    // Esc goes back to where the finder was opened
+    h.press_code(KeyCode::Esc);
+    assert_eq!(h.app.mode(), &Mode::Content);
+    assert!(h.screen().contains("Alpha chapter one text."));
+
+    h.app.handle_key(ctrl('p'));
+    h.press("a");
+    h.app.handle_key(ctrl('n'));
+    h.press_code(KeyCode::Up);
+    h.press_code(KeyCode::Backspace);
+    h.press("bta");
+    h.press_code(KeyCode::Enter);
+    assert_eq!(h.app.mode(), &Mode::Content);
+    assert!(h.screen().contains("Beta only chapter."));
+}
+
+#[test]
+fn filters_are_recalled_with_up_and_down_after_a_restart() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    for query in ["alpha", "beta"] {
+        h.press("/");
+        h.press(query);
+        h.press_code(KeyCode::Tab);
+        h.press_code(KeyCode::Backspace);
+    }
+    h.restart();
+
+    h.press("/");
+    h.press("al");
+    h.press_code(KeyCode::Up);
+    let screen = h.screen();
+    assert!(screen.contains("Filter: beta_"), "{}", screen);
+    h.press_code(KeyCode::Up);
+    h.press_code(KeyCode::Up);
+    assert!(h.screen().contains("Filter: alpha_"));
+    h.press_code(KeyCode::Down);
+    assert!(h.screen().contains("Filter: beta_"));
+    h.press_code(KeyCode::Down);
+    assert!(h.screen().contains("Filter: al_"));
+
+    h.press_code(KeyCode::Up);
+    h.press_code(KeyCode::Tab);
+    let screen = h.screen();
+    assert!(screen.contains("beta — Ann (Never)") && !screen.contains("alpha — Ann ("), "{}", screen);
+}
+
+#[test]
+fn undeclared_language_is_told_from_the_text() {
+    let french = "<p>Il ne savait pas que la mer est une chose que les enfants aiment dans le Midi.</p>";
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("roman.epub", &[french])]);
+    let path = h.dir.path().join("books").join("roman.epub");
+    let opf = common::read_zip_entry(&path, "OEBPS/content.opf").replace("<dc:language>en</dc:language>", "");
+    common::remove_zip_entry(&path, "OEBPS/content.opf");
+    common::add_zip_entry(&path, "OEBPS/content.opf", &opf);
+    h.restart();
+
+    h.press("/lang:fr");
+    h.press_code(KeyCode::Tab);
+    let screen = h.screen();
+    assert!(screen.contains("Books 1/2: lang:fr") && screen.contains("roman — Ann (Never)"), "{}", screen);
+    assert!(screen.contains("Language  fr"), "{}", screen);
+}
+
+#[test]
+fn saved_filters_show_as_collection_folders() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    h.press_code(KeyCode::Enter);
+    h.press_code(KeyCode::Tab);
+    h.press("/unread pages:<5");
+    h.press_code(KeyCode::Tab);
+    assert!(h.screen().contains("Books 1/2: unread"));
+    h.press("+Short");
+    assert!(h.screen().contains("Save collection as: Short_"));
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("Books 1/2: Short"));
+
+    // Backspace leaves the collection; it is listed above the books
+    h.press_code(KeyCode::Backspace);
+    let rows = h.render();
+    assert!(rows[1].contains("▸ Short (unread"), "{:?}", rows);
+    assert!(rows[2].contains("alpha — Ann ("));
+
+    h.press("kk");
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("Books 1/2: Short"));
+    assert!(h.screen().contains("beta — Ann (Never)"));
+
+    // It follows the library: once Beta is opened, it holds nothing
+    h.press_code(KeyCode::Backspace);
+    h.press("jj");
+    h.press_code(KeyCode::Enter);
+    h.press_code(KeyCode::Tab);
+    h.restart();
+    h.press_code(KeyCode::Enter);
+    let screen = h.screen();
+    assert!(screen.contains("Books 0/2: Short"), "{}", screen);
+    assert!(!screen.contains("beta — Ann ("));
+
+    h.press_code(KeyCode::Backspace);
+    h.press("kd");
+    let screen = h.screen();
+    assert!(screen.contains("Removed collection Short"));
+    assert!(!screen.contains("▸"));
+}
+
+#[test]
+fn title_shows_progress_through_the_whole_book() {
+    let short = "<p>Short.</p>";
+    let long = format!("<p>{}</p>", "Much longer text. ".repeat(20));
+    let mut h = Harness::new(&[("uneven.epub", &[short, long.as_str()])]);
+    h.press_code(KeyCode::Enter);
+    let first = h.screen();
+    assert!(first.contains("Progress: 100% | Book: "), "{}", first);
+    let book = |screen: &str| -> u32 {
+        let rest = &screen[screen.find("Book: ").unwrap() + 6..];
+        rest[..rest.find('%').unwrap()].parse().unwrap()
+    };
+    // The short part is only a sliver of the book's text
+    assert!(book(&first) < 10, "{}", first);
+    h.press("l");
+    assert_eq!(book(&h.screen()), 100);
+}
+
+#[test]
+fn bottom_bar_shows_the_clock_and_reading_time() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    let rows = h.render();
+    let border = &rows[rows.len() - 3];
+    let clock = border.split(" | ").next().unwrap().rsplit(' ').next().unwrap();
+    assert!(
+        clock.len() == 5 && clock.as_bytes()[2] == b':' && clock.replace(':', "").parse::<u32>().is_ok(),
+        "{}",
+        border
+    );
+    assert!(border.contains("| 0 min read today ┐"), "{}", border);
+}
+
+#[test]
+fn scrolling_is_saved_in_batches() {
+    let lines: Vec<String> = (1..=60).map(|i| format!("<p>Line {}.</p>", i)).collect();
+    let long = lines.concat();
+    let mut h = Harness::new(&[("long.epub", &[long.as_str(), "<p>Next part.</p>"])]);
+    h.press_code(KeyCode::Enter);
+    h.render();
+    let book = h.app.remote_status().book.unwrap();
+    let path = h.dir.path().join("bookmarks.json");
+    let on_disk = |book: &str| {
+        let bookmarks = Bookmarks::load_from(&path).unwrap();
+        bookmarks.get_bookmark(book).map(|b| (b.chapter, b.scroll_offset))
+    };
+
+    // The first position of a book is written at once, further scrolling waits
+    h.press("j");
+    let first = on_disk(&book).unwrap();
+    h.press("jjj");
+    assert_eq!(on_disk(&book), Some(first));
+
+    // Changing part writes right away
+    h.press("l");
+    let status = h.app.remote_status();
+    assert_eq!(on_disk(&book), Some((status.chapter - 1, status.scroll_offset)));
+
+    h.press("j");
+    h.app.flush();
+    assert_eq!(on_disk(&book), Some((status.chapter - 1, h.app.remote_status().scroll_offset)));
+}
+
+#[test]
+fn arrow_keys_scroll_wide_preformatted_text() {
+    let wide = format!("<pre>{}END</pre>", "x".repeat(70));
+    let chapter = format!("<p>Intro.</p>{}", wide);
+    let mut h = Harness::new(&[("code.epub", &[chapter.as_str()])]);
+    h.press_code(KeyCode::Enter);
+    let screen = h.screen();
+    assert!(screen.contains("Intro.") && !screen.contains("END"), "{}", screen);
+
+    for _ in 0..4 {
+        h.press_code(KeyCode::Right);
+    }
+    let screen = h.screen();
+    assert!(screen.contains("xEND"), "{}", screen);
+    assert!(screen.contains("Intro."), "prose stays in place");
+
+    h.press_code(KeyCode::Left);
+    assert!(!h.screen().contains("END"));
+}
+
+#[test]
+fn wrap_toggle_cuts_long_lines() {
+    let words: Vec<String> = (1..=40).map(|i| format!("w{:02}", i)).collect();
+    let chapter = format!("<p>{}</p>", words.join(" "));
+    let mut h = Harness::new(&[("long.epub", &[chapter.as_str()])]);
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("w20"));
+
+    h.press("W");
+    let screen = h.screen();
+    assert!(screen.contains("[NO WRAP]") && screen.contains("w01") && !screen.contains("w20"), "{}", screen);
+    for _ in 0..20 {
+        h.press_code(KeyCode::Right);
+    }
+    let screen = h.screen();
+    assert!(screen.contains("w40") && !screen.contains("w01"), "{}", screen);
+
+    h.press("W");
+    assert!(h.screen().contains("w01"));
+}
+
+#[test]
+fn dyslexia_friendly_layout_is_kept_per_book() {
+    let words: Vec<String> = (1..=40).map(|i| format!("w{:02}", i)).collect();
+    let chapter = format!("<p>{}</p><p>Second paragraph.</p>", words.join(" "));
+    let mut h = Harness::new(&[("long.epub", &[chapter.as_str()]), ("other.epub", &["<p>Other book.</p>"])]);
+    h.resize(120, 24);
+    h.press_code(KeyCode::Enter);
+    h.press("D");
+    let rows = h.render();
+    assert!(rows.iter().any(|row| row.contains("Dyslexia-friendly layout on")), "{}", rows.join("\n"));
+    let first = rows.iter().position(|row| row.contains("w01   w02")).unwrap_or_else(|| panic!("{}", rows.join("\n")));
+    assert!(!rows[first].contains("w11"), "{}", rows.join("\n"));
+    let second = rows.iter().position(|row| row.contains("Second   paragraph.")).unwrap();
+    assert!(rows[second - 1].trim_matches(|c| c == '│' || c == ' ').is_empty(), "{}", rows.join("\n"));
+
+    h.restart();
+    h.resize(120, 24);
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("w01   w02"));
+    h.press_code(KeyCode::Tab);
+    h.press("j");
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("Other book."));
+
+    h.press_code(KeyCode::Tab);
+    h.press("k");
+    h.press_code(KeyCode::Enter);
+    h.press("D");
+    assert!(h.screen().contains("w01 w02"));
+}
+
+#[test]
+fn large_print_draws_big_letters() {
+    let mut h = Harness::new(&[("big.epub", &["<p>Hi there, this line is long enough to wrap in big letters.</p>"])]);
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("Hi there"));
+
+    h.press("z");
+    let rows = h.render();
+    let top = rows.iter().position(|row| row.contains("█ █ ▀█▀")).unwrap_or_else(|| panic!("{}", rows.join("\n")));
+    assert!(rows[top + 1].contains("█▀█  █"), "{}", rows.join("\n"));
+    assert!(!rows.iter().any(|row| row.contains("Hi there")));
+    assert!(rows.iter().any(|row| row.contains("Part 2/2")));
+    // Nineteen letters to a line of an 80-column pane, so it wraps
+    let lines = rows.iter().filter(|row| row.contains('▀') || row.contains('█')).count();
+    assert!(lines >= 3 * GLYPH_HEIGHT, "{}", rows.join("\n"));
+
+    h.press("z");
+    assert!(h.screen().contains("Hi there, this line is long enough"));
+}
+
+#[test]
+fn backtick_switches_between_the_two_latest_books() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    h.press("`");
+    assert!(h.screen().contains("No other book opened yet"));
+    h.press_code(KeyCode::Enter);
+    h.press("l");
+    let alpha = h.screen();
+    h.press_code(KeyCode::Tab);
+    h.press("j");
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("Beta only chapter."));
+
+    h.press("`");
+    let screen = h.screen();
+    // The same text in the content pane, right of the library list
+    let text = |screen: &str| screen.lines().nth(1).map(|line| line.chars().skip(23).collect::<String>());
+    assert_eq!(text(&screen), text(&alpha), "{}", screen);
+    assert!(screen.contains("Part 3/"), "{}", screen);
+    h.press("`");
+    assert!(h.screen().contains("Beta only chapter."));
+}
+
+#[test]
+fn last_read_times_are_relative_unless_configured() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    h.resize(120, 24);
+    h.press_code(KeyCode::Enter);
+    h.press("l");
+    h.press_code(KeyCode::Tab);
+    let screen = h.screen();
+    assert!(screen.contains("alpha — Ann (67%, just now)") && screen.contains("beta — Ann (Never)"), "{}", screen);
+    h.press("i");
+    let year = chrono::Local::now().format("%Y").to_string();
+    let screen = h.screen();
+    assert!(screen.contains(&format!("{}-", year)) && screen.contains("(just now), part 3"), "{}", screen);
+    h.press_code(KeyCode::Esc);
+
+    h.app.set_last_read_format("%Y");
+    assert!(h.screen().contains(&format!("alpha — Ann (67%, {})", year)));
+    h.app.set_last_read_format("%Q");
+    assert!(h.screen().contains("alpha — Ann (67%, just now)"));
+
+    // The progress is kept in the library index for the next session
+    h.restart();
+    h.resize(120, 24);
+    assert!(h.screen().contains("alpha — Ann (67%, just now)"), "{}", h.screen());
+}
+
+#[test]
+fn undo_returns_to_the_place_before_a_chapter_change() {
+    let lines: Vec<String> = (1..=60).map(|i| format!("<p>Line {}.</p>", i)).collect();
+    let long = lines.concat();
+    let mut h = Harness::new(&[("long.epub", &[long.as_str(), "<p>Next part.</p>"])]);
+    h.press_code(KeyCode::Enter);
+    h.render();
+    h.press("jjjjj");
+    let before = h.app.remote_status();
+    assert!(before.scroll_offset > 0);
+    h.press("l");
+    assert_eq!(h.app.remote_status().chapter, before.chapter + 1);
+
+    h.press("u");
+    let after = h.app.remote_status();
+    assert_eq!((after.chapter, after.scroll_offset), (before.chapter, before.scroll_offset));
+
+    // Nothing is left to undo
+    h.press("u");
+    assert_eq!(h.app.remote_status().chapter, before.chapter);
+}
+
+#[test]
+fn number_slots_survive_a_restart() {
+    let lines: Vec<String> = (1..=60).map(|i| format!("<p>Line {}.</p>", i)).collect();
+    let long = lines.concat();
+    let mut h = Harness::new(&[("long.epub", &[long.as_str(), "<p>Next part.</p>"])]);
+    h.press_code(KeyCode::Enter);
+    h.render();
+    h.press("jjjj");
+    let saved = h.app.remote_status();
+    h.press("b3");
+    assert!(h.screen().contains("Saved to slot 3"));
+
+    h.press("l");
+    h.press("'4");
+    assert!(h.screen().contains("Slot 4 is empty"));
+    h.press("'3");
+    let jumped = h.app.remote_status();
+    assert_eq!((jumped.chapter, jumped.scroll_offset), (saved.chapter, saved.scroll_offset));
+
+    h.press("l");
+    h.restart();
+    h.press_code(KeyCode::Enter);
+    assert_eq!(h.app.remote_status().chapter, saved.chapter + 1);
+    h.press("'3");
+    assert_eq!(h.app.remote_status().scroll_offset, saved.scroll_offset);
+}
+
+#[test]
+fn saved_places_are_listed_with_their_text() {
+    let lines: Vec<String> = (1..=60).map(|i| format!("<p>Line {}.</p>", i)).collect();
+    let long = lines.concat();
+    let mut h = Harness::new(&[("long.epub", &[long.as_str()])]);
+    h.press_code(KeyCode::Enter);
+    h.render();
+    h.press("jjjj");
+    let saved = h.app.remote_status();
+    h.press("b3");
+    h.press("'");
+    let screen = h.screen();
+    assert!(screen.contains("Jump to slot"), "{}", screen);
+    assert!(screen.contains(&format!("3  Part {}, line {}", saved.chapter, saved.scroll_offset + 1)), "{}", screen);
+    assert!(screen.contains("Line 15. Line 16."), "{}", screen);
+
+    h.press("3");
+    assert!(!h.screen().contains("Jump to slot"));
+}
+
+#[test]
+fn highlights_are_listed_to_jump_to_and_delete() {
+    let lines: Vec<String> = (1..=60).map(|i| format!("<p>Line {}.</p>", i)).collect();
+    let long = lines.concat();
+    let mut h = Harness::new(&[("long.epub", &[long.as_str(), "<p>Next part.</p>"])]);
+    h.press_code(KeyCode::Enter);
+    let book = h.app.remote_status().book.unwrap();
+    h.app.flush();
+    let mut bookmarks = Bookmarks::load_from(h.dir.path().join("bookmarks.json")).unwrap();
+    for (chapter, text) in [(1, "Line 40."), (2, "Next part.")] {
+        let highlight = Highlight { chapter, text: text.to_string(), note: None, created: chrono::Utc::now() };
+        bookmarks.add_highlight(&book, highlight);
+    }
+    bookmarks.save().unwrap();
+    h.restart();
+    h.press_code(KeyCode::Enter);
+
+    h.press("A");
+    assert_eq!(h.app.mode(), &Mode::Highlights);
+    let screen = h.screen();
+    assert!(screen.contains("Highlights (2)"), "{}", screen);
+    assert!(screen.contains("Part 2  Line 40."), "{}", screen);
+    assert!(screen.contains("Part 3  Next part."));
+
+    h.press_code(KeyCode::Enter);
+    assert_eq!(h.app.mode(), &Mode::Content);
+    let rows = h.render();
+    assert!(rows.iter().any(|row| row.contains("Line 40.")), "{}", rows.join("\n"));
+    assert!(!rows.iter().any(|row| row.contains("Line 1.")));
+
+    h.press("A");
+    h.press("d");
+    let screen = h.screen();
+    assert!(screen.contains("Highlights (1)"), "{}", screen);
+    assert!(!screen.contains("Line 40."));
+    h.press_code(KeyCode::Esc);
+    h.restart();
+    h.press_code(KeyCode::Enter);
+    h.press("A");
+    assert!(h.screen().contains("Highlights (1)"));
+}
+
+#[test]
+fn publisher_pages_are_skipped_between_parts() {
+    let chapters: &[&str] = &[
+        "<p>Chapter one.</p>",
+        "<h1>About the Publisher</h1><p>Sign up for our newsletter.</p>",
+        "<p>Chapter two.</p>",
+    ];
+    let mut h = Harness::new(&[("book.epub", chapters)]);
+    h.app.set_skip_boilerplate(true);
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("Chapter one."));
+
+    h.press("l");
+    let screen = h.screen();
+    assert!(screen.contains("Chapter two.") && screen.contains("Part 4/4"), "{}", screen);
+    assert!(screen.contains("Skipped 1 publisher page"));
+    h.press("h");
+    assert!(h.screen().contains("Chapter one."));
+
+    h.app.set_skip_boilerplate(false);
+    h.press("l");
+    assert!(h.screen().contains("About the Publisher"));
+}
+
+#[test]
+fn the_loop_sleeps_until_something_is_due() {
+    let lines: Vec<String> = (1..=60).map(|i| format!("<p>Line {}.</p>", i)).collect();
+    let long = lines.concat();
+    let mut h = Harness::new(&[("long.epub", &[long.as_str()])]);
+    assert!(h.app.next_wakeup() <= std::time::Duration::from_secs(60));
+    h.press_code(KeyCode::Enter);
+    h.render();
+    h.app.flush();
+    let second = chrono::Local::now().format("%S").to_string();
+    assert!(h.app.next_wakeup() > std::time::Duration::from_secs(2) || second.as_str() >= "58");
+
+    // A scroll position waiting to be written wakes the loop soon; the
+    // first scroll writes the new bookmark at once
+    h.press("jj");
+    assert!(h.app.next_wakeup() <= std::time::Duration::from_secs(2));
+}
+
+#[test]
+fn counts_repeat_motions() {
+    let lines: Vec<String> = (1..=400).map(|i| format!("<p>Line {}.</p>", i)).collect();
+    let long = lines.concat();
+    let mut h = Harness::new(&[("long.epub", &[long.as_str(), "<p>Three.</p>", "<p>Four.</p>"])]);
+    h.press_code(KeyCode::Enter);
+    h.render();
+    h.press("1");
+    h.press("0");
+    assert!(h.screen().contains("10_"));
+    h.press("j");
+    assert_eq!(h.app.remote_status().scroll_offset, 10);
+    h.press("3k");
+    assert_eq!(h.app.remote_status().scroll_offset, 7);
+
+    h.press("2l");
+    assert!(h.screen().contains("Four."));
+    h.press("9h");
+    assert!(h.screen().contains("Part 1/"));
+    h.press("2G");
+    h.render();
+    let status = h.app.remote_status();
+    assert!(status.chapter == 2 && status.scroll_offset > 0, "{:?}", status);
+}
+
+#[test]
+fn macros_replay_recorded_keys() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    h.press_code(KeyCode::Enter);
+    h.press("Ma");
+    assert!(h.screen().contains("recording @a"));
+    h.press("l");
+    h.press("M");
+    let screen = h.screen();
+    assert!(screen.contains("Recorded 1 key to @a") && !screen.contains("recording @a"), "{}", screen);
+    assert!(screen.contains("Part 3/4"));
+
+    h.press("h@a");
+    assert!(h.screen().contains("Part 3/4"));
+    h.press("hh2@a");
+    assert!(h.screen().contains("Part 3/4"));
+    h.press("@@");
+    assert!(h.screen().contains("Part 4/4"));
+    h.press("@z");
+    assert!(h.screen().contains("Nothing recorded to @z"));
+}
+
+#[test]
+fn chapter_sidebar_lists_parts_with_read_markers() {
+    let long: Vec<String> = (1..=60).map(|i| format!("<p>Line {}.</p>", i)).collect();
+    let long = long.concat();
+    let chapters: &[&str] = &["<p>Short one.</p>", "<p>Short two.</p>", long.as_str()];
+    let mut h = Harness::new(&[("alpha.epub", chapters)]);
+    h.press_code(KeyCode::Enter);
+    h.render();
+    // Shown down to its end, the first chapter is read through
+    h.press("l");
+    h.press("l");
+    h.render();
+    h.press("c");
+    let rows = h.render();
+    assert!(rows[0].contains("Parts (1/4 read)"), "{}", rows[0]);
+    let row = |name: &str| rows.iter().find(|r| r.contains(name)).cloned().unwrap_or_default();
+    assert!(row("Chapter 1").contains("✓ Chapter 1"));
+    assert!(row("Chapter 2").contains("· Chapter 2"), "only looked at: {}", row("Chapter 2"));
+    assert!(row("Chapter 3").contains("· Chapter 3"), "not scrolled to its end: {}", row("Chapter 3"));
+    assert!(row("Part 1").contains("  Part 1"), "parts without a TOC entry get a number");
+
+    // Kept in the bookmark, and the sidebar stays open
+    h.restart();
+    h.press_code(KeyCode::Enter);
+    assert!(h.render()[0].contains("Parts (1/4 read)"));
+
+    h.press("c");
+    assert!(h.render()[0].contains("Continue reading"));
+}
+
+#[test]
+fn library_previews_the_selected_book() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    let path = h.dir.path().join("books").join("beta.epub");
+    let opf = common::read_zip_entry(&path, "OEBPS/content.opf").replace(
+        "</metadata>",
+        "<dc:description>&lt;p&gt;A &lt;b&gt;short&lt;/b&gt; tale.&lt;/p&gt;&lt;p&gt;Second paragraph.&lt;/p&gt;</dc:description></metadata>",
+    );
+    common::remove_zip_entry(&path, "OEBPS/content.opf");
+    common::add_zip_entry(&path, "OEBPS/content.opf", &opf);
+
+    let screen = h.screen();
+    assert!(screen.contains("Preview"), "{}", screen);
+    assert!(screen.contains("No description"));
+    assert!(screen.contains("Language  en"));
+
+    h.press("j");
+    let screen = h.screen();
+    assert!(screen.contains("Length    about 1 pages (4 words, 2 parts)"), "{}", screen);
+    assert!(screen.contains("A short tale."));
+    assert!(screen.contains("Second paragraph."));
+
+    // The open book is shown as it is read instead
+    h.press_code(KeyCode::Enter);
+    h.press_code(KeyCode::Tab);
+    let screen = h.screen();
+    assert!(screen.contains("Beta only chapter."), "{}", screen);
+    assert!(!screen.contains("Preview"));
+}
+
+#[test]
+fn library_can_preview_the_first_page() {
+    let long = format!("<p>{}</p>", "Body text of the real first chapter. ".repeat(20));
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("gamma.epub", &["<p>Contents</p>", &long])]);
+    h.press("p");
+    let screen = h.screen();
+    assert!(screen.contains("First page: alpha"), "{}", screen);
+    // Without a long enough part, the longest is shown
+    assert!(screen.contains("Alpha chapter three text."));
+
+    h.press("j");
+    let screen = h.screen();
+    assert!(screen.contains("Body text of the real first chapter."), "{}", screen);
+    assert!(!screen.contains("Contents"));
+
+    h.press("p");
+    assert!(h.screen().contains("Preview"));
+}
+
+#[test]
+fn virtual_pages_do_not_depend_on_the_terminal_size() {
+    let chapter = format!("<p>{}</p>", "virtual ".repeat(600));
+    let mut h = Harness::new(&[("long.epub", &[chapter.as_str()])]);
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("p. 1 / 4"), "{}", h.screen());
+
+    while !h.screen().contains("p. 2 / 4") {
+        h.press("j");
+    }
+    // A few rows into the page, slowly enough not to speed scrolling up,
+    // so a wider row starting earlier is still on it
+    for _ in 0..3 {
+        std::thread::sleep(std::time::Duration::from_millis(110));
+        h.press("j");
+    }
+    assert!(h.screen().contains("p. 2 / 4"));
+    h.resize(120, 40);
+    assert!(h.screen().contains("p. 2 / 4"), "{}", h.screen());
+}
+
+#[test]
+fn go_to_a_print_page_from_the_page_list() {
+    let filler = "Filler words for the page. ".repeat(40);
+    let chapter = format!(r#"<p>{filler}</p><p><span id="pg6"/>Page six starts here.</p><p>{filler}</p>"#);
+    let long = format!("<p>{}</p>", "virtual ".repeat(600));
+    let mut h = Harness::new(&[("print.epub", &[chapter.as_str(), "<p>Last.</p>"]), ("virtual.epub", &[long.as_str()])]);
+    let path = h.dir.path().join("books").join("print.epub");
+    let ncx = common::read_zip_entry(&path, "OEBPS/toc.ncx").replace(
+        "</navMap>",
+        r#"</navMap><pageList><pageTarget id="p5" type="normal"><navLabel><text>5</text></navLabel><content src="ch0.xhtml"/></pageTarget>
+        <pageTarget id="p6" type="normal"><navLabel><text>6</text></navLabel><content src="ch0.xhtml#pg6"/></pageTarget>
+        <pageTarget id="p7" type="normal"><navLabel><text>7</text></navLabel><content src="ch1.xhtml"/></pageTarget></pageList>"#,
+    );
+    common::remove_zip_entry(&path, "OEBPS/toc.ncx");
+    common::add_zip_entry(&path, "OEBPS/toc.ncx", &ncx);
+
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("print p. 5 | p. 1 / 2"), "{}", h.screen());
+    h.press("G6");
+    assert!(h.screen().contains("Go to page: 6_"));
+    h.press_code(KeyCode::Enter);
+    let screen = h.screen();
+    assert!(screen.contains("print p. 6"), "{}", screen);
+    assert!(screen.lines().nth(1).is_some_and(|line| line.contains("Page six starts here.")), "{}", screen);
+
+    h.press("G7");
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("Last."));
+    h.press("G99");
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("No page 99 in this book"));
+
+    // Without a page list, pages are virtual ones
+    h.press_code(KeyCode::Tab);
+    h.press("j");
+    h.press_code(KeyCode::Enter);
+    h.press("G3");
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains(" p. 3 / 4 "), "{}", h.screen());
+}
+
+#[test]
+fn footnotes_can_be_followed_and_returned_from() {
+    let filler = "Filler words for the page. ".repeat(40);
+    let text = format!(r#"<p>{filler}</p><p>The claim<a href="ch1.xhtml#n1"><sup>1</sup></a> needs a source.</p><p>{filler}</p>"#);
+    let notes = format!(r#"<p>{filler}</p><p id="n1">1. The note text.</p><p>{filler}</p>"#);
+    let mut h = Harness::new(&[("notes.epub", &[text.as_str(), notes.as_str()])]);
+    h.press_code(KeyCode::Enter);
+    h.render();
+    h.press("f");
+    assert!(h.screen().contains("No note reference on screen"));
+
+    for _ in 0..10 {
+        h.press("j");
+        std::thread::sleep(std::time::Duration::from_millis(110));
+    }
+    let before = h.screen();
+    assert!(before.contains("needs a source."), "{}", before);
+    h.press("f");
+    let screen = h.screen();
+    assert!(screen.contains("Note 1 | Backspace: Back"), "{}", screen);
+    assert!(screen.lines().nth(1).is_some_and(|line| line.contains("1. The note text.")), "{}", screen);
+
+    h.press_code(KeyCode::Backspace);
+    let screen = h.screen();
+    assert_eq!(screen.lines().nth(1), before.lines().nth(1), "{}", screen);
+    h.press_code(KeyCode::Backspace);
+    assert!(h.screen().contains("No note to go back from"));
+}
+
+#[test]
+fn notes_are_previewed_without_leaving_the_page() {
+    let filler = "Filler words for the page. ".repeat(40);
+    let text = r#"<p>One<a href="ch1.xhtml#n1">1</a> and two<a href="ch1.xhtml#g">*</a>.</p>"#;
+    let notes = format!(r#"<p>{filler}</p><p id="n1">1. The first note, with enough text to stand alone.</p><dl><dt id="g">Two</dt><dd>Defined in the glossary at length.</dd></dl>"#);
+    let mut h = Harness::new(&[("notes.epub", &[text, notes.as_str()])]);
+    h.press_code(KeyCode::Enter);
+    h.render();
+    h.press("F");
+    let screen = h.screen();
+    assert!(screen.contains("Note 1 (1/2)"), "{}", screen);
+    assert!(screen.contains("1. The first note, with enough"), "{}", screen);
+    assert!(screen.contains("Part 2/3"));
+
+    h.press("F");
+    let screen = h.screen();
+    assert!(screen.contains("Note * (2/2)") && screen.contains("Two Defined in the glossary at"), "{}", screen);
+    h.press("F");
+    assert!(h.screen().contains("Note 1 (1/2)"));
+
+    h.press_code(KeyCode::Esc);
+    let screen = h.screen();
+    assert!(!screen.contains("Note 1 (1/2)") && screen.contains("Part 2/3"));
+
+    // Enter goes on to the note
+    h.press("F");
+    h.press_code(KeyCode::Enter);
+    let screen = h.screen();
+    assert!(screen.contains("Part 3/3") && screen.contains("Note 1 | Backspace: Back"), "{}", screen);
+}
+
+#[test]
+fn chapters_keep_notes_shown_in_the_parts_sidebar() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    h.press_code(KeyCode::Enter);
+    h.press("l");
+    h.press("n");
+    h.press("Main point");
+    h.app.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::ALT));
+    h.press("Second");
+    let screen = h.screen();
+    assert!(screen.contains("Note on") && screen.contains("Main point") && screen.contains("Second_"), "{}", screen);
+    h.press_code(KeyCode::Enter);
+    h.press("c");
+    let rows = h.render();
+    assert_eq!(rows.iter().filter(|row| row.contains('✎')).count(), 1, "{}", rows.join("\n"));
+
+    h.restart();
+    h.press_code(KeyCode::Enter);
+    h.press("n");
+    assert!(h.screen().contains("Second_"));
+    for _ in 0..20 {
+        h.press_code(KeyCode::Backspace);
+    }
+    h.press_code(KeyCode::Enter);
+    h.press("c");
+    assert!(!h.screen().contains('✎'));
+}
+
+#[test]
+fn validation_reports_structural_problems() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    let path = h.dir.path().join("books").join("alpha.epub");
+    common::remove_zip_entry(&path, "OEBPS/ch1.xhtml");
+    common::remove_zip_entry(&path, "OEBPS/toc.ncx");
+    common::add_zip_entry(
+        &path,
+        "OEBPS/toc.ncx",
+        r#"<?xml version="1.0"?><ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1"><navMap>
+        <navPoint id="a" playOrder="1"><navLabel><text>One</text></navLabel><content src="ch0.xhtml#start"/></navPoint>
+        <navPoint id="b" playOrder="2"><navLabel><text>Two</text></navLabel><content src="gone.xhtml"/></navPoint>
+        </navMap></ncx>"#,
+    );
+    h.resize(120, 30);
+    h.press("V");
+    assert_eq!(h.app.mode(), &Mode::Validation);
+    let screen = h.screen();
+    assert!(screen.contains("4 problems found"), "{}", screen);
+    assert!(screen.contains("OEBPS/ch1.xhtml (ch1) is missing from the archive"));
+    assert!(screen.contains("\"One\" links to #start, which OEBPS/ch0.xhtml lacks"));
+    assert!(screen.contains("\"Two\" links to OEBPS/gone.xhtml, which is not in the manifest"));
+    assert!(screen.contains("Part 3: OEBPS/ch1.xhtml is missing from the archive"));
+
+    h.press_code(KeyCode::Esc);
+    h.press("jV");
+    assert!(h.screen().contains("No problems found"));
+    h.press_code(KeyCode::Esc);
+    assert_eq!(h.app.mode(), &Mode::FileList);
+}
+
+#[test]
+fn chapter_sidebar_falls_back_to_headings_without_a_toc() {
+    let chapters: &[&str] = &["<h1>The <em>First</em> Move</h1><p>One.</p>", "<h2>Interlude</h2><p>Two.</p>", "<p>No heading.</p>"];
+    let mut h = Harness::new(&[("gamma.epub", chapters)]);
+    let path = h.dir.path().join("books").join("gamma.epub");
+    common::remove_zip_entry(&path, "OEBPS/toc.ncx");
+    common::add_zip_entry(
+        &path,
+        "OEBPS/toc.ncx",
+        r#"<?xml version="1.0"?><ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1"><navMap/></ncx>"#,
+    );
+    h.press_code(KeyCode::Enter);
+    h.press("c");
+    let screen = h.screen();
+    assert!(screen.contains("gamma"), "title page heading: {}", screen);
+    assert!(screen.contains("The First Move"));
+    assert!(screen.contains("Interlude"));
+    assert!(screen.contains("Part 4"));
+}
+
+#[test]
+fn minimap_shows_the_viewport_beside_the_text() {
+    let words: Vec<String> = (1..=400).map(|i| format!("w{:03}", i)).collect();
+    let chapter = format!("<h1>Opening</h1><p>{}</p>", words.join(" "));
+    let mut h = Harness::new(&[("long.epub", &[chapter.as_str()])]);
+    h.press_code(KeyCode::Enter);
+    h.press("m");
+    let rows = h.render();
+    let pane = |row: &str| row.split('│').nth(3).unwrap_or_default().to_string();
+    assert!(pane(&rows[1]).ends_with(" ━━"), "heading marked: {:?}", rows[1]);
+    assert!(pane(&rows[2]).ends_with(" ██"));
+
+    h.press("m");
+    assert!(!pane(&h.render()[2]).contains('█'));
+}
+
+#[test]
+fn layout_preferences_survive_a_restart() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    // Where the content pane's border starts on the top row
+    let content_column = |rows: &[String]| rows[0].chars().skip(1).position(|c| c == '┌').map(|column| column + 1);
+    assert_eq!(content_column(&h.render()), Some(24));
+    h.press(">>");
+    h.press_code(KeyCode::Enter);
+    h.press("#W");
+    let rows = h.render();
+    assert_eq!(content_column(&rows), Some(32));
+    assert!(rows[0].contains("[NO WRAP"));
+
+    h.restart();
+    h.press_code(KeyCode::Enter);
+    let rows = h.render();
+    assert_eq!(content_column(&rows), Some(32));
+    assert!(rows[0].contains("[NO WRAP"));
+    assert!(rows[1].contains("│   1 "), "{}", rows.join("\n"));
+
+    // Narrowed all the way, the library is hidden while reading
+    h.press("<<<<<<<<");
+    let rows = h.render();
+    assert!(rows[0].starts_with("┌Part 2/4"), "{}", rows.join("\n"));
+    h.press_code(KeyCode::Tab);
+    assert!(h.screen().contains("alpha"));
+}
+
+#[test]
+fn line_numbers_count_wrapped_rows() {
+    let words: Vec<String> = (1..=120).map(|i| format!("w{:03}", i)).collect();
+    let chapter = format!("<p>{}</p>", words.join(" "));
+    let mut h = Harness::new(&[("long.epub", &[chapter.as_str()])]);
+    h.press_code(KeyCode::Enter);
+    h.press("#");
+    let rows = h.render();
+    let pane = |row: &str| row.split('│').nth(3).unwrap_or_default().to_string();
+    assert!(pane(&rows[1]).starts_with("   1 w001"));
+    assert!(pane(&rows[2]).starts_with("   2 w"));
+
+    h.press("#");
+    assert!(pane(&h.render()[1]).starts_with("w001"));
+}
+
+#[test]
+fn inspector_shows_package_internals() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    h.press_code(KeyCode::Enter);
+    h.press("I");
+    assert_eq!(h.app.mode(), &Mode::Inspector);
+    let screen = h.screen();
+    assert!(screen.contains("Part 2 of 4: id ch0, href OEBPS/ch0.xhtml"), "{}", screen);
+    assert!(screen.contains("EPUB 2.0, package document OEBPS/content.opf"));
+    assert!(screen.contains("creator: Ann"));
+    assert!(screen.contains("▶ 2. ch0 → OEBPS/ch0.xhtml"));
+
+    // Scrolled far enough, the manifest comes into view
+    for _ in 0..12 {
+        h.press("j");
+    }
+    assert!(h.screen().contains("ncx  OEBPS/toc.ncx  application/x-dtbncx+xml"));
+
+    h.press_code(KeyCode::Esc);
+    assert_eq!(h.app.mode(), &Mode::Content);
+}
+
+#[test]
+fn statistics_summarize_the_library() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    h.press_code(KeyCode::Enter);
+    h.press_code(KeyCode::Tab);
+    h.press("L");
+    assert_eq!(h.app.mode(), &Mode::Statistics);
+    let screen = h.screen();
+    assert!(screen.contains("Library statistics"), "{}", screen);
+    assert!(screen.contains("2 books"));
+    assert!(screen.contains("Read: 1 (50%)"));
+    assert!(screen.contains("Unread: 1 (50%)"));
+    assert!(screen.contains("en: 2"));
+    assert!(screen.contains("Ann: 2"));
+
+    h.press_code(KeyCode::Esc);
+    assert_eq!(h.app.mode(), &Mode::FileList);
+}
+
+#[test]
+fn statistics_while_reading_measure_the_part() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    h.press_code(KeyCode::Enter);
+    h.press("L");
+    assert_eq!(h.app.mode(), &Mode::Statistics);
+    let screen = h.screen();
+    assert!(screen.contains("Statistics of"), "{}", screen);
+    assert!(screen.contains("Flesch reading ease"), "{}", screen);
+    assert!(screen.contains("Unique words"));
+
+    h.press_code(KeyCode::Esc);
+    assert_eq!(h.app.mode(), &Mode::Content);
+}
+
+#[test]
+fn pick_opens_an_unread_book() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    h.press_code(KeyCode::Enter);
+    h.press_code(KeyCode::Tab);
+    // Alpha was opened, so only Beta is left to pick
+    h.press("R");
+    assert_eq!(h.app.mode(), &Mode::Content);
+    assert!(h.screen().contains("Beta only chapter."));
+
+    h.press_code(KeyCode::Tab);
+    h.press("R");
+    assert!(h.screen().contains("No unread books to pick from"));
+}
+
+#[test]
+fn queue_offers_the_next_book_on_finishing_one() {
+    let gamma: &[&str] = &["<p>Gamma only chapter.</p>"];
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA), ("gamma.epub", gamma)]);
+    h.press("a");
+    h.press("j");
+    h.press("a");
+    h.press("j");
+    h.press("a");
+    assert!(h.screen().contains("Queued gamma as number 3"));
+
+    h.press("Q");
+    assert_eq!(h.app.mode(), &Mode::Queue);
+    h.press("jjK");
+    let rows = h.render();
+    assert!(rows.iter().any(|row| row.contains("2. gamma")));
+    assert!(rows.iter().any(|row| row.contains("3. beta")));
+    h.press("kk");
+    h.press_code(KeyCode::Enter);
+    assert_eq!(h.app.mode(), &Mode::Content);
+    assert!(h.screen().contains("Alpha chapter one text."));
+
+    h.press("l");
+    h.render();
+    h.press("l");
+    h.render();
+    h.app.tick();
+    assert!(h.screen().contains("Finished! Next in your queue: gamma (N: start it)"));
+    h.press("N");
+    assert!(h.screen().contains("Gamma only chapter."));
+
+    // The finished book left the queue, and the order was saved
+    h.restart();
+    h.press("Q");
+    let rows = h.render();
+    assert!(rows.iter().any(|row| row.contains("1. gamma")));
+    assert!(!rows.iter().any(|row| row.contains(". alpha")));
+}
+
+#[test]
+fn finishing_a_book_offers_to_mark_and_rate_it() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    h.press_code(KeyCode::Enter);
+    h.press("ll");
+    h.render();
+    h.press("j");
+    assert_eq!(h.app.mode(), &Mode::Completion);
+    let screen = h.screen();
+    assert!(screen.contains("You have reached the end of the book."));
+    assert!(screen.contains("f: Mark it finished"));
+    assert!(screen.contains("Your queue is empty"));
+
+    h.press("f4");
+    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let screen = h.screen();
+    assert!(screen.contains(&format!("Finished {}", today)));
+    assert!(screen.contains("Rating ★★★★☆"));
+    h.press_code(KeyCode::Esc);
+    assert_eq!(h.app.mode(), &Mode::Content);
+
+    h.press("k");
+    assert_eq!(h.app.mode(), &Mode::Content);
+
+    // The finish date is kept, and counted in the statistics
+    h.restart();
+    h.press("L");
+    let screen = h.screen();
+    assert!(screen.contains("Finished: 1 (100%)"));
+    assert!(screen.contains(&format!("{}  alpha", today)));
+}
+
+#[test]
+fn finished_books_stand_out_and_can_be_hidden() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    h.press("f");
+    assert!(h.screen().contains("Open the book before marking it finished"));
+
+    // Reading to the end marks a book finished
+    h.press_code(KeyCode::Enter);
+    h.press("l");
+    h.render();
+    h.press("l");
+    h.render();
+    h.app.tick();
+    assert!(h.screen().contains("Finished alpha"));
+    h.press_code(KeyCode::Tab);
+    h.press("j");
+    let rows = h.render();
+    assert!(rows[1].contains("✓ alpha — Ann"), "{}", rows.join("\n"));
+    assert_eq!(h.terminal.backend().buffer().get(3, 1).fg, ratatui::style::Color::Green);
+
+    h.press("F");
+    let rows = h.render();
+    assert!(rows[0].contains("Books, unfinished") && rows[1].contains("beta — Ann"), "{}", rows.join("\n"));
+    assert!(!rows.iter().any(|row| row.contains("alpha — Ann")));
+
+    // Hiding them is kept, and f takes the mark off again
+    h.restart();
+    let screen = h.screen();
+    assert!(!screen.contains("alpha — Ann"), "{}", screen);
+    h.press("F");
+    h.press("k");
+    h.press("f");
+    assert!(h.screen().contains("Unfinished alpha"));
+    h.press("F");
+    let rows = h.render();
+    assert!(rows[1].contains("100% alpha — Ann"), "back under Continue reading: {}", rows.join("\n"));
+    assert!(rows[4].starts_with("│alpha — Ann"), "{}", rows.join("\n"));
+}
+
+/// Gives the EPUB at `path` an EPUB 2 cover, red above blue.
+fn add_cover(path: &std::path::Path) {
+    let opf = common::read_zip_entry(path, "OEBPS/content.opf")
+        .replace("</metadata>", "<meta name=\"cover\" content=\"cover\"/></metadata>")
+        .replace("<manifest>", "<manifest><item id=\"cover\" href=\"cover.png\" media-type=\"image/png\"/>");
+    common::remove_zip_entry(path, "OEBPS/content.opf");
+    common::add_zip_entry(path, "OEBPS/content.opf", &opf);
+    let cover = image::RgbImage::from_fn(10, 16, |_, y| image::Rgb(if y < 8 { [255, 0, 0] } else { [0, 0, 255] }));
+    let mut png = Vec::new();
+    cover.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png).unwrap();
+    common::add_zip_entry(path, "OEBPS/cover.png", png);
+}
+
+#[test]
+fn book_info_shows_the_cover_beside_the_details() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    add_cover(&h.dir.path().join("books").join("alpha.epub"));
+    h.press("i");
+    let rows = h.render();
+    // The popup starts at column 12; the cover fills its left side in
+    // half blocks, with the details beside it
+    let (top, bottom) = (h.terminal.backend().buffer().get(13, 2).clone(), h.terminal.backend().buffer().get(13, 15).clone());
+    assert_eq!((top.symbol(), top.fg, top.bg), ("▀", Color::Rgb(255, 0, 0), Color::Rgb(255, 0, 0)), "{}", rows.join("\n"));
+    assert_eq!(bottom.bg, Color::Rgb(0, 0, 255));
+    assert!(rows[2].contains("▀ alpha"), "{}", rows.join("\n"));
+    assert_eq!(h.app.take_graphics(), None);
+    h.press_code(KeyCode::Esc);
+
+    // Kitty and sixel pictures are written after the frame, and taken
+    // away again when the popup closes
+    h.app.set_graphics(Some(Protocol::Kitty));
+    h.press("i");
+    h.render();
+    let shown = h.app.take_graphics().unwrap();
+    assert!(!shown.redraw && shown.sequence.contains("\x1b_Ga=T,f=100"), "{:?}", shown);
+    h.render();
+    assert_eq!(h.app.take_graphics(), None);
+    h.press_code(KeyCode::Esc);
+    h.render();
+    assert_eq!(h.app.take_graphics().unwrap().sequence, images::KITTY_DELETE);
+
+    h.app.set_graphics(Some(Protocol::Sixel));
+    h.press("i");
+    h.render();
+    assert!(h.app.take_graphics().unwrap().sequence.contains("\x1bPq"));
+    h.press_code(KeyCode::Esc);
+    h.render();
+    assert!(h.app.take_graphics().unwrap().redraw);
+
+    // Books without a cover keep the whole popup for their details
+    h.press("ji");
+    assert!(h.render()[2].contains("│beta"));
+}
+
+#[test]
+fn the_shelf_shows_books_as_cards_moved_through_with_hjkl() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA), ("delta.epub", BETA), ("gamma.epub", BETA), ("omega.epub", BETA)]);
+    add_cover(&h.dir.path().join("books").join("alpha.epub"));
+    h.resize(80, 30);
+    h.press("B");
+    assert_eq!(h.app.mode(), &Mode::Shelf);
+    let rows = h.render();
+    // Four cards to a row: alpha's cover above its title, title cards for
+    // the others
+    let cover = h.terminal.backend().buffer().get(8, 2).clone();
+    assert_eq!((cover.symbol(), cover.fg), ("▀", Color::Rgb(255, 0, 0)), "{}", rows.join("\n"));
+    assert!(rows[9].contains("alpha") && rows[2].contains("beta") && rows[2].contains("gamma"), "{}", rows.join("\n"));
+    assert!(rows[13].contains("omega"), "{}", rows.join("\n"));
+
+    // The selected card's border stands out
+    let selected = |h: &mut Harness, x: u16, y: u16| {
+        h.render();
+        h.terminal.backend().buffer().get(x, y).fg == Color::Yellow
+    };
+    assert!(selected(&mut h, 1, 1));
+    h.press("j");
+    assert!(selected(&mut h, 1, 12) && !selected(&mut h, 1, 1));
+    h.press("h");
+    assert!(selected(&mut h, 55, 1));
+    // Nothing is above the top row
+    h.press("k");
+    assert!(selected(&mut h, 55, 1));
+    h.press("hh");
+    h.press_code(KeyCode::Enter);
+    assert_eq!(h.app.mode(), &Mode::Content);
+    assert!(h.screen().contains("Beta only chapter."));
+
+    // Tab goes back to the shelf, which is kept for the next session
+    h.press_code(KeyCode::Tab);
+    assert_eq!(h.app.mode(), &Mode::Shelf);
+    h.restart();
+    assert_eq!(h.app.mode(), &Mode::Shelf);
+    h.press("B");
+    assert_eq!(h.app.mode(), &Mode::FileList);
+}
+
+#[test]
+fn book_info_keeps_a_rating_and_review() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    h.press("ji");
+    assert_eq!(h.app.mode(), &Mode::BookInfo);
+    let screen = h.screen();
+    assert!(screen.contains("Book info"), "{}", screen);
+    assert!(screen.contains("By        Ann"));
+    assert!(screen.contains("Last read never"));
+    h.press("4");
+    assert!(h.screen().contains("Open the book before rating it"));
+    h.press_code(KeyCode::Esc);
+
+    h.press_code(KeyCode::Enter);
+    h.press("i");
+    h.press("4");
+    h.press("rSlow start, great end.");
+    let screen = h.screen();
+    assert!(screen.contains("Review: Slow start, great end._"), "{}", screen);
+    h.press_code(KeyCode::Enter);
+    let screen = h.screen();
+    assert!(screen.contains("Rating    ★★★★☆"), "{}", screen);
+    assert!(screen.contains("Slow start, great end."));
+
+    // Kept in the bookmarks file
+    h.restart();
+    h.press("ji");
+    assert!(h.screen().contains("Rating    ★★★★☆"));
+    h.press("0");
+    assert!(h.screen().contains("Rating    not rated"));
+}
+
+#[test]
+fn copy_yanks_the_paragraph_or_the_screen() {
+    let mut h = Harness::new(&[("two.epub", &["<p>First paragraph here.</p><p>Second paragraph.</p>"])]);
+    h.press_code(KeyCode::Enter);
+    h.render();
+    h.press("y");
+    assert_eq!(h.app.take_clipboard().as_deref(), Some("First paragraph here."));
+    assert!(h.screen().contains("Copied the paragraph (21 characters)"));
+
+    h.press("Y");
+    let screen = h.app.take_clipboard().unwrap();
+    assert!(screen.contains("First paragraph here.\n"), "{:?}", screen);
+    assert!(screen.contains("Second paragraph."));
+
+    // The message gives way to the help bar at the next key
+    h.press("j");
+    assert!(h.app.status().is_none());
+    assert_eq!(h.app.take_clipboard(), None);
+}
+
+/// Serves `body` once for every request on a free local port.
+fn serve_once(status: u16, body: Vec<u8>) -> String {
+    let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+    let addr = server.server_addr().to_ip().unwrap();
+    std::thread::spawn(move || {
+        if let Ok(request) = server.recv() {
+            let _ = request.respond(tiny_http::Response::from_data(body).with_status_code(status));
+        }
+    });
+    format!("http://{}", addr)
+}
+
+fn wait_for_download(h: &mut Harness) {
+    for _ in 0..200 {
+        h.app.tick();
+        if !h.app.is_downloading() {
+            return;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    panic!("download did not finish");
+}
+
+#[test]
+fn url_downloads_into_the_library_and_opens() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    let source = h.dir.path().join("source.epub");
+    common::write_epub(&source, "gamma", &["<p>Downloaded text.</p>"]);
+    let base = serve_once(200, std::fs::read(&source).unwrap());
+
+    h.press("o");
+    assert!(h.screen().contains("Open URL: _"));
+    h.press(&format!("{}/files/gamma.epub?x=1", base));
+    h.press_code(KeyCode::Enter);
+    assert!(h.app.is_downloading());
+    assert!(h.screen().contains("Downloading gamma.epub"));
+    wait_for_download(&mut h);
+
+    assert_eq!(h.app.mode(), &Mode::Content);
+    assert!(h.screen().contains("Downloaded text."));
+    assert!(h.dir.path().join("books").join("gamma.epub").exists());
+    h.press_code(KeyCode::Tab);
+    assert!(h.screen().contains("gamma — Ann ("), "listed in the library");
+}
+
+#[test]
+fn failed_download_is_reported() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    let base = serve_once(404, b"missing".to_vec());
+    h.app.open_url(&format!("{}/gone.epub", base));
+    wait_for_download(&mut h);
+    let screen = h.screen();
+    assert!(screen.contains("Download failed"), "{}", screen);
+    assert_eq!(std::fs::read_dir(h.dir.path().join("books")).unwrap().count(), 1, "no partial file left behind");
+}
+
+#[test]
+fn piped_text_is_read_without_keeping_a_place() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    h.app.open_document(Document::new("stdin", "First para\nwrapped here.\n\nSecond <para> & more."));
+    assert_eq!(h.app.mode(), &Mode::Content);
+    let screen = h.screen();
+    assert!(screen.contains("First para wrapped here."), "{}", screen);
+    assert!(screen.contains("    Second <para> & more."));
+    assert!(screen.contains("Part 1/1"));
+    h.press("jlb1");
+
+    h.restart();
+    for file in ["bookmarks.json", "history.json"] {
+        let saved = std::fs::read_to_string(h.dir.path().join(file)).unwrap_or_default();
+        assert!(!saved.contains("stdin"), "{} mentions stdin: {}", file, saved);
+    }
+}
+
+#[test]
+fn piped_html_goes_through_the_formatter() {
+    let mut h = Harness::new(&[]);
+    h.app.open_document(Document::new("stdin", "<html><body><h1>Article</h1><p>Some <em>piped</em> text.</p></body></html>"));
+    let screen = h.screen();
+    assert!(screen.contains("Article"));
+    assert!(screen.contains("Some piped text."));
+    h.press("c");
+    assert!(h.screen().contains("Article"), "part titled after its heading");
+}
+
+#[test]
+fn now_reading_describes_the_open_book() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    assert_eq!(h.app.now_reading(), None);
+    h.press_code(KeyCode::Enter);
+    h.press("lll");
+    h.render();
+    let activity = h.app.now_reading().unwrap();
+    assert_eq!(activity.title, "alpha");
+    assert_eq!(activity.author.as_deref(), Some("Ann"));
+    assert_eq!(activity.progress, 100);
+    let status = h.app.reading_status();
+    assert_eq!((status.chapter, status.total_chapters, status.minutes_left), (4, 4, Some(0)));
+    assert_eq!(status.text, "alpha 100% (0m left)");
+
+    h.press_code(KeyCode::Tab);
+    assert_eq!(h.app.now_reading(), None, "not reading in the book list");
+    assert_eq!(h.app.reading_status().title.as_deref(), Some("alpha"), "still the book open");
+}
+
+#[cfg(unix)]
+#[test]
+fn hooks_run_on_reading_events() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    let log = h.dir.path().join("events.log");
+    let command = format!(r#"echo "$BOOKRAT_EVENT $BOOKRAT_CHAPTER/$BOOKRAT_TOTAL_CHAPTERS $BOOKRAT_TITLE" >> {}"#, log.display());
+    h.app.set_hooks(bookrat::config::HooksConfig {
+        book_opened: Some(command.clone()),
+        chapter_finished: Some(command.clone()),
+        book_finished: Some(command),
+        highlight_created: None,
+    });
+    h.press_code(KeyCode::Enter);
+    h.render();
+    h.press("l");
+    h.render();
+    h.press("l");
+    h.render();
+    h.app.tick();
+
+    let expected = ["book_opened 2/4 alpha", "chapter_finished 2/4 alpha", "chapter_finished 3/4 alpha", "book_finished 4/4 alpha"];
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    let mut lines: Vec<String> = Vec::new();
+    while std::time::Instant::now() < deadline {
+        lines = std::fs::read_to_string(&log).unwrap_or_default().lines().map(str::to_string).collect();
+        if lines.len() >= expected.len() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    // Hooks run in the background, so they may finish in any order
+    lines.sort();
+    let mut expected = expected.map(str::to_string).to_vec();
+    expected.sort();
+    assert_eq!(lines, expected);
+}
+
+#[test]
+fn sidecars_carry_the_position_beside_the_book() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    h.app.use_sidecars();
+    h.press_code(KeyCode::Enter);
+    h.press("l");
+    h.app.flush();
+    let sidecar = h.dir.path().join("books/alpha.bookrat.json");
+    let saved: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&sidecar).unwrap()).unwrap();
+    assert_eq!(saved["chapter"], 2);
+    assert!(!h.dir.path().join("books/beta.bookrat.json").exists(), "only books with a place get one");
+
+    // Another machine read further and synced only the sidecar
+    let newer = r#"{"chapter": 3, "scroll_offset": 0, "last_read": "2099-01-01T00:00:00Z"}"#;
+    std::fs::write(&sidecar, newer).unwrap();
+    h.restart();
+    h.app.use_sidecars();
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("Part 4/4"));
+}
+
+#[test]
+fn recently_read_books_are_pinned_above_the_list() {
+    let beta: &[&str] = &["<p>Beta one.</p>", "<p>Beta two.</p>"];
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", beta), ("gamma.epub", BETA)]);
+    assert!(!h.screen().contains("Continue reading"), "nothing read yet");
+    h.press_code(KeyCode::Enter);
+    h.press("l");
+    h.press_code(KeyCode::Tab);
+    h.press("j");
+    h.press_code(KeyCode::Enter);
+    h.press("l");
+    h.press_code(KeyCode::Tab);
+
+    // Beta was read last, and alpha was left in its second chapter
+    let rows = h.render();
+    assert!(rows[0].contains("Continue reading"), "{}", rows.join("\n"));
+    assert!(rows[1].contains("beta — Ann"), "{}", rows.join("\n"));
+    assert!(rows[2].contains("alpha — Ann"), "{}", rows.join("\n"));
+    assert!(rows[4].contains("┌Books"), "{}", rows.join("\n"));
+    h.resize(120, 24);
+    let rows = h.render();
+    assert!(rows[2].contains(" 37% alpha — Ann · part 3/4"), "{}", rows.join("\n"));
+
+    // The cursor moves up into the section, and Enter goes back to the book
+    h.press("kk");
+    h.press_code(KeyCode::Enter);
+    let screen = h.screen();
+    assert!(screen.contains("Part 3/4") && screen.contains("Alpha chapter two text."), "{}", screen);
+
+    // Filtering shows the matches only
+    h.press_code(KeyCode::Tab);
+    h.press("/");
+    h.press("gam");
+    let rows = h.render();
+    assert!(rows[0].contains("Books") && !rows.iter().any(|row| row.contains("Continue reading")), "{}", rows.join("\n"));
+}
+
+#[test]
+fn copies_of_a_book_are_listed_once_and_picked_from() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    let copies = h.dir.path().join("books").join("Copies");
+    std::fs::create_dir(&copies).unwrap();
+    common::write_epub(&copies.join("alpha.epub"), "alpha", ALPHA);
+    h.restart();
+    h.resize(120, 24);
+    let rows = h.render();
+    assert_eq!(rows.iter().filter(|row| row.contains("alpha — Ann")).count(), 1, "{}", rows.join("\n"));
+    assert!(rows[1].contains("alpha — Ann ×2 (Never)"), "{}", rows.join("\n"));
+
+    h.press_code(KeyCode::Enter);
+    let screen = h.screen();
+    assert!(screen.contains("Copies of alpha — Ann"), "{}", screen);
+    assert!(screen.contains("│alpha.epub (Never)") && screen.contains("│Copies/alpha.epub (Never)"), "{}", screen);
+    h.press("jk");
+    h.press_code(KeyCode::Enter);
+    let read = copies.join("alpha.epub");
+    assert_eq!(h.app.remote_status().book.as_deref(), read.to_str());
+
+    // The copy read is the one kept in the list from then on
+    h.press("l");
+    h.press_code(KeyCode::Tab);
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("Copies/alpha.epub (67%, just now)"), "{}", h.screen());
+    h.press_code(KeyCode::Esc);
+    h.restart();
+    h.resize(120, 24);
+    let rows = h.render();
+    assert!(rows[4].contains("alpha — Ann ×2 (67%, just now)"), "{}", rows.join("\n"));
+}
+
+#[test]
+fn book_info_shows_the_publisher_date_and_formatted_description() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    let path = h.dir.path().join("books").join("alpha.epub");
+    let opf = common::read_zip_entry(&path, "OEBPS/content.opf").replace(
+        "<dc:language>en</dc:language>",
+        "<dc:language>en</dc:language>
+    <dc:publisher>Penguin Classics</dc:publisher>
+    <dc:date>2008-06-01T00:00:00+00:00</dc:date>
+    <dc:subject>Fiction</dc:subject>
+    <dc:description>&lt;p&gt;A &lt;i&gt;short&lt;/i&gt; book &amp;amp; more.&lt;/p&gt;&lt;p&gt;Second paragraph.&lt;/p&gt;</dc:description>",
+    );
+    common::remove_zip_entry(&path, "OEBPS/content.opf");
+    common::add_zip_entry(&path, "OEBPS/content.opf", &opf);
+    h.restart();
+    h.press("i");
+    let rows = h.render();
+    let row = |text: &str| rows.iter().position(|row| row.contains(text)).unwrap_or_else(|| panic!("{}", rows.join("\n")));
+    assert!(rows[row("Publisher")].contains("Penguin Classics"));
+    assert!(rows[row("Published")].contains("2008-06-01") && !rows[row("Published")].contains("T00"));
+    assert!(rows[row("Subjects")].contains("Fiction"));
+    assert!(rows[row("Language")].contains("en"));
+
+    // The description's markup is formatted, not shown
+    let first = row("A short book & more.");
+    assert!(row("Second paragraph.") > first);
+    assert!(!rows.iter().any(|row| row.contains("<p>") || row.contains("_short_")));
+    let x = rows[first][..rows[first].find("short").unwrap()].chars().count() as u16;
+    assert!(h.terminal.backend().buffer().get(x, first as u16).modifier.contains(Modifier::ITALIC));
+}
+
+#[test]
+fn books_are_renamed_moved_and_deleted_keeping_their_bookmarks() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA), ("beta.epub", BETA)]);
+    let books = h.dir.path().join("books");
+    h.resize(120, 24);
+    h.press_code(KeyCode::Enter);
+    h.press("l");
+    h.press_code(KeyCode::Tab);
+    h.press("r");
+    assert!(h.screen().contains("Close alpha (x) before changing its file"));
+    h.press_code(KeyCode::Tab);
+    h.press("x");
+
+    // Renaming keeps the extension and the bookmark
+    h.press("r");
+    assert!(h.screen().contains("Rename alpha.epub to: alpha.epub_"), "{}", h.screen());
+    for _ in 0.."alpha.epub".len() {
+        h.press_code(KeyCode::Backspace);
+    }
+    h.press("omega");
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("Renamed alpha.epub to omega.epub"), "{}", h.screen());
+    assert!(!books.join("alpha.epub").exists() && books.join("omega.epub").exists());
+    assert!(h.screen().contains("alpha — Ann (67%, just now)"), "{}", h.screen());
+
+    // Moving makes the folders, and the book stays selected
+    h.press("m");
+    assert!(h.screen().contains("Move omega.epub to folder: _"), "{}", h.screen());
+    h.press("Classics/Greek");
+    h.press_code(KeyCode::Enter);
+    let moved = books.join("Classics").join("Greek").join("omega.epub");
+    assert!(moved.exists(), "{}", h.screen());
+    h.restart();
+    h.resize(120, 24);
+    assert!(h.screen().contains("alpha — Ann (67%, just now)"), "{}", h.screen());
+    h.press_code(KeyCode::Enter);
+    assert_eq!(h.app.remote_status().book.as_deref(), moved.to_str());
+    assert!(h.screen().contains("Part 3/4"), "reopened where it was left: {}", h.screen());
+    h.press("x");
+
+    // Deleting asks first
+    h.press("jd");
+    assert!(h.screen().contains("Delete beta.epub? y: Delete"), "{}", h.screen());
+    h.press("n");
+    assert!(books.join("beta.epub").exists());
+    h.press("dy");
+    assert!(!books.join("beta.epub").exists());
+    let screen = h.screen();
+    assert!(screen.contains("Deleted beta") && !screen.contains("beta — Ann"), "{}", screen);
+}
+
+/// Tries moving the only book to `folder` and checks it was refused.
+fn assert_move_refused(h: &mut Harness, folder: &str) {
+    h.resize(120, 24);
+    h.press("m");
+    h.press(folder);
+    h.press_code(KeyCode::Enter);
+    assert!(h.screen().contains("Give a folder inside the library"), "{}", h.screen());
+    assert!(h.dir.path().join("books").join("alpha.epub").exists());
+    assert!(!h.dir.path().join("outside").exists());
+}
+
+#[test]
+fn books_are_not_moved_to_an_absolute_folder() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    let outside = h.dir.path().join("outside");
+    assert_move_refused(&mut h, outside.to_str().unwrap());
+}
+
+#[test]
+fn books_are_not_moved_above_the_library() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    assert_move_refused(&mut h, "../outside");
+    assert_move_refused(&mut h, "Classics/../../outside");
+}
+
+#[test]
+fn books_are_hyphenated_in_their_detected_language() {
+    let russian = "<p>Все счастливые семьи похожи друг на друга, каждая несчастливая семья несчастлива по-своему.</p>";
+    let mut h = Harness::new(&[("roman.epub", &[russian])]);
+    let path = h.dir.path().join("books").join("roman.epub");
+    let opf = common::read_zip_entry(&path, "OEBPS/content.opf").replace("<dc:language>en</dc:language>", "");
+    common::remove_zip_entry(&path, "OEBPS/content.opf");
+    common::add_zip_entry(&path, "OEBPS/content.opf", &opf);
+    h.restart();
+    h.resize(44, 20);
+    h.press_code(KeyCode::Enter);
+
+    // Told to be Russian, the long word is broken between its syllables
+    let rows = h.render();
+    assert!(rows[2].contains("друг на друга, каждая нес-"), "{}", rows.join("\n"));
+    assert!(rows[3].contains("│частливая семья"), "{}", rows.join("\n"));
+}
+
+#[cfg(unix)]
+#[test]
+fn words_are_looked_up_in_the_dictionary_of_the_book_language() {
+    let mut h = Harness::new(&[("alpha.epub", ALPHA)]);
+    let path = h.dir.path().join("books").join("alpha.epub");
+    let opf = common::read_zip_entry(&path, "OEBPS/content.opf").replace("<dc:language>en</dc:language>", "<dc:language>de</dc:language>");
+    common::remove_zip_entry(&path, "OEBPS/content.opf");
+    common::add_zip_entry(&path, "OEBPS/content.opf", &opf);
+    h.restart();
+    let opened = h.dir.path().join("opened");
+    h.app.set_dictionary(bookrat::config::DictionaryConfig {
+        command: Some(format!(r#"echo "$BOOKRAT_URL" > {}"#, opened.display())),
+        ..Default::default()
+    });
+    h.press_code(KeyCode::Enter);
+    h.press("K");
+    h.press("Straße");
+    assert!(h.screen().contains("Look up: Straße_"), "{}", h.screen());
+    h.press_code(KeyCode::Enter);
+    let url = "https://de.wiktionary.org/wiki/Stra%C3%9Fe";
+    assert!(h.screen().contains(&format!("Looking up Straße at {}", url)), "{}", h.screen());
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while std::time::Instant::now() < deadline && std::fs::read_to_string(&opened).unwrap_or_default().is_empty() {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    assert_eq!(std::fs::read_to_string(&opened).unwrap().trim(), url);
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use std::io::{Read, Write};
+
+use bookrat::{bookmark::Bookmarks, library::ScanOptions, web::WebServer};
+
+fn get(addr: std::net::SocketAddr, path: &str) -> String {
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n", path).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn web_reader_shares_bookmarks() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let books = dir.path().join("books");
+    std::fs::create_dir(&books).unwrap();
+    common::write_epub(&books.join("alpha.epub"), "alpha", &["<p>One.</p>", "<p>Two <em>x</em>.</p>"]);
+    let bookmarks_path = dir.path().join("bookmarks.json");
+
+    let server = WebServer::bind("127.0.0.1:0", &[books.to_string_lossy().to_string()], ScanOptions::default(), &bookmarks_path).unwrap();
+    let addr = server.local_addr().unwrap();
+    std::thread::spawn(move || server.run());
+
+    let library = get(addr, "/");
+    assert!(library.starts_with("HTTP/1.1 200"));
+    assert!(library.contains("<a href=\"/book/0\">alpha</a>"));
+    assert!(library.contains("never read"));
+
+    let first = get(addr, "/book/0");
+    assert!(first.contains("Part 2/3"));
+    assert!(first.contains("<p>One.</p>"));
+
+    let second = get(addr, "/book/0?part=3");
+    assert!(second.contains("<p>Two <em>x</em>.</p>"));
+    assert!(second.contains("?part=2\">&larr; Previous"));
+
+    let book = books.join("alpha.epub");
+    let bookmarks = Bookmarks::load_from(&bookmarks_path).unwrap();
+    assert_eq!(bookmarks.get_bookmark(book.to_str().unwrap()).unwrap().chapter, 2);
+    assert!(get(addr, "/").contains("part 3, last read"));
+
+    assert!(get(addr, "/book/7").starts_with("HTTP/1.1 404"));
+}